@@ -0,0 +1,293 @@
+//! Build script of the BSPs, generating the memory layout of the board
+//!
+//! [generate] writes `memory.x` into the linker search path, where
+//! `link.x` of `cortex-m-rt` finds it. A `memory.x` in the root of the
+//! application takes precedence. Next to it goes `flash.rs`, which the BSP
+//! includes for its `FLASH_*` constants. It also makes sure the linker flag
+//! -Tdefmt.x is added for the examples using defmt.
+//!
+//! The end of the flash can be kept free of the program, e.g. for a
+//! filesystem or configuration partition, by setting the environment
+//! variable `FLASH_RESERVED_BYTES` to its size, like `64K` or `1M`.
+//!
+//! With `FLASH_LAYOUT=bootloader` or `FLASH_LAYOUT=app`, the program is
+//! linked into the bootloader or the active slot of the A/B layout of
+//! `board_common::boot`, instead of the start of the flash.
+//!
+//! ```ignore
+//! fn main() {
+//!     board_build::generate(2 * board_build::MB, board_build::Defmt::WithFeature);
+//! }
+//! ```
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// A kilobyte
+pub const KB: u32 = 1024;
+/// A megabyte
+pub const MB: u32 = 1024 * KB;
+
+/// Size of an erasable sector of the flash
+const SECTOR_SIZE: u32 = 4 * KB;
+
+/// Size of the bootloader partition of the A/B layout, with the boot2
+const BOOTLOADER_SIZE: u32 = 32 * KB;
+
+/// When the examples are linked with -Tdefmt.x
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Defmt {
+    /// Always, for BSPs whose examples log with defmt in any case
+    Always,
+    /// Only with the `defmt` feature of the BSP
+    WithFeature,
+}
+
+/// Where the program goes, set by `FLASH_LAYOUT`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Layout {
+    /// The start of the flash, the default
+    Single,
+    /// The bootloader partition of the A/B layout
+    Bootloader,
+    /// The active slot of the A/B layout
+    App,
+}
+
+/// Offsets and sizes of the program and the partitions in the flash
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FlashMap {
+    program_start: u32,
+    program_len: u32,
+    reserved_offset: u32,
+    state: u32,
+    active: u32,
+    dfu: u32,
+    slot: u32,
+}
+
+/// Size of the flash of a board sold with different flash sizes
+///
+/// `variants` pairs the `flash-*` features of the BSP with their size. The
+/// largest of the enabled ones wins, as features are additive, and without
+/// any the size is `default`.
+pub fn flash_size_from_features(default: u32, variants: &[(&str, u32)]) -> u32 {
+    largest_enabled(default, variants, |feature| {
+        let var = format!("CARGO_FEATURE_{}", feature.to_uppercase().replace('-', "_"));
+        env::var_os(var).is_some()
+    })
+}
+
+fn largest_enabled(default: u32, variants: &[(&str, u32)], enabled: impl Fn(&str) -> bool) -> u32 {
+    variants
+        .iter()
+        .filter(|(feature, _)| enabled(feature))
+        .map(|&(_, size)| size)
+        .max()
+        .unwrap_or(default)
+}
+
+/// Parses a size in bytes or with a `K` or `M` suffix
+fn parse_size(value: &str) -> Option<u32> {
+    let value = value.trim();
+    let (digits, unit) = if let Some(digits) = value.strip_suffix(['K', 'k'].as_ref()) {
+        (digits, KB)
+    } else if let Some(digits) = value.strip_suffix(['M', 'm'].as_ref()) {
+        (digits, MB)
+    } else {
+        (value, 1)
+    };
+    digits
+        .parse::<u32>()
+        .ok()
+        .and_then(|size| size.checked_mul(unit))
+}
+
+/// Parses `FLASH_RESERVED_BYTES`
+fn flash_reserved_bytes() -> u32 {
+    match env::var("FLASH_RESERVED_BYTES") {
+        Ok(value) => parse_size(&value)
+            .unwrap_or_else(|| panic!("FLASH_RESERVED_BYTES is not a size: {}", value.trim())),
+        Err(_) => 0,
+    }
+}
+
+/// Parses `FLASH_LAYOUT`
+fn flash_layout() -> Layout {
+    match env::var("FLASH_LAYOUT").as_deref() {
+        Err(_) | Ok("") | Ok("single") => Layout::Single,
+        Ok("bootloader") => Layout::Bootloader,
+        Ok("app") => Layout::App,
+        Ok(other) => panic!("FLASH_LAYOUT is not single, bootloader or app: {}", other),
+    }
+}
+
+/// Splits the flash between the program, the partitions of the A/B layout
+/// and the reserved end
+fn flash_map(flash_size: u32, reserved: u32, layout: Layout) -> Result<FlashMap, String> {
+    if reserved & (SECTOR_SIZE - 1) != 0 || reserved >= flash_size - SECTOR_SIZE {
+        return Err(format!(
+            "FLASH_RESERVED_BYTES has to be a multiple of 4K, smaller than the {}K of flash",
+            flash_size / KB
+        ));
+    }
+    let reserved_offset = flash_size - reserved;
+
+    // The A/B layout splits the flash in front of the reserved end into the
+    // bootloader, the state sector, the active slot, and the dfu slot
+    // followed by a spare sector
+    let state = BOOTLOADER_SIZE;
+    let active = state + SECTOR_SIZE;
+    let slot = reserved_offset.saturating_sub(active + SECTOR_SIZE) / 2 / SECTOR_SIZE * SECTOR_SIZE;
+    let dfu = active + slot;
+
+    if layout == Layout::App && slot < 64 * KB {
+        return Err(
+            "FLASH_LAYOUT=app needs two slots of at least 64K in front of the reserved end".into(),
+        );
+    }
+    let (program_start, program_len) = match layout {
+        Layout::Single => (0, reserved_offset),
+        Layout::Bootloader => (0, BOOTLOADER_SIZE),
+        Layout::App => (active, slot),
+    };
+    Ok(FlashMap {
+        program_start,
+        program_len,
+        reserved_offset,
+        state,
+        active,
+        dfu,
+        slot,
+    })
+}
+
+/// Writes `memory.x` and `flash.rs` for a board with `flash_size` bytes of
+/// flash, see the [crate] documentation
+///
+/// Call it from the `main` of the build script.
+pub fn generate(flash_size: u32, defmt: Defmt) {
+    let out = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    let reserved = flash_reserved_bytes();
+    let map = flash_map(flash_size, reserved, flash_layout()).unwrap_or_else(|e| panic!("{}", e));
+
+    let memory_x = format!(
+        r#"MEMORY {{
+    BOOT2 : ORIGIN = {:#x}, LENGTH = 0x100
+    FLASH : ORIGIN = {:#x}, LENGTH = {}K - 0x100
+    RAM   : ORIGIN = 0x20000000, LENGTH = 256K
+}}
+
+/* The end of the flash, which the program leaves free */
+__flash_reserved_start = 0x10000000 + {:#x};
+__flash_reserved_end = 0x10000000 + {:#x};
+
+EXTERN(BOOT2_FIRMWARE)
+
+SECTIONS {{
+    /* ### Boot loader */
+    .boot2 ORIGIN(BOOT2) :
+    {{
+        KEEP(*(.boot2));
+    }} > BOOT2
+}} INSERT BEFORE .text;
+"#,
+        0x1000_0000 + map.program_start,
+        0x1000_0100 + map.program_start,
+        map.program_len / KB,
+        map.reserved_offset,
+        flash_size
+    );
+    fs::write(out.join("memory.x"), memory_x).unwrap();
+
+    let flash_rs = format!(
+        "/// Size of the flash on the board, in bytes
+pub const FLASH_SIZE_BYTES: u32 = {};
+
+/// Size of the end of the flash which the program leaves free, in bytes
+///
+/// Set by the `FLASH_RESERVED_BYTES` environment variable during the build.
+pub const FLASH_RESERVED_BYTES: u32 = {};
+
+/// Offset of the reserved end of the flash from the start of the flash
+pub const FLASH_RESERVED_OFFSET: u32 = {};
+
+/// Partitions of the A/B layout, see [board_common::boot]
+///
+/// Programs are linked into them when the `FLASH_LAYOUT` environment
+/// variable is set to `bootloader` or `app` during the build.
+pub const FLASH_PARTITIONS: board_common::Partitions = board_common::Partitions {{
+    state: {:#x},
+    active: {:#x},
+    dfu: {:#x},
+    slot: {:#x},
+}};
+",
+        flash_size, reserved, map.reserved_offset, map.state, map.active, map.dfu, map.slot
+    );
+    fs::write(out.join("flash.rs"), flash_rs).unwrap();
+
+    println!("cargo:rustc-link-search={}", out.display());
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=FLASH_RESERVED_BYTES");
+    println!("cargo:rerun-if-env-changed=FLASH_LAYOUT");
+    if defmt == Defmt::Always || env::var_os("CARGO_FEATURE_DEFMT").is_some() {
+        println!("cargo:rustc-link-arg-examples=-Tdefmt.x");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn largest_enabled_flash_size_wins() {
+        let variants = [("flash-8mb", 8 * MB), ("flash-16mb", 16 * MB)];
+        assert_eq!(largest_enabled(4 * MB, &variants, |_| false), 4 * MB);
+        assert_eq!(
+            largest_enabled(4 * MB, &variants, |f| f == "flash-8mb"),
+            8 * MB
+        );
+        assert_eq!(largest_enabled(4 * MB, &variants, |_| true), 16 * MB);
+        // A smaller variant replaces the default
+        let variants = [("flash-2mb", 2 * MB)];
+        assert_eq!(largest_enabled(8 * MB, &variants, |_| true), 2 * MB);
+    }
+
+    #[test]
+    fn sizes_are_parsed_with_units() {
+        assert_eq!(parse_size("4096"), Some(4096));
+        assert_eq!(parse_size(" 64K "), Some(64 * KB));
+        assert_eq!(parse_size("1m"), Some(MB));
+        assert_eq!(parse_size("4096M"), None);
+        assert_eq!(parse_size("64KB"), None);
+        assert_eq!(parse_size(""), None);
+    }
+
+    #[test]
+    fn flash_is_split_into_partitions() {
+        let map = flash_map(2 * MB, 64 * KB, Layout::Single).unwrap();
+        assert_eq!(map.reserved_offset, 2 * MB - 64 * KB);
+        assert_eq!(
+            (map.program_start, map.program_len),
+            (0, map.reserved_offset)
+        );
+        assert_eq!((map.state, map.active), (32 * KB, 36 * KB));
+        // Two equal slots and a spare sector fill the rest
+        assert_eq!(map.dfu, map.active + map.slot);
+        assert_eq!(map.dfu + map.slot + SECTOR_SIZE, map.reserved_offset);
+
+        let map = flash_map(2 * MB, 0, Layout::App).unwrap();
+        assert_eq!((map.program_start, map.program_len), (map.active, map.slot));
+        let map = flash_map(2 * MB, 0, Layout::Bootloader).unwrap();
+        assert_eq!((map.program_start, map.program_len), (0, BOOTLOADER_SIZE));
+    }
+
+    #[test]
+    fn bad_reservations_are_rejected() {
+        assert!(flash_map(2 * MB, 1000, Layout::Single).is_err());
+        assert!(flash_map(2 * MB, 2 * MB, Layout::Single).is_err());
+        assert!(flash_map(MB, MB - 64 * KB, Layout::App).is_err());
+    }
+}
@@ -0,0 +1,67 @@
+//! A display backlight dimmed by PWM
+//!
+//! The LCD boards drive the backlight of their display from the B output of
+//! a PWM slice. [Backlight] takes the slice and the pin, and sets the
+//! brightness in percent.
+//!
+//! ```ignore
+//! let pwm_slices = hal::pwm::Slices::new(pac.PWM, &mut pac.RESETS);
+//! let mut backlight: Backlight<Pwm4, _> =
+//!     Backlight::new(pwm_slices.pwm4, pins.gp25.reconfigure());
+//! backlight.set_brightness(80);
+//! ```
+
+use embedded_hal::pwm::SetDutyCycle;
+use hal::gpio::{AnyPin, FunctionPwm, Pin};
+use hal::pwm::{FreeRunning, Slice, SliceId, ValidPwmOutputPin, B};
+
+use crate::hal;
+
+/// A backlight on the B output of the PWM slice `S`, with the pin `P`
+pub struct Backlight<S, P>
+where
+    S: SliceId,
+    P: AnyPin,
+    P::Id: ValidPwmOutputPin<S, B>,
+{
+    pwm: Slice<S, FreeRunning>,
+    pin: Pin<P::Id, FunctionPwm, P::Pull>,
+}
+
+impl<S, P> Backlight<S, P>
+where
+    S: SliceId,
+    P: AnyPin,
+    P::Id: ValidPwmOutputPin<S, B>,
+{
+    /// Drive the backlight from `pwm`
+    ///
+    /// The slice counts up and down in phase-correct mode, so with the
+    /// system clock of 125 MHz it runs at about 954 Hz.
+    pub fn new(mut pwm: Slice<S, FreeRunning>, pin: P) -> Self {
+        pwm.set_ph_correct();
+        pwm.enable();
+        let pin = pwm.channel_b.output_to(pin);
+        Backlight { pwm, pin }
+    }
+
+    /// Set the brightness in percent, values above 100 are clamped
+    pub fn set_brightness(&mut self, percent: u8) {
+        let _ = self.pwm.channel_b.set_duty_cycle_percent(percent.min(100));
+    }
+
+    /// Turn the backlight on at full brightness
+    pub fn on(&mut self) {
+        let _ = self.pwm.channel_b.set_duty_cycle_fully_on();
+    }
+
+    /// Turn the backlight off
+    pub fn off(&mut self) {
+        let _ = self.pwm.channel_b.set_duty_cycle_fully_off();
+    }
+
+    /// Release the PWM slice and the pin
+    pub fn free(self) -> (Slice<S, FreeRunning>, Pin<P::Id, FunctionPwm, P::Pull>) {
+        (self.pwm, self.pin)
+    }
+}
@@ -0,0 +1,592 @@
+//! Bringing up a board
+//!
+//! [Board::take] does what every application does first: it takes the
+//! peripherals, starts the crystal oscillator and PLLs, and sets up the
+//! pins, the timer and a delay. Each BSP defines its `Board` as a
+//! [Board] of its `Pins` with [board_pins], so an application starts with
+//!
+//! ```ignore
+//! let mut board = rp_pico::Board::take().unwrap();
+//! let mut led = board.pins.led.into_push_pull_output();
+//! loop {
+//!     led.set_high().unwrap();
+//!     board.delay.delay_ms(500);
+//!     led.set_low().unwrap();
+//!     board.delay.delay_ms(500);
+//! }
+//! ```
+
+use crate::hal;
+use hal::clocks::{Clock, ClocksManager};
+use hal::fugit::HertzU32;
+use hal::pac;
+use hal::pac::vreg_and_chip_reset::vreg::VSEL_A;
+use hal::pll::common_configs::PLL_USB_48MHZ;
+use hal::pll::{setup_pll_blocking, PLLConfig};
+use hal::sio::{HwDivider, Interp0, Interp1, Sio, SioFifo, SioGpioBank0, SioGpioQspi};
+use hal::xosc::setup_xosc_blocking;
+use hal::{Timer, Watchdog};
+
+/// The pins of a board, as set up by its BSP
+pub trait BoardPins {
+    /// Frequency of the crystal oscillator on the board
+    const XOSC_CRYSTAL_FREQ: u32;
+
+    /// GPIO of a LED which the boot ROM may blink while in BOOTSEL mode, see
+    /// [reset_to_usb_boot](crate::usb_boot::reset_to_usb_boot)
+    ///
+    /// Only set for a plain LED which lights up when the GPIO is high.
+    const ACTIVITY_LED: Option<u8> = None;
+
+    /// Takes the GPIOs and configures them for the board
+    fn new(
+        io: pac::IO_BANK0,
+        pads: pac::PADS_BANK0,
+        sio: SioGpioBank0,
+        resets: &mut pac::RESETS,
+    ) -> Self;
+}
+
+/// Implements [BoardPins] for the pins of a BSP, and declares its `Board`
+///
+/// Each BSP calls it once, with the type of its pins, the frequency of its
+/// crystal, and the GPIO of its [activity LED](BoardPins::ACTIVITY_LED) if
+/// it has one:
+///
+/// ```ignore
+/// board_common::board_pins!(Pins, XOSC_CRYSTAL_FREQ, activity_led: 25);
+/// ```
+#[macro_export]
+macro_rules! board_pins {
+    (@impl $pins:ty, $xosc:expr, $led:expr) => {
+        impl $crate::BoardPins for $pins {
+            const XOSC_CRYSTAL_FREQ: u32 = $xosc;
+            const ACTIVITY_LED: ::core::option::Option<u8> = $led;
+
+            fn new(
+                io: $crate::hal::pac::IO_BANK0,
+                pads: $crate::hal::pac::PADS_BANK0,
+                sio: $crate::hal::sio::SioGpioBank0,
+                resets: &mut $crate::hal::pac::RESETS,
+            ) -> Self {
+                <$pins>::new(io, pads, sio, resets)
+            }
+        }
+
+        /// The board with its clocks, pins, timer and delay set up, see
+        /// [board_common::Board::take]
+        pub type Board = $crate::Board<$pins>;
+    };
+    ($pins:ty, $xosc:expr) => {
+        $crate::board_pins!(@impl $pins, $xosc, ::core::option::Option::None);
+    };
+    ($pins:ty, $xosc:expr, activity_led: $led:expr) => {
+        $crate::board_pins!(@impl $pins, $xosc, ::core::option::Option::Some($led));
+    };
+}
+
+/// Declares the `Pins` of a BSP with [hal::bsp_pins], and with the `embassy`
+/// feature of the BSP the same pins for `embassy-rp`
+///
+/// The pins are listed like for [hal::bsp_pins], after the path of the
+/// embassy pins. With the `embassy` feature, the macro also declares
+/// `EmbassyPins`, with the `embassy_rp` peripheral of each pin, and the
+/// `embassy_pins!` macro, which takes them out of the
+/// `embassy_rp::Peripherals`. The BSP re-exports `EmbassyPins` under that
+/// path:
+///
+/// ```ignore
+/// board_common::bsp_pins!(
+///     embassy: crate::embassy::Pins;
+///
+///     /// GPIO 25 is connected to the LED
+///     Gpio25 { name: led },
+/// );
+///
+/// // src/embassy.rs
+/// #[doc(inline)]
+/// pub use crate::EmbassyPins as Pins;
+///
+/// // An application
+/// let p = embassy_rp::init(Default::default());
+/// let pins = rp_pico::embassy_pins!(p);
+/// let led = Output::new(pins.led, Level::Low);
+/// ```
+///
+/// The path is needed as `$crate` in `embassy_pins!` would name
+/// `board-common` instead of the BSP.
+#[macro_export]
+macro_rules! bsp_pins {
+    (embassy: $krate:tt $(:: $path:ident)+; $($pins:tt)*) => {
+        $crate::hal::bsp_pins!($($pins)*);
+        $crate::__embassy_pins!(($) $krate $(:: $path)+; $($pins)*);
+    };
+}
+
+/// The embassy half of [bsp_pins], `$d` is a `$` for the nested macro
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __embassy_pins {
+    (
+        ($d:tt) $krate:tt $(:: $path:ident)+;
+        $(
+            $(#[$id_attr:meta])*
+            $Id:ident {
+                $(#[$name_attr:meta])*
+                name: $name:ident $(,)?
+                $(aliases: { $($aliases:tt)* } $(,)?)*
+            } $(,)?
+        )+
+    ) => {
+        /// The pins of the board, as taken by
+        /// [embassy_pins!](crate::embassy_pins)
+        #[cfg(feature = "embassy")]
+        #[doc(hidden)]
+        pub struct EmbassyPins {
+            $(
+                #[doc = concat!("GPIO ", $crate::__embassy_pin!($Id number))]
+                pub $name: $crate::__embassy_pin!($Id type),
+            )+
+        }
+
+        /// Takes the pins of the board out of the `embassy_rp::Peripherals`
+        /// in `p`, the other fields of `p` can still be used
+        #[cfg(feature = "embassy")]
+        #[macro_export]
+        macro_rules! embassy_pins {
+            ($d p:ident) => {
+                $d $krate $(:: $path)+ {
+                    $($name: $crate::__embassy_pin!($Id take $d p),)+
+                }
+            };
+        }
+    };
+}
+
+/// The `embassy_rp` peripheral of a GPIO, for [bsp_pins]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __embassy_pin {
+    (Gpio0 $($rest:tt)*) => {
+        $crate::__embassy_pin!(@ 0, PIN_0 $($rest)*)
+    };
+    (Gpio1 $($rest:tt)*) => {
+        $crate::__embassy_pin!(@ 1, PIN_1 $($rest)*)
+    };
+    (Gpio2 $($rest:tt)*) => {
+        $crate::__embassy_pin!(@ 2, PIN_2 $($rest)*)
+    };
+    (Gpio3 $($rest:tt)*) => {
+        $crate::__embassy_pin!(@ 3, PIN_3 $($rest)*)
+    };
+    (Gpio4 $($rest:tt)*) => {
+        $crate::__embassy_pin!(@ 4, PIN_4 $($rest)*)
+    };
+    (Gpio5 $($rest:tt)*) => {
+        $crate::__embassy_pin!(@ 5, PIN_5 $($rest)*)
+    };
+    (Gpio6 $($rest:tt)*) => {
+        $crate::__embassy_pin!(@ 6, PIN_6 $($rest)*)
+    };
+    (Gpio7 $($rest:tt)*) => {
+        $crate::__embassy_pin!(@ 7, PIN_7 $($rest)*)
+    };
+    (Gpio8 $($rest:tt)*) => {
+        $crate::__embassy_pin!(@ 8, PIN_8 $($rest)*)
+    };
+    (Gpio9 $($rest:tt)*) => {
+        $crate::__embassy_pin!(@ 9, PIN_9 $($rest)*)
+    };
+    (Gpio10 $($rest:tt)*) => {
+        $crate::__embassy_pin!(@ 10, PIN_10 $($rest)*)
+    };
+    (Gpio11 $($rest:tt)*) => {
+        $crate::__embassy_pin!(@ 11, PIN_11 $($rest)*)
+    };
+    (Gpio12 $($rest:tt)*) => {
+        $crate::__embassy_pin!(@ 12, PIN_12 $($rest)*)
+    };
+    (Gpio13 $($rest:tt)*) => {
+        $crate::__embassy_pin!(@ 13, PIN_13 $($rest)*)
+    };
+    (Gpio14 $($rest:tt)*) => {
+        $crate::__embassy_pin!(@ 14, PIN_14 $($rest)*)
+    };
+    (Gpio15 $($rest:tt)*) => {
+        $crate::__embassy_pin!(@ 15, PIN_15 $($rest)*)
+    };
+    (Gpio16 $($rest:tt)*) => {
+        $crate::__embassy_pin!(@ 16, PIN_16 $($rest)*)
+    };
+    (Gpio17 $($rest:tt)*) => {
+        $crate::__embassy_pin!(@ 17, PIN_17 $($rest)*)
+    };
+    (Gpio18 $($rest:tt)*) => {
+        $crate::__embassy_pin!(@ 18, PIN_18 $($rest)*)
+    };
+    (Gpio19 $($rest:tt)*) => {
+        $crate::__embassy_pin!(@ 19, PIN_19 $($rest)*)
+    };
+    (Gpio20 $($rest:tt)*) => {
+        $crate::__embassy_pin!(@ 20, PIN_20 $($rest)*)
+    };
+    (Gpio21 $($rest:tt)*) => {
+        $crate::__embassy_pin!(@ 21, PIN_21 $($rest)*)
+    };
+    (Gpio22 $($rest:tt)*) => {
+        $crate::__embassy_pin!(@ 22, PIN_22 $($rest)*)
+    };
+    (Gpio23 $($rest:tt)*) => {
+        $crate::__embassy_pin!(@ 23, PIN_23 $($rest)*)
+    };
+    (Gpio24 $($rest:tt)*) => {
+        $crate::__embassy_pin!(@ 24, PIN_24 $($rest)*)
+    };
+    (Gpio25 $($rest:tt)*) => {
+        $crate::__embassy_pin!(@ 25, PIN_25 $($rest)*)
+    };
+    (Gpio26 $($rest:tt)*) => {
+        $crate::__embassy_pin!(@ 26, PIN_26 $($rest)*)
+    };
+    (Gpio27 $($rest:tt)*) => {
+        $crate::__embassy_pin!(@ 27, PIN_27 $($rest)*)
+    };
+    (Gpio28 $($rest:tt)*) => {
+        $crate::__embassy_pin!(@ 28, PIN_28 $($rest)*)
+    };
+    (Gpio29 $($rest:tt)*) => {
+        $crate::__embassy_pin!(@ 29, PIN_29 $($rest)*)
+    };
+    (@ $n:literal, $pin:ident number) => {
+        stringify!($n)
+    };
+    (@ $n:literal, $pin:ident type) => {
+        ::embassy_rp::peripherals::$pin
+    };
+    (@ $n:literal, $pin:ident take $p:ident) => {
+        $p.$pin
+    };
+}
+
+/// Frequency of the system clock, see [Board::take_with]
+///
+/// The peripheral clock follows the system clock, while the USB and ADC
+/// clocks stay at 48 MHz for all presets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ClockPreset {
+    /// 48 MHz, to save power
+    Underclock48,
+    /// 125 MHz, the default of the Pico SDK
+    Standard,
+    /// 133 MHz, the highest frequency in the datasheet
+    Overclock133,
+    /// 200 MHz, with the core voltage raised to 1.15V
+    ///
+    /// Beyond the datasheet, but it works on most chips.
+    Overclock200,
+}
+
+impl ClockPreset {
+    /// Configuration of the system PLL for a 12 MHz crystal
+    fn pll_sys(self) -> PLLConfig {
+        let (vco_mhz, post_div1, post_div2) = match self {
+            ClockPreset::Underclock48 => (1440, 6, 5),
+            ClockPreset::Standard => (1500, 6, 2),
+            ClockPreset::Overclock133 => (1596, 6, 2),
+            ClockPreset::Overclock200 => (1200, 6, 1),
+        };
+        PLLConfig {
+            vco_freq: HertzU32::MHz(vco_mhz),
+            refdiv: 1,
+            post_div1,
+            post_div2,
+        }
+    }
+
+    /// Core voltage needed by the preset, if it is above the default of 1.10V
+    fn voltage(self) -> Option<VSEL_A> {
+        match self {
+            ClockPreset::Overclock200 => Some(VSEL_A::VOLTAGE1_15),
+            _ => None,
+        }
+    }
+}
+
+/// Cortex-M core peripherals left over by [Board::take]
+#[allow(non_snake_case)]
+pub struct Core {
+    pub CPUID: pac::CPUID,
+    pub DCB: pac::DCB,
+    pub DWT: pac::DWT,
+    pub MPU: pac::MPU,
+    pub NVIC: pac::NVIC,
+    pub SCB: pac::SCB,
+}
+
+/// RP2040 peripherals left over by [Board::take]
+#[allow(non_snake_case)]
+pub struct Peripherals {
+    pub ADC: pac::ADC,
+    pub BUSCTRL: pac::BUSCTRL,
+    pub DMA: pac::DMA,
+    pub I2C0: pac::I2C0,
+    pub I2C1: pac::I2C1,
+    pub IO_QSPI: pac::IO_QSPI,
+    pub PADS_QSPI: pac::PADS_QSPI,
+    pub PIO0: pac::PIO0,
+    pub PIO1: pac::PIO1,
+    pub PPB: pac::PPB,
+    pub PSM: pac::PSM,
+    pub PWM: pac::PWM,
+    pub RESETS: pac::RESETS,
+    pub ROSC: pac::ROSC,
+    pub RTC: pac::RTC,
+    pub SPI0: pac::SPI0,
+    pub SPI1: pac::SPI1,
+    pub SYSCFG: pac::SYSCFG,
+    pub SYSINFO: pac::SYSINFO,
+    pub TBMAN: pac::TBMAN,
+    pub UART0: pac::UART0,
+    pub UART1: pac::UART1,
+    pub USBCTRL_DPRAM: pac::USBCTRL_DPRAM,
+    pub USBCTRL_REGS: pac::USBCTRL_REGS,
+    pub VREG_AND_CHIP_RESET: pac::VREG_AND_CHIP_RESET,
+    pub XIP_CTRL: pac::XIP_CTRL,
+    pub XIP_SSI: pac::XIP_SSI,
+}
+
+/// A board with its clocks, pins, timer and delay set up
+pub struct Board<P> {
+    /// The pins, as named by the BSP
+    pub pins: P,
+    /// The clocks, running at the frequencies of the [ClockPreset]
+    pub clocks: ClocksManager,
+    /// The microsecond timer
+    pub timer: Timer,
+    /// A delay based on the SysTick of the core
+    pub delay: cortex_m::delay::Delay,
+    /// The watchdog, generating its tick every microsecond
+    pub watchdog: Watchdog,
+    /// Inter-core FIFO
+    pub fifo: SioFifo,
+    /// Hardware divider
+    pub hwdivider: HwDivider,
+    /// Interpolator 0
+    pub interp0: Interp0,
+    /// Interpolator 1
+    pub interp1: Interp1,
+    /// The QSPI pins of the flash
+    pub gpio_qspi: SioGpioQspi,
+    /// The RP2040 peripherals which weren't used
+    pub peripherals: Peripherals,
+    /// The core peripherals which weren't used
+    pub core: Core,
+}
+
+impl<P: BoardPins> Board<P> {
+    /// Sets up the board with the [Standard](ClockPreset::Standard) clocks
+    ///
+    /// Returns `None` if the peripherals were already taken.
+    ///
+    /// # Panics
+    ///
+    /// If the crystal oscillator or the PLLs fail to start.
+    pub fn take() -> Option<Self> {
+        Self::take_with(ClockPreset::Standard)
+    }
+
+    /// Sets up the board with the system clock given by `preset`
+    ///
+    /// Returns `None` if the peripherals were already taken.
+    ///
+    /// # Panics
+    ///
+    /// If the crystal oscillator or the PLLs fail to start.
+    pub fn take_with(preset: ClockPreset) -> Option<Self> {
+        let pac = pac::Peripherals::take()?;
+        let core = pac::CorePeripherals::take()?;
+        Some(Self::setup(pac, core, preset))
+    }
+
+    /// Sets up the board from peripherals which were already taken
+    ///
+    /// For frameworks which take the peripherals themselves, like the `init`
+    /// task of RTIC which gets them in its context.
+    ///
+    /// # Panics
+    ///
+    /// If the crystal oscillator or the PLLs fail to start.
+    pub fn from_peripherals(
+        pac: pac::Peripherals,
+        core: pac::CorePeripherals,
+        preset: ClockPreset,
+    ) -> Self {
+        Self::setup(pac, core, preset)
+    }
+
+    /// Sets up the board again, with the [Standard](ClockPreset::Standard)
+    /// clocks, whoever owns the peripherals
+    ///
+    /// The system and reference clocks are moved back to the ring oscillator
+    /// first, so the PLLs can be restarted whatever the application did with
+    /// them. Meant for panic handlers, which have to start from scratch.
+    ///
+    /// # Safety
+    ///
+    /// Nothing else may use the peripherals anymore, including the other
+    /// core, so the caller must never return to the application.
+    ///
+    /// # Panics
+    ///
+    /// If the crystal oscillator or the PLLs fail to start.
+    pub unsafe fn steal() -> Self {
+        let pac = pac::Peripherals::steal();
+        let core = pac::CorePeripherals::steal();
+
+        pac.CLOCKS.clk_sys_ctrl().modify(|_, w| w.src().clk_ref());
+        while pac.CLOCKS.clk_sys_selected().read().bits() != 1 {}
+        pac.CLOCKS
+            .clk_ref_ctrl()
+            .modify(|_, w| w.src().rosc_clksrc_ph());
+        while pac.CLOCKS.clk_ref_selected().read().bits() != 1 {}
+
+        Self::setup(pac, core, ClockPreset::Standard)
+    }
+
+    /// The common part of [Board::take_with], [Board::from_peripherals] and
+    /// [Board::steal]
+    fn setup(mut pac: pac::Peripherals, core: pac::CorePeripherals, preset: ClockPreset) -> Self {
+        #[cfg(feature = "defmt")]
+        defmt::debug!("board: starting the clocks, {}", preset);
+        // Raise the core voltage before speeding up
+        if let Some(voltage) = preset.voltage() {
+            hal::vreg::set_voltage(&mut pac.VREG_AND_CHIP_RESET, voltage);
+        }
+
+        let mut watchdog = Watchdog::new(pac.WATCHDOG);
+        let clocks = init_clocks(
+            P::XOSC_CRYSTAL_FREQ,
+            preset,
+            ClockDevices {
+                xosc: pac.XOSC,
+                clocks: pac.CLOCKS,
+                pll_sys: pac.PLL_SYS,
+                pll_usb: pac.PLL_USB,
+            },
+            &mut pac.RESETS,
+            &mut watchdog,
+        )
+        .expect("failed to start the clocks");
+        #[cfg(feature = "defmt")]
+        defmt::info!(
+            "board: system clock at {=u32} Hz",
+            clocks.system_clock.freq().to_Hz()
+        );
+
+        let sio = Sio::new(pac.SIO);
+        let pins = P::new(
+            pac.IO_BANK0,
+            pac.PADS_BANK0,
+            sio.gpio_bank0,
+            &mut pac.RESETS,
+        );
+        let timer = Timer::new(pac.TIMER, &mut pac.RESETS, &clocks);
+        let delay = cortex_m::delay::Delay::new(core.SYST, clocks.system_clock.freq().to_Hz());
+
+        Board {
+            pins,
+            clocks,
+            timer,
+            delay,
+            watchdog,
+            fifo: sio.fifo,
+            hwdivider: sio.hwdivider,
+            interp0: sio.interp0,
+            interp1: sio.interp1,
+            gpio_qspi: sio.gpio_qspi,
+            peripherals: Peripherals {
+                ADC: pac.ADC,
+                BUSCTRL: pac.BUSCTRL,
+                DMA: pac.DMA,
+                I2C0: pac.I2C0,
+                I2C1: pac.I2C1,
+                IO_QSPI: pac.IO_QSPI,
+                PADS_QSPI: pac.PADS_QSPI,
+                PIO0: pac.PIO0,
+                PIO1: pac.PIO1,
+                PPB: pac.PPB,
+                PSM: pac.PSM,
+                PWM: pac.PWM,
+                RESETS: pac.RESETS,
+                ROSC: pac.ROSC,
+                RTC: pac.RTC,
+                SPI0: pac.SPI0,
+                SPI1: pac.SPI1,
+                SYSCFG: pac.SYSCFG,
+                SYSINFO: pac.SYSINFO,
+                TBMAN: pac.TBMAN,
+                UART0: pac.UART0,
+                UART1: pac.UART1,
+                USBCTRL_DPRAM: pac.USBCTRL_DPRAM,
+                USBCTRL_REGS: pac.USBCTRL_REGS,
+                VREG_AND_CHIP_RESET: pac.VREG_AND_CHIP_RESET,
+                XIP_CTRL: pac.XIP_CTRL,
+                XIP_SSI: pac.XIP_SSI,
+            },
+            core: Core {
+                CPUID: core.CPUID,
+                DCB: core.DCB,
+                DWT: core.DWT,
+                MPU: core.MPU,
+                NVIC: core.NVIC,
+                SCB: core.SCB,
+            },
+        }
+    }
+}
+
+/// The peripherals which generate the clocks
+struct ClockDevices {
+    xosc: pac::XOSC,
+    clocks: pac::CLOCKS,
+    pll_sys: pac::PLL_SYS,
+    pll_usb: pac::PLL_USB,
+}
+
+/// Like [hal::clocks::init_clocks_and_plls], with the system PLL set up for
+/// `preset`
+fn init_clocks(
+    xosc_crystal_freq: u32,
+    preset: ClockPreset,
+    devices: ClockDevices,
+    resets: &mut pac::RESETS,
+    watchdog: &mut Watchdog,
+) -> Option<ClocksManager> {
+    let xosc = setup_xosc_blocking(devices.xosc, HertzU32::from_raw(xosc_crystal_freq)).ok()?;
+
+    // Configure watchdog tick generation to tick over every microsecond
+    watchdog.enable_tick_generation((xosc_crystal_freq / 1_000_000) as u8);
+
+    let mut clocks = ClocksManager::new(devices.clocks);
+
+    let pll_sys = setup_pll_blocking(
+        devices.pll_sys,
+        xosc.operating_frequency(),
+        preset.pll_sys(),
+        &mut clocks,
+        resets,
+    )
+    .ok()?;
+    let pll_usb = setup_pll_blocking(
+        devices.pll_usb,
+        xosc.operating_frequency(),
+        PLL_USB_48MHZ,
+        &mut clocks,
+        resets,
+    )
+    .ok()?;
+
+    clocks.init_default(&xosc, &pll_sys, &pll_usb).ok()?;
+    Some(clocks)
+}
@@ -0,0 +1,724 @@
+//! A/B firmware updates through a bootloader partition
+//!
+//! Built with the environment variable `FLASH_LAYOUT=bootloader`, a program
+//! is linked into the first 32K of the flash, and with `FLASH_LAYOUT=app`
+//! into the active slot behind it. The `FLASH_PARTITIONS` of the BSP tell
+//! both where the [Partitions] are:
+//!
+//! | Partition  | Holds                                          |
+//! |------------|------------------------------------------------|
+//! | bootloader | boot2 and the [Bootloader]                     |
+//! | state      | one sector with the progress of an update      |
+//! | active     | the application, which runs                    |
+//! | dfu        | a new or the previous application, and a spare |
+//!
+//! The application downloads a new image into the dfu partition with the
+//! [FirmwareUpdater], e.g. through the [DfuClass](crate::dfu::DfuClass), and
+//! resets. The bootloader then checks the CRC of the image, and swaps it
+//! with the active one, sector by sector. The progress of the swap is kept
+//! in the state sector, so a power loss only delays it.
+//!
+//! The bootloader starts the watchdog before it runs a new image for the
+//! first time. If the watchdog resets the board before the image calls
+//! [FirmwareUpdater::mark_booted], the bootloader swaps the images back.
+//! `mark_booted` stops the watchdog again, so an application which wants
+//! one of its own starts it afterwards.
+//!
+//! ```ignore
+//! let flash = Flash::new(bsp::FLASH_SIZE_BYTES, Core1::Stopped);
+//! let mut updater = FirmwareUpdater::new(flash, bsp::FLASH_PARTITIONS)?;
+//! // Everything came up, keep this image
+//! updater.mark_booted(&mut board.watchdog)?;
+//! board.watchdog.start(WATCHDOG_PERIOD);
+//! ```
+
+use embedded_hal_0_2::watchdog::WatchdogDisable;
+use embedded_storage::nor_flash::MultiwriteNorFlash;
+use hal::fugit::MicrosDurationU32;
+use hal::pac;
+use hal::Watchdog;
+
+use crate::hal;
+use crate::kv::crc32;
+
+const XIP_BASE: u32 = 0x1000_0000;
+/// Vector table offset register of the core
+const VTOR: *mut u32 = 0xe000_ed08 as *mut u32;
+const SECTOR_SIZE: u32 = 4096;
+/// The boot2 in front of the vector table of each image
+const BOOT2_SIZE: u32 = 256;
+/// The RAM, including the two scratch banks
+const RAM: core::ops::RangeInclusive<u32> = 0x2000_0000..=0x2004_2000;
+
+/// Marks the state sector in use, "ABU1"
+const STATE_MAGIC: u32 = 0x3155_4241;
+const MAGIC_AT: u32 = 0;
+const LEN_AT: u32 = 4;
+const CRC_AT: u32 = 8;
+/// Number of sectors swapped
+const SECTORS_AT: u32 = 12;
+// Flags, words which are cleared when set
+const READY_AT: u32 = 16;
+const SWAP_AT: u32 = 20;
+const SWAPPED_AT: u32 = 24;
+const BOOTED_AT: u32 = 28;
+const ROLLBACK_AT: u32 = 32;
+const ROLLED_BACK_AT: u32 = 36;
+const CANCELLED_AT: u32 = 40;
+/// Bits cleared one by one for each step of the swap, and then of the swap
+/// back
+const PROGRESS_AT: u32 = 64;
+const PROGRESS_LEN: u32 = (SECTOR_SIZE - PROGRESS_AT) / 2;
+/// Each sector is swapped in three steps, through the spare sector
+const STEPS_PER_SECTOR: u32 = 3;
+
+/// Frequency of the ring oscillator at reset, in MHz, which drives the
+/// watchdog until the application sets up the clocks
+const ROSC_MHZ: u8 = 6;
+/// Time a new image has to feed the watchdog, or mark itself booted
+const TRIAL_SECONDS: u32 = 5;
+
+/// Bytes moved through RAM at once
+const CHUNK: usize = 256;
+
+/// Where the partitions of the A/B layout are, as offsets from the start
+/// of the flash
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Partitions {
+    /// The state sector
+    pub state: u32,
+    /// The active slot
+    pub active: u32,
+    /// The dfu slot, followed by the spare sector
+    pub dfu: u32,
+    /// Size of a slot, which limits the size of an image
+    pub slot: u32,
+}
+
+/// Errors of the [FirmwareUpdater] and the [Bootloader]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    /// The flash failed
+    Flash(E),
+    /// The partitions don't fit into the flash, or aren't aligned to sectors
+    Partition,
+    /// The image doesn't fit into a slot
+    TooLarge,
+    /// The image was written out of order, or without
+    /// [FirmwareUpdater::begin]
+    Sequence,
+    /// The image doesn't start with a vector table for the active slot
+    Image,
+    /// The running image hasn't marked itself booted yet
+    Testing,
+}
+
+/// Where an update is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum UpdateState {
+    /// No update was started
+    Idle,
+    /// An image is being written to the dfu slot
+    Downloading,
+    /// The image was written, and is swapped in on the next reset
+    Ready,
+    /// The bootloader is swapping the images
+    Swapping,
+    /// The image was swapped in, and runs until it marks itself booted
+    Testing,
+    /// The image has marked itself booted
+    Booted,
+    /// The bootloader is swapping the images back
+    RollingBack,
+    /// The image didn't mark itself booted, and the previous one was
+    /// restored
+    RolledBack,
+    /// The image didn't match its CRC, and was never started
+    Cancelled,
+}
+
+/// How the bootloader starts the active image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Boot {
+    /// The image is known to work
+    Normal,
+    /// The image is new, and watched by the watchdog
+    Trial,
+}
+
+/// Access to the partitions, shared by the updater and the bootloader
+struct Slots<F> {
+    flash: F,
+    parts: Partitions,
+}
+
+impl<F: MultiwriteNorFlash> Slots<F> {
+    fn new(flash: F, parts: Partitions) -> Result<Self, Error<F::Error>> {
+        let fits = match parts.dfu.checked_add(parts.slot + SECTOR_SIZE) {
+            Some(end) => end <= flash.capacity() as u32,
+            None => false,
+        };
+        if (parts.state | parts.active | parts.dfu | parts.slot) & (SECTOR_SIZE - 1) != 0
+            || parts.slot <= BOOT2_SIZE
+            || parts.state + SECTOR_SIZE > parts.active
+            || parts.active + parts.slot > parts.dfu
+            || !fits
+            || parts.slot / SECTOR_SIZE * STEPS_PER_SECTOR > PROGRESS_LEN * 8
+            || F::ERASE_SIZE as u32 != SECTOR_SIZE
+            || 4 % F::WRITE_SIZE != 0
+            || F::READ_SIZE != 1
+        {
+            return Err(Error::Partition);
+        }
+        Ok(Slots { flash, parts })
+    }
+
+    fn read(&mut self, at: u32, buf: &mut [u8]) -> Result<(), Error<F::Error>> {
+        self.flash.read(at, buf).map_err(Error::Flash)
+    }
+
+    fn write(&mut self, at: u32, buf: &[u8]) -> Result<(), Error<F::Error>> {
+        self.flash.write(at, buf).map_err(Error::Flash)
+    }
+
+    fn erase_sector(&mut self, at: u32) -> Result<(), Error<F::Error>> {
+        self.flash.erase(at, at + SECTOR_SIZE).map_err(Error::Flash)
+    }
+
+    /// Reads a word of the state sector
+    fn word(&mut self, at: u32) -> Result<u32, Error<F::Error>> {
+        let mut buf = [0; 4];
+        self.read(self.parts.state + at, &mut buf)?;
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    fn set_word(&mut self, at: u32, value: u32) -> Result<(), Error<F::Error>> {
+        self.write(self.parts.state + at, &value.to_le_bytes())
+    }
+
+    fn flag(&mut self, at: u32) -> Result<bool, Error<F::Error>> {
+        Ok(self.word(at)? != u32::MAX)
+    }
+
+    fn set_flag(&mut self, at: u32) -> Result<(), Error<F::Error>> {
+        self.set_word(at, 0)
+    }
+
+    fn state(&mut self) -> Result<UpdateState, Error<F::Error>> {
+        if self.word(MAGIC_AT)? != STATE_MAGIC {
+            return Ok(UpdateState::Idle);
+        }
+        // The flag set last tells the state, later flags come first
+        let flags = [
+            (CANCELLED_AT, UpdateState::Cancelled),
+            (ROLLED_BACK_AT, UpdateState::RolledBack),
+            (ROLLBACK_AT, UpdateState::RollingBack),
+            (BOOTED_AT, UpdateState::Booted),
+            (SWAPPED_AT, UpdateState::Testing),
+            (SWAP_AT, UpdateState::Swapping),
+            (READY_AT, UpdateState::Ready),
+        ];
+        for &(at, state) in flags.iter() {
+            if self.flag(at)? {
+                return Ok(state);
+            }
+        }
+        Ok(UpdateState::Downloading)
+    }
+
+    /// CRC of the first `len` bytes of the dfu slot
+    fn image_crc(&mut self, len: u32) -> Result<u32, Error<F::Error>> {
+        let mut buf = [0; CHUNK];
+        let mut crc = 0;
+        let mut at = 0;
+        while at < len {
+            let n = (len - at).min(CHUNK as u32) as usize;
+            self.read(self.parts.dfu + at, &mut buf[..n])?;
+            crc = crc32(crc, &buf[..n]);
+            at += n as u32;
+        }
+        Ok(crc)
+    }
+
+    fn is_erased(&mut self, at: u32) -> Result<bool, Error<F::Error>> {
+        let mut buf = [0; CHUNK];
+        for chunk in (at..at + SECTOR_SIZE).step_by(CHUNK) {
+            self.read(chunk, &mut buf)?;
+            if buf.iter().any(|&b| b != 0xff) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Number of sectors of the active slot up to the last one in use
+    fn used_sectors(&mut self) -> Result<u32, Error<F::Error>> {
+        for sector in (0..self.parts.slot / SECTOR_SIZE).rev() {
+            if !self.is_erased(self.parts.active + sector * SECTOR_SIZE)? {
+                return Ok(sector + 1);
+            }
+        }
+        Ok(0)
+    }
+
+    fn copy_sector(&mut self, from: u32, to: u32) -> Result<(), Error<F::Error>> {
+        self.erase_sector(to)?;
+        let mut buf = [0; CHUNK];
+        for offset in (0..SECTOR_SIZE).step_by(CHUNK) {
+            self.read(from + offset, &mut buf)?;
+            if buf.iter().any(|&b| b != 0xff) {
+                self.write(to + offset, &buf)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Number of steps of swap `pass` which are done
+    fn progress(&mut self, pass: u32) -> Result<u32, Error<F::Error>> {
+        let start = self.parts.state + PROGRESS_AT + pass * PROGRESS_LEN;
+        let mut byte = [0; 1];
+        for i in 0..PROGRESS_LEN {
+            self.read(start + i, &mut byte)?;
+            if byte[0] != 0 {
+                // Bits are cleared from the lowest one up
+                return Ok(i * 8 + byte[0].trailing_zeros());
+            }
+        }
+        Ok(PROGRESS_LEN * 8)
+    }
+
+    fn set_progress(&mut self, pass: u32, step: u32) -> Result<(), Error<F::Error>> {
+        let at = PROGRESS_AT + pass * PROGRESS_LEN + step / 8;
+        let aligned = at & !(F::WRITE_SIZE as u32 - 1);
+        // Writing only clears bits, so the other bytes stay as they are
+        let mut buf = [0xff; 4];
+        buf[(at - aligned) as usize] = !(1 << (step % 8));
+        self.write(self.parts.state + aligned, &buf[..F::WRITE_SIZE])
+    }
+
+    /// Swaps the first `sectors` of the active and the dfu slot, continuing
+    /// where an earlier run of `pass` stopped
+    ///
+    /// Each step copies from a sector which the step doesn't change, so it
+    /// can be repeated after a power loss.
+    fn swap(&mut self, sectors: u32, pass: u32) -> Result<(), Error<F::Error>> {
+        let spare = self.parts.dfu + self.parts.slot;
+        for step in self.progress(pass)?..sectors * STEPS_PER_SECTOR {
+            let offset = step / STEPS_PER_SECTOR * SECTOR_SIZE;
+            let active = self.parts.active + offset;
+            let dfu = self.parts.dfu + offset;
+            match step % STEPS_PER_SECTOR {
+                0 => self.copy_sector(active, spare)?,
+                1 => self.copy_sector(dfu, active)?,
+                _ => self.copy_sector(spare, dfu)?,
+            }
+            self.set_progress(pass, step)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes a new image into the dfu slot, for the application
+///
+/// See the [module](self) documentation.
+pub struct FirmwareUpdater<F> {
+    slots: Slots<F>,
+    downloading: bool,
+    /// End of the image written so far
+    len: u32,
+    /// End of the erased part of the dfu slot
+    erased: u32,
+}
+
+impl<F: MultiwriteNorFlash> FirmwareUpdater<F> {
+    /// Creates the updater for the `partitions` of `flash`, usually the
+    /// `FLASH_PARTITIONS` of the BSP
+    pub fn new(flash: F, partitions: Partitions) -> Result<Self, Error<F::Error>> {
+        Ok(FirmwareUpdater {
+            slots: Slots::new(flash, partitions)?,
+            downloading: false,
+            len: 0,
+            erased: 0,
+        })
+    }
+
+    /// The partitions of the layout
+    pub fn partitions(&self) -> &Partitions {
+        &self.slots.parts
+    }
+
+    /// Where the last update is
+    pub fn state(&mut self) -> Result<UpdateState, Error<F::Error>> {
+        self.slots.state()
+    }
+
+    /// Keeps the running image, if it is being tested, and stops the
+    /// `watchdog` which the bootloader started for the trial
+    ///
+    /// Until then, a reset by the watchdog brings back the previous image.
+    /// The watchdog keeps running when the flag can't be written. Outside
+    /// of a trial, a watchdog started by the application is left alone.
+    pub fn mark_booted<W: WatchdogDisable>(
+        &mut self,
+        watchdog: &mut W,
+    ) -> Result<(), Error<F::Error>> {
+        if self.slots.state()? == UpdateState::Testing {
+            #[cfg(feature = "defmt")]
+            defmt::info!("boot: the new image is marked booted");
+            self.slots.set_flag(BOOTED_AT)?;
+            watchdog.disable();
+        }
+        Ok(())
+    }
+
+    /// Starts writing a new image, dropping one which wasn't swapped in yet
+    ///
+    /// Fails with [Error::Testing] until the running image has marked
+    /// itself booted, as the previous image would be lost.
+    pub fn begin(&mut self) -> Result<(), Error<F::Error>> {
+        if self.slots.state()? == UpdateState::Testing {
+            return Err(Error::Testing);
+        }
+        self.slots.erase_sector(self.slots.parts.state)?;
+        self.slots.set_word(MAGIC_AT, STATE_MAGIC)?;
+        self.downloading = true;
+        self.len = 0;
+        self.erased = 0;
+        Ok(())
+    }
+
+    /// Writes `data` at `offset` of the image
+    ///
+    /// The image starts with the boot2 at the start of the active slot, like
+    /// a program built with `FLASH_LAYOUT=app`. Each write has to start at or
+    /// behind the end of the previous one, and be aligned to the write size
+    /// of the flash.
+    pub fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), Error<F::Error>> {
+        if !self.downloading || offset < self.len {
+            return Err(Error::Sequence);
+        }
+        let end = match offset.checked_add(data.len() as u32) {
+            Some(end) if end <= self.slots.parts.slot => end,
+            _ => return Err(Error::TooLarge),
+        };
+        while self.erased < end {
+            self.slots
+                .erase_sector(self.slots.parts.dfu + self.erased)?;
+            self.erased += SECTOR_SIZE;
+        }
+        self.slots.write(self.slots.parts.dfu + offset, data)?;
+        self.len = end;
+        Ok(())
+    }
+
+    /// Finishes the image, which is swapped in on the next reset
+    ///
+    /// Fails with [Error::Image] if the image wasn't built for the active
+    /// slot.
+    pub fn finish(&mut self) -> Result<(), Error<F::Error>> {
+        if !self.downloading {
+            return Err(Error::Sequence);
+        }
+        let parts = self.slots.parts;
+        if self.len < BOOT2_SIZE + 8 {
+            return Err(Error::Image);
+        }
+        let mut vectors = [0; 8];
+        self.slots.read(parts.dfu + BOOT2_SIZE, &mut vectors)?;
+        let stack = u32::from_le_bytes([vectors[0], vectors[1], vectors[2], vectors[3]]);
+        let reset = u32::from_le_bytes([vectors[4], vectors[5], vectors[6], vectors[7]]);
+        let code = XIP_BASE + parts.active + BOOT2_SIZE..XIP_BASE + parts.active + parts.slot;
+        // The reset handler is Thumb code, with the lowest bit set
+        if !RAM.contains(&stack) || reset & 1 == 0 || !code.contains(&(reset & !1)) {
+            return Err(Error::Image);
+        }
+
+        let crc = self.slots.image_crc(self.len)?;
+        self.slots.set_word(LEN_AT, self.len)?;
+        self.slots.set_word(CRC_AT, crc)?;
+        self.slots.set_flag(READY_AT)?;
+        self.downloading = false;
+        #[cfg(feature = "defmt")]
+        defmt::info!(
+            "boot: image of {=u32} bytes ready for the next reset",
+            self.len
+        );
+        Ok(())
+    }
+
+    /// Gives the flash back
+    pub fn free(self) -> F {
+        self.slots.flash
+    }
+}
+
+/// Swaps in new images and starts the active one
+///
+/// See the [module](self) documentation.
+pub struct Bootloader<F> {
+    slots: Slots<F>,
+}
+
+impl<F: MultiwriteNorFlash> Bootloader<F> {
+    /// Creates the bootloader for the `partitions` of `flash`, usually the
+    /// `FLASH_PARTITIONS` of the BSP
+    pub fn new(flash: F, partitions: Partitions) -> Result<Self, Error<F::Error>> {
+        Ok(Bootloader {
+            slots: Slots::new(flash, partitions)?,
+        })
+    }
+
+    /// Finishes a pending swap, and tells how to start the active image
+    ///
+    /// `watchdog_reset` tells whether the watchdog reset the board, because
+    /// it timed out or was forced by [log_panic](crate::crash::log_panic),
+    /// which rolls back an image being tested.
+    pub fn prepare(&mut self, watchdog_reset: bool) -> Result<Boot, Error<F::Error>> {
+        match self.slots.state()? {
+            UpdateState::Ready => {
+                let len = self.slots.word(LEN_AT)?;
+                let crc = self.slots.word(CRC_AT)?;
+                if len > self.slots.parts.slot || self.slots.image_crc(len)? != crc {
+                    #[cfg(feature = "defmt")]
+                    defmt::warn!("boot: the new image doesn't match its CRC, cancelled");
+                    self.slots.set_flag(CANCELLED_AT)?;
+                    return Ok(Boot::Normal);
+                }
+                // The previous image may be longer than the new one
+                let sectors = len.div_ceil(SECTOR_SIZE).max(self.slots.used_sectors()?);
+                self.slots.set_word(SECTORS_AT, sectors)?;
+                self.slots.set_flag(SWAP_AT)?;
+                self.swap_in(sectors)
+            }
+            UpdateState::Swapping => {
+                let sectors = self.slots.word(SECTORS_AT)?;
+                self.swap_in(sectors)
+            }
+            UpdateState::Testing if watchdog_reset => {
+                self.slots.set_flag(ROLLBACK_AT)?;
+                self.roll_back()
+            }
+            UpdateState::Testing => Ok(Boot::Trial),
+            UpdateState::RollingBack => self.roll_back(),
+            _ => Ok(Boot::Normal),
+        }
+    }
+
+    /// Gives the flash back
+    pub fn free(self) -> F {
+        self.slots.flash
+    }
+
+    fn swap_in(&mut self, sectors: u32) -> Result<Boot, Error<F::Error>> {
+        #[cfg(feature = "defmt")]
+        defmt::info!("boot: swapping in the new image, {=u32} sectors", sectors);
+        self.slots.swap(sectors, 0)?;
+        self.slots.set_flag(SWAPPED_AT)?;
+        Ok(Boot::Trial)
+    }
+
+    fn roll_back(&mut self) -> Result<Boot, Error<F::Error>> {
+        let sectors = self.slots.word(SECTORS_AT)?;
+        #[cfg(feature = "defmt")]
+        defmt::warn!(
+            "boot: rolling back to the previous image, {=u32} sectors",
+            sectors
+        );
+        self.slots.swap(sectors, 1)?;
+        self.slots.set_flag(ROLLED_BACK_AT)?;
+        Ok(Boot::Normal)
+    }
+
+    /// Prepares the active image, and jumps to it
+    ///
+    /// A new image is started with the `watchdog` running, which it has to
+    /// feed, or restart with its own period, until it marks itself booted.
+    pub fn boot(mut self, watchdog: &mut Watchdog) -> ! {
+        // SAFETY: reading the reason of the reset has no side effects
+        let reason = unsafe { (*pac::WATCHDOG::ptr()).reason().read() };
+        let watchdog_reset =
+            watchdog_reset(reason.timer().bit_is_set(), reason.force().bit_is_set());
+        // Flash errors only come from a bad layout, which `new` rejects
+        let boot = self.prepare(watchdog_reset).unwrap_or(Boot::Normal);
+        #[cfg(feature = "defmt")]
+        defmt::info!("boot: starting the active image, {}", boot);
+        if boot == Boot::Trial {
+            watchdog.enable_tick_generation(ROSC_MHZ);
+            watchdog.pause_on_debug(true);
+            watchdog.start(MicrosDurationU32::secs(TRIAL_SECONDS));
+        }
+
+        let vector_table = XIP_BASE + self.slots.parts.active + BOOT2_SIZE;
+        // SAFETY: `finish` checked the vector table of the image, and
+        // nothing of the bootloader is used after the jump
+        unsafe {
+            core::ptr::write_volatile(VTOR, vector_table);
+            cortex_m::asm::bootload(vector_table as *const u32)
+        }
+    }
+}
+
+/// Whether the watchdog reset the board, from the `timer` and `force` bits
+/// of its reason register
+///
+/// Both count as a failed boot of an image on trial: the first when it hangs,
+/// the second when it panics, as the crash reporter forces the reset.
+fn watchdog_reset(timer: bool, force: bool) -> bool {
+    timer || force
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec::Vec;
+
+    use embedded_storage::nor_flash::{NorFlash, ReadNorFlash};
+
+    use super::*;
+    use crate::flash::RamFlash;
+
+    type TestFlash = RamFlash<{ 64 * 1024 }>;
+
+    const PARTS: Partitions = Partitions {
+        state: 0x0000,
+        active: 0x1000,
+        dfu: 0x7000,
+        slot: 0x6000,
+    };
+
+    /// An image of `len` bytes with a valid vector table, `seed` tells
+    /// images apart
+    fn image(len: usize, seed: u8) -> Vec<u8> {
+        let mut image: Vec<u8> = (0..len)
+            .map(|i| (i as u8).wrapping_mul(7).wrapping_add(seed))
+            .collect();
+        let stack = 0x2004_0000u32;
+        let reset = XIP_BASE + PARTS.active + BOOT2_SIZE + 0xc1;
+        image[256..260].copy_from_slice(&stack.to_le_bytes());
+        image[260..264].copy_from_slice(&reset.to_le_bytes());
+        image
+    }
+
+    fn with_active(image: &[u8]) -> TestFlash {
+        let mut flash = TestFlash::new();
+        flash.write(PARTS.active, image).unwrap();
+        flash
+    }
+
+    fn download(flash: TestFlash, image: &[u8]) -> TestFlash {
+        let mut updater = FirmwareUpdater::new(flash, PARTS).unwrap();
+        updater.begin().unwrap();
+        for (i, chunk) in image.chunks(1024).enumerate() {
+            updater.write(i as u32 * 1024, chunk).unwrap();
+        }
+        updater.finish().unwrap();
+        assert_eq!(updater.state(), Ok(UpdateState::Ready));
+        updater.free()
+    }
+
+    /// Runs the bootloader after a reset
+    fn reset(flash: TestFlash, timer: bool, force: bool) -> (TestFlash, Boot) {
+        let mut bootloader = Bootloader::new(flash, PARTS).unwrap();
+        let boot = bootloader.prepare(watchdog_reset(timer, force)).unwrap();
+        (bootloader.free(), boot)
+    }
+
+    fn state(flash: TestFlash) -> (TestFlash, UpdateState) {
+        let mut updater = FirmwareUpdater::new(flash, PARTS).unwrap();
+        let state = updater.state().unwrap();
+        (updater.free(), state)
+    }
+
+    /// The watchdog of the trial, running until it is disabled
+    struct TrialWatchdog(bool);
+
+    impl WatchdogDisable for TrialWatchdog {
+        fn disable(&mut self) {
+            self.0 = false;
+        }
+    }
+
+    fn active(flash: &mut TestFlash, len: usize) -> Vec<u8> {
+        let mut buf = std::vec![0; len];
+        flash.read(PARTS.active, &mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn marked_booted_image_is_kept() {
+        let old = image(12288, 1);
+        let new = image(8192, 2);
+        let flash = download(with_active(&old), &new);
+
+        let (flash, boot) = reset(flash, false, false);
+        assert_eq!(boot, Boot::Trial);
+        let mut updater = FirmwareUpdater::new(flash, PARTS).unwrap();
+        assert_eq!(updater.begin(), Err(Error::Testing));
+        let mut watchdog = TrialWatchdog(true);
+        updater.mark_booted(&mut watchdog).unwrap();
+        assert_eq!(updater.state(), Ok(UpdateState::Booted));
+        assert!(!watchdog.0);
+
+        // Once booted, a watchdog of the application is left running
+        let mut watchdog = TrialWatchdog(true);
+        updater.mark_booted(&mut watchdog).unwrap();
+        assert!(watchdog.0);
+
+        let (mut flash, boot) = reset(updater.free(), true, false);
+        assert_eq!(boot, Boot::Normal);
+        assert_eq!(active(&mut flash, new.len()), new);
+    }
+
+    #[test]
+    fn hanging_trial_image_is_rolled_back() {
+        let old = image(12288, 1);
+        let flash = download(with_active(&old), &image(16384, 2));
+
+        let (flash, boot) = reset(flash, false, false);
+        assert_eq!(boot, Boot::Trial);
+        let (mut flash, boot) = reset(flash, true, false);
+        assert_eq!(boot, Boot::Normal);
+        assert_eq!(active(&mut flash, old.len()), old);
+        assert_eq!(state(flash).1, UpdateState::RolledBack);
+    }
+
+    #[test]
+    fn panicking_trial_image_is_rolled_back() {
+        let old = image(12288, 1);
+        let flash = download(with_active(&old), &image(16384, 2));
+
+        let (flash, boot) = reset(flash, false, false);
+        assert_eq!(boot, Boot::Trial);
+        // log_panic forces the reset through the watchdog
+        let (mut flash, boot) = reset(flash, false, true);
+        assert_eq!(boot, Boot::Normal);
+        assert_eq!(active(&mut flash, old.len()), old);
+        assert_eq!(state(flash).1, UpdateState::RolledBack);
+    }
+
+    #[test]
+    fn power_cycle_keeps_testing() {
+        let flash = download(with_active(&image(4096, 1)), &image(4096, 2));
+        let (flash, _) = reset(flash, false, false);
+        let (flash, boot) = reset(flash, false, false);
+        assert_eq!(boot, Boot::Trial);
+        assert_eq!(state(flash).1, UpdateState::Testing);
+    }
+
+    #[test]
+    fn corrupt_image_is_cancelled() {
+        let old = image(8192, 1);
+        let mut flash = download(with_active(&old), &image(8192, 2));
+        flash.write(PARTS.dfu + 4096, &[0; 4]).unwrap();
+
+        let (mut flash, boot) = reset(flash, false, false);
+        assert_eq!(boot, Boot::Normal);
+        assert_eq!(active(&mut flash, old.len()), old);
+        assert_eq!(state(flash).1, UpdateState::Cancelled);
+    }
+}
@@ -0,0 +1,446 @@
+//! Crash reports which survive the reset
+//!
+//! `panic_halt` stops a board which panics, and nobody ever learns why. With
+//! [log_panic] as panic handler, the panic message, the return address and a
+//! dump of the top of the stack are kept in a part of the RAM which isn't
+//! cleared on start-up, and the board reboots through the watchdog. On the
+//! next boot, [last_crash] returns the report, and [CrashReporter] prints it
+//! on a USB serial port.
+//!
+//! ```ignore
+//! #[panic_handler]
+//! fn panic(info: &core::panic::PanicInfo) -> ! {
+//!     board_common::crash::log_panic(info)
+//! }
+//!
+//! let mut reporter = last_crash().map(CrashReporter::new);
+//! loop {
+//!     usb_dev.poll(&mut [&mut serial]);
+//!     if let Some(reporter) = &mut reporter {
+//!         reporter.poll(&mut serial);
+//!     }
+//! }
+//! ```
+//!
+//! The report lives in the `.uninit` section of `cortex-m-rt`, so it is lost
+//! on power loss, and a new image, or a bootloader which uses the same RAM,
+//! may overwrite it. A checksum makes sure such a report is never returned.
+//!
+//! The reset is forced through the watchdog, which the
+//! [Bootloader](crate::Bootloader) counts as a failed boot of an image on
+//! trial, like a timeout of the watchdog, so a new image which panics is
+//! rolled back.
+
+#[cfg(target_arch = "arm")]
+use core::arch::asm;
+use core::borrow::BorrowMut;
+use core::fmt::{self, Write};
+use core::mem::{size_of, MaybeUninit};
+use core::panic::PanicInfo;
+use core::ptr::{self, addr_of, addr_of_mut};
+use core::sync::atomic::{compiler_fence, AtomicBool, Ordering};
+
+use usb_device::bus::UsbBus;
+use usbd_serial::SerialPort;
+
+use crate::hal::pac;
+use crate::kv::crc32;
+
+/// Bytes of the panic message which are kept, longer ones are cut off
+pub const MESSAGE_LEN: usize = 192;
+
+/// Words of the stack which are kept, from the stack pointer upwards
+pub const STACK_WORDS: usize = 32;
+
+/// End of the SRAM, the stack dump stops there
+const RAM_END: u32 = 0x2004_2000;
+
+/// "CRSH"
+const MAGIC: u32 = 0x4853_5243;
+
+/// Bytes of the report as text, see [CrashReporter]
+const TEXT_LEN: usize = 768;
+
+/// The record in RAM, with a checksum over everything after `crc`
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Record {
+    magic: u32,
+    crc: u32,
+    report: CrashReport,
+}
+
+#[link_section = ".uninit.board_common.CRASH"]
+static mut RECORD: MaybeUninit<Record> = MaybeUninit::uninit();
+
+/// Set by the first panic, so a panic while logging doesn't loop
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
+/// What was left behind by the last panic
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct CrashReport {
+    pc: u32,
+    sp: u32,
+    message_len: u32,
+    message: [u8; MESSAGE_LEN],
+    stack: [u32; STACK_WORDS],
+}
+
+impl CrashReport {
+    /// The panic message, with the file and line of the panic
+    pub fn message(&self) -> &str {
+        let len = (self.message_len as usize).min(MESSAGE_LEN);
+        match core::str::from_utf8(&self.message[..len]) {
+            Ok(message) => message,
+            // Cut off in the middle of a character
+            Err(e) => core::str::from_utf8(&self.message[..e.valid_up_to()]).unwrap_or(""),
+        }
+    }
+
+    /// The return address when the panic handler was entered
+    ///
+    /// Look it up with `addr2line -e <elf>`, together with the return
+    /// addresses in the [stack](Self::stack), to find the calls which led
+    /// to the panic.
+    pub fn pc(&self) -> u32 {
+        self.pc
+    }
+
+    /// The stack pointer in the panic handler
+    pub fn sp(&self) -> u32 {
+        self.sp
+    }
+
+    /// The words on the stack, starting at the [stack pointer](Self::sp)
+    pub fn stack(&self) -> &[u32] {
+        let available = (RAM_END.saturating_sub(self.sp) / 4) as usize;
+        &self.stack[..available.min(STACK_WORDS)]
+    }
+}
+
+impl fmt::Debug for CrashReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CrashReport")
+            .field("message", &self.message())
+            .field("pc", &self.pc)
+            .field("sp", &self.sp)
+            .finish()
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for CrashReport {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(
+            f,
+            "CrashReport {{ message: {=str}, pc: {=u32:#010x}, sp: {=u32:#010x} }}",
+            self.message(),
+            self.pc,
+            self.sp
+        )
+    }
+}
+
+impl fmt::Display for CrashReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\r\n", self.message().trim_end())?;
+        write!(f, "PC 0x{:08x}  SP 0x{:08x}\r\n", self.pc, self.sp)?;
+        for (line, words) in self.stack().chunks(4).enumerate() {
+            write!(f, "{:08x}:", self.sp as usize + line * 16)?;
+            for word in words {
+                write!(f, " {:08x}", word)?;
+            }
+            write!(f, "\r\n")?;
+        }
+        Ok(())
+    }
+}
+
+/// Formats into a byte buffer, cutting off what doesn't fit
+pub(crate) struct Cursor<'a> {
+    pub(crate) buf: &'a mut [u8],
+    pub(crate) len: usize,
+}
+
+impl Write for Cursor<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let n = s.len().min(self.buf.len() - self.len);
+        self.buf[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+        Ok(())
+    }
+}
+
+fn record_crc(record: &Record) -> u32 {
+    // SAFETY: the report only holds integers, without any padding
+    let bytes = unsafe {
+        core::slice::from_raw_parts(
+            addr_of!(record.report).cast::<u8>(),
+            size_of::<CrashReport>(),
+        )
+    };
+    crc32(0, bytes)
+}
+
+/// The report of the panic which caused the last reset, if any
+pub fn last_crash() -> Option<CrashReport> {
+    // SAFETY: only read as a whole, and only trusted with the right
+    // checksum. Whatever is in the RAM after a power-up is a valid record
+    // of integers.
+    let record = unsafe { ptr::read_volatile(addr_of!(RECORD).cast::<Record>()) };
+    if record.magic == MAGIC && record.crc == record_crc(&record) {
+        Some(record.report)
+    } else {
+        None
+    }
+}
+
+/// Forgets the report, so [last_crash] returns `None` until the next panic
+pub fn clear_last_crash() {
+    // SAFETY: a single word, not referenced anywhere else
+    unsafe { ptr::write_volatile(addr_of_mut!(RECORD).cast::<u32>(), 0) };
+}
+
+/// Keeps a report of the panic in RAM, then reboots through the watchdog
+///
+/// Call it from the `#[panic_handler]` of the application. The message is
+/// cut off after [MESSAGE_LEN] bytes.
+#[inline(always)]
+pub fn log_panic(info: &PanicInfo<'_>) -> ! {
+    let pc = link_register();
+    if !PANICKING.load(Ordering::Relaxed) {
+        PANICKING.store(true, Ordering::Relaxed);
+        let sp = cortex_m::register::msp::read();
+        // SAFETY: this is the only panic being handled, and the record is
+        // initialised by the first write
+        let record = unsafe {
+            let record = addr_of_mut!(RECORD).cast::<Record>();
+            ptr::write_bytes(record, 0, 1);
+            &mut *record
+        };
+        record.report.pc = pc;
+        record.report.sp = sp;
+        let words = (RAM_END.saturating_sub(sp) / 4) as usize;
+        for (i, word) in record.report.stack.iter_mut().take(words).enumerate() {
+            // SAFETY: between the stack pointer and the end of the SRAM
+            *word = unsafe { ptr::read_volatile((sp as *const u32).add(i)) };
+        }
+        let mut cursor = Cursor {
+            buf: &mut record.report.message,
+            len: 0,
+        };
+        let _ = write!(cursor, "{}", info);
+        record.report.message_len = cursor.len as u32;
+        record.crc = record_crc(record);
+        record.magic = MAGIC;
+    }
+    reset()
+}
+
+/// The return address of the function it is inlined into
+#[inline(always)]
+fn link_register() -> u32 {
+    #[cfg(target_arch = "arm")]
+    {
+        let lr;
+        // SAFETY: only reads the link register
+        unsafe { asm!("mov {}, lr", out(reg) lr, options(nomem, nostack, preserves_flags)) };
+        lr
+    }
+    // The unit tests of the crate run on the host
+    #[cfg(not(target_arch = "arm"))]
+    0
+}
+
+/// Resets the chip through the watchdog
+fn reset() -> ! {
+    compiler_fence(Ordering::SeqCst);
+    cortex_m::asm::dsb();
+    // SAFETY: the application is over, nobody else uses the watchdog anymore
+    let watchdog = unsafe { &*pac::WATCHDOG::ptr() };
+    watchdog.ctrl().write(|w| w.trigger().set_bit());
+    loop {
+        cortex_m::asm::nop();
+    }
+}
+
+/// Prints a [CrashReport] on a USB serial port
+///
+/// The report is printed whenever the host opens the port, that is sets DTR,
+/// so a terminal opened at any time shows it.
+pub struct CrashReporter {
+    text: [u8; TEXT_LEN],
+    len: usize,
+    sent: usize,
+}
+
+impl CrashReporter {
+    /// Prints `report`, as far as it fits into 768 bytes
+    pub fn new(report: CrashReport) -> Self {
+        let mut text = [0; TEXT_LEN];
+        let mut cursor = Cursor {
+            buf: &mut text,
+            len: 0,
+        };
+        let _ = write!(cursor, "\r\nCrash before the last reset:\r\n{}", report);
+        let len = cursor.len;
+        CrashReporter { text, len, sent: 0 }
+    }
+
+    /// The report as text
+    pub fn text(&self) -> &[u8] {
+        &self.text[..self.len]
+    }
+
+    /// Sends as much of the report as `serial` takes
+    ///
+    /// Call it after every poll of the USB device.
+    pub fn poll<B, RS, WS>(&mut self, serial: &mut SerialPort<'_, B, RS, WS>)
+    where
+        B: UsbBus,
+        RS: BorrowMut<[u8]>,
+        WS: BorrowMut<[u8]>,
+    {
+        if !serial.dtr() {
+            self.sent = 0;
+            return;
+        }
+        if self.sent < self.len {
+            if let Ok(count) = serial.write(&self.text[self.sent..self.len]) {
+                self.sent += count;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::string::String;
+    use std::vec::Vec;
+
+    use super::*;
+
+    /// A report of a panic with `message` at `sp`, written like [log_panic]
+    /// does, with the index of each word on the stack
+    fn report(message: &str, sp: u32) -> CrashReport {
+        let mut report = CrashReport {
+            pc: 0x1000_1235,
+            sp,
+            message_len: 0,
+            message: [0; MESSAGE_LEN],
+            stack: [0; STACK_WORDS],
+        };
+        for (i, word) in report.stack.iter_mut().enumerate() {
+            *word = i as u32;
+        }
+        let mut cursor = Cursor {
+            buf: &mut report.message,
+            len: 0,
+        };
+        let _ = write!(cursor, "{}", message);
+        report.message_len = cursor.len as u32;
+        report
+    }
+
+    fn text(reporter: &CrashReporter) -> &str {
+        core::str::from_utf8(reporter.text()).unwrap()
+    }
+
+    #[test]
+    fn cursor_cuts_off_what_does_not_fit() {
+        let mut buf = [0; 8];
+        let mut cursor = Cursor {
+            buf: &mut buf,
+            len: 0,
+        };
+        write!(cursor, "hello").unwrap();
+        write!(cursor, " world").unwrap();
+        assert_eq!(cursor.len, 8);
+        write!(cursor, "!").unwrap();
+        assert_eq!(cursor.len, 8);
+        assert_eq!(&buf, b"hello wo");
+    }
+
+    #[test]
+    fn message_is_cut_off_after_message_len_bytes() {
+        let long: String = "0123456789".repeat(20);
+        let cut = report(&long, 0x2004_0000);
+        assert_eq!(cut.message_len as usize, MESSAGE_LEN);
+        assert_eq!(cut.message(), &long[..MESSAGE_LEN]);
+
+        let short = report("panicked at src/main.rs:3:5:\nboom", 0x2004_0000);
+        assert_eq!(short.message(), "panicked at src/main.rs:3:5:\nboom");
+    }
+
+    #[test]
+    fn message_is_cut_off_before_a_split_character() {
+        // The last "é" only has its first byte within MESSAGE_LEN
+        let mut message = "a".repeat(MESSAGE_LEN - 1);
+        message.push_str("éé");
+        let report = report(&message, 0x2004_0000);
+        assert_eq!(report.message_len as usize, MESSAGE_LEN);
+        assert_eq!(report.message(), &message[..MESSAGE_LEN - 1]);
+    }
+
+    #[test]
+    fn message_len_is_clamped() {
+        let mut report = report("boom", 0x2004_0000);
+        // A record with a broken length, which the checksum didn't catch
+        report.message_len = u32::MAX;
+        assert_eq!(report.message().len(), MESSAGE_LEN);
+        assert!(report.message().starts_with("boom\0"));
+    }
+
+    #[test]
+    fn stack_stops_at_the_end_of_ram() {
+        let words = |sp| report("", sp).stack().len();
+        assert_eq!(words(0x2000_1000), STACK_WORDS);
+        assert_eq!(words(RAM_END - 4 * STACK_WORDS as u32), STACK_WORDS);
+        assert_eq!(words(RAM_END - 4 * STACK_WORDS as u32 + 4), STACK_WORDS - 1);
+        assert_eq!(words(RAM_END - 8), 2);
+        assert_eq!(words(RAM_END - 6), 1);
+        assert_eq!(words(RAM_END), 0);
+        assert_eq!(words(RAM_END + 0x100), 0);
+        assert_eq!(report("", RAM_END - 8).stack(), [0, 1]);
+    }
+
+    #[test]
+    fn display_shows_message_registers_and_stack() {
+        let report = report("panicked at src/main.rs:3:5:\nboom\n", RAM_END - 24);
+        assert_eq!(
+            std::format!("{}", report),
+            "panicked at src/main.rs:3:5:\nboom\r\n\
+             PC 0x10001235  SP 0x20041fe8\r\n\
+             20041fe8: 00000000 00000001 00000002 00000003\r\n\
+             20041ff8: 00000004 00000005\r\n"
+        );
+    }
+
+    #[test]
+    fn reporter_prints_a_heading_and_the_report() {
+        let report = report("boom", RAM_END);
+        let reporter = CrashReporter::new(report);
+        assert_eq!(
+            text(&reporter),
+            "\r\nCrash before the last reset:\r\nboom\r\nPC 0x10001235  SP 0x20042000\r\n"
+        );
+    }
+
+    #[test]
+    fn longest_report_fits_into_the_text() {
+        let message: String = "x".repeat(MESSAGE_LEN);
+        let report = report(&message, 0x2004_0000);
+        let reporter = CrashReporter::new(report);
+        let lines: Vec<&str> = text(&reporter).split("\r\n").collect();
+        // The heading, message, registers and 8 lines of stack, each
+        // terminated
+        assert_eq!(lines.len(), 1 + 1 + 1 + 1 + 8 + 1);
+        assert_eq!(lines[11], "20040070: 0000001c 0000001d 0000001e 0000001f");
+        assert_eq!(lines[12], "");
+        assert_eq!(reporter.text().len(), 632);
+        assert!(reporter.text().len() < TEXT_LEN);
+    }
+}
@@ -0,0 +1,169 @@
+//! Waiting for buttons with the `embedded-hal-async` traits
+//!
+//! The contacts of a button bounce for a few milliseconds after it was
+//! pressed or released. [wait_for_level] waits for a level on the pin of a
+//! button, and then for the contacts to settle, so a press is only seen
+//! once. The `AsyncUserButton`s of the `embassy` module wait with it.
+//!
+//! Only available with the `embedded-hal-async` dependency, which the
+//! `embassy` feature enables.
+
+use core::convert::Infallible;
+
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::digital::Wait;
+
+/// Milliseconds the contacts of a button bounce after a press or release
+pub const DEBOUNCE_MS: u32 = 10;
+
+/// Waits until `pin` is high, or low if `high` is false, and then
+/// [DEBOUNCE_MS] with `delay`
+///
+/// Returns after [DEBOUNCE_MS] if the pin is already at the level.
+pub async fn wait_for_level<P, D>(pin: &mut P, high: bool, delay: &mut D)
+where
+    P: Wait<Error = Infallible>,
+    D: DelayNs,
+{
+    let _ = if high {
+        pin.wait_for_high().await
+    } else {
+        pin.wait_for_low().await
+    };
+    delay.delay_ms(DEBOUNCE_MS).await;
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::cell::RefCell;
+    use core::future::Future;
+    use core::pin::pin;
+    use core::task::{Context, Poll, Waker};
+    use std::collections::VecDeque;
+    use std::rc::Rc;
+    use std::vec::Vec;
+
+    use embedded_hal::digital::ErrorType;
+
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    enum Event {
+        /// The pin changed to high or low
+        Edge(bool),
+        /// The delay waited for milliseconds
+        Delay(u32),
+    }
+
+    type Log = Rc<RefCell<Vec<Event>>>;
+
+    /// A button pin which goes through `levels` while it is waited for
+    struct FakePin {
+        level: bool,
+        levels: VecDeque<bool>,
+        log: Log,
+    }
+
+    impl FakePin {
+        fn wait_for(&mut self, high: bool) {
+            while self.level != high {
+                self.level = self.levels.pop_front().expect("waited forever");
+                self.log.borrow_mut().push(Event::Edge(self.level));
+            }
+        }
+    }
+
+    impl ErrorType for FakePin {
+        type Error = Infallible;
+    }
+
+    impl Wait for FakePin {
+        async fn wait_for_high(&mut self) -> Result<(), Infallible> {
+            self.wait_for(true);
+            Ok(())
+        }
+
+        async fn wait_for_low(&mut self) -> Result<(), Infallible> {
+            self.wait_for(false);
+            Ok(())
+        }
+
+        async fn wait_for_rising_edge(&mut self) -> Result<(), Infallible> {
+            unimplemented!()
+        }
+
+        async fn wait_for_falling_edge(&mut self) -> Result<(), Infallible> {
+            unimplemented!()
+        }
+
+        async fn wait_for_any_edge(&mut self) -> Result<(), Infallible> {
+            unimplemented!()
+        }
+    }
+
+    struct FakeDelay(Log);
+
+    impl DelayNs for FakeDelay {
+        async fn delay_ns(&mut self, ns: u32) {
+            self.0.borrow_mut().push(Event::Delay(ns / 1_000_000));
+        }
+
+        async fn delay_ms(&mut self, ms: u32) {
+            self.0.borrow_mut().push(Event::Delay(ms));
+        }
+    }
+
+    /// Runs `future`, which never has to wait as the fakes return at once
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut context = Context::from_waker(Waker::noop());
+        match pin!(future).poll(&mut context) {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("the fakes never wait"),
+        }
+    }
+
+    fn fakes(level: bool, levels: &[bool]) -> (FakePin, FakeDelay, Log) {
+        let log = Log::default();
+        let pin = FakePin {
+            level,
+            levels: levels.iter().copied().collect(),
+            log: log.clone(),
+        };
+        (pin, FakeDelay(log.clone()), log)
+    }
+
+    #[test]
+    fn settles_after_the_level_is_reached() {
+        let (mut pin, mut delay, log) = fakes(false, &[true]);
+        block_on(wait_for_level(&mut pin, true, &mut delay));
+        assert_eq!(*log.borrow(), [Event::Edge(true), Event::Delay(10)]);
+    }
+
+    #[test]
+    fn settles_if_already_at_the_level() {
+        let (mut pin, mut delay, log) = fakes(false, &[]);
+        block_on(wait_for_level(&mut pin, false, &mut delay));
+        assert_eq!(*log.borrow(), [Event::Delay(10)]);
+    }
+
+    #[test]
+    fn a_click_settles_after_each_edge() {
+        // The release, press and release of an active low button
+        let (mut pin, mut delay, log) = fakes(true, &[false, true]);
+        for high in [true, false, true] {
+            block_on(wait_for_level(&mut pin, high, &mut delay));
+        }
+        assert_eq!(
+            *log.borrow(),
+            [
+                Event::Delay(10),
+                Event::Edge(false),
+                Event::Delay(10),
+                Event::Edge(true),
+                Event::Delay(10),
+            ]
+        );
+    }
+}
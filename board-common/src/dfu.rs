@@ -0,0 +1,429 @@
+//! USB Device Firmware Upgrade class, downloading a new image into the dfu
+//! slot of the [A/B layout](crate::boot)
+//!
+//! Implements the download of DFU 1.1, with the interface in DFU mode next
+//! to the other classes of the application, so `dfu-util` finds it without
+//! a detach:
+//!
+//! ```sh
+//! dfu-util -D app.uf2
+//! ```
+//!
+//! The image is a program built with `FLASH_LAYOUT=app`, either as UF2 file
+//! or as raw binary starting with its boot2. Once the download is complete,
+//! [DfuClass::update_ready] tells the application to reset, and the
+//! bootloader swaps in the new image.
+//!
+//! ```ignore
+//! let mut updater = FirmwareUpdater::new(flash, bsp::FLASH_PARTITIONS)?;
+//! updater.mark_booted(&mut board.watchdog)?;
+//! let mut dfu = DfuClass::new(&usb_bus, updater);
+//! loop {
+//!     usb_dev.poll(&mut [&mut dfu]);
+//!     if dfu.update_ready() {
+//!         cortex_m::peripheral::SCB::sys_reset();
+//!     }
+//! }
+//! ```
+
+use embedded_storage::nor_flash::MultiwriteNorFlash;
+use usb_device::class_prelude::*;
+use usb_device::control::{Recipient, RequestType};
+use usb_device::LangID;
+
+use crate::boot::{Error, FirmwareUpdater};
+
+/// Application specific class
+const CLASS_APPLICATION: u8 = 0xfe;
+const SUBCLASS_DFU: u8 = 0x01;
+/// The interface is in DFU mode, not in run-time mode
+const PROTOCOL_DFU_MODE: u8 = 0x02;
+const DESC_DFU_FUNCTIONAL: u8 = 0x21;
+/// Downloads are supported, uploads and manifesting without a reset aren't
+const ATTR_CAN_DNLOAD: u8 = 0x01;
+const DETACH_TIMEOUT_MS: u16 = 1000;
+/// Bytes per download request, which have to fit the control buffer of
+/// `usb-device`
+const TRANSFER_SIZE: u16 = 128;
+const DFU_VERSION: u16 = 0x0110;
+
+const REQ_DETACH: u8 = 0;
+const REQ_DNLOAD: u8 = 1;
+const REQ_GETSTATUS: u8 = 3;
+const REQ_CLRSTATUS: u8 = 4;
+const REQ_GETSTATE: u8 = 5;
+const REQ_ABORT: u8 = 6;
+
+const SECTOR_SIZE: usize = 4096;
+const XIP_BASE: u32 = 0x1000_0000;
+
+const UF2_BLOCK_SIZE: usize = 512;
+const UF2_MAGIC_START0: u32 = 0x0a32_4655;
+const UF2_MAGIC_START1: u32 = 0x9e5d_5157;
+const UF2_MAGIC_END: u32 = 0x0ab1_6f30;
+const UF2_FLAG_NOT_MAIN_FLASH: u32 = 0x0000_0001;
+const UF2_FLAG_FAMILY_ID: u32 = 0x0000_2000;
+const UF2_FAMILY_RP2040: u32 = 0xe48b_ff56;
+const UF2_DATA_AT: usize = 32;
+const UF2_MAX_PAYLOAD: usize = 476;
+
+/// States of the DFU interface
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DfuState {
+    Idle = 2,
+    DnloadSync = 3,
+    DnloadIdle = 5,
+    ManifestSync = 6,
+    Manifest = 7,
+    ManifestWaitReset = 8,
+    Error = 10,
+}
+
+/// Status codes of the DFU interface
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+enum Status {
+    Ok = 0x00,
+    Target = 0x01,
+    File = 0x02,
+    Write = 0x03,
+    Address = 0x08,
+    StalledPkt = 0x0f,
+}
+
+impl Status {
+    fn from_error<E>(error: Error<E>) -> Self {
+        match error {
+            Error::Flash(_) => Status::Write,
+            Error::TooLarge | Error::Sequence => Status::Address,
+            Error::Image => Status::File,
+            Error::Partition | Error::Testing => Status::Target,
+        }
+    }
+}
+
+/// How the image is sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    /// The bytes of the image, one after the other
+    Raw,
+    /// UF2 blocks, each with its address
+    Uf2,
+}
+
+fn word(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+/// USB DFU class for a [FirmwareUpdater], see the [module](self)
+/// documentation
+pub struct DfuClass<F> {
+    iface: InterfaceNumber,
+    name: StringIndex,
+    updater: FirmwareUpdater<F>,
+    state: DfuState,
+    status: Status,
+    /// Format of the download, known from its first bytes
+    format: Option<Format>,
+    /// Offset in the image of the next raw bytes
+    offset: u32,
+    /// A UF2 block being received
+    block: [u8; UF2_BLOCK_SIZE],
+    block_len: usize,
+    /// Sector of the image in the buffer, which isn't written yet
+    sector: Option<u32>,
+    buf: [u8; SECTOR_SIZE],
+}
+
+impl<F: MultiwriteNorFlash> DfuClass<F> {
+    /// Creates the class, which writes downloads with `updater`
+    pub fn new<B: UsbBus>(alloc: &UsbBusAllocator<B>, updater: FirmwareUpdater<F>) -> Self {
+        DfuClass {
+            iface: alloc.interface(),
+            name: alloc.string(),
+            updater,
+            state: DfuState::Idle,
+            status: Status::Ok,
+            format: None,
+            offset: 0,
+            block: [0; UF2_BLOCK_SIZE],
+            block_len: 0,
+            sector: None,
+            buf: [0xff; SECTOR_SIZE],
+        }
+    }
+
+    /// The updater, e.g. to mark the running image booted
+    pub fn updater(&mut self) -> &mut FirmwareUpdater<F> {
+        &mut self.updater
+    }
+
+    /// Whether a new image was downloaded, and the board should be reset to
+    /// swap it in
+    pub fn update_ready(&self) -> bool {
+        self.state == DfuState::ManifestWaitReset
+    }
+
+    /// Gives the updater back
+    pub fn free(self) -> FirmwareUpdater<F> {
+        self.updater
+    }
+
+    /// Drops a download
+    fn abort(&mut self) {
+        self.state = DfuState::Idle;
+        self.status = Status::Ok;
+        self.format = None;
+        self.offset = 0;
+        self.block_len = 0;
+        self.sector = None;
+    }
+
+    fn fail(&mut self, status: Status) {
+        #[cfg(feature = "defmt")]
+        defmt::warn!("dfu: download failed, {}", status);
+        self.state = DfuState::Error;
+        self.status = status;
+    }
+
+    fn download(&mut self, data: &[u8]) {
+        if self.state == DfuState::Idle {
+            self.abort();
+            if let Err(e) = self.updater.begin() {
+                return self.fail(Status::from_error(e));
+            }
+        }
+        let format = *self.format.get_or_insert_with(|| {
+            if data.len() >= 8
+                && word(data, 0) == UF2_MAGIC_START0
+                && word(data, 4) == UF2_MAGIC_START1
+            {
+                Format::Uf2
+            } else {
+                Format::Raw
+            }
+        });
+        let result = match format {
+            Format::Raw => {
+                let offset = self.offset;
+                self.offset += data.len() as u32;
+                self.put(offset, data).map_err(Status::from_error)
+            }
+            Format::Uf2 => self.receive_uf2(data),
+        };
+        match result {
+            Ok(()) => self.state = DfuState::DnloadSync,
+            Err(status) => self.fail(status),
+        }
+    }
+
+    fn receive_uf2(&mut self, mut data: &[u8]) -> Result<(), Status> {
+        while !data.is_empty() {
+            let n = data.len().min(UF2_BLOCK_SIZE - self.block_len);
+            self.block[self.block_len..self.block_len + n].copy_from_slice(&data[..n]);
+            self.block_len += n;
+            data = &data[n..];
+            if self.block_len == UF2_BLOCK_SIZE {
+                self.block_len = 0;
+                self.uf2_block()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the payload of the UF2 block in `self.block`
+    fn uf2_block(&mut self) -> Result<(), Status> {
+        let block = &self.block;
+        if word(block, 0) != UF2_MAGIC_START0
+            || word(block, 4) != UF2_MAGIC_START1
+            || word(block, UF2_BLOCK_SIZE - 4) != UF2_MAGIC_END
+        {
+            return Err(Status::File);
+        }
+        let flags = word(block, 8);
+        let address = word(block, 12);
+        let size = word(block, 16) as usize;
+        if flags & UF2_FLAG_NOT_MAIN_FLASH != 0
+            || (flags & UF2_FLAG_FAMILY_ID != 0 && word(block, 28) != UF2_FAMILY_RP2040)
+        {
+            return Ok(());
+        }
+        if size > UF2_MAX_PAYLOAD {
+            return Err(Status::File);
+        }
+
+        // Addresses are where the image runs, in the active slot
+        let start = XIP_BASE + self.updater.partitions().active;
+        let offset = address.checked_sub(start).ok_or(Status::Address)?;
+        let mut payload = [0; UF2_MAX_PAYLOAD];
+        payload[..size].copy_from_slice(&block[UF2_DATA_AT..UF2_DATA_AT + size]);
+        self.put(offset, &payload[..size])
+            .map_err(Status::from_error)
+    }
+
+    /// Puts `data` at `offset` of the image into the buffer, writing the
+    /// buffered sector once the data moves on to the next one
+    fn put(&mut self, mut offset: u32, mut data: &[u8]) -> Result<(), Error<F::Error>> {
+        while !data.is_empty() {
+            let sector = offset / SECTOR_SIZE as u32;
+            match self.sector {
+                Some(current) if current == sector => (),
+                Some(current) if current > sector => return Err(Error::Sequence),
+                _ => {
+                    self.flush()?;
+                    self.sector = Some(sector);
+                    self.buf.fill(0xff);
+                }
+            }
+            let at = offset as usize % SECTOR_SIZE;
+            let n = data.len().min(SECTOR_SIZE - at);
+            self.buf[at..at + n].copy_from_slice(&data[..n]);
+            offset += n as u32;
+            data = &data[n..];
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Error<F::Error>> {
+        match self.sector.take() {
+            Some(sector) => self.updater.write(sector * SECTOR_SIZE as u32, &self.buf),
+            None => Ok(()),
+        }
+    }
+
+    /// Writes the rest of the image and marks it ready
+    fn manifest(&mut self) {
+        let result = if self.block_len != 0 {
+            // A UF2 file ends on a whole block
+            Err(Status::File)
+        } else {
+            self.flush()
+                .and_then(|()| self.updater.finish())
+                .map_err(Status::from_error)
+        };
+        match result {
+            Ok(()) => self.state = DfuState::ManifestWaitReset,
+            Err(status) => self.fail(status),
+        }
+    }
+}
+
+impl<B: UsbBus, F: MultiwriteNorFlash> UsbClass<B> for DfuClass<F> {
+    fn get_configuration_descriptors(
+        &self,
+        writer: &mut DescriptorWriter,
+    ) -> usb_device::Result<()> {
+        writer.interface_alt(
+            self.iface,
+            0,
+            CLASS_APPLICATION,
+            SUBCLASS_DFU,
+            PROTOCOL_DFU_MODE,
+            Some(self.name),
+        )?;
+        let detach = DETACH_TIMEOUT_MS.to_le_bytes();
+        let transfer = TRANSFER_SIZE.to_le_bytes();
+        let version = DFU_VERSION.to_le_bytes();
+        writer.write(
+            DESC_DFU_FUNCTIONAL,
+            &[
+                ATTR_CAN_DNLOAD,
+                detach[0],
+                detach[1],
+                transfer[0],
+                transfer[1],
+                version[0],
+                version[1],
+            ],
+        )
+    }
+
+    fn get_string(&self, index: StringIndex, _lang_id: LangID) -> Option<&str> {
+        if index == self.name {
+            Some("RP2040 firmware")
+        } else {
+            None
+        }
+    }
+
+    fn reset(&mut self) {
+        // The host resets the bus after the download, which the
+        // application answers with a reset of the board
+        if self.state != DfuState::ManifestWaitReset {
+            self.abort();
+        }
+    }
+
+    fn control_in(&mut self, xfer: ControlIn<B>) {
+        let req = xfer.request();
+        if req.request_type != RequestType::Class
+            || req.recipient != Recipient::Interface
+            || req.index != u16::from(u8::from(self.iface))
+        {
+            return;
+        }
+        match req.request {
+            REQ_GETSTATUS => {
+                // Downloads are written right away, so the host never has to
+                // wait for them
+                let state = match self.state {
+                    DfuState::DnloadSync => {
+                        self.state = DfuState::DnloadIdle;
+                        self.state
+                    }
+                    DfuState::ManifestSync => {
+                        self.manifest();
+                        match self.state {
+                            DfuState::ManifestWaitReset => DfuState::Manifest,
+                            state => state,
+                        }
+                    }
+                    state => state,
+                };
+                xfer.accept_with(&[self.status as u8, 0, 0, 0, state as u8, 0])
+                    .ok();
+            }
+            REQ_GETSTATE => {
+                xfer.accept_with(&[self.state as u8]).ok();
+            }
+            _ => {
+                // Uploads aren't supported
+                self.fail(Status::StalledPkt);
+                xfer.reject().ok();
+            }
+        }
+    }
+
+    fn control_out(&mut self, xfer: ControlOut<B>) {
+        let req = xfer.request();
+        if req.request_type != RequestType::Class
+            || req.recipient != Recipient::Interface
+            || req.index != u16::from(u8::from(self.iface))
+        {
+            return;
+        }
+        let accepted = match (req.request, self.state) {
+            (REQ_DNLOAD, DfuState::Idle) | (REQ_DNLOAD, DfuState::DnloadIdle) if req.length > 0 => {
+                self.download(xfer.data());
+                true
+            }
+            (REQ_DNLOAD, DfuState::DnloadIdle) => {
+                self.state = DfuState::ManifestSync;
+                true
+            }
+            (REQ_CLRSTATUS, DfuState::Error) | (REQ_ABORT, _) => {
+                self.abort();
+                true
+            }
+            // Already in DFU mode
+            (REQ_DETACH, _) => true,
+            _ => false,
+        };
+        if accepted {
+            xfer.accept().ok();
+        } else {
+            self.fail(Status::StalledPkt);
+            xfer.reject().ok();
+        }
+    }
+}
@@ -0,0 +1,717 @@
+//! A partition of the flash as a disk of 512 byte blocks
+//!
+//! [FlashDisk] implements the [BlockDevice] of `embedded_sdmmc`, so the
+//! firmware can keep files in the flash, and the
+//! [MscClass](crate::usb_msc::MscClass) shows the same partition to the USB
+//! host as a drive.
+//!
+//! Only one of them may use the disk at a time, as neither knows what the
+//! other one has cached. The disk starts out with the firmware, and is
+//! handed to the host with [FlashDisk::give_to_host]. The host gives it back
+//! by ejecting the drive, or the firmware takes it back with
+//! [FlashDisk::take_from_host]. Meanwhile, the other side gets
+//! [DiskError::Busy], or an empty drive on the host.
+//!
+//! ```ignore
+//! let flash = Flash::new(bsp::FLASH_SIZE_BYTES, Core1::Stopped);
+//! let disk = FlashDisk::new(flash, bsp::FLASH_RESERVED_OFFSET, bsp::FLASH_RESERVED_BYTES)?;
+//! let mut volume_mgr = VolumeManager::new(&disk, time_source);
+//! let volume = match volume_mgr.open_volume(VolumeIdx(0)) {
+//!     Ok(volume) => volume,
+//!     Err(_) => {
+//!         disk.format(b"DATA")?;
+//!         volume_mgr.open_volume(VolumeIdx(0))?
+//!     }
+//! };
+//! ```
+
+use core::cell::{Cell, RefCell};
+
+use embedded_sdmmc::{Block, BlockCount, BlockDevice, BlockIdx};
+use embedded_storage::nor_flash::MultiwriteNorFlash;
+
+/// Size of a block of the disk
+const BLOCK_SIZE: usize = Block::LEN;
+/// Size of a sector of the flash, which is erased at once
+const SECTOR_SIZE: usize = 4096;
+const BLOCKS_PER_SECTOR: u32 = (SECTOR_SIZE / BLOCK_SIZE) as u32;
+
+/// Start of the FAT partition, behind the partition table in the first
+/// sector
+const PARTITION_START: u32 = BLOCKS_PER_SECTOR;
+/// Blocks before the FATs, so that they start on a sector
+const RESERVED_BLOCKS: u32 = BLOCKS_PER_SECTOR;
+const ROOT_ENTRIES: u32 = 512;
+const ROOT_BLOCKS: u32 = ROOT_ENTRIES * 32 / BLOCK_SIZE as u32;
+/// Number of clusters FAT16 can have
+const FAT16_CLUSTERS: core::ops::RangeInclusive<u32> = 4085..=65524;
+
+/// Errors of the [FlashDisk]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DiskError<E> {
+    /// The flash failed
+    Flash(E),
+    /// The partition isn't aligned to sectors of the flash
+    Partition,
+    /// The blocks are outside of the disk
+    OutOfRange,
+    /// The other side is using the disk
+    Busy,
+    /// The disk is too small for FAT16, which needs at least 2.1 MB
+    TooSmall,
+}
+
+/// Who may use the [FlashDisk]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Owner {
+    /// The firmware, through [BlockDevice]
+    Firmware,
+    /// The USB host, through the [MscClass](crate::usb_msc::MscClass)
+    Host,
+}
+
+struct Inner<F> {
+    flash: F,
+    /// Start of the partition in the flash
+    offset: u32,
+    blocks: u32,
+    /// A sector being updated
+    sector: [u8; SECTOR_SIZE],
+}
+
+impl<F: MultiwriteNorFlash> Inner<F> {
+    fn read(&mut self, start: u32, buf: &mut [u8]) -> Result<(), DiskError<F::Error>> {
+        let count = (buf.len() / BLOCK_SIZE) as u32;
+        match start.checked_add(count) {
+            Some(end) if end <= self.blocks => (),
+            _ => return Err(DiskError::OutOfRange),
+        }
+        let at = self.offset + start * BLOCK_SIZE as u32;
+        self.flash.read(at, buf).map_err(DiskError::Flash)
+    }
+
+    /// Writes `count` blocks from `start`, which `source` copies into the
+    /// buffer it gets with their index
+    ///
+    /// Each sector is only erased when a bit has to change from 0 to 1, and
+    /// only written when a block changed.
+    fn write(
+        &mut self,
+        start: u32,
+        count: u32,
+        mut source: impl FnMut(u32, &mut [u8]),
+    ) -> Result<(), DiskError<F::Error>> {
+        match start.checked_add(count) {
+            Some(end) if end <= self.blocks => (),
+            _ => return Err(DiskError::OutOfRange),
+        }
+        let mut done = 0;
+        while done < count {
+            let block = start + done;
+            let first = (block % BLOCKS_PER_SECTOR) as usize;
+            let len = (BLOCKS_PER_SECTOR - block % BLOCKS_PER_SECTOR).min(count - done) as usize;
+            let at = self.offset + (block / BLOCKS_PER_SECTOR) * SECTOR_SIZE as u32;
+            self.flash
+                .read(at, &mut self.sector)
+                .map_err(DiskError::Flash)?;
+
+            let (mut changed, mut erase) = (false, false);
+            let mut new = [0; BLOCK_SIZE];
+            for i in 0..len {
+                source(done + i as u32, &mut new);
+                let old = &mut self.sector[(first + i) * BLOCK_SIZE..][..BLOCK_SIZE];
+                for (old, &new) in old.iter_mut().zip(new.iter()) {
+                    changed |= *old != new;
+                    erase |= *old & new != new;
+                    *old = new;
+                }
+            }
+
+            if erase {
+                self.flash
+                    .erase(at, at + SECTOR_SIZE as u32)
+                    .map_err(DiskError::Flash)?;
+                self.flash
+                    .write(at, &self.sector)
+                    .map_err(DiskError::Flash)?;
+            } else if changed {
+                let range = first * BLOCK_SIZE..(first + len) * BLOCK_SIZE;
+                self.flash
+                    .write(at + range.start as u32, &self.sector[range])
+                    .map_err(DiskError::Flash)?;
+            }
+            done += len as u32;
+        }
+        Ok(())
+    }
+}
+
+/// A partition of the flash as a disk, see the [module](self) documentation
+pub struct FlashDisk<F> {
+    inner: RefCell<Inner<F>>,
+    blocks: u32,
+    owner: Cell<Owner>,
+    /// Counts the hand-overs to the host, so it notices the medium changed
+    handovers: Cell<u32>,
+}
+
+impl<F: MultiwriteNorFlash> FlashDisk<F> {
+    /// Uses the `size` bytes at `offset` of the flash as a disk
+    ///
+    /// Both have to be multiples of 4K, the sector size of the flash.
+    pub fn new(flash: F, offset: u32, size: u32) -> Result<Self, DiskError<F::Error>> {
+        // Erase and write sizes are powers of two
+        if (offset | size) as usize & (SECTOR_SIZE - 1) != 0
+            || SECTOR_SIZE & (F::ERASE_SIZE - 1) != 0
+            || BLOCK_SIZE & (F::WRITE_SIZE - 1) != 0
+            || size == 0
+        {
+            return Err(DiskError::Partition);
+        }
+        Ok(FlashDisk {
+            inner: RefCell::new(Inner {
+                flash,
+                offset,
+                blocks: size / BLOCK_SIZE as u32,
+                sector: [0; SECTOR_SIZE],
+            }),
+            blocks: size / BLOCK_SIZE as u32,
+            owner: Cell::new(Owner::Firmware),
+            handovers: Cell::new(0),
+        })
+    }
+
+    /// Number of blocks of the disk
+    pub fn blocks(&self) -> u32 {
+        self.blocks
+    }
+
+    /// Who may use the disk right now
+    pub fn owner(&self) -> Owner {
+        self.owner.get()
+    }
+
+    /// Lets the USB host use the disk
+    ///
+    /// Close all files and volumes first, as the host may change anything on
+    /// the disk.
+    pub fn give_to_host(&self) {
+        if self.owner.get() != Owner::Host {
+            #[cfg(feature = "defmt")]
+            defmt::debug!("disk: given to the host");
+            self.owner.set(Owner::Host);
+            self.handovers.set(self.handovers.get().wrapping_add(1));
+        }
+    }
+
+    /// Takes the disk back from the USB host, which sees the drive as empty
+    ///
+    /// The host may not have written everything yet, so wait for it to eject
+    /// the drive instead, if possible.
+    pub fn take_from_host(&self) {
+        self.owner.set(Owner::Firmware);
+    }
+
+    /// Creates an empty FAT16 filesystem on the disk, named `label`
+    ///
+    /// The disk gets a partition table, as `embedded_sdmmc` expects one.
+    pub fn format(&self, label: &[u8]) -> Result<(), DiskError<F::Error>> {
+        let mut inner = self.borrow(Owner::Firmware)?;
+        let layout = Layout::new(inner.blocks).ok_or(DiskError::TooSmall)?;
+        #[cfg(feature = "defmt")]
+        defmt::info!("disk: formatting {=u32} blocks", inner.blocks);
+        let mut name = [b' '; 11];
+        for (c, &l) in name.iter_mut().zip(label) {
+            *c = l.to_ascii_uppercase();
+        }
+        inner.write(0, layout.data_start(), |block, buf| {
+            layout.block(block, &name, buf)
+        })
+    }
+
+    /// Releases the flash
+    pub fn free(self) -> F {
+        self.inner.into_inner().flash
+    }
+
+    /// Number of times the disk was given to the host
+    pub(crate) fn handovers(&self) -> u32 {
+        self.handovers.get()
+    }
+
+    /// Ejects the medium, on request of the host
+    pub(crate) fn eject(&self) {
+        self.take_from_host();
+    }
+
+    /// Reads whole blocks, for `owner`
+    pub(crate) fn read_blocks(
+        &self,
+        owner: Owner,
+        start: u32,
+        buf: &mut [u8],
+    ) -> Result<(), DiskError<F::Error>> {
+        self.borrow(owner)?.read(start, buf)
+    }
+
+    /// Writes whole blocks, for `owner`
+    pub(crate) fn write_blocks(
+        &self,
+        owner: Owner,
+        start: u32,
+        data: &[u8],
+    ) -> Result<(), DiskError<F::Error>> {
+        let count = (data.len() / BLOCK_SIZE) as u32;
+        self.borrow(owner)?.write(start, count, |i, buf| {
+            buf.copy_from_slice(&data[i as usize * BLOCK_SIZE..][..BLOCK_SIZE])
+        })
+    }
+
+    /// The flash, if `owner` may use it and nobody else is using it right
+    /// now, e.g. in an interrupt
+    fn borrow(
+        &self,
+        owner: Owner,
+    ) -> Result<core::cell::RefMut<'_, Inner<F>>, DiskError<F::Error>> {
+        if self.owner.get() != owner {
+            return Err(DiskError::Busy);
+        }
+        self.inner.try_borrow_mut().map_err(|_| DiskError::Busy)
+    }
+}
+
+impl<F: MultiwriteNorFlash> BlockDevice for FlashDisk<F> {
+    type Error = DiskError<F::Error>;
+
+    fn read(
+        &self,
+        blocks: &mut [Block],
+        start_block_idx: BlockIdx,
+        _reason: &str,
+    ) -> Result<(), Self::Error> {
+        let mut inner = self.borrow(Owner::Firmware)?;
+        for (block, idx) in blocks.iter_mut().zip(start_block_idx.0..) {
+            inner.read(idx, &mut block.contents)?;
+        }
+        Ok(())
+    }
+
+    fn write(&self, blocks: &[Block], start_block_idx: BlockIdx) -> Result<(), Self::Error> {
+        let count = blocks.len() as u32;
+        self.borrow(Owner::Firmware)?
+            .write(start_block_idx.0, count, |i, buf| {
+                buf.copy_from_slice(&blocks[i as usize].contents)
+            })
+    }
+
+    fn num_blocks(&self) -> Result<BlockCount, Self::Error> {
+        Ok(BlockCount(self.blocks()))
+    }
+}
+
+/// Shares the disk with the [MscClass](crate::usb_msc::MscClass)
+impl<F: MultiwriteNorFlash> BlockDevice for &FlashDisk<F> {
+    type Error = DiskError<F::Error>;
+
+    fn read(
+        &self,
+        blocks: &mut [Block],
+        start_block_idx: BlockIdx,
+        reason: &str,
+    ) -> Result<(), Self::Error> {
+        FlashDisk::read(self, blocks, start_block_idx, reason)
+    }
+
+    fn write(&self, blocks: &[Block], start_block_idx: BlockIdx) -> Result<(), Self::Error> {
+        FlashDisk::write(self, blocks, start_block_idx)
+    }
+
+    fn num_blocks(&self) -> Result<BlockCount, Self::Error> {
+        FlashDisk::num_blocks(self)
+    }
+}
+
+/// The blocks of an empty FAT16 filesystem
+struct Layout {
+    /// Blocks of the partition
+    blocks: u32,
+    blocks_per_cluster: u32,
+    blocks_per_fat: u32,
+}
+
+impl Layout {
+    /// The layout for a disk of `disk_blocks`, with the smallest clusters
+    /// FAT16 allows
+    fn new(disk_blocks: u32) -> Option<Self> {
+        let blocks = disk_blocks.checked_sub(PARTITION_START)?;
+        let mut blocks_per_cluster = 1;
+        while blocks_per_cluster <= 64 {
+            let usable = blocks.checked_sub(RESERVED_BLOCKS + ROOT_BLOCKS)?;
+            // Two bytes per cluster, plus the two reserved entries
+            let entries = usable / blocks_per_cluster + 2;
+            let blocks_per_fat = (entries * 2).div_ceil(BLOCK_SIZE as u32);
+            let clusters = usable.checked_sub(2 * blocks_per_fat)? / blocks_per_cluster;
+            if clusters < *FAT16_CLUSTERS.start() {
+                return None;
+            }
+            if clusters <= *FAT16_CLUSTERS.end() {
+                return Some(Layout {
+                    blocks,
+                    blocks_per_cluster,
+                    blocks_per_fat,
+                });
+            }
+            blocks_per_cluster *= 2;
+        }
+        None
+    }
+
+    /// First block of the FATs, counted from the start of the disk
+    fn fat_start(&self) -> u32 {
+        PARTITION_START + RESERVED_BLOCKS
+    }
+
+    fn root_start(&self) -> u32 {
+        self.fat_start() + 2 * self.blocks_per_fat
+    }
+
+    /// First block behind the root directory, where the clusters begin
+    fn data_start(&self) -> u32 {
+        self.root_start() + ROOT_BLOCKS
+    }
+
+    /// Fills `buf` with the block at `block` of the disk
+    fn block(&self, block: u32, label: &[u8; 11], buf: &mut [u8]) {
+        buf.fill(0);
+        if block == 0 {
+            self.partition_table(buf);
+        } else if block == PARTITION_START {
+            self.boot_sector(label, buf);
+        } else if block == self.fat_start() || block == self.fat_start() + self.blocks_per_fat {
+            // The media type and the end of chain marker
+            buf[..4].copy_from_slice(&[0xf8, 0xff, 0xff, 0xff]);
+        } else if block == self.root_start() {
+            buf[..11].copy_from_slice(label);
+            // Volume label
+            buf[11] = 0x08;
+        }
+    }
+
+    fn partition_table(&self, buf: &mut [u8]) {
+        let entry = &mut buf[446..462];
+        // Not bootable, no CHS addresses
+        entry[1..4].copy_from_slice(&[0xfe, 0xff, 0xff]);
+        // FAT16 with LBA
+        entry[4] = 0x0e;
+        entry[5..8].copy_from_slice(&[0xfe, 0xff, 0xff]);
+        entry[8..12].copy_from_slice(&PARTITION_START.to_le_bytes());
+        entry[12..16].copy_from_slice(&self.blocks.to_le_bytes());
+        buf[510..512].copy_from_slice(&[0x55, 0xaa]);
+    }
+
+    fn boot_sector(&self, label: &[u8; 11], buf: &mut [u8]) {
+        buf[0..3].copy_from_slice(&[0xeb, 0x3c, 0x90]);
+        buf[3..11].copy_from_slice(b"MSWIN4.1");
+        buf[11..13].copy_from_slice(&(BLOCK_SIZE as u16).to_le_bytes());
+        buf[13] = self.blocks_per_cluster as u8;
+        buf[14..16].copy_from_slice(&(RESERVED_BLOCKS as u16).to_le_bytes());
+        buf[16] = 2;
+        buf[17..19].copy_from_slice(&(ROOT_ENTRIES as u16).to_le_bytes());
+        if self.blocks <= u32::from(u16::MAX) {
+            buf[19..21].copy_from_slice(&(self.blocks as u16).to_le_bytes());
+        } else {
+            buf[32..36].copy_from_slice(&self.blocks.to_le_bytes());
+        }
+        // Fixed disk
+        buf[21] = 0xf8;
+        buf[22..24].copy_from_slice(&(self.blocks_per_fat as u16).to_le_bytes());
+        buf[24..26].copy_from_slice(&63u16.to_le_bytes());
+        buf[26..28].copy_from_slice(&255u16.to_le_bytes());
+        buf[28..32].copy_from_slice(&PARTITION_START.to_le_bytes());
+        buf[36] = 0x80;
+        // Extended boot signature, followed by serial number, label and type
+        buf[38] = 0x29;
+        buf[39..43].copy_from_slice(&0x2040_2040u32.to_le_bytes());
+        buf[43..54].copy_from_slice(label);
+        buf[54..62].copy_from_slice(b"FAT16   ");
+        buf[510..512].copy_from_slice(&[0x55, 0xaa]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::flash::{FlashError, RamFlash};
+
+    const SIZE: usize = 64 * 1024;
+
+    type TestFlash = RamFlash<SIZE>;
+
+    fn disk() -> FlashDisk<TestFlash> {
+        FlashDisk::new(TestFlash::new(), 0, SIZE as u32).unwrap()
+    }
+
+    /// `count` blocks, `seed` tells them apart
+    fn blocks(count: usize, seed: u8) -> Vec<u8> {
+        (0..count * BLOCK_SIZE)
+            .map(|i| (i as u8).wrapping_mul(13).wrapping_add(seed))
+            .collect()
+    }
+
+    fn read(disk: &FlashDisk<TestFlash>, start: u32, count: usize) -> Vec<u8> {
+        let mut buf = std::vec![0; count * BLOCK_SIZE];
+        disk.read_blocks(disk.owner(), start, &mut buf).unwrap();
+        buf
+    }
+
+    /// Block `block` of a disk formatted with `layout`
+    fn formatted(layout: &Layout, block: u32) -> [u8; BLOCK_SIZE] {
+        let mut buf = [0xaa; BLOCK_SIZE];
+        layout.block(block, b"DATA       ", &mut buf);
+        buf
+    }
+
+    fn u16_at(buf: &[u8], at: usize) -> u16 {
+        u16::from_le_bytes([buf[at], buf[at + 1]])
+    }
+
+    fn u32_at(buf: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes([buf[at], buf[at + 1], buf[at + 2], buf[at + 3]])
+    }
+
+    /// Checks the boot sector of a disk of `disk_blocks`, and returns its
+    /// blocks per cluster and per FAT
+    fn check_boot_sector(disk_blocks: u32) -> (u8, u16) {
+        let layout = Layout::new(disk_blocks).unwrap();
+        let boot = formatted(&layout, PARTITION_START);
+        assert_eq!(boot[..3], [0xeb, 0x3c, 0x90]);
+        assert_eq!(u16_at(&boot, 11), 512);
+        assert_eq!(u16_at(&boot, 14), RESERVED_BLOCKS as u16);
+        assert_eq!(boot[16], 2);
+        assert_eq!(u16_at(&boot, 17), 512);
+        assert_eq!(boot[21], 0xf8);
+        assert_eq!(u32_at(&boot, 28), PARTITION_START);
+        assert_eq!(boot[38], 0x29);
+        assert_eq!(&boot[43..54], b"DATA       ");
+        assert_eq!(&boot[54..62], b"FAT16   ");
+        assert_eq!(boot[510..], [0x55, 0xaa]);
+
+        let blocks = disk_blocks - PARTITION_START;
+        if blocks <= 0xffff {
+            assert_eq!((u16_at(&boot, 19), u32_at(&boot, 32)), (blocks as u16, 0));
+        } else {
+            assert_eq!((u16_at(&boot, 19), u32_at(&boot, 32)), (0, blocks));
+        }
+
+        // Both FATs start with the media type, and have room for all
+        // clusters
+        let (per_cluster, per_fat) = (boot[13], u16_at(&boot, 22));
+        let fat = layout.fat_start();
+        for start in [fat, fat + u32::from(per_fat)] {
+            assert_eq!(formatted(&layout, start)[..4], [0xf8, 0xff, 0xff, 0xff]);
+        }
+        let clusters = (blocks - RESERVED_BLOCKS - ROOT_BLOCKS - 2 * u32::from(per_fat))
+            / u32::from(per_cluster);
+        assert!(FAT16_CLUSTERS.contains(&clusters), "{} clusters", clusters);
+        assert!((clusters + 2) * 2 <= u32::from(per_fat) * 512);
+        (per_cluster, per_fat)
+    }
+
+    #[test]
+    fn layout_of_4_mb() {
+        assert_eq!(check_boot_sector(4 << 11), (1, 32));
+        let layout = Layout::new(4 << 11).unwrap();
+        assert_eq!(
+            (layout.fat_start(), layout.root_start(), layout.data_start()),
+            (16, 80, 112)
+        );
+    }
+
+    #[test]
+    fn layout_of_14_mb() {
+        assert_eq!(check_boot_sector(14 << 11), (1, 112));
+        let layout = Layout::new(14 << 11).unwrap();
+        assert_eq!(
+            (layout.fat_start(), layout.root_start(), layout.data_start()),
+            (16, 240, 272)
+        );
+    }
+
+    #[test]
+    fn large_disks_have_larger_clusters() {
+        assert_eq!(check_boot_sector(64 << 11), (2, 256));
+    }
+
+    #[test]
+    fn every_size_fits_fat16() {
+        for sectors in 521..=(32 << 8) {
+            check_boot_sector(sectors * BLOCKS_PER_SECTOR);
+        }
+    }
+
+    #[test]
+    fn partition_table_and_root_directory() {
+        let layout = Layout::new(4 << 11).unwrap();
+        let table = formatted(&layout, 0);
+        assert_eq!(table[446 + 4], 0x0e);
+        assert_eq!(u32_at(&table, 446 + 8), PARTITION_START);
+        assert_eq!(u32_at(&table, 446 + 12), (4 << 11) - PARTITION_START);
+        assert_eq!(table[510..], [0x55, 0xaa]);
+
+        let root = formatted(&layout, layout.root_start());
+        assert_eq!((&root[..11], root[11]), (&b"DATA       "[..], 0x08));
+        assert!(formatted(&layout, layout.root_start() + 1)
+            .iter()
+            .all(|&b| b == 0));
+    }
+
+    #[test]
+    fn too_small_below_2_1_mb() {
+        // 520 sectors leave 4084 clusters, one too few
+        assert!(Layout::new(520 * BLOCKS_PER_SECTOR).is_none());
+        assert_eq!(
+            Layout::new(521 * BLOCKS_PER_SECTOR)
+                .unwrap()
+                .blocks_per_cluster,
+            1
+        );
+        assert!(Layout::new(0).is_none());
+        assert_eq!(disk().format(b"DATA"), Err(DiskError::TooSmall));
+    }
+
+    #[test]
+    fn partition_has_to_be_aligned() {
+        for (offset, size) in [(0x800, 0x4000), (0, 0x4800), (0x1000, 0)] {
+            assert!(matches!(
+                FlashDisk::new(TestFlash::new(), offset, size),
+                Err(DiskError::Partition)
+            ));
+        }
+        let disk = FlashDisk::new(TestFlash::new(), 0x1000, 0x4000).unwrap();
+        assert_eq!(disk.blocks(), 32);
+    }
+
+    #[test]
+    fn writes_part_of_a_sector() {
+        let disk = disk();
+        let first = blocks(8, 1);
+        disk.write_blocks(Owner::Firmware, 8, &first).unwrap();
+
+        // Setting bits erases the sector, which keeps the other blocks
+        let second = blocks(2, 2);
+        disk.write_blocks(Owner::Firmware, 11, &second).unwrap();
+        let mut expected = first.clone();
+        expected[3 * BLOCK_SIZE..5 * BLOCK_SIZE].copy_from_slice(&second);
+        assert_eq!(read(&disk, 8, 8), expected);
+        assert!(read(&disk, 16, 1).iter().all(|&b| b == 0xff));
+    }
+
+    #[test]
+    fn writes_across_sectors() {
+        let disk = disk();
+        let data = blocks(12, 3);
+        disk.write_blocks(Owner::Firmware, 5, &data).unwrap();
+        assert_eq!(read(&disk, 5, 12), data);
+        assert!(read(&disk, 0, 5).iter().all(|&b| b == 0xff));
+        assert!(read(&disk, 17, 7).iter().all(|&b| b == 0xff));
+
+        let data = blocks(3, 4);
+        disk.write_blocks(Owner::Firmware, 15, &data).unwrap();
+        assert_eq!(read(&disk, 15, 3), data);
+        assert_eq!(read(&disk, 5, 10), blocks(12, 3)[..10 * BLOCK_SIZE]);
+    }
+
+    #[test]
+    fn writes_through_the_block_device() {
+        let disk = disk();
+        let mut written = [Block::new(), Block::new()];
+        written[0].contents.fill(0x12);
+        written[1].contents.fill(0x34);
+        BlockDevice::write(&disk, &written, BlockIdx(7)).unwrap();
+        let mut read = [Block::new(), Block::new()];
+        BlockDevice::read(&disk, &mut read, BlockIdx(7), "test").unwrap();
+        assert_eq!(read[0].contents, written[0].contents);
+        assert_eq!(read[1].contents, written[1].contents);
+        assert_eq!(disk.num_blocks().unwrap().0, 128);
+    }
+
+    #[test]
+    fn only_changes_are_written() {
+        let disk = disk();
+        let data = blocks(2, 5);
+        disk.write_blocks(Owner::Firmware, 2, &data).unwrap();
+
+        // The same data again doesn't touch the flash
+        disk.inner.borrow_mut().flash.cut_power_after(0);
+        disk.write_blocks(Owner::Firmware, 2, &data).unwrap();
+
+        // Clearing bits only writes the block, without an erase
+        disk.inner.borrow_mut().flash.cut_power_after(BLOCK_SIZE);
+        disk.write_blocks(Owner::Firmware, 3, &[0; BLOCK_SIZE])
+            .unwrap();
+        let mut cleared = data.clone();
+        cleared[BLOCK_SIZE..].fill(0);
+        assert_eq!(read(&disk, 2, 2), cleared);
+
+        // Setting them again needs an erase
+        assert_eq!(
+            disk.write_blocks(Owner::Firmware, 2, &data),
+            Err(DiskError::Flash(FlashError::PowerLoss))
+        );
+    }
+
+    #[test]
+    fn blocks_outside_are_out_of_range() {
+        let disk = disk();
+        let mut buf = [0; 2 * BLOCK_SIZE];
+        assert_eq!(
+            disk.read_blocks(Owner::Firmware, 127, &mut buf),
+            Err(DiskError::OutOfRange)
+        );
+        assert_eq!(
+            disk.write_blocks(Owner::Firmware, u32::MAX, &buf),
+            Err(DiskError::OutOfRange)
+        );
+        disk.write_blocks(Owner::Firmware, 126, &buf).unwrap();
+    }
+
+    #[test]
+    fn busy_while_the_other_side_owns_it() {
+        let disk = disk();
+        let mut buf = [0; BLOCK_SIZE];
+        assert_eq!(
+            disk.read_blocks(Owner::Host, 0, &mut buf),
+            Err(DiskError::Busy)
+        );
+
+        disk.give_to_host();
+        disk.give_to_host();
+        assert_eq!((disk.owner(), disk.handovers()), (Owner::Host, 1));
+        let mut block = [Block::new()];
+        assert_eq!(
+            BlockDevice::read(&disk, &mut block, BlockIdx(0), "test"),
+            Err(DiskError::Busy)
+        );
+        assert_eq!(
+            BlockDevice::write(&disk, &block, BlockIdx(0)),
+            Err(DiskError::Busy)
+        );
+        assert_eq!(disk.format(b"DATA"), Err(DiskError::Busy));
+        disk.write_blocks(Owner::Host, 0, &buf).unwrap();
+
+        disk.eject();
+        assert_eq!(disk.owner(), Owner::Firmware);
+        assert_eq!(
+            disk.write_blocks(Owner::Host, 0, &buf),
+            Err(DiskError::Busy)
+        );
+        BlockDevice::read(&disk, &mut block, BlockIdx(0), "test").unwrap();
+        assert_eq!(block[0].contents, buf);
+    }
+}
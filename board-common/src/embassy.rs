@@ -0,0 +1,411 @@
+//! The drivers of the boards on top of `embassy-rp`
+//!
+//! With the `embassy` feature, each BSP has an `embassy` module, which names
+//! the pins of the board for `embassy-rp` and sets up its buttons, LEDs,
+//! battery, NeoPixels and display with the async drivers of this module:
+//!
+//! - [ActiveLow] and [ActiveHigh] around a `gpio::Input` are
+//!   [AsyncUserButton]s, and around a `gpio::Output`
+//!   [StatusLed](crate::StatusLed)s
+//! - [VoltageDivider] around an ADC [Channel] reads the battery with the
+//!   async ADC driver
+//! - [NeoPixel] sends the colours of WS2812 LEDs with a PIO state machine
+//!   and DMA
+//! - [SpiDisplay] sends a [Framebuffer] to a SPI LCD with DMA
+//!
+//! ```ignore
+//! #[embassy_executor::main]
+//! async fn main(_spawner: Spawner) {
+//!     let p = embassy_rp::init(Default::default());
+//!     let pins = adafruit_qt_py_rp2040::embassy_pins!(p);
+//!     let mut button = embassy::button(pins.button);
+//!     loop {
+//!         button.wait_for_press().await;
+//!         ...
+//!     }
+//! }
+//! ```
+//!
+//! `embassy-rp` brings its own runtime, critical section and second stage
+//! bootloader, so build the BSP without its default features.
+//!
+//! Only available with the `embassy` feature.
+
+use core::convert::Infallible;
+
+use embassy_rp::adc::{self, Adc, Channel};
+use embassy_rp::dma::{self, AnyChannel};
+use embassy_rp::gpio::{AnyPin, Level, Output, Pin};
+use embassy_rp::pio::{Common, FifoJoin, PioPin, ShiftConfig, ShiftDirection, StateMachine};
+use embassy_rp::spi::{self, Spi};
+use embassy_rp::{clocks, into_ref, Peripheral, PeripheralRef};
+use embassy_time::{Delay, Timer};
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::Rectangle;
+use embedded_hal::digital::InputPin;
+use embedded_hal_async::digital::Wait;
+use fixed::types::U24F8;
+use smart_leds::RGB8;
+
+use crate::debounce::wait_for_level;
+use crate::{lipo_percentage, ActiveHigh, ActiveLow, UserButton, VoltageDivider};
+
+pub use crate::debounce::DEBOUNCE_MS;
+pub use crate::framebuffer::Framebuffer;
+
+/// A [UserButton] which can be waited for
+///
+/// The waits return once the contacts have settled, [DEBOUNCE_MS] after
+/// the button changed, so a press is only seen once.
+#[allow(async_fn_in_trait)]
+pub trait AsyncUserButton: UserButton {
+    /// Waits until the button is pressed, returns right away if it is held
+    async fn wait_for_press(&mut self);
+
+    /// Waits until the button is released, returns right away if it isn't
+    /// held
+    async fn wait_for_release(&mut self);
+
+    /// Waits for the next click, a press followed by the release
+    async fn wait_for_click(&mut self) {
+        self.wait_for_release().await;
+        self.wait_for_press().await;
+        self.wait_for_release().await;
+    }
+}
+
+impl<P> AsyncUserButton for ActiveHigh<P>
+where
+    P: InputPin<Error = Infallible> + Wait<Error = Infallible>,
+{
+    async fn wait_for_press(&mut self) {
+        wait_for_level(&mut self.0, true, &mut Delay).await
+    }
+
+    async fn wait_for_release(&mut self) {
+        wait_for_level(&mut self.0, false, &mut Delay).await
+    }
+}
+
+impl<P> AsyncUserButton for ActiveLow<P>
+where
+    P: InputPin<Error = Infallible> + Wait<Error = Infallible>,
+{
+    async fn wait_for_press(&mut self) {
+        wait_for_level(&mut self.0, false, &mut Delay).await
+    }
+
+    async fn wait_for_release(&mut self) {
+        wait_for_level(&mut self.0, true, &mut Delay).await
+    }
+}
+
+impl<'d> VoltageDivider<Channel<'d>> {
+    /// Reads the current battery voltage with the async ADC driver
+    ///
+    /// # Return
+    ///
+    /// The current voltage in millivolts
+    pub async fn millivolts(&mut self, adc: &mut Adc<'_, adc::Async>) -> u16 {
+        // A failed conversion is simply repeated
+        let raw_value = loop {
+            if let Ok(value) = adc.read(&mut self.pin).await {
+                break value;
+            }
+        };
+        self.millivolts_from_raw(raw_value)
+    }
+
+    /// Estimates the charge of a single cell LiPo battery, see
+    /// [lipo_percentage]
+    pub async fn percentage(&mut self, adc: &mut Adc<'_, adc::Async>) -> u8 {
+        lipo_percentage(self.millivolts(adc).await)
+    }
+}
+
+/// Microseconds to wait after a write: a colour takes 30 µs, the joined TX
+/// FIFO holds 8 of them, and the LEDs latch after 50 µs without data
+const WS2812_LATCH_US: u64 = 8 * 30 + 60;
+
+/// A chain of `N` WS2812 LEDs, driven by the PIO state machine `S`
+///
+/// The colours are fed to the state machine by DMA, so [write](Self::write)
+/// doesn't keep the CPU busy while they are sent.
+pub struct NeoPixel<'d, P: embassy_rp::pio::Instance, const S: usize, const N: usize> {
+    dma: PeripheralRef<'d, AnyChannel>,
+    sm: StateMachine<'d, P, S>,
+    power: Option<Output<'d, AnyPin>>,
+}
+
+impl<'d, P: embassy_rp::pio::Instance, const S: usize, const N: usize> NeoPixel<'d, P, S, N> {
+    /// Loads the WS2812 program into `common`, and runs it on `sm` to send
+    /// the colours on `pin`
+    pub fn new(
+        common: &mut Common<'d, P>,
+        mut sm: StateMachine<'d, P, S>,
+        dma: impl Peripheral<P = impl dma::Channel> + 'd,
+        pin: impl PioPin,
+    ) -> Self {
+        into_ref!(dma);
+
+        // Each bit is T1 + T2 + T3 cycles of the state machine: always high
+        // for T1, high or low for T2 depending on the bit, and low for T3
+        const T1: u8 = 2;
+        const T2: u8 = 5;
+        const T3: u8 = 3;
+        const CYCLES_PER_BIT: u32 = (T1 + T2 + T3) as u32;
+
+        let side_set = pio::SideSet::new(false, 1, false);
+        let mut a: pio::Assembler<32> = pio::Assembler::new_with_side_set(side_set);
+        let mut wrap_target = a.label();
+        let mut wrap_source = a.label();
+        let mut do_zero = a.label();
+        a.set_with_side_set(pio::SetDestination::PINDIRS, 1, 0);
+        a.bind(&mut wrap_target);
+        a.out_with_delay_and_side_set(pio::OutDestination::X, 1, T3 - 1, 0);
+        a.jmp_with_delay_and_side_set(pio::JmpCondition::XIsZero, &mut do_zero, T1 - 1, 1);
+        a.jmp_with_delay_and_side_set(pio::JmpCondition::Always, &mut wrap_target, T2 - 1, 1);
+        a.bind(&mut do_zero);
+        a.nop_with_delay_and_side_set(T2 - 1, 0);
+        a.bind(&mut wrap_source);
+        let program = a.assemble_with_wrap(wrap_source, wrap_target);
+
+        let mut config = embassy_rp::pio::Config::default();
+        let out_pin = common.make_pio_pin(pin);
+        config.set_out_pins(&[&out_pin]);
+        config.set_set_pins(&[&out_pin]);
+        config.use_program(&common.load_program(&program), &[&out_pin]);
+        // 800 kHz for the bits, in kHz so the clock fits into 24 bits
+        let clock_freq = U24F8::from_num(clocks::clk_sys_freq() / 1000);
+        let bit_freq = U24F8::from_num(800) * CYCLES_PER_BIT;
+        config.clock_divider = clock_freq / bit_freq;
+        config.fifo_join = FifoJoin::TxOnly;
+        // 24 bits of each word, from the top
+        config.shift_out = ShiftConfig {
+            auto_fill: true,
+            threshold: 24,
+            direction: ShiftDirection::Left,
+        };
+        sm.set_config(&config);
+        sm.set_enable(true);
+
+        NeoPixel {
+            dma: dma.map_into(),
+            sm,
+            power: None,
+        }
+    }
+
+    /// Switches the power of the LEDs on with `pin`, for the boards which
+    /// can switch it off
+    pub fn with_power(self, pin: impl Pin) -> Self {
+        NeoPixel {
+            power: Some(Output::new(pin.degrade(), Level::High)),
+            ..self
+        }
+    }
+
+    /// Switches the power of the LEDs on or off, if the board can
+    pub fn set_power(&mut self, on: bool) {
+        if let Some(power) = &mut self.power {
+            power.set_level(on.into());
+        }
+    }
+
+    /// Sends the colours, and waits until the LEDs show them
+    pub async fn write(&mut self, colors: &[RGB8; N]) {
+        let mut words = [0u32; N];
+        for (word, color) in words.iter_mut().zip(colors) {
+            // GRB, in the top 24 bits
+            *word =
+                (u32::from(color.g) << 24) | (u32::from(color.r) << 16) | (u32::from(color.b) << 8);
+        }
+        self.sm.tx().dma_push(self.dma.reborrow(), &words).await;
+        Timer::after_micros(WS2812_LATCH_US).await;
+    }
+}
+
+/// The commands of the MIPI Display Command Set, which the usual SPI LCD
+/// controllers understand
+pub mod dcs {
+    /// Software reset
+    pub const SWRESET: u8 = 0x01;
+    /// Sleep out
+    pub const SLPOUT: u8 = 0x11;
+    /// Normal display mode on
+    pub const NORON: u8 = 0x13;
+    /// Display inversion off
+    pub const INVOFF: u8 = 0x20;
+    /// Display inversion on
+    pub const INVON: u8 = 0x21;
+    /// Display on
+    pub const DISPON: u8 = 0x29;
+    /// Column address set
+    pub const CASET: u8 = 0x2a;
+    /// Row address set
+    pub const RASET: u8 = 0x2b;
+    /// Memory write
+    pub const RAMWR: u8 = 0x2c;
+    /// Memory data access control, the orientation and colour order
+    pub const MADCTL: u8 = 0x36;
+    /// Interface pixel format
+    pub const COLMOD: u8 = 0x3a;
+}
+
+/// A command of the start-up of a display, see [SpiDisplay::init]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct InitStep {
+    /// The command, like [dcs::SLPOUT]
+    pub command: u8,
+    /// Its parameters
+    pub data: &'static [u8],
+    /// Milliseconds to wait after the command
+    pub delay_ms: u64,
+}
+
+impl InitStep {
+    /// Sends `command` with its parameters in `data`, without waiting
+    pub const fn new(command: u8, data: &'static [u8]) -> Self {
+        InitStep {
+            command,
+            data,
+            delay_ms: 0,
+        }
+    }
+
+    /// Waits `delay_ms` after the command
+    pub const fn delay(self, delay_ms: u64) -> Self {
+        InitStep { delay_ms, ..self }
+    }
+}
+
+/// A LCD on a SPI bus, with a data/command pin, which understands the
+/// [dcs] commands
+///
+/// The pixels are sent from a [Framebuffer] by DMA, so
+/// [flush](Self::flush) doesn't keep the CPU busy while they are sent.
+pub struct SpiDisplay<'d, T: spi::Instance> {
+    spi: Spi<'d, T, spi::Async>,
+    dc: Output<'d, AnyPin>,
+    cs: Output<'d, AnyPin>,
+    reset: Option<Output<'d, AnyPin>>,
+    backlight: Option<Output<'d, AnyPin>>,
+    size: Size,
+    offset: Point,
+}
+
+impl<'d, T: spi::Instance> SpiDisplay<'d, T> {
+    /// A display of `size` on `spi`, with its data/command and chip select
+    /// pins
+    pub fn new(spi: Spi<'d, T, spi::Async>, dc: impl Pin, cs: impl Pin, size: Size) -> Self {
+        SpiDisplay {
+            spi,
+            dc: Output::new(dc.degrade(), Level::Low),
+            cs: Output::new(cs.degrade(), Level::High),
+            reset: None,
+            backlight: None,
+            size,
+            offset: Point::zero(),
+        }
+    }
+
+    /// Resets the controller with `pin` in [init](Self::init)
+    pub fn with_reset(self, pin: impl Pin) -> Self {
+        SpiDisplay {
+            reset: Some(Output::new(pin.degrade(), Level::High)),
+            ..self
+        }
+    }
+
+    /// Switches the backlight on with `pin` once the display is set up
+    pub fn with_backlight(self, pin: impl Pin) -> Self {
+        SpiDisplay {
+            backlight: Some(Output::new(pin.degrade(), Level::Low)),
+            ..self
+        }
+    }
+
+    /// Places the display at `offset` within the memory of the controller
+    pub fn with_offset(self, offset: Point) -> Self {
+        SpiDisplay { offset, ..self }
+    }
+
+    /// The size of the display in pixels
+    pub fn size(&self) -> Size {
+        self.size
+    }
+
+    /// Resets the controller, sends `sequence` and switches the backlight on
+    pub async fn init(&mut self, sequence: &[InitStep]) {
+        if let Some(reset) = &mut self.reset {
+            reset.set_low();
+            Timer::after_millis(10).await;
+            reset.set_high();
+            Timer::after_millis(120).await;
+        }
+        for step in sequence {
+            self.command(step.command, step.data).await;
+            if step.delay_ms > 0 {
+                Timer::after_millis(step.delay_ms).await;
+            }
+        }
+        #[cfg(feature = "defmt")]
+        defmt::debug!(
+            "display: {=u32}x{=u32} ready",
+            self.size.width,
+            self.size.height
+        );
+        self.set_backlight(true);
+    }
+
+    /// Switches the backlight on or off, if there is one
+    pub fn set_backlight(&mut self, on: bool) {
+        if let Some(backlight) = &mut self.backlight {
+            backlight.set_level(on.into());
+        }
+    }
+
+    /// Sends `command` with its parameters in `data`
+    pub async fn command(&mut self, command: u8, data: &[u8]) {
+        self.cs.set_low();
+        self.dc.set_low();
+        // Neither the bus nor the DMA can fail
+        let _ = self.spi.write(&[command]).await;
+        if !data.is_empty() {
+            self.dc.set_high();
+            let _ = self.spi.write(data).await;
+        }
+        self.cs.set_high();
+    }
+
+    /// Sends the whole `framebuffer` to the display
+    pub async fn flush(&mut self, framebuffer: &Framebuffer<'_>) {
+        self.flush_area(
+            framebuffer.as_bytes(),
+            Rectangle::new(Point::zero(), framebuffer.size()),
+        )
+        .await
+    }
+
+    /// Sends the pixels of `area`, row by row in RGB565 like a
+    /// [Framebuffer], for example of a sprite
+    pub async fn flush_area(&mut self, pixels: &[u8], area: Rectangle) {
+        let Some(bottom_right) = area.bottom_right() else {
+            return;
+        };
+        let start = area.top_left + self.offset;
+        let end = bottom_right + self.offset;
+        let (x0, x1) = (start.x as u16, end.x as u16);
+        let (y0, y1) = (start.y as u16, end.y as u16);
+        let [x0h, x0l] = x0.to_be_bytes();
+        let [x1h, x1l] = x1.to_be_bytes();
+        let [y0h, y0l] = y0.to_be_bytes();
+        let [y1h, y1l] = y1.to_be_bytes();
+        self.command(dcs::CASET, &[x0h, x0l, x1h, x1l]).await;
+        self.command(dcs::RASET, &[y0h, y0l, y1h, y1l]).await;
+        let len = (area.size.width * area.size.height * 2) as usize;
+        self.command(dcs::RAMWR, &pixels[..len.min(pixels.len())])
+            .await;
+    }
+}
@@ -0,0 +1,457 @@
+//! The QSPI flash of the board, as a [NorFlash]
+//!
+//! The flash can't be read while it is erased or programmed, which means
+//! that neither core may run code from it or access its memory mapping. So
+//! [Flash] calls the erase and program functions of the boot ROM from a
+//! function in RAM, with interrupts disabled. Core 1 has to be stopped or
+//! parked in RAM too, see [Core1].
+//!
+//! Erasing a sector takes about 50 ms, during which interrupts stay
+//! disabled. DMA from the flash has to be stopped by the application.
+//!
+//! [RamFlash] behaves like a NOR flash in memory, to try out code using the
+//! flash on the host.
+
+use core::sync::atomic::{compiler_fence, Ordering};
+
+use embedded_storage::nor_flash::{
+    ErrorType, MultiwriteNorFlash, NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash,
+};
+
+/// Start of the flash in the address space
+const XIP_BASE: u32 = 0x1000_0000;
+/// Smallest unit the flash can program
+const PAGE_SIZE: u32 = 256;
+/// Smallest unit the flash can erase
+const SECTOR_SIZE: u32 = 4096;
+/// The ROM erases 64K blocks with this command, if the range allows
+const BLOCK_SIZE: u32 = 65536;
+const BLOCK_ERASE_CMD: u8 = 0xd8;
+
+/// Base address of the SIO, for the inter-core FIFO
+const SIO_BASE: u32 = 0xd000_0000;
+const FIFO_ST: u32 = SIO_BASE + 0x50;
+const FIFO_WR: u32 = SIO_BASE + 0x54;
+const FIFO_RD: u32 = SIO_BASE + 0x58;
+/// FIFO_ST: the FIFO to this core holds a word
+const FIFO_ST_VLD: u32 = 1 << 0;
+/// FIFO_ST: the FIFO to the other core has room
+const FIFO_ST_RDY: u32 = 1 << 1;
+
+/// Sent by core 0 to park core 1, and echoed once it is parked
+const LOCKOUT_START: u32 = 0x7366_6c6b;
+/// Sent by core 0 to release core 1
+const LOCKOUT_END: u32 = 0x656e_6466;
+
+/// Errors of [Flash] and [RamFlash]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FlashError {
+    /// The offset or length isn't a multiple of the write or erase size
+    NotAligned,
+    /// The range is outside of the flash
+    OutOfBounds,
+    /// The power was cut, see [RamFlash::cut_power_after]
+    PowerLoss,
+}
+
+impl NorFlashError for FlashError {
+    fn kind(&self) -> NorFlashErrorKind {
+        match self {
+            FlashError::NotAligned => NorFlashErrorKind::NotAligned,
+            FlashError::OutOfBounds => NorFlashErrorKind::OutOfBounds,
+            FlashError::PowerLoss => NorFlashErrorKind::Other,
+        }
+    }
+}
+
+/// Checks a range of the flash, `align` is a power of two
+fn check(capacity: u32, offset: u32, len: usize, align: u32) -> Result<(), FlashError> {
+    let len = len as u32;
+    if (offset | len) & (align - 1) != 0 {
+        return Err(FlashError::NotAligned);
+    }
+    match offset.checked_add(len) {
+        Some(end) if end <= capacity => Ok(()),
+        _ => Err(FlashError::OutOfBounds),
+    }
+}
+
+/// What core 1 does while the flash is busy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Core1 {
+    /// Core 1 isn't running, or never touches the flash
+    Stopped,
+    /// Core 1 is parked through the inter-core FIFO
+    ///
+    /// Its `SIO_IRQ_PROC1` handler has to call [core1_lockout_handler], and
+    /// no other messages may be in flight on the FIFO.
+    Lockout,
+}
+
+type VoidFn = unsafe extern "C" fn();
+type EraseFn = unsafe extern "C" fn(u32, usize, u32, u8);
+type ProgramFn = unsafe extern "C" fn(u32, *const u8, usize);
+
+/// Addresses of the flash functions of the boot ROM
+#[derive(Clone, Copy)]
+struct RomFuncs {
+    connect_internal_flash: VoidFn,
+    flash_exit_xip: VoidFn,
+    flash_range_erase: EraseFn,
+    flash_range_program: ProgramFn,
+    flash_flush_cache: VoidFn,
+}
+
+impl RomFuncs {
+    fn lookup() -> Self {
+        // SAFETY: the functions are looked up by their documented tags in
+        // the function table of the boot ROM
+        unsafe {
+            RomFuncs {
+                connect_internal_flash: core::mem::transmute::<usize, VoidFn>(rom_func(*b"IF")),
+                flash_exit_xip: core::mem::transmute::<usize, VoidFn>(rom_func(*b"EX")),
+                flash_range_erase: core::mem::transmute::<usize, EraseFn>(rom_func(*b"RE")),
+                flash_range_program: core::mem::transmute::<usize, ProgramFn>(rom_func(*b"RP")),
+                flash_flush_cache: core::mem::transmute::<usize, VoidFn>(rom_func(*b"FC")),
+            }
+        }
+    }
+}
+
+/// Looks up a function of the boot ROM by its tag
+///
+/// # Safety
+///
+/// Only valid on the RP2040, where the boot ROM is mapped at address 0.
+unsafe fn rom_func(tag: [u8; 2]) -> usize {
+    type LookupFn = unsafe extern "C" fn(*const u16, u32) -> usize;
+    let table = core::ptr::read_volatile(0x14 as *const u16) as *const u16;
+    let lookup = core::mem::transmute::<usize, LookupFn>(core::ptr::read_volatile(
+        0x18 as *const u16,
+    ) as usize);
+    lookup(table, u32::from(u16::from_le_bytes(tag)))
+}
+
+/// An operation on the flash, carried out by [flash_op]
+#[derive(Clone, Copy)]
+enum Op {
+    Erase { addr: u32, len: u32 },
+    Program { addr: u32, data: *const u8 },
+}
+
+/// Runs `op` with XIP disabled, then restores XIP with the boot2 copied to
+/// RAM
+///
+/// # Safety
+///
+/// Interrupts have to be disabled, and core 1 must not access the flash.
+#[inline(never)]
+#[link_section = ".data.ram_func"]
+unsafe fn flash_op(funcs: &RomFuncs, boot2: *const u32, op: Op) {
+    // Set the thumb bit to call the boot2 as a function
+    let boot2 = core::mem::transmute::<usize, VoidFn>(boot2 as usize + 1);
+
+    compiler_fence(Ordering::SeqCst);
+    (funcs.connect_internal_flash)();
+    (funcs.flash_exit_xip)();
+    match op {
+        Op::Erase { addr, len } => {
+            (funcs.flash_range_erase)(addr, len as usize, BLOCK_SIZE, BLOCK_ERASE_CMD)
+        }
+        Op::Program { addr, data } => (funcs.flash_range_program)(addr, data, PAGE_SIZE as usize),
+    }
+    (funcs.flash_flush_cache)();
+    boot2();
+    compiler_fence(Ordering::SeqCst);
+}
+
+/// Acknowledges [LOCKOUT_START], and waits in RAM until core 0 sends
+/// [LOCKOUT_END]
+///
+/// Core 0 leaves XIP as soon as it reads the acknowledgement, so nothing
+/// after it may run from the flash, like in the lockout handler of the
+/// pico-sdk.
+#[inline(never)]
+#[link_section = ".data.ram_func"]
+unsafe fn core1_parked() {
+    while core::ptr::read_volatile(FIFO_ST as *const u32) & FIFO_ST_RDY == 0 {}
+    core::ptr::write_volatile(FIFO_WR as *mut u32, LOCKOUT_START);
+    // Inline, as cortex_m::asm::sev may be a call into the flash
+    #[cfg(target_arch = "arm")]
+    core::arch::asm!("sev", options(nomem, nostack, preserves_flags));
+    loop {
+        while core::ptr::read_volatile(FIFO_ST as *const u32) & FIFO_ST_VLD == 0 {}
+        if core::ptr::read_volatile(FIFO_RD as *const u32) == LOCKOUT_END {
+            return;
+        }
+    }
+}
+
+fn fifo_write(word: u32) {
+    // SAFETY: the FIFO registers of the SIO are only accessed by the core
+    // they belong to
+    unsafe {
+        while core::ptr::read_volatile(FIFO_ST as *const u32) & FIFO_ST_RDY == 0 {}
+        core::ptr::write_volatile(FIFO_WR as *mut u32, word);
+    }
+    cortex_m::asm::sev();
+}
+
+fn fifo_read() -> u32 {
+    // SAFETY: see fifo_write
+    unsafe {
+        while core::ptr::read_volatile(FIFO_ST as *const u32) & FIFO_ST_VLD == 0 {
+            cortex_m::asm::wfe();
+        }
+        core::ptr::read_volatile(FIFO_RD as *const u32)
+    }
+}
+
+/// Parks core 1 in RAM while core 0 uses the flash, see [Core1::Lockout]
+///
+/// Call it from the `SIO_IRQ_PROC1` interrupt handler of core 1.
+///
+/// # Return
+///
+/// The word read from the FIFO, if it wasn't a request to park.
+pub fn core1_lockout_handler() -> Option<u32> {
+    // SAFETY: see fifo_write
+    let word = unsafe {
+        if core::ptr::read_volatile(FIFO_ST as *const u32) & FIFO_ST_VLD == 0 {
+            return None;
+        }
+        core::ptr::read_volatile(FIFO_RD as *const u32)
+    };
+    if word != LOCKOUT_START {
+        return Some(word);
+    }
+    cortex_m::interrupt::free(|_| {
+        // SAFETY: runs from RAM with interrupts disabled, and acknowledges
+        // from there
+        unsafe { core1_parked() };
+    });
+    None
+}
+
+/// The QSPI flash of the board, see the [module](self) documentation
+///
+/// Offsets start at the beginning of the flash, where the boot2 is. Use the
+/// `FLASH_RESERVED_OFFSET` of the BSP to stay clear of the program.
+pub struct Flash {
+    capacity: u32,
+    core1: Core1,
+    funcs: RomFuncs,
+    /// Copy of the boot2, which sets up the fast XIP again
+    boot2: [u32; 64],
+}
+
+impl Flash {
+    /// Takes the flash of `capacity` bytes, usually `FLASH_SIZE_BYTES` of the
+    /// BSP
+    pub fn new(capacity: u32, core1: Core1) -> Self {
+        let mut boot2 = [0; 64];
+        // SAFETY: the boot2 is at the start of the flash, which is mapped
+        // while XIP is enabled
+        unsafe {
+            core::ptr::copy_nonoverlapping(XIP_BASE as *const u32, boot2.as_mut_ptr(), 64);
+        }
+        Flash {
+            capacity,
+            core1,
+            funcs: RomFuncs::lookup(),
+            boot2,
+        }
+    }
+
+    fn run(&mut self, op: Op) {
+        cortex_m::interrupt::free(|_| {
+            if self.core1 == Core1::Lockout {
+                fifo_write(LOCKOUT_START);
+                while fifo_read() != LOCKOUT_START {}
+            }
+            // SAFETY: interrupts are disabled and core 1 is out of the way
+            unsafe { flash_op(&self.funcs, self.boot2.as_ptr(), op) };
+            if self.core1 == Core1::Lockout {
+                fifo_write(LOCKOUT_END);
+            }
+        });
+    }
+}
+
+impl ErrorType for Flash {
+    type Error = FlashError;
+}
+
+impl ReadNorFlash for Flash {
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        check(self.capacity, offset, bytes.len(), 1)?;
+        // SAFETY: the range is within the flash, mapped at XIP_BASE
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                (XIP_BASE + offset) as *const u8,
+                bytes.as_mut_ptr(),
+                bytes.len(),
+            );
+        }
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity as usize
+    }
+}
+
+impl NorFlash for Flash {
+    const WRITE_SIZE: usize = 1;
+    const ERASE_SIZE: usize = SECTOR_SIZE as usize;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        if to < from {
+            return Err(FlashError::OutOfBounds);
+        }
+        check(self.capacity, from, (to - from) as usize, SECTOR_SIZE)?;
+        if to > from {
+            self.run(Op::Erase {
+                addr: from,
+                len: to - from,
+            });
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        check(self.capacity, offset, bytes.len(), 1)?;
+        // The ROM programs whole pages, so the bytes are padded with 0xff,
+        // which leaves the rest of the page as it is
+        let mut done = 0;
+        while done < bytes.len() {
+            let at = offset + done as u32;
+            let page = at & !(PAGE_SIZE - 1);
+            let start = (at - page) as usize;
+            let len = (PAGE_SIZE as usize - start).min(bytes.len() - done);
+            let mut buf = [0xff; PAGE_SIZE as usize];
+            buf[start..start + len].copy_from_slice(&bytes[done..done + len]);
+            self.run(Op::Program {
+                addr: page,
+                data: buf.as_ptr(),
+            });
+            done += len;
+        }
+        Ok(())
+    }
+}
+
+impl MultiwriteNorFlash for Flash {}
+
+/// A NOR flash in memory, for the host
+///
+/// Like a real flash, erasing sets all bits, and writing can only clear
+/// them. [RamFlash::cut_power_after] simulates a power loss in the middle of
+/// a write or erase.
+pub struct RamFlash<const SIZE: usize> {
+    data: [u8; SIZE],
+    /// Bytes which can still be written or erased before the power is cut
+    budget: Option<usize>,
+}
+
+impl<const SIZE: usize> RamFlash<SIZE> {
+    /// Creates an erased flash
+    pub fn new() -> Self {
+        RamFlash {
+            data: [0xff; SIZE],
+            budget: None,
+        }
+    }
+
+    /// Fails all writes and erases after `bytes` more bytes were changed,
+    /// leaving the last one half done
+    pub fn cut_power_after(&mut self, bytes: usize) {
+        self.budget = Some(bytes);
+    }
+
+    /// Powers the flash up again after [RamFlash::cut_power_after]
+    pub fn restore_power(&mut self) {
+        self.budget = None;
+    }
+
+    /// The contents of the flash
+    pub fn data(&self) -> &[u8; SIZE] {
+        &self.data
+    }
+
+    /// Uses up one byte of the budget
+    fn spend(&mut self) -> Result<(), FlashError> {
+        match &mut self.budget {
+            Some(0) => Err(FlashError::PowerLoss),
+            Some(budget) => {
+                *budget -= 1;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl<const SIZE: usize> Default for RamFlash<SIZE> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const SIZE: usize> ErrorType for RamFlash<SIZE> {
+    type Error = FlashError;
+}
+
+impl<const SIZE: usize> ReadNorFlash for RamFlash<SIZE> {
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        check(SIZE as u32, offset, bytes.len(), 1)?;
+        let offset = offset as usize;
+        bytes.copy_from_slice(&self.data[offset..offset + bytes.len()]);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        SIZE
+    }
+}
+
+impl<const SIZE: usize> NorFlash for RamFlash<SIZE> {
+    const WRITE_SIZE: usize = 4;
+    const ERASE_SIZE: usize = SECTOR_SIZE as usize;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        if to < from {
+            return Err(FlashError::OutOfBounds);
+        }
+        check(SIZE as u32, from, (to - from) as usize, SECTOR_SIZE)?;
+        for i in from as usize..to as usize {
+            // An interrupted erase leaves the rest of the sector as it was
+            self.spend()?;
+            self.data[i] = 0xff;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        check(SIZE as u32, offset, bytes.len(), Self::WRITE_SIZE as u32)?;
+        let offset = offset as usize;
+        for (i, &byte) in bytes.iter().enumerate() {
+            let old = self.data[offset + i];
+            // An interrupted write clears only some of the bits
+            if let Err(e) = self.spend() {
+                self.data[offset + i] = old & (byte | 0xf0);
+                return Err(e);
+            }
+            self.data[offset + i] = old & byte;
+        }
+        Ok(())
+    }
+}
+
+impl<const SIZE: usize> MultiwriteNorFlash for RamFlash<SIZE> {}
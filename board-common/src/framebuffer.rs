@@ -0,0 +1,160 @@
+//! A framebuffer for the SPI LCDs
+//!
+//! [Framebuffer] keeps the pixels of a display in RGB565, two bytes each in
+//! the big-endian order the controllers expect, so the whole buffer can be
+//! sent to the display in one transfer.
+//!
+//! Only available with the `embedded-graphics` dependency, which the
+//! `embassy` and `panic-screen` features enable.
+
+use core::convert::Infallible;
+
+use embedded_graphics::pixelcolor::raw::{RawData, RawU16};
+use embedded_graphics::pixelcolor::Rgb565;
+use embedded_graphics::prelude::*;
+
+/// The pixels of a display in RGB565, in the byte order of the SPI LCDs
+///
+/// Draw into it with `embedded-graphics`, then send it with
+/// `embassy::SpiDisplay::flush`.
+pub struct Framebuffer<'a> {
+    buf: &'a mut [u8],
+    size: Size,
+}
+
+impl<'a> Framebuffer<'a> {
+    /// Uses `buf` for the pixels of a display of `size`
+    ///
+    /// # Panics
+    ///
+    /// If `buf` is shorter than 2 bytes per pixel.
+    pub fn new(buf: &'a mut [u8], size: Size) -> Self {
+        let len = (size.width * size.height * 2) as usize;
+        assert!(buf.len() >= len, "framebuffer too small");
+        Framebuffer {
+            buf: &mut buf[..len],
+            size,
+        }
+    }
+
+    /// The pixels, row by row
+    pub fn as_bytes(&self) -> &[u8] {
+        self.buf
+    }
+}
+
+impl OriginDimensions for Framebuffer<'_> {
+    fn size(&self) -> Size {
+        self.size
+    }
+}
+
+impl DrawTarget for Framebuffer<'_> {
+    type Color = Rgb565;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let (width, height) = (self.size.width as i32, self.size.height as i32);
+        for Pixel(point, color) in pixels {
+            if !(0..width).contains(&point.x) || !(0..height).contains(&point.y) {
+                continue;
+            }
+            let index = (point.y * width + point.x) as usize * 2;
+            let raw = RawU16::from(color).into_inner();
+            self.buf[index..index + 2].copy_from_slice(&raw.to_be_bytes());
+        }
+        Ok(())
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        let raw = RawU16::from(color).into_inner().to_be_bytes();
+        for pixel in self.buf.chunks_exact_mut(2) {
+            pixel.copy_from_slice(&raw);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_graphics::primitives::Rectangle;
+
+    use super::*;
+
+    const SIZE: Size = Size::new(3, 2);
+
+    #[test]
+    fn pixels_are_big_endian_row_by_row() {
+        let mut buf = [0; 12];
+        let mut framebuffer = Framebuffer::new(&mut buf, SIZE);
+        let pixels = [
+            Pixel(Point::new(0, 0), Rgb565::RED),
+            Pixel(Point::new(2, 0), Rgb565::GREEN),
+            Pixel(Point::new(1, 1), Rgb565::BLUE),
+        ];
+        framebuffer.draw_iter(pixels).unwrap();
+        assert_eq!(
+            framebuffer.as_bytes(),
+            [0xf8, 0x00, 0, 0, 0x07, 0xe0, 0, 0, 0x00, 0x1f, 0, 0]
+        );
+    }
+
+    #[test]
+    fn pixels_outside_are_skipped() {
+        let mut buf = [0; 12];
+        let mut framebuffer = Framebuffer::new(&mut buf, SIZE);
+        let outside = [
+            Point::new(-1, 0),
+            Point::new(3, 0),
+            Point::new(0, -1),
+            Point::new(0, 2),
+        ];
+        framebuffer
+            .draw_iter(outside.iter().map(|&point| Pixel(point, Rgb565::WHITE)))
+            .unwrap();
+        assert_eq!(framebuffer.as_bytes(), [0; 12]);
+    }
+
+    #[test]
+    fn areas_are_clipped() {
+        let mut buf = [0; 12];
+        let mut framebuffer = Framebuffer::new(&mut buf, SIZE);
+        let area = Rectangle::new(Point::new(1, 1), Size::new(4, 4));
+        framebuffer.fill_solid(&area, Rgb565::WHITE).unwrap();
+        assert_eq!(
+            framebuffer.as_bytes(),
+            [0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 0xff, 0xff]
+        );
+    }
+
+    #[test]
+    fn clear_fills_every_pixel() {
+        let mut buf = [0; 12];
+        let mut framebuffer = Framebuffer::new(&mut buf, SIZE);
+        framebuffer.clear(Rgb565::BLUE).unwrap();
+        assert!(framebuffer
+            .as_bytes()
+            .chunks(2)
+            .all(|pixel| pixel == [0x00, 0x1f]));
+    }
+
+    #[test]
+    fn a_larger_buffer_is_cut_to_the_display() {
+        let mut buf = [0xaa; 20];
+        let mut framebuffer = Framebuffer::new(&mut buf, SIZE);
+        assert_eq!(framebuffer.size(), SIZE);
+        framebuffer.clear(Rgb565::BLACK).unwrap();
+        assert_eq!(framebuffer.as_bytes(), [0; 12]);
+        assert_eq!(buf[12..], [0xaa; 8]);
+    }
+
+    #[test]
+    #[should_panic(expected = "framebuffer too small")]
+    fn a_smaller_buffer_panics() {
+        let mut buf = [0; 11];
+        Framebuffer::new(&mut buf, SIZE);
+    }
+}
@@ -0,0 +1,753 @@
+//! Key-value store for settings, in a partition of the flash
+//!
+//! The partition is split into sectors, of which one is active at a time. A
+//! new value is appended to the active sector, behind the older values of
+//! the same key. Once the sector is full, the latest value of each key is
+//! copied to the next sector, which then becomes the active one. The
+//! sectors take turns in a ring, so they wear evenly.
+//!
+//! Each value and each sector carries a checksum, so a write interrupted by
+//! a power loss is detected. An interrupted value is ignored, and the
+//! sector is replaced on the next write. An interrupted copy to the next
+//! sector is ignored as a whole, the previous sector stays active.
+//!
+//! All values have to fit into a single sector, minus some overhead.
+//!
+//! The store works on any [NorFlash], like the [Flash](crate::flash::Flash)
+//! of the board, or [RamFlash](crate::flash::RamFlash) to try it out on the
+//! host.
+//!
+//! ```ignore
+//! let flash = Flash::new(bsp::FLASH_SIZE_BYTES, Core1::Stopped);
+//! let mut settings = KvStore::mount(flash, bsp::FLASH_RESERVED_OFFSET, bsp::FLASH_RESERVED_BYTES)?;
+//! settings.set(b"brightness", &[128])?;
+//! let mut buf = [0; 1];
+//! let brightness = settings.get(b"brightness", &mut buf)?;
+//! ```
+
+use embedded_storage::nor_flash::NorFlash;
+
+/// Marks a sector in use, "KVS1"
+const SECTOR_MAGIC: u32 = 0x3153_564b;
+/// Magic, generation and inverted generation
+const SECTOR_HEADER_SIZE: u32 = 12;
+/// Kind, key length, value length and checksum
+const RECORD_HEADER_SIZE: u32 = 8;
+
+/// A record holding the value of a key
+const KIND_VALUE: u8 = 0x01;
+/// A record removing a key
+const KIND_REMOVED: u8 = 0x02;
+
+/// Bytes moved through RAM at once
+const CHUNK: usize = 32;
+
+/// Errors of the [KvStore]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    /// The flash failed
+    Flash(E),
+    /// The partition isn't aligned to sectors, or has less than 2 of them
+    Partition,
+    /// The key and value don't fit into a sector
+    TooLarge,
+    /// All values together don't fit into a sector
+    Full,
+    /// The buffer is too small for the value
+    BufferTooSmall,
+}
+
+/// A record in a sector
+#[derive(Debug, Clone, Copy)]
+struct Record {
+    /// Offset in the flash
+    at: u32,
+    kind: u8,
+    key_len: u8,
+    value_len: u16,
+}
+
+impl Record {
+    fn key_at(&self) -> u32 {
+        self.at + RECORD_HEADER_SIZE
+    }
+
+    fn value_at(&self) -> u32 {
+        self.key_at() + u32::from(self.key_len)
+    }
+
+    fn len(&self) -> u32 {
+        record_len(usize::from(self.key_len), usize::from(self.value_len))
+    }
+}
+
+/// What follows in a sector
+enum Step {
+    Record(Record),
+    /// Erased flash, where the next record goes
+    Free,
+    /// A record which wasn't written completely
+    Torn,
+}
+
+/// Size of a record in the flash, rounded up to whole words
+fn record_len(key_len: usize, value_len: usize) -> u32 {
+    let len = RECORD_HEADER_SIZE + key_len as u32 + value_len as u32;
+    (len + 3) & !3
+}
+
+/// CRC-32 (IEEE) of `bytes`, continuing from `crc`
+pub(crate) fn crc32(mut crc: u32, bytes: &[u8]) -> u32 {
+    crc = !crc;
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Key-value store in a partition of a flash, see the [module](self)
+/// documentation
+pub struct KvStore<F: NorFlash> {
+    flash: F,
+    /// Start of the partition in the flash
+    offset: u32,
+    sectors: u32,
+    /// Index of the active sector
+    active: u32,
+    generation: u32,
+    /// Offset of the next record in the active sector, the sector size if
+    /// it can't take any more records
+    end: u32,
+}
+
+impl<F: NorFlash> KvStore<F> {
+    /// Opens the store in the `size` bytes at `offset` of the flash
+    ///
+    /// If the partition holds no store yet, an empty one is created.
+    pub fn mount(flash: F, offset: u32, size: u32) -> Result<Self, Error<F::Error>> {
+        let sector_size = F::ERASE_SIZE as u32;
+        // Erase sizes are powers of two
+        if (offset | size) & (sector_size - 1) != 0
+            || size / sector_size < 2
+            || 4 % F::WRITE_SIZE != 0
+            || 4 % F::READ_SIZE != 0
+        {
+            return Err(Error::Partition);
+        }
+
+        let mut store = KvStore {
+            flash,
+            offset,
+            sectors: size / sector_size,
+            active: 0,
+            generation: 0,
+            end: 0,
+        };
+        let mut found = false;
+        for sector in 0..store.sectors {
+            if let Some(generation) = store.sector_generation(sector)? {
+                if !found || generation > store.generation {
+                    store.active = sector;
+                    store.generation = generation;
+                    found = true;
+                }
+            }
+        }
+
+        if found {
+            store.end = store.scan_end()?;
+            #[cfg(feature = "defmt")]
+            defmt::debug!(
+                "kv: mounted sector {=u32} of generation {=u32}",
+                store.active,
+                store.generation
+            );
+        } else {
+            #[cfg(feature = "defmt")]
+            defmt::info!("kv: no store found, creating an empty one");
+            store.erase_sector(0)?;
+            store.write_sector_header(0, 1)?;
+            store.active = 0;
+            store.generation = 1;
+            store.end = SECTOR_HEADER_SIZE;
+        }
+        Ok(store)
+    }
+
+    /// Reads the value of `key` into `buf`
+    ///
+    /// Returns `None` if the key has no value.
+    pub fn get<'b>(
+        &mut self,
+        key: &[u8],
+        buf: &'b mut [u8],
+    ) -> Result<Option<&'b [u8]>, Error<F::Error>> {
+        let mut latest = None;
+        let mut at = self.sector_start(self.active) + SECTOR_HEADER_SIZE;
+        while let Step::Record(record) = self.step(at)? {
+            if self.key_equals(&record, key)? {
+                latest = Some(record);
+            }
+            at += record.len();
+        }
+
+        match latest {
+            Some(record) if record.kind == KIND_VALUE => {
+                let len = usize::from(record.value_len);
+                let buf = buf.get_mut(..len).ok_or(Error::BufferTooSmall)?;
+                self.read(record.value_at(), buf)?;
+                Ok(Some(buf))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Sets `key` to `value`
+    pub fn set(&mut self, key: &[u8], value: &[u8]) -> Result<(), Error<F::Error>> {
+        if key.len() > usize::from(u8::MAX)
+            || value.len() > usize::from(u16::MAX)
+            || record_len(key.len(), value.len()) > F::ERASE_SIZE as u32 - SECTOR_HEADER_SIZE
+        {
+            return Err(Error::TooLarge);
+        }
+        self.append(KIND_VALUE, key, value)
+    }
+
+    /// Removes the value of `key`
+    pub fn remove(&mut self, key: &[u8]) -> Result<(), Error<F::Error>> {
+        if key.len() > usize::from(u8::MAX) {
+            return Err(Error::TooLarge);
+        }
+        self.append(KIND_REMOVED, key, &[])
+    }
+
+    /// Releases the flash
+    pub fn free(self) -> F {
+        self.flash
+    }
+
+    fn sector_start(&self, sector: u32) -> u32 {
+        self.offset + sector * F::ERASE_SIZE as u32
+    }
+
+    fn sector_end(&self, sector: u32) -> u32 {
+        self.sector_start(sector) + F::ERASE_SIZE as u32
+    }
+
+    fn read(&mut self, at: u32, buf: &mut [u8]) -> Result<(), Error<F::Error>> {
+        self.flash.read(at, buf).map_err(Error::Flash)
+    }
+
+    fn write(&mut self, at: u32, buf: &[u8]) -> Result<(), Error<F::Error>> {
+        self.flash.write(at, buf).map_err(Error::Flash)
+    }
+
+    fn erase_sector(&mut self, sector: u32) -> Result<(), Error<F::Error>> {
+        let (from, to) = (self.sector_start(sector), self.sector_end(sector));
+        self.flash.erase(from, to).map_err(Error::Flash)
+    }
+
+    /// The generation of a sector in use, `None` if it isn't
+    fn sector_generation(&mut self, sector: u32) -> Result<Option<u32>, Error<F::Error>> {
+        let mut header = [0; SECTOR_HEADER_SIZE as usize];
+        self.read(self.sector_start(sector), &mut header)?;
+        let word =
+            |i: usize| u32::from_le_bytes([header[i], header[i + 1], header[i + 2], header[i + 3]]);
+        let generation = word(4);
+        // An interrupted write leaves bits set in both copies of the
+        // generation, so they don't match
+        if word(0) == SECTOR_MAGIC && generation == !word(8) && generation != u32::MAX {
+            Ok(Some(generation))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn write_sector_header(&mut self, sector: u32, generation: u32) -> Result<(), Error<F::Error>> {
+        let mut header = [0; SECTOR_HEADER_SIZE as usize];
+        header[0..4].copy_from_slice(&SECTOR_MAGIC.to_le_bytes());
+        header[4..8].copy_from_slice(&generation.to_le_bytes());
+        header[8..12].copy_from_slice(&(!generation).to_le_bytes());
+        self.write(self.sector_start(sector), &header)
+    }
+
+    /// Reads what follows at `at` in the active sector
+    fn step(&mut self, at: u32) -> Result<Step, Error<F::Error>> {
+        let end = self.sector_end(self.active);
+        if at + RECORD_HEADER_SIZE > end {
+            return Ok(Step::Free);
+        }
+        let mut header = [0; RECORD_HEADER_SIZE as usize];
+        self.read(at, &mut header)?;
+        if header.iter().all(|&b| b == 0xff) {
+            return Ok(Step::Free);
+        }
+
+        let record = Record {
+            at,
+            kind: header[0],
+            key_len: header[1],
+            value_len: u16::from_le_bytes([header[2], header[3]]),
+        };
+        let crc = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        if (record.kind != KIND_VALUE && record.kind != KIND_REMOVED)
+            || at + record.len() > end
+            || self.record_crc(&record)? != crc
+        {
+            return Ok(Step::Torn);
+        }
+        Ok(Step::Record(record))
+    }
+
+    /// Offset of the next record in the active sector
+    fn scan_end(&mut self) -> Result<u32, Error<F::Error>> {
+        let mut at = self.sector_start(self.active) + SECTOR_HEADER_SIZE;
+        loop {
+            match self.step(at)? {
+                Step::Record(record) => at += record.len(),
+                Step::Free => return Ok(at),
+                // Nothing can be written behind a torn record, the next
+                // write has to move on to a new sector
+                Step::Torn => return Ok(self.sector_end(self.active)),
+            }
+        }
+    }
+
+    fn record_crc(&mut self, record: &Record) -> Result<u32, Error<F::Error>> {
+        let value_len = record.value_len.to_le_bytes();
+        let mut crc = crc32(
+            0,
+            &[record.kind, record.key_len, value_len[0], value_len[1]],
+        );
+        let mut at = record.key_at();
+        let end = record.value_at() + u32::from(record.value_len);
+        let mut buf = [0; CHUNK];
+        while at < end {
+            let len = CHUNK.min((end - at) as usize);
+            self.read_unaligned(at, &mut buf[..len])?;
+            crc = crc32(crc, &buf[..len]);
+            at += len as u32;
+        }
+        Ok(crc)
+    }
+
+    /// Reads from any offset, even if the flash needs aligned reads
+    fn read_unaligned(&mut self, at: u32, buf: &mut [u8]) -> Result<(), Error<F::Error>> {
+        let start = at & !3;
+        let mut words = [0; CHUNK + 8];
+        let skip = (at - start) as usize;
+        let len = (skip + buf.len() + 3) & !3;
+        self.read(start, &mut words[..len])?;
+        buf.copy_from_slice(&words[skip..skip + buf.len()]);
+        Ok(())
+    }
+
+    fn key_equals(&mut self, record: &Record, key: &[u8]) -> Result<bool, Error<F::Error>> {
+        if usize::from(record.key_len) != key.len() {
+            return Ok(false);
+        }
+        let mut buf = [0; CHUNK];
+        for (i, part) in key.chunks(CHUNK).enumerate() {
+            let buf = &mut buf[..part.len()];
+            self.read_unaligned(record.key_at() + (i * CHUNK) as u32, buf)?;
+            if buf != part {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Whether both records are for the same key
+    fn same_key(&mut self, a: &Record, b: &Record) -> Result<bool, Error<F::Error>> {
+        if a.key_len != b.key_len {
+            return Ok(false);
+        }
+        let mut buf_a = [0; CHUNK];
+        let mut buf_b = [0; CHUNK];
+        let mut i = 0;
+        while i < u32::from(a.key_len) {
+            let len = CHUNK.min((u32::from(a.key_len) - i) as usize);
+            self.read_unaligned(a.key_at() + i, &mut buf_a[..len])?;
+            self.read_unaligned(b.key_at() + i, &mut buf_b[..len])?;
+            if buf_a[..len] != buf_b[..len] {
+                return Ok(false);
+            }
+            i += len as u32;
+        }
+        Ok(true)
+    }
+
+    /// Whether `record` holds the latest value of its key, which isn't
+    /// about to be replaced by `key`
+    fn is_live(&mut self, record: &Record, key: &[u8]) -> Result<bool, Error<F::Error>> {
+        if record.kind != KIND_VALUE || self.key_equals(record, key)? {
+            return Ok(false);
+        }
+        let mut at = record.at + record.len();
+        while let Step::Record(later) = self.step(at)? {
+            if self.same_key(record, &later)? {
+                return Ok(false);
+            }
+            at += later.len();
+        }
+        Ok(true)
+    }
+
+    fn append(&mut self, kind: u8, key: &[u8], value: &[u8]) -> Result<(), Error<F::Error>> {
+        let len = record_len(key.len(), value.len());
+        if self.end + len <= self.sector_end(self.active) {
+            self.write_record(self.end, kind, key, value)?;
+            self.end += len;
+            return Ok(());
+        }
+        self.move_to_next_sector(kind, key, value)
+    }
+
+    /// Copies the latest values to the next sector, together with the new
+    /// record, and makes it the active one
+    fn move_to_next_sector(
+        &mut self,
+        kind: u8,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<(), Error<F::Error>> {
+        let first = self.sector_start(self.active) + SECTOR_HEADER_SIZE;
+
+        // Check that everything fits before touching the flash
+        let mut needed = SECTOR_HEADER_SIZE;
+        if kind == KIND_VALUE {
+            needed += record_len(key.len(), value.len());
+        }
+        let mut at = first;
+        while let Step::Record(record) = self.step(at)? {
+            if self.is_live(&record, key)? {
+                needed += record.len();
+            }
+            at += record.len();
+        }
+        if needed > F::ERASE_SIZE as u32 {
+            return Err(Error::Full);
+        }
+
+        let next = (self.active + 1) % self.sectors;
+        #[cfg(feature = "defmt")]
+        defmt::debug!("kv: moving to sector {=u32}", next);
+        self.erase_sector(next)?;
+        let mut to = self.sector_start(next) + SECTOR_HEADER_SIZE;
+        let mut at = first;
+        while let Step::Record(record) = self.step(at)? {
+            if self.is_live(&record, key)? {
+                self.copy(record.at, to, record.len())?;
+                to += record.len();
+            }
+            at += record.len();
+        }
+        if kind == KIND_VALUE {
+            self.write_record(to, kind, key, value)?;
+            to += record_len(key.len(), value.len());
+        }
+
+        // Only a complete header makes the sector count
+        self.write_sector_header(next, self.generation.wrapping_add(1))?;
+        self.active = next;
+        self.generation = self.generation.wrapping_add(1);
+        self.end = to;
+        Ok(())
+    }
+
+    fn copy(&mut self, from: u32, to: u32, len: u32) -> Result<(), Error<F::Error>> {
+        let mut buf = [0; CHUNK];
+        let mut done = 0;
+        while done < len {
+            let part = CHUNK.min((len - done) as usize);
+            self.read(from + done, &mut buf[..part])?;
+            self.write(to + done, &buf[..part])?;
+            done += part as u32;
+        }
+        Ok(())
+    }
+
+    fn write_record(
+        &mut self,
+        at: u32,
+        kind: u8,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<(), Error<F::Error>> {
+        let value_len = (value.len() as u16).to_le_bytes();
+        let mut crc = crc32(0, &[kind, key.len() as u8, value_len[0], value_len[1]]);
+        crc = crc32(crc, key);
+        crc = crc32(crc, value);
+        let crc = crc.to_le_bytes();
+        let header = [
+            kind,
+            key.len() as u8,
+            value_len[0],
+            value_len[1],
+            crc[0],
+            crc[1],
+            crc[2],
+            crc[3],
+        ];
+
+        // The header goes first, so the checksum fails until the record is
+        // complete
+        let mut buf = [0xff; CHUNK];
+        let mut fill = 0;
+        let mut to = at;
+        for &byte in header.iter().chain(key).chain(value) {
+            buf[fill] = byte;
+            fill += 1;
+            if fill == CHUNK {
+                self.write(to, &buf)?;
+                to += CHUNK as u32;
+                buf = [0xff; CHUNK];
+                fill = 0;
+            }
+        }
+        if fill > 0 {
+            self.write(to, &buf[..(fill + 3) & !3])?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec::Vec;
+
+    use embedded_storage::nor_flash::NorFlash;
+
+    use super::*;
+    use crate::flash::RamFlash;
+
+    const SIZE: usize = 4 * 4096;
+
+    type TestFlash = RamFlash<SIZE>;
+
+    fn mount(flash: TestFlash) -> KvStore<TestFlash> {
+        KvStore::mount(flash, 0, SIZE as u32).unwrap()
+    }
+
+    fn get(store: &mut KvStore<TestFlash>, key: &[u8]) -> Option<Vec<u8>> {
+        let mut buf = [0; 4096];
+        store
+            .get(key, &mut buf)
+            .unwrap()
+            .map(|value| value.to_vec())
+    }
+
+    /// A store whose active sector is nearly full, with `key0` to `key9`
+    /// set to a value of 40 bytes each
+    fn nearly_full() -> KvStore<TestFlash> {
+        let mut store = mount(TestFlash::new());
+        let mut round = 0u8;
+        loop {
+            for i in 0..10u8 {
+                let key = [b'k', b'e', b'y', b'0' + i];
+                if store.end + record_len(key.len(), 40) > store.sector_end(store.active) {
+                    return store;
+                }
+                store.set(&key, &[round; 40]).unwrap();
+            }
+            round += 1;
+        }
+    }
+
+    /// The values `nearly_full` leaves behind
+    fn nearly_full_values() -> Vec<Vec<u8>> {
+        let mut store = nearly_full();
+        (0..10u8)
+            .map(|i| get(&mut store, &[b'k', b'e', b'y', b'0' + i]).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn set_get_remove() {
+        let mut store = mount(TestFlash::new());
+        assert_eq!(get(&mut store, b"brightness"), None);
+
+        store.set(b"brightness", &[128]).unwrap();
+        store.set(b"name", b"pico").unwrap();
+        store.set(b"empty", &[]).unwrap();
+        assert_eq!(get(&mut store, b"brightness"), Some(std::vec![128]));
+        assert_eq!(get(&mut store, b"name"), Some(b"pico".to_vec()));
+        assert_eq!(get(&mut store, b"empty"), Some(Vec::new()));
+
+        store.set(b"brightness", &[64]).unwrap();
+        store.remove(b"name").unwrap();
+        assert_eq!(get(&mut store, b"brightness"), Some(std::vec![64]));
+        assert_eq!(get(&mut store, b"name"), None);
+
+        let mut small = [0; 3];
+        store.set(b"name", b"badger").unwrap();
+        assert_eq!(store.get(b"name", &mut small), Err(Error::BufferTooSmall));
+
+        // Everything survives a remount
+        let mut store = mount(store.free());
+        assert_eq!(get(&mut store, b"brightness"), Some(std::vec![64]));
+        assert_eq!(get(&mut store, b"name"), Some(b"badger".to_vec()));
+        assert_eq!(get(&mut store, b"empty"), Some(Vec::new()));
+    }
+
+    #[test]
+    fn rejects_bad_partitions_and_sizes() {
+        assert!(matches!(
+            KvStore::mount(TestFlash::new(), 0, 4096),
+            Err(Error::Partition)
+        ));
+        assert!(matches!(
+            KvStore::mount(TestFlash::new(), 100, 8192),
+            Err(Error::Partition)
+        ));
+
+        let mut store = mount(TestFlash::new());
+        assert_eq!(store.set(&[0; 256], &[]), Err(Error::TooLarge));
+        assert_eq!(store.set(b"key", &[0; 4096]), Err(Error::TooLarge));
+
+        // A value filling a whole sector leaves no room for another one
+        let most = 4096 - SECTOR_HEADER_SIZE as usize - RECORD_HEADER_SIZE as usize - 4;
+        store.set(b"big", &std::vec![7; most]).unwrap();
+        assert_eq!(get(&mut store, b"big").map(|value| value.len()), Some(most));
+        assert_eq!(store.set(b"more", &[7; 100]), Err(Error::Full));
+        assert_eq!(get(&mut store, b"big").map(|value| value.len()), Some(most));
+    }
+
+    #[test]
+    fn full_sector_is_compacted_into_the_next() {
+        let mut store = nearly_full();
+        let values = nearly_full_values();
+        assert_eq!((store.active, store.generation), (0, 1));
+
+        store.set(b"key0", &[0xaa; 40]).unwrap();
+        assert_eq!((store.active, store.generation), (1, 2));
+        // Only the latest value of each key was copied
+        assert_eq!(
+            store.end,
+            SECTOR_HEADER_SIZE + 10 * record_len(4, 40) + store.sector_start(1)
+        );
+        assert_eq!(get(&mut store, b"key0"), Some(std::vec![0xaa; 40]));
+        for (i, value) in values.iter().enumerate().skip(1) {
+            assert_eq!(
+                get(&mut store, &[b'k', b'e', b'y', b'0' + i as u8]).as_ref(),
+                Some(value)
+            );
+        }
+
+        // Removed keys are dropped, and the sectors are used in a ring
+        store.remove(b"key9").unwrap();
+        for round in 0..2000u32 {
+            store.set(b"key1", &round.to_le_bytes()).unwrap();
+        }
+        let mut store = mount(store.free());
+        assert!(store.generation > 5);
+        assert_eq!(store.active, (store.generation - 1) % 4);
+        assert_eq!(
+            get(&mut store, b"key1"),
+            Some(1999u32.to_le_bytes().to_vec())
+        );
+        assert_eq!(get(&mut store, b"key2"), Some(values[2].clone()));
+        assert_eq!(get(&mut store, b"key9"), None);
+    }
+
+    #[test]
+    fn cut_in_a_record_keeps_the_old_value() {
+        let len = record_len(b"key".len(), 10) as usize;
+        // The padding behind the value is left erased
+        let written = RECORD_HEADER_SIZE as usize + b"key".len() + 10;
+        for cut in 0..=len {
+            let mut store = mount(TestFlash::new());
+            store.set(b"key", &[1; 10]).unwrap();
+            store.set(b"other", &[3; 5]).unwrap();
+
+            let mut flash = store.free();
+            flash.cut_power_after(cut);
+            let mut store = mount(flash);
+            let result = store.set(b"key", &[2; 10]);
+            assert_eq!(result.is_ok(), cut == len, "cut after {}", cut);
+
+            let mut flash = store.free();
+            flash.restore_power();
+            let mut store = mount(flash);
+            let expected = if cut >= written { [2; 10] } else { [1; 10] };
+            assert_eq!(get(&mut store, b"key"), Some(expected.to_vec()));
+            assert_eq!(get(&mut store, b"other"), Some(std::vec![3; 5]));
+
+            // The store keeps working, past a torn record too
+            store.set(b"key", &[4; 10]).unwrap();
+            let mut store = mount(store.free());
+            assert_eq!(get(&mut store, b"key"), Some(std::vec![4; 10]));
+            assert_eq!(get(&mut store, b"other"), Some(std::vec![3; 5]));
+        }
+    }
+
+    #[test]
+    fn cut_in_a_compaction_keeps_the_old_sector() {
+        let values = nearly_full_values();
+        // Erasing the next sector, then writing the 9 other values, the new
+        // one and the header
+        let len = 4096 + 10 * record_len(4, 40) as usize + SECTOR_HEADER_SIZE as usize;
+        for cut in (0..len).step_by(37).chain(len - 12..=len) {
+            let mut flash = nearly_full().free();
+            flash.cut_power_after(cut);
+            let mut store = mount(flash);
+            let result = store.set(b"key0", &[0xaa; 40]);
+            assert_eq!(result.is_ok(), cut == len, "cut after {}", cut);
+
+            let mut flash = store.free();
+            flash.restore_power();
+            let mut store = mount(flash);
+            // The inverted generation 0xffff_fffd only needs a half written
+            // byte, the header is complete with the generation
+            if cut >= len - 4 {
+                assert_eq!(store.active, 1);
+                assert_eq!(get(&mut store, b"key0"), Some(std::vec![0xaa; 40]));
+            } else {
+                assert_eq!(store.active, 0, "cut after {}", cut);
+                assert_eq!(get(&mut store, b"key0"), Some(values[0].clone()));
+            }
+            for (i, value) in values.iter().enumerate().skip(1) {
+                let key = [b'k', b'e', b'y', b'0' + i as u8];
+                assert_eq!(get(&mut store, &key).as_ref(), Some(value));
+            }
+
+            // The next write compacts again
+            store.set(b"key0", &[0xbb; 40]).unwrap();
+            let mut store = mount(store.free());
+            assert_eq!(get(&mut store, b"key0"), Some(std::vec![0xbb; 40]));
+            assert_eq!(get(&mut store, b"key5"), Some(values[5].clone()));
+        }
+    }
+
+    #[test]
+    fn torn_record_fails_its_checksum() {
+        let mut store = mount(TestFlash::new());
+        store.set(b"k", &[1, 2, 3, 4]).unwrap();
+        let at = store.end;
+        store.set(b"k", &[5, 6, 7, 8]).unwrap();
+
+        // Clear the first byte of the value, after the header and the key
+        let mut flash = store.free();
+        flash.write(at + 8, &[0xff, 0x00, 0xff, 0xff]).unwrap();
+        let mut store = mount(flash);
+        assert!(matches!(store.step(at), Ok(Step::Torn)));
+        assert_eq!(get(&mut store, b"k"), Some(std::vec![1, 2, 3, 4]));
+        assert_eq!(store.end, store.sector_end(0));
+
+        // A write behind it moves on to the next sector
+        store.set(b"j", &[9]).unwrap();
+        assert_eq!(store.active, 1);
+        assert_eq!(get(&mut store, b"k"), Some(std::vec![1, 2, 3, 4]));
+        assert_eq!(get(&mut store, b"j"), Some(std::vec![9]));
+    }
+}
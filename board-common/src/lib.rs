@@ -0,0 +1,363 @@
+//! Traits and helpers shared by the Board Support Packages
+//!
+//! Many boards have a battery, a user button or a status LED, but each one
+//! wires them up differently. The BSPs implement the traits of this crate for
+//! their hardware, so applications which only need "a button" or "a LED" can
+//! be written once and run on any of them.
+//!
+//! ```ignore
+//! fn blink_while_pressed(button: &mut impl UserButton, led: &mut impl StatusLed) {
+//!     if button.is_pressed() {
+//!         led.toggle();
+//!     } else {
+//!         led.off();
+//!     }
+//! }
+//! ```
+#![no_std]
+
+pub extern crate rp2040_hal as hal;
+
+pub mod backlight;
+pub mod board;
+pub mod boot;
+pub mod crash;
+#[cfg(feature = "embedded-hal-async")]
+pub mod debounce;
+pub mod dfu;
+pub mod disk;
+#[cfg(feature = "embassy")]
+pub mod embassy;
+pub mod flash;
+#[cfg(feature = "embedded-graphics")]
+pub mod framebuffer;
+pub mod kv;
+#[cfg(feature = "panic-screen")]
+pub mod panic_screen;
+#[cfg(feature = "rtic")]
+pub mod rtic;
+pub mod usb_boot;
+#[cfg(any(feature = "defmt-usb", feature = "log-usb"))]
+pub mod usb_log;
+pub mod usb_msc;
+
+pub use backlight::Backlight;
+pub use board::{Board, BoardPins, ClockPreset};
+pub use boot::{Bootloader, FirmwareUpdater, Partitions};
+pub use crash::CrashReporter;
+pub use dfu::DfuClass;
+pub use disk::FlashDisk;
+#[cfg(feature = "embassy")]
+pub use embassy::AsyncUserButton;
+pub use embedded_storage;
+pub use flash::{Core1, Flash};
+pub use kv::KvStore;
+#[cfg(feature = "panic-screen")]
+pub use panic_screen::PanicScreen;
+pub use usb_boot::BaudTouch;
+pub use usb_msc::MscClass;
+
+use core::convert::Infallible;
+
+use embedded_hal::digital::{ErrorType, InputPin, OutputPin, StatefulOutputPin};
+use embedded_hal_0_2::adc::OneShot;
+use hal::Adc;
+
+/// Something that reports the voltage of a battery
+pub trait BatteryMonitor {
+    /// Reads the current battery voltage
+    ///
+    /// # Return
+    ///
+    /// The current voltage in millivolts
+    fn millivolts(&mut self, adc: &mut Adc) -> u16;
+
+    /// Estimates the charge of a single cell LiPo battery, see
+    /// [lipo_percentage]
+    fn percentage(&mut self, adc: &mut Adc) -> u8 {
+        lipo_percentage(self.millivolts(adc))
+    }
+}
+
+/// A button the user can press
+pub trait UserButton {
+    /// Whether the button is held down right now
+    fn is_pressed(&mut self) -> bool;
+}
+
+/// A LED showing the state of the application
+pub trait StatusLed {
+    /// Switches the LED on or off
+    fn set(&mut self, on: bool);
+
+    /// Whether the LED is switched on
+    fn is_on(&mut self) -> bool;
+
+    /// Switches the LED on
+    fn on(&mut self) {
+        self.set(true)
+    }
+
+    /// Switches the LED off
+    fn off(&mut self) {
+        self.set(false)
+    }
+
+    /// Switches the LED on if it was off, and off if it was on
+    fn toggle(&mut self) {
+        let on = self.is_on();
+        self.set(!on)
+    }
+}
+
+fn infallible<T>(result: Result<T, Infallible>) -> T {
+    match result {
+        Ok(value) => value,
+        Err(e) => match e {},
+    }
+}
+
+/// A button or LED which is active while its pin is high
+///
+/// A button connects the pin to 3.3V when pressed, usually with a pull-down
+/// on the pin. A LED is connected between the pin and ground.
+pub struct ActiveHigh<P>(pub P);
+
+impl<P> ActiveHigh<P> {
+    /// Wraps `pin`
+    pub fn new(pin: P) -> Self {
+        ActiveHigh(pin)
+    }
+
+    /// Releases the pin
+    pub fn free(self) -> P {
+        self.0
+    }
+}
+
+impl<P: InputPin<Error = Infallible>> UserButton for ActiveHigh<P> {
+    fn is_pressed(&mut self) -> bool {
+        infallible(self.0.is_high())
+    }
+}
+
+impl<P: StatefulOutputPin<Error = Infallible>> StatusLed for ActiveHigh<P> {
+    fn set(&mut self, on: bool) {
+        infallible(self.0.set_state(on.into()))
+    }
+
+    fn is_on(&mut self) -> bool {
+        infallible(self.0.is_set_high())
+    }
+}
+
+/// A button or LED which is active while its pin is low
+///
+/// A button connects the pin to ground when pressed, usually with a pull-up
+/// on the pin. A LED is connected between 3.3V and the pin.
+pub struct ActiveLow<P>(pub P);
+
+impl<P> ActiveLow<P> {
+    /// Wraps `pin`
+    pub fn new(pin: P) -> Self {
+        ActiveLow(pin)
+    }
+
+    /// Releases the pin
+    pub fn free(self) -> P {
+        self.0
+    }
+}
+
+impl<P: InputPin<Error = Infallible>> UserButton for ActiveLow<P> {
+    fn is_pressed(&mut self) -> bool {
+        infallible(self.0.is_low())
+    }
+}
+
+impl<P: StatefulOutputPin<Error = Infallible>> StatusLed for ActiveLow<P> {
+    fn set(&mut self, on: bool) {
+        infallible(self.0.set_state((!on).into()))
+    }
+
+    fn is_on(&mut self) -> bool {
+        infallible(self.0.is_set_low())
+    }
+}
+
+/// A battery measured by an ADC pin through a voltage divider
+///
+/// `ratio` is the factor the divider reduces the voltage by, e.g. 3 for the
+/// VSYS monitor of the Raspberry Pi Pico.
+pub struct VoltageDivider<P> {
+    pin: P,
+    ratio: u16,
+}
+
+impl<P> VoltageDivider<P> {
+    /// Creates a new battery voltage reader
+    pub fn new(pin: P, ratio: u16) -> Self {
+        VoltageDivider { pin, ratio }
+    }
+
+    /// The voltage before the divider in millivolts, from a raw reading of
+    /// the ADC
+    pub fn millivolts_from_raw(&self, raw_value: u16) -> u16 {
+        divided_millivolts(raw_value, self.ratio)
+    }
+
+    /// Releases the ADC pin
+    pub fn free(self) -> P {
+        self.pin
+    }
+}
+
+impl<P> BatteryMonitor for VoltageDivider<P>
+where
+    Adc: OneShot<Adc, u16, P>,
+{
+    fn millivolts(&mut self, adc: &mut Adc) -> u16 {
+        // A failed conversion is simply repeated
+        let raw_value: u16 = loop {
+            match adc.read(&mut self.pin) {
+                Ok(val) => break val,
+                Err(nb::Error::WouldBlock) | Err(nb::Error::Other(_)) => (),
+            }
+        };
+
+        self.millivolts_from_raw(raw_value)
+    }
+}
+
+/// The voltage before a divider by `ratio`, from a raw reading of the ADC
+fn divided_millivolts(raw_value: u16, ratio: u16) -> u16 {
+    // The raw ADC value is in the range of 0..4096, where 0 = 0V and
+    // 4096 = 3.3V
+    let value = u64::from(raw_value) * 3300 * u64::from(ratio) / 4096;
+    #[cfg(feature = "defmt")]
+    defmt::trace!("battery: {=u64} mV, raw {=u16}", value, raw_value);
+    value.min(u64::from(u16::MAX)) as u16
+}
+
+/// Voltages of a single LiPo cell while discharging, with their charge in
+/// percent
+const LIPO_CURVE: [(u16, u8); 8] = [
+    (4200, 100),
+    (4030, 80),
+    (3870, 60),
+    (3790, 40),
+    (3730, 20),
+    (3610, 10),
+    (3450, 5),
+    (3300, 0),
+];
+
+/// Estimates the charge of a single cell LiPo battery from its voltage
+///
+/// The estimate follows a typical discharge curve and is only rough,
+/// especially while the battery is being charged or under heavy load.
+///
+/// # Return
+///
+/// The charge in percent, from 0 to 100
+pub fn lipo_percentage(millivolts: u16) -> u8 {
+    let (full_mv, _) = LIPO_CURVE[0];
+    if millivolts >= full_mv {
+        return 100;
+    }
+    for pair in LIPO_CURVE.windows(2) {
+        let (high_mv, high) = pair[0];
+        let (low_mv, low) = pair[1];
+        if millivolts >= low_mv {
+            let span = u32::from(high - low) * u32::from(millivolts - low_mv);
+            return low + (span / u32::from(high_mv - low_mv)) as u8;
+        }
+    }
+    0
+}
+
+/// An output pin which isn't connected to anything
+///
+/// Useful for drivers which require a pin the board doesn't have, like the
+/// reset line of a display which is tied to the reset of the RP2040.
+pub struct DummyPin;
+
+impl ErrorType for DummyPin {
+    type Error = Infallible;
+}
+
+impl OutputPin for DummyPin {
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl embedded_hal_0_2::digital::v2::OutputPin for DummyPin {
+    type Error = Infallible;
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn voltage_divider_scales_by_its_ratio() {
+        let vsys = VoltageDivider::new((), 3);
+        assert_eq!(vsys.millivolts_from_raw(0), 0);
+        // Half of the 3.3V reference, times 3
+        assert_eq!(vsys.millivolts_from_raw(2048), 4950);
+        assert_eq!(vsys.millivolts_from_raw(4095), 9897);
+        let direct = VoltageDivider::new((), 1);
+        assert_eq!(direct.millivolts_from_raw(2048), 1650);
+    }
+
+    #[test]
+    fn divided_millivolts_are_clamped() {
+        assert_eq!(divided_millivolts(4095, 19), 62684);
+        assert_eq!(divided_millivolts(4095, 20), u16::MAX);
+        assert_eq!(divided_millivolts(u16::MAX, u16::MAX), u16::MAX);
+    }
+
+    #[test]
+    fn lipo_percentage_end_points() {
+        assert_eq!(lipo_percentage(4200), 100);
+        assert_eq!(lipo_percentage(5000), 100);
+        assert_eq!(lipo_percentage(3300), 0);
+    }
+
+    #[test]
+    fn lipo_percentage_follows_the_curve() {
+        for (millivolts, percent) in LIPO_CURVE {
+            assert_eq!(lipo_percentage(millivolts), percent);
+        }
+    }
+
+    #[test]
+    fn lipo_percentage_interpolates() {
+        // Half way from 80 % at 4030 mV to 100 % at 4200 mV
+        assert_eq!(lipo_percentage(4115), 90);
+        // A quarter of the way from 10 % at 3610 mV to 20 % at 3730 mV
+        assert_eq!(lipo_percentage(3640), 12);
+        // Rounded down, 2.5 % half way between 0 % and 5 %
+        assert_eq!(lipo_percentage(3375), 2);
+    }
+
+    #[test]
+    fn lipo_percentage_is_0_below_3300_mv() {
+        assert_eq!(lipo_percentage(3299), 0);
+        assert_eq!(lipo_percentage(2500), 0);
+        assert_eq!(lipo_percentage(0), 0);
+    }
+}
@@ -0,0 +1,280 @@
+//! Showing a panic on the display of the board
+//!
+//! With `panic_halt`, a board with a display freezes with whatever was shown
+//! last, and a panic looks like a hang of the application. The BSPs of such
+//! boards have a `panic-screen` feature instead, which installs a panic
+//! handler that sets the board up again with [Board::steal](crate::Board::steal),
+//! starts the display from scratch and shows the panic message on a
+//! [PanicScreen]. Where the board has a button, pressing it reboots into the
+//! USB bootloader, see [wait_for_bootsel].
+//!
+//! ```ignore
+//! #[panic_handler]
+//! fn panic(info: &PanicInfo) -> ! {
+//!     if !panic_screen::first_panic() {
+//!         panic_screen::halt();
+//!     }
+//!     let mut board = unsafe { Board::steal() };
+//!     let mut display = ...;
+//!     let _ = PanicScreen::new(Rgb565::WHITE, Rgb565::RED)
+//!         .with_hint("Press A for the USB bootloader")
+//!         .draw(&mut display, info);
+//!     panic_screen::wait_for_bootsel(|| button.is_pressed(), Pins::ACTIVITY_LED)
+//! }
+//! ```
+//!
+//! Only available with the `panic-screen` feature.
+
+use core::fmt::{self, Write};
+use core::sync::atomic::{self, AtomicBool, Ordering};
+
+use embedded_graphics::mono_font::ascii::{FONT_10X20, FONT_6X10, FONT_6X13_BOLD};
+use embedded_graphics::mono_font::{MonoFont, MonoTextStyle};
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::Rectangle;
+use embedded_graphics::text::{Baseline, Text};
+
+use crate::crash::Cursor;
+use crate::usb_boot::reset_to_usb_boot;
+
+/// Bytes of the message which are shown at most
+const TEXT_LEN: usize = 512;
+
+/// Areas narrower than this get the small title and margin
+const SMALL_WIDTH: u32 = 200;
+
+/// Set by the first panic
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
+/// Whether this is the first panic
+///
+/// A panic while the screen is set up panics again, and the handler should
+/// [halt] then instead of trying once more.
+pub fn first_panic() -> bool {
+    if PANICKING.load(Ordering::Relaxed) {
+        return false;
+    }
+    PANICKING.store(true, Ordering::Relaxed);
+    true
+}
+
+/// Stops, like `panic_halt`
+pub fn halt() -> ! {
+    loop {
+        atomic::compiler_fence(Ordering::SeqCst);
+    }
+}
+
+/// Waits for a press of a button, then reboots into the USB bootloader
+///
+/// A button which is already held when the wait starts has to be released
+/// first, so the press which caused the panic doesn't count.
+/// `activity_led` is blinked by the bootloader, see
+/// [BoardPins::ACTIVITY_LED](crate::BoardPins::ACTIVITY_LED).
+pub fn wait_for_bootsel(mut pressed: impl FnMut() -> bool, activity_led: Option<u8>) -> ! {
+    while pressed() {}
+    while !pressed() {}
+    reset_to_usb_boot(activity_led)
+}
+
+/// The layout of the panic screen
+///
+/// A title, the panic message with the place of the panic, wrapped to the
+/// width of the screen, and a hint at the bottom.
+#[derive(Debug, Clone, Copy)]
+pub struct PanicScreen<'a, C> {
+    foreground: C,
+    background: C,
+    area: Option<Rectangle>,
+    hint: Option<&'a str>,
+}
+
+impl<'a, C: PixelColor> PanicScreen<'a, C> {
+    /// Draws in `foreground` on `background`, over the whole display
+    pub fn new(foreground: C, background: C) -> Self {
+        PanicScreen {
+            foreground,
+            background,
+            area: None,
+            hint: None,
+        }
+    }
+
+    /// Draws within `area` instead, for example the visible part of a
+    /// round display
+    pub fn with_area(self, area: Rectangle) -> Self {
+        PanicScreen {
+            area: Some(area),
+            ..self
+        }
+    }
+
+    /// Shows `hint` at the bottom, like the button to press
+    pub fn with_hint(self, hint: &'a str) -> Self {
+        PanicScreen {
+            hint: Some(hint),
+            ..self
+        }
+    }
+
+    /// Draws `message`, usually the `PanicInfo`
+    ///
+    /// The message is cut off after 512 bytes, or when the area is full.
+    pub fn draw<D>(&self, display: &mut D, message: &dyn fmt::Display) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = C>,
+    {
+        let area = match self.area {
+            Some(area) => area,
+            None => display.bounding_box(),
+        };
+        display.fill_solid(&area, self.background)?;
+
+        let (title_font, margin): (&MonoFont<'_>, i32) = if area.size.width < SMALL_WIDTH {
+            (&FONT_6X13_BOLD, 2)
+        } else {
+            (&FONT_10X20, 8)
+        };
+        let body_font = &FONT_6X10;
+        let line_height = body_font.character_size.height as i32;
+        let left = area.top_left.x + margin;
+        let mut top = area.top_left.y + margin;
+        let mut bottom = area.top_left.y + area.size.height as i32 - margin;
+
+        let title = MonoTextStyle::new(title_font, self.foreground);
+        Text::with_baseline("PANIC", Point::new(left, top), title, Baseline::Top).draw(display)?;
+        top += title_font.character_size.height as i32 + margin / 2;
+
+        let body = MonoTextStyle::new(body_font, self.foreground);
+        if let Some(hint) = self.hint {
+            bottom -= line_height;
+            Text::with_baseline(hint, Point::new(left, bottom), body, Baseline::Top)
+                .draw(display)?;
+            bottom -= margin / 2;
+        }
+
+        let mut buf = [0; TEXT_LEN];
+        let mut cursor = Cursor {
+            buf: &mut buf,
+            len: 0,
+        };
+        let _ = write!(cursor, "{}", message);
+        let len = cursor.len;
+        let text = match core::str::from_utf8(&buf[..len]) {
+            Ok(text) => text,
+            // Cut off in the middle of a character
+            Err(e) => core::str::from_utf8(&buf[..e.valid_up_to()]).unwrap_or(""),
+        };
+
+        let columns = ((area.size.width as i32 - 2 * margin)
+            / body_font.character_size.width as i32)
+            .max(1) as usize;
+        for line in Wrapped::new(text, columns) {
+            if top + line_height > bottom {
+                break;
+            }
+            Text::with_baseline(line, Point::new(left, top), body, Baseline::Top).draw(display)?;
+            top += line_height;
+        }
+        Ok(())
+    }
+}
+
+/// The lines of a text, wrapped at spaces to at most `columns` characters
+struct Wrapped<'a> {
+    rest: &'a str,
+    columns: usize,
+}
+
+impl<'a> Wrapped<'a> {
+    fn new(text: &'a str, columns: usize) -> Self {
+        Wrapped {
+            rest: text.trim_end(),
+            columns,
+        }
+    }
+}
+
+impl<'a> Iterator for Wrapped<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        if self.rest.is_empty() {
+            return None;
+        }
+        let end = self.rest.find('\n').unwrap_or(self.rest.len());
+        let paragraph = &self.rest[..end];
+        let (line, next) = match paragraph.char_indices().nth(self.columns) {
+            // Fits, continue after the line break
+            None => (paragraph, (end + 1).min(self.rest.len())),
+            // Break at the space after the last word which fits
+            Some((cut, ' ')) => (&paragraph[..cut], cut + 1),
+            Some((cut, _)) => match paragraph[..cut].rfind(' ') {
+                Some(space) if space > 0 => (&paragraph[..space], space + 1),
+                // A single long word, like a path
+                _ => (&paragraph[..cut], cut),
+            },
+        };
+        self.rest = &self.rest[next..];
+        Some(line.trim_end())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec::Vec;
+
+    use super::*;
+
+    fn wrap(text: &str, columns: usize) -> Vec<&str> {
+        Wrapped::new(text, columns).collect()
+    }
+
+    #[test]
+    fn short_lines_are_kept() {
+        assert_eq!(wrap("boom", 10), ["boom"]);
+        assert_eq!(wrap("", 10), [] as [&str; 0]);
+        assert_eq!(wrap("boom\n\n", 10), ["boom"]);
+    }
+
+    #[test]
+    fn newlines_start_a_line() {
+        assert_eq!(
+            wrap("panicked at src/main.rs:3:5:\nboom", 40),
+            ["panicked at src/main.rs:3:5:", "boom"]
+        );
+        assert_eq!(wrap("abcd\nef", 4), ["abcd", "ef"]);
+        assert_eq!(wrap("ab\n\ncd", 4), ["ab", "", "cd"]);
+    }
+
+    #[test]
+    fn breaks_at_the_last_space_which_fits() {
+        assert_eq!(wrap("hello world foo", 11), ["hello world", "foo"]);
+        assert_eq!(wrap("hello world foo", 8), ["hello", "world", "foo"]);
+        assert_eq!(
+            wrap("called `Option::unwrap()` on a `None` value", 20),
+            ["called", "`Option::unwrap()`", "on a `None` value"]
+        );
+    }
+
+    #[test]
+    fn long_words_are_broken_anywhere() {
+        assert_eq!(
+            wrap("src/some/long/path.rs", 8),
+            ["src/some", "/long/pa", "th.rs"]
+        );
+        assert_eq!(
+            wrap("at src/some/path.rs", 8),
+            ["at", "src/some", "/path.rs"]
+        );
+    }
+
+    #[test]
+    fn columns_count_characters() {
+        assert_eq!(wrap("ééééé", 2), ["éé", "éé", "é"]);
+        assert_eq!(wrap("grüß dich", 4), ["grüß", "dich"]);
+        assert_eq!(wrap("größer", 4), ["größ", "er"]);
+    }
+}
@@ -0,0 +1,170 @@
+//! A monotonic for RTIC 2, on the alarm 0 of the microsecond timer
+//!
+//! RTIC 2 leaves the timers to the application: async tasks wait with a
+//! [Monotonic], which wakes them up from the interrupt of a hardware timer.
+//! [Mono] counts the microseconds of the `TIMER` peripheral, so it keeps
+//! running at the same rate whatever the system clock is, and wakes up the
+//! tasks with `TIMER_IRQ_0`.
+//!
+//! The interrupt handler is declared by the application with
+//! [rtic_monotonic](crate::rtic_monotonic), and the monotonic started in the
+//! `init` task with the alarm 0 of the timer:
+//!
+//! ```ignore
+//! board_common::rtic_monotonic!();
+//!
+//! #[rtic::app(device = rp_pico::hal::pac)]
+//! mod app {
+//!     use board_common::rtic::{ExtU64, Mono};
+//!
+//!     #[init]
+//!     fn init(cx: init::Context) -> (Shared, Local) {
+//!         let mut board =
+//!             rp_pico::Board::from_peripherals(cx.device, cx.core, ClockPreset::Standard);
+//!         Mono::start(board.timer.alarm_0().unwrap());
+//!         blink::spawn().ok();
+//!         // ...
+//!     }
+//!
+//!     #[task]
+//!     async fn blink(_: blink::Context) {
+//!         loop {
+//!             Mono::delay(500.millis()).await;
+//!             // ...
+//!         }
+//!     }
+//! }
+//! ```
+
+use core::future::Future;
+
+use hal::fugit;
+use hal::pac::{self, Interrupt::TIMER_IRQ_0};
+use hal::timer::Alarm0;
+use rtic_time::{Monotonic, TimeoutError, TimerQueue};
+
+pub use fugit::ExtU64;
+
+/// The waiting tasks, sorted by the instant they wake up at
+static TIMER_QUEUE: TimerQueue<Mono> = TimerQueue::new();
+
+/// A point in time, in microseconds since the timer started
+pub type Instant = fugit::TimerInstantU64<1_000_000>;
+
+/// A span of time, in microseconds
+pub type Duration = fugit::TimerDurationU64<1_000_000>;
+
+/// The monotonic of the board, counting microseconds
+pub struct Mono;
+
+impl Mono {
+    /// Starts waking up the tasks which wait on the monotonic
+    ///
+    /// Takes the alarm 0 of the timer, so nothing else can use it, and
+    /// unmasks `TIMER_IRQ_0`. Call it once, from the `init` task.
+    pub fn start(_alarm: Alarm0) {
+        timer().inte().modify(|_, w| w.alarm_0().set_bit());
+        TIMER_QUEUE.initialize(Mono);
+        #[cfg(feature = "defmt")]
+        defmt::debug!("rtic: monotonic started");
+        // Note (safety): the handler only touches the timer queue, which is
+        // initialized now
+        unsafe { pac::NVIC::unmask(TIMER_IRQ_0) };
+    }
+
+    /// The current instant
+    pub fn now() -> Instant {
+        <Self as Monotonic>::now()
+    }
+
+    /// Waits for `duration`
+    pub async fn delay(duration: Duration) {
+        TIMER_QUEUE.delay(duration).await;
+    }
+
+    /// Waits until `instant`, without the drift of successive delays
+    pub async fn delay_until(instant: Instant) {
+        TIMER_QUEUE.delay_until(instant).await;
+    }
+
+    /// Runs `future`, giving up if it doesn't complete within `duration`
+    pub async fn timeout_after<F: Future>(
+        duration: Duration,
+        future: F,
+    ) -> Result<F::Output, TimeoutError> {
+        TIMER_QUEUE.timeout_after(duration, future).await
+    }
+
+    /// Wakes up the tasks whose instant has come
+    ///
+    /// # Safety
+    ///
+    /// Only for the interrupt handler declared by
+    /// [rtic_monotonic](crate::rtic_monotonic).
+    #[doc(hidden)]
+    pub unsafe fn on_interrupt() {
+        TIMER_QUEUE.on_monotonic_interrupt();
+    }
+}
+
+impl Monotonic for Mono {
+    const ZERO: Self::Instant = Instant::from_ticks(0);
+    const TICK_PERIOD: Self::Duration = Duration::from_ticks(1);
+
+    type Instant = Instant;
+    type Duration = Duration;
+
+    fn now() -> Self::Instant {
+        let timer = timer();
+        // Reading the raw registers doesn't latch the high word, so read it
+        // again to catch a carry out of the low word
+        loop {
+            let high = timer.timerawh().read().bits();
+            let low = timer.timerawl().read().bits();
+            if high == timer.timerawh().read().bits() {
+                return Instant::from_ticks((u64::from(high) << 32) | u64::from(low));
+            }
+        }
+    }
+
+    fn set_compare(instant: Self::Instant) {
+        // The alarm only compares the low word. Further than that, or in the
+        // past, arm it for the next wrap of the low word: the timer queue
+        // sets it again when it fires early.
+        let ticks = match instant.checked_duration_since(Self::now()) {
+            Some(duration) if duration.ticks() <= u64::from(u32::MAX) => instant.ticks() as u32,
+            _ => 0,
+        };
+        // Note (safety): any value is a valid alarm
+        timer().alarm0().write(|w| unsafe { w.bits(ticks) });
+    }
+
+    fn clear_compare_flag() {
+        timer().intr().write(|w| w.alarm_0().clear_bit_by_one());
+    }
+
+    fn pend_interrupt() {
+        pac::NVIC::pend(TIMER_IRQ_0);
+    }
+}
+
+/// The registers of the timer
+fn timer() -> &'static pac::timer::RegisterBlock {
+    // Note (safety): the monotonic only reads the counter and owns the alarm 0
+    unsafe { &*pac::TIMER::ptr() }
+}
+
+/// Declares the `TIMER_IRQ_0` handler of [Mono]
+///
+/// Outside of the `#[rtic::app]` module, as RTIC only knows about the
+/// interrupts of its own tasks.
+#[macro_export]
+macro_rules! rtic_monotonic {
+    () => {
+        #[no_mangle]
+        #[allow(non_snake_case)]
+        unsafe extern "C" fn TIMER_IRQ_0() {
+            $crate::rtic::Mono::on_interrupt();
+        }
+    };
+}
@@ -0,0 +1,109 @@
+//! Rebooting into the USB bootloader from the host
+//!
+//! Arduino tools, and many upload scripts, ask a board to reboot into its
+//! bootloader by opening its USB serial port at 1200 baud and closing it
+//! again. [BaudTouch] watches a [SerialPort] for that "touch", and reboots
+//! into BOOTSEL mode with the board's activity LED, so a new UF2 file can be
+//! copied without pressing any buttons.
+//!
+//! ```ignore
+//! let mut serial = SerialPort::new(&usb_bus);
+//! let touch = BaudTouch::for_board::<rp_pico::Pins>();
+//! loop {
+//!     usb_dev.poll(&mut [&mut serial]);
+//!     touch.poll(&serial);
+//! }
+//! ```
+//!
+//! On Linux, the touch is `stty -F /dev/ttyACM0 1200`.
+
+use core::borrow::BorrowMut;
+
+use usb_device::bus::UsbBus;
+use usbd_serial::SerialPort;
+
+use crate::hal;
+use crate::BoardPins;
+
+/// Baud rate of the Arduino touch
+pub const TOUCH_BAUD: u32 = 1200;
+
+/// Reboots into the USB bootloader of the boot ROM
+///
+/// The ROM blinks the LED on `activity_led` while the bootloader is busy,
+/// see [BoardPins::ACTIVITY_LED]. Both the mass storage and the PICOBOOT
+/// interface are enabled, so the board can be flashed by copying a UF2 file
+/// or with `picotool`.
+pub fn reset_to_usb_boot(activity_led: Option<u8>) -> ! {
+    let mask = match activity_led {
+        Some(gpio) => 1 << gpio,
+        None => 0,
+    };
+    hal::rom_data::reset_to_usb_boot(mask, 0);
+    // The ROM resets the chip through the watchdog, which takes a moment
+    loop {
+        cortex_m::asm::nop();
+    }
+}
+
+/// Watches a serial port for the host asking to reboot into the bootloader
+///
+/// The touch is seen once the host has set the line coding to the touch baud
+/// rate and DTR is low, which is the case after closing a port opened at that
+/// rate. Opening a port at any other rate, or keeping it open, does nothing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BaudTouch {
+    baud: u32,
+    activity_led: Option<u8>,
+}
+
+impl BaudTouch {
+    /// Watches for [TOUCH_BAUD], and reboots with `activity_led`
+    pub const fn new(activity_led: Option<u8>) -> Self {
+        BaudTouch {
+            baud: TOUCH_BAUD,
+            activity_led,
+        }
+    }
+
+    /// Watches for [TOUCH_BAUD], and reboots with the activity LED of the
+    /// board with pins `P`
+    pub const fn for_board<P: BoardPins>() -> Self {
+        Self::new(P::ACTIVITY_LED)
+    }
+
+    /// Watches for `baud` instead of [TOUCH_BAUD]
+    pub const fn with_baud(self, baud: u32) -> Self {
+        BaudTouch { baud, ..self }
+    }
+
+    /// Whether the host has touched `serial`
+    pub fn is_touched<B, RS, WS>(&self, serial: &SerialPort<'_, B, RS, WS>) -> bool
+    where
+        B: UsbBus,
+        RS: BorrowMut<[u8]>,
+        WS: BorrowMut<[u8]>,
+    {
+        serial.line_coding().data_rate() == self.baud && !serial.dtr()
+    }
+
+    /// Reboots into the USB bootloader if the host has touched `serial`
+    ///
+    /// Call it after every poll of the USB device.
+    pub fn poll<B, RS, WS>(&self, serial: &SerialPort<'_, B, RS, WS>)
+    where
+        B: UsbBus,
+        RS: BorrowMut<[u8]>,
+        WS: BorrowMut<[u8]>,
+    {
+        if self.is_touched(serial) {
+            #[cfg(feature = "defmt")]
+            defmt::info!(
+                "usb: touched at {=u32} baud, rebooting into BOOTSEL",
+                self.baud
+            );
+            reset_to_usb_boot(self.activity_led);
+        }
+    }
+}
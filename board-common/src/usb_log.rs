@@ -0,0 +1,471 @@
+//! Logging over the USB serial port of the board
+//!
+//! Most boards are used without a debug probe, so RTT isn't available. With
+//! the `defmt-usb` feature, this module provides the `defmt` global logger,
+//! and with the `log-usb` feature a logger for the `log` crate. Both put the
+//! logs into a ring buffer of [RING_LEN] bytes, which can be written from
+//! any context, including interrupts and the second core. [drain] sends the
+//! contents of the ring on a [SerialPort], and is called in the USB
+//! interrupt, right after polling the USB device:
+//!
+//! ```ignore
+//! unsafe { usb_log::init_log(log::LevelFilter::Info) };
+//! pac::NVIC::unmask(pac::Interrupt::USBCTRL_IRQ);
+//! log::info!("up and running");
+//!
+//! #[interrupt]
+//! fn USBCTRL_IRQ() {
+//!     ...
+//!     usb_dev.poll(&mut [serial]);
+//!     usb_log::drain(serial);
+//! }
+//! ```
+//!
+//! Each log pends the USB interrupt of the core it runs on, so the logs of
+//! the core which handles the interrupt are sent right away, and those of the
+//! other core with the next USB interrupt.
+//!
+//! A log is never written in part, and never waits for the host: when there
+//! isn't enough room in the ring, because nobody has opened the port or the
+//! host doesn't keep up, the whole log is dropped and [dropped] counts it.
+//! The logs which are already in the ring are sent once the host reads
+//! again, so the logs of the start-up are still there when the port is
+//! opened a bit later.
+//!
+//! With `defmt-usb`, leave out `defmt-rtt` and decode the port with
+//! `defmt-print -e <elf> serial --path /dev/ttyACM0`. With `log-usb`, the
+//! records are lines of text, for any terminal. Enable only one of them, the
+//! port can't carry both.
+
+use core::borrow::BorrowMut;
+use core::cell::UnsafeCell;
+use core::ptr;
+use core::slice;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+
+use usb_device::bus::UsbBus;
+use usbd_serial::SerialPort;
+
+use crate::hal::pac;
+
+/// Bytes of logs which are kept until the host reads them
+pub const RING_LEN: usize = 1024;
+
+static RING: LogRing = LogRing::new();
+
+/// Set while a log is written into the ring, a log written by the
+/// formatting of another one is dropped
+static TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// Set while the ring is drained
+static DRAINING: AtomicBool = AtomicBool::new(false);
+
+/// A ring buffer with a single writer and a single reader
+///
+/// The positions run freely and wrap around at `usize::MAX`, which works as
+/// [RING_LEN] is a power of two. The writer only touches the bytes after
+/// `head`, and the reader only those between `tail` and `head`. The RP2040
+/// has no compare-and-swap, so the writers take turns under a critical
+/// section, and each counter is only ever stored by one side.
+struct LogRing {
+    buf: UnsafeCell<[u8; RING_LEN]>,
+    /// End of the logs which are complete, stored by the writer
+    head: AtomicUsize,
+    /// End of the bytes which were sent, stored by the reader
+    tail: AtomicUsize,
+    /// End of the log being written
+    cursor: UnsafeCell<usize>,
+    /// Whether the log being written didn't fit
+    overflow: UnsafeCell<bool>,
+    dropped: AtomicU32,
+}
+
+// SAFETY: the writer and the reader only share the atomics, and the
+// bytes are handed over through them, see the methods
+unsafe impl Sync for LogRing {}
+
+impl LogRing {
+    const fn new() -> Self {
+        LogRing {
+            buf: UnsafeCell::new([0; RING_LEN]),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            cursor: UnsafeCell::new(0),
+            overflow: UnsafeCell::new(false),
+            dropped: AtomicU32::new(0),
+        }
+    }
+
+    /// Starts a log
+    ///
+    /// # Safety
+    ///
+    /// Only one writer at a time, from [begin](Self::begin) to
+    /// [commit](Self::commit).
+    unsafe fn begin(&self) {
+        *self.cursor.get() = self.head.load(Ordering::Relaxed);
+        *self.overflow.get() = false;
+    }
+
+    /// Adds `bytes` to the log, or drops the log if they don't fit
+    ///
+    /// # Safety
+    ///
+    /// See [begin](Self::begin).
+    unsafe fn write(&self, bytes: &[u8]) {
+        if *self.overflow.get() {
+            return;
+        }
+        let cursor = *self.cursor.get();
+        let used = cursor.wrapping_sub(self.tail.load(Ordering::Acquire));
+        if used + bytes.len() > RING_LEN {
+            *self.overflow.get() = true;
+            return;
+        }
+        // Through the pointer, the reader may hold a slice of the other bytes
+        let buf = self.buf.get().cast::<u8>();
+        let start = cursor % RING_LEN;
+        let first = bytes.len().min(RING_LEN - start);
+        ptr::copy_nonoverlapping(bytes.as_ptr(), buf.add(start), first);
+        ptr::copy_nonoverlapping(bytes[first..].as_ptr(), buf, bytes.len() - first);
+        *self.cursor.get() = cursor.wrapping_add(bytes.len());
+    }
+
+    /// Hands the log over to the reader, or counts it as dropped
+    ///
+    /// # Safety
+    ///
+    /// See [begin](Self::begin).
+    unsafe fn commit(&self) {
+        if *self.overflow.get() {
+            self.count_dropped();
+        } else {
+            self.head.store(*self.cursor.get(), Ordering::Release);
+        }
+    }
+
+    /// # Safety
+    ///
+    /// See [begin](Self::begin).
+    unsafe fn count_dropped(&self) {
+        let dropped = self.dropped.load(Ordering::Relaxed);
+        self.dropped
+            .store(dropped.wrapping_add(1), Ordering::Relaxed);
+    }
+
+    /// The logs which are complete, up to the end of the buffer
+    ///
+    /// # Safety
+    ///
+    /// Only one reader at a time, from [readable](Self::readable) to
+    /// [consume](Self::consume).
+    unsafe fn readable(&self) -> &[u8] {
+        let head = self.head.load(Ordering::Acquire);
+        let tail = self.tail.load(Ordering::Relaxed);
+        let start = tail % RING_LEN;
+        let len = head.wrapping_sub(tail).min(RING_LEN - start);
+        slice::from_raw_parts(self.buf.get().cast::<u8>().add(start), len)
+    }
+
+    /// Frees the first `count` bytes of [readable](Self::readable)
+    ///
+    /// # Safety
+    ///
+    /// See [readable](Self::readable).
+    unsafe fn consume(&self, count: usize) {
+        let tail = self.tail.load(Ordering::Relaxed);
+        self.tail.store(tail.wrapping_add(count), Ordering::Release);
+    }
+}
+
+/// Sends as much of the logs as `serial` takes
+///
+/// Call it in the USB interrupt, after polling the USB device. A call while
+/// another one is still draining, on the other core for example, returns
+/// right away.
+pub fn drain<B, RS, WS>(serial: &mut SerialPort<'_, B, RS, WS>)
+where
+    B: UsbBus,
+    RS: BorrowMut<[u8]>,
+    WS: BorrowMut<[u8]>,
+{
+    let busy = critical_section::with(|_| {
+        let busy = DRAINING.load(Ordering::Relaxed);
+        DRAINING.store(true, Ordering::Relaxed);
+        busy
+    });
+    if busy {
+        return;
+    }
+    loop {
+        // SAFETY: DRAINING keeps out the other readers
+        let bytes = unsafe { RING.readable() };
+        if bytes.is_empty() {
+            break;
+        }
+        match serial.write(bytes) {
+            // SAFETY: as above
+            Ok(count) if count > 0 => unsafe { RING.consume(count) },
+            // Full, the next interrupt continues
+            _ => break,
+        }
+    }
+    DRAINING.store(false, Ordering::Release);
+}
+
+/// The number of logs which were dropped since the start
+///
+/// Wraps around after `u32::MAX`.
+pub fn dropped() -> u32 {
+    RING.dropped.load(Ordering::Relaxed)
+}
+
+/// Wakes up the USB interrupt to send the new log
+fn wake() {
+    cortex_m::peripheral::NVIC::pend(pac::Interrupt::USBCTRL_IRQ);
+}
+
+#[cfg(feature = "defmt-usb")]
+mod defmt_logger {
+    use core::ptr::addr_of_mut;
+    use core::sync::atomic::Ordering;
+
+    use critical_section::RestoreState;
+
+    use super::{wake, RING, TAKEN};
+
+    /// The state of the critical section from before the log
+    static mut RESTORE: RestoreState = RestoreState::invalid();
+
+    static mut ENCODER: defmt::Encoder = defmt::Encoder::new();
+
+    #[defmt::global_logger]
+    struct UsbLogger;
+
+    fn write_ring(bytes: &[u8]) {
+        // SAFETY: only called between acquire and release
+        unsafe { RING.write(bytes) }
+    }
+
+    // SAFETY: the logger is only taken in a critical section, and taking it
+    // again from there panics
+    unsafe impl defmt::Logger for UsbLogger {
+        fn acquire() {
+            // SAFETY: released in `release`
+            let restore = unsafe { critical_section::acquire() };
+            if TAKEN.load(Ordering::Relaxed) {
+                panic!("defmt logger taken reentrantly");
+            }
+            TAKEN.store(true, Ordering::Relaxed);
+            // SAFETY: the logger is taken, nobody else writes
+            unsafe {
+                *addr_of_mut!(RESTORE) = restore;
+                RING.begin();
+                (*addr_of_mut!(ENCODER)).start_frame(write_ring);
+            }
+        }
+
+        unsafe fn flush() {}
+
+        unsafe fn release() {
+            (*addr_of_mut!(ENCODER)).end_frame(write_ring);
+            RING.commit();
+            TAKEN.store(false, Ordering::Relaxed);
+            critical_section::release(*addr_of_mut!(RESTORE));
+            wake();
+        }
+
+        unsafe fn write(bytes: &[u8]) {
+            (*addr_of_mut!(ENCODER)).write(bytes, write_ring);
+        }
+    }
+}
+
+#[cfg(feature = "log-usb")]
+pub use self::log_logger::init_log;
+
+#[cfg(feature = "log-usb")]
+mod log_logger {
+    use core::fmt::{self, Write};
+    use core::sync::atomic::Ordering;
+
+    use super::{wake, RING, TAKEN};
+
+    static LOGGER: UsbLogger = UsbLogger;
+
+    /// Sets up the logger of the `log` crate, for the records up to `level`
+    ///
+    /// # Safety
+    ///
+    /// Nothing may log yet, the RP2040 can't set the logger atomically.
+    pub unsafe fn init_log(level: log::LevelFilter) {
+        // Only fails if a logger was set before, which then stays
+        let _ = log::set_logger_racy(&LOGGER);
+        log::set_max_level_racy(level);
+    }
+
+    struct UsbLogger;
+
+    /// Writes the text of a record into the ring
+    struct RingWriter;
+
+    impl Write for RingWriter {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            // SAFETY: only used while the logger is taken
+            unsafe { RING.write(s.as_bytes()) };
+            Ok(())
+        }
+    }
+
+    impl log::Log for UsbLogger {
+        fn enabled(&self, _metadata: &log::Metadata<'_>) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record<'_>) {
+            critical_section::with(|_| {
+                // SAFETY: the critical section and TAKEN keep out the
+                // other writers
+                unsafe {
+                    if TAKEN.load(Ordering::Relaxed) {
+                        RING.count_dropped();
+                        return;
+                    }
+                    TAKEN.store(true, Ordering::Relaxed);
+                    RING.begin();
+                    let _ = write!(
+                        RingWriter,
+                        "{:<5} {}: {}\r\n",
+                        record.level(),
+                        record.target(),
+                        record.args()
+                    );
+                    RING.commit();
+                    TAKEN.store(false, Ordering::Relaxed);
+                }
+            });
+            wake();
+        }
+
+        fn flush(&self) {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec::Vec;
+
+    use super::*;
+
+    /// Writes a log made of `parts`
+    fn log(ring: &LogRing, parts: &[&[u8]]) {
+        // SAFETY: the tests are the only writer
+        unsafe {
+            ring.begin();
+            for part in parts {
+                ring.write(part);
+            }
+            ring.commit();
+        }
+    }
+
+    /// Reads and consumes everything, in the pieces the ring hands out
+    fn read(ring: &LogRing) -> Vec<Vec<u8>> {
+        let mut pieces = Vec::new();
+        loop {
+            // SAFETY: the tests are the only reader
+            let piece = unsafe { ring.readable() }.to_vec();
+            if piece.is_empty() {
+                return pieces;
+            }
+            unsafe { ring.consume(piece.len()) };
+            pieces.push(piece);
+        }
+    }
+
+    fn bytes(len: usize, seed: u8) -> Vec<u8> {
+        (0..len).map(|i| (i as u8).wrapping_add(seed)).collect()
+    }
+
+    #[test]
+    fn logs_are_read_in_order() {
+        let ring = LogRing::new();
+        log(&ring, &[b"abc", b"d"]);
+        log(&ring, &[b"ef"]);
+        assert_eq!(unsafe { ring.readable() }, b"abcdef");
+        unsafe { ring.consume(2) };
+        assert_eq!(read(&ring), [b"cdef"]);
+        assert_eq!(ring.dropped.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn logs_wrap_around_the_end() {
+        let ring = LogRing::new();
+        log(&ring, &[&bytes(1000, 0)]);
+        read(&ring);
+
+        let wrapped = bytes(100, 1);
+        log(&ring, &[&wrapped[..50], &wrapped[50..]]);
+        assert_eq!(read(&ring), [&wrapped[..24], &wrapped[24..]]);
+    }
+
+    #[test]
+    fn positions_wrap_around() {
+        let ring = LogRing::new();
+        ring.head.store(usize::MAX - 9, Ordering::Relaxed);
+        ring.tail.store(usize::MAX - 9, Ordering::Relaxed);
+
+        let wrapped = bytes(20, 2);
+        log(&ring, &[&wrapped]);
+        assert_eq!(read(&ring), [&wrapped[..10], &wrapped[10..]]);
+        assert_eq!(ring.head.load(Ordering::Relaxed), 10);
+    }
+
+    #[test]
+    fn overflowing_logs_are_dropped_whole() {
+        let ring = LogRing::new();
+        let first = bytes(1000, 3);
+        log(&ring, &[&first]);
+
+        // The first part fits, the second one doesn't, and nothing of the
+        // log is committed
+        log(&ring, &[&[0xaa; 20], &[0xbb; 10]]);
+        assert_eq!(unsafe { ring.readable() }, &first[..]);
+        // Later parts which would fit are dropped as well
+        log(&ring, &[&[0xcc; 30], b"x"]);
+        assert_eq!(ring.dropped.load(Ordering::Relaxed), 2);
+
+        // The next log takes the room of the dropped ones
+        log(&ring, &[&[0xdd; 24]]);
+        assert_eq!(read(&ring).concat(), [&first[..], &[0xdd; 24]].concat());
+
+        // Even if the ring is empty, a log longer than it never fits
+        log(&ring, &[&bytes(RING_LEN + 1, 4)]);
+        assert!(read(&ring).is_empty());
+        assert_eq!(ring.dropped.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn consume_frees_room() {
+        let ring = LogRing::new();
+        let full = bytes(RING_LEN, 5);
+        log(&ring, &[&full]);
+        log(&ring, &[b"a"]);
+        assert_eq!(ring.dropped.load(Ordering::Relaxed), 1);
+
+        unsafe { ring.consume(1) };
+        log(&ring, &[b"b"]);
+        assert_eq!(ring.dropped.load(Ordering::Relaxed), 1);
+        assert_eq!(read(&ring), [&full[1..], b"b"]);
+    }
+
+    #[test]
+    fn dropped_wraps_around() {
+        let ring = LogRing::new();
+        ring.dropped.store(u32::MAX, Ordering::Relaxed);
+        log(&ring, &[&bytes(RING_LEN + 1, 6)]);
+        assert_eq!(ring.dropped.load(Ordering::Relaxed), 0);
+    }
+}
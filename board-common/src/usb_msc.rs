@@ -0,0 +1,905 @@
+//! USB mass storage class, showing a [FlashDisk] as a drive on the host
+//!
+//! Implements the Bulk-Only Transport with the SCSI commands which Linux,
+//! macOS and Windows use for a removable drive. The drive only holds a
+//! medium while the firmware has given the disk to the host, see
+//! [FlashDisk::give_to_host]. Ejecting the drive on the host gives the disk
+//! back to the firmware.
+//!
+//! ```ignore
+//! let mut msc = MscClass::new(&usb_bus, &disk);
+//! let mut usb_dev = UsbDeviceBuilder::new(&usb_bus, UsbVidPid(0x16c0, 0x27dd))
+//!     .strings(&[StringDescriptors::default().product("Flash disk")])
+//!     .unwrap()
+//!     .build();
+//! disk.give_to_host();
+//! loop {
+//!     usb_dev.poll(&mut [&mut msc]);
+//! }
+//! ```
+
+use embedded_storage::nor_flash::MultiwriteNorFlash;
+use usb_device::class_prelude::*;
+use usb_device::control::{Recipient, RequestType};
+
+use crate::disk::{DiskError, FlashDisk, Owner};
+
+/// Mass storage class
+const CLASS_MSC: u8 = 0x08;
+/// SCSI transparent command set
+const SUBCLASS_SCSI: u8 = 0x06;
+/// Bulk-Only Transport
+const PROTOCOL_BBB: u8 = 0x50;
+
+const REQ_GET_MAX_LUN: u8 = 0xfe;
+const REQ_BULK_ONLY_RESET: u8 = 0xff;
+
+const PACKET_SIZE: usize = 64;
+const BLOCK_SIZE: usize = 512;
+/// Blocks written to the flash at once, a sector of the flash
+const BUFFER_SIZE: usize = 4096;
+
+const CBW_SIGNATURE: u32 = 0x4342_5355;
+const CBW_LEN: usize = 31;
+const CSW_SIGNATURE: u32 = 0x5342_5355;
+
+const TEST_UNIT_READY: u8 = 0x00;
+const REQUEST_SENSE: u8 = 0x03;
+const INQUIRY: u8 = 0x12;
+const MODE_SENSE_6: u8 = 0x1a;
+const START_STOP_UNIT: u8 = 0x1b;
+const PREVENT_ALLOW_MEDIUM_REMOVAL: u8 = 0x1e;
+const READ_FORMAT_CAPACITIES: u8 = 0x23;
+const READ_CAPACITY_10: u8 = 0x25;
+const READ_10: u8 = 0x28;
+const WRITE_10: u8 = 0x2a;
+const VERIFY_10: u8 = 0x2f;
+const SYNCHRONIZE_CACHE_10: u8 = 0x35;
+const MODE_SENSE_10: u8 = 0x5a;
+
+/// Sense key, additional sense code and qualifier of a failed command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Sense(u8, u8, u8);
+
+impl Sense {
+    const NONE: Sense = Sense(0x00, 0x00, 0x00);
+    const MEDIUM_NOT_PRESENT: Sense = Sense(0x02, 0x3a, 0x00);
+    const BECOMING_READY: Sense = Sense(0x02, 0x04, 0x01);
+    const MEDIUM_ERROR: Sense = Sense(0x03, 0x11, 0x00);
+    const INVALID_COMMAND: Sense = Sense(0x05, 0x20, 0x00);
+    const OUT_OF_RANGE: Sense = Sense(0x05, 0x21, 0x00);
+    const MEDIUM_CHANGED: Sense = Sense(0x06, 0x28, 0x00);
+
+    fn from_error<E>(error: DiskError<E>) -> Self {
+        match error {
+            DiskError::Busy => Sense::BECOMING_READY,
+            DiskError::OutOfRange => Sense::OUT_OF_RANGE,
+            _ => Sense::MEDIUM_ERROR,
+        }
+    }
+}
+
+/// Where the transport is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Waiting for a command block
+    Command,
+    /// Sending data to the host
+    DataIn,
+    /// Receiving data from the host
+    DataOut,
+    /// Sending the command status
+    Status,
+}
+
+/// What a command does after its command block
+enum Reply {
+    /// Send the first bytes of the buffer
+    Data(usize),
+    /// Send blocks of the disk
+    Read { start: u32, count: u32 },
+    /// Receive blocks of the disk
+    Write { start: u32, count: u32 },
+    /// Done, successfully or not
+    Done,
+}
+
+/// USB mass storage class for a [FlashDisk], see the [module](self)
+/// documentation
+pub struct MscClass<'a, B: UsbBus, F> {
+    iface: InterfaceNumber,
+    read_ep: EndpointOut<'a, B>,
+    write_ep: EndpointIn<'a, B>,
+    disk: &'a FlashDisk<F>,
+    state: State,
+    /// Tag of the current command, returned in its status
+    tag: u32,
+    /// Bytes the host wants to transfer in the data phase
+    expected: u32,
+    /// Bytes transferred in the data phase so far
+    transferred: u32,
+    failed: bool,
+    sense: Sense,
+    /// Hand-overs of the disk the host was told about
+    handovers: u32,
+    /// Data to send, or blocks received but not written yet
+    buf: [u8; BUFFER_SIZE],
+    /// End of the data in the buffer
+    buf_len: usize,
+    /// Bytes of the buffer which were sent already
+    buf_pos: usize,
+    /// The next block to send, or the block at the start of the buffer
+    block: u32,
+    /// Blocks left to send or receive
+    blocks_left: u32,
+    /// The data phase ends with a zero length packet
+    zlp: bool,
+}
+
+impl<'a, B: UsbBus, F: MultiwriteNorFlash> MscClass<'a, B, F> {
+    /// Creates the class for `disk`
+    pub fn new(alloc: &'a UsbBusAllocator<B>, disk: &'a FlashDisk<F>) -> Self {
+        MscClass {
+            iface: alloc.interface(),
+            read_ep: alloc.bulk(PACKET_SIZE as u16),
+            write_ep: alloc.bulk(PACKET_SIZE as u16),
+            disk,
+            state: State::Command,
+            tag: 0,
+            expected: 0,
+            transferred: 0,
+            failed: false,
+            sense: Sense::NONE,
+            handovers: disk.handovers(),
+            buf: [0; BUFFER_SIZE],
+            buf_len: 0,
+            buf_pos: 0,
+            block: 0,
+            blocks_left: 0,
+            zlp: false,
+        }
+    }
+
+    /// Fails the current command with `sense`
+    fn fail(&mut self, sense: Sense) -> Reply {
+        self.failed = true;
+        self.sense = sense;
+        Reply::Done
+    }
+
+    /// Whether the host may access the medium, which it has to be told
+    /// about when it changed
+    fn check_medium(&mut self) -> Result<(), Sense> {
+        if self.disk.owner() != Owner::Host {
+            return Err(Sense::MEDIUM_NOT_PRESENT);
+        }
+        if self.handovers != self.disk.handovers() {
+            self.handovers = self.disk.handovers();
+            return Err(Sense::MEDIUM_CHANGED);
+        }
+        Ok(())
+    }
+
+    /// Puts `data` into the buffer to be sent
+    fn data(&mut self, data: &[u8]) -> Reply {
+        self.buf[..data.len()].copy_from_slice(data);
+        Reply::Data(data.len())
+    }
+
+    /// Runs the SCSI command `cb`
+    fn execute(&mut self, cb: &[u8; 16]) -> Reply {
+        let lba = u32::from_be_bytes([cb[2], cb[3], cb[4], cb[5]]);
+        let count = u32::from(u16::from_be_bytes([cb[7], cb[8]]));
+        let last_block = self.disk.blocks().saturating_sub(1);
+
+        if cb[0] != INQUIRY && cb[0] != REQUEST_SENSE {
+            self.sense = Sense::NONE;
+        }
+        let needs_medium = !matches!(
+            cb[0],
+            INQUIRY | REQUEST_SENSE | START_STOP_UNIT | PREVENT_ALLOW_MEDIUM_REMOVAL
+        );
+        if needs_medium {
+            if let Err(sense) = self.check_medium() {
+                return self.fail(sense);
+            }
+        }
+
+        match cb[0] {
+            TEST_UNIT_READY | PREVENT_ALLOW_MEDIUM_REMOVAL | VERIFY_10 | SYNCHRONIZE_CACHE_10 => {
+                Reply::Done
+            }
+            INQUIRY => {
+                let mut inquiry = [0; 36];
+                // Removable direct access device, SPC-2
+                inquiry[1] = 0x80;
+                inquiry[2] = 0x04;
+                inquiry[3] = 0x02;
+                inquiry[4] = 31;
+                inquiry[8..16].copy_from_slice(b"rp-rs   ");
+                inquiry[16..32].copy_from_slice(b"RP2040 Flash    ");
+                inquiry[32..36].copy_from_slice(b"1.0 ");
+                self.data(&inquiry)
+            }
+            REQUEST_SENSE => {
+                let Sense(key, asc, ascq) = self.sense;
+                self.sense = Sense::NONE;
+                let mut sense = [0; 18];
+                sense[0] = 0x70;
+                sense[2] = key;
+                sense[7] = 10;
+                sense[12] = asc;
+                sense[13] = ascq;
+                self.data(&sense)
+            }
+            MODE_SENSE_6 => self.data(&[3, 0, 0, 0]),
+            MODE_SENSE_10 => self.data(&[0, 6, 0, 0, 0, 0, 0, 0]),
+            START_STOP_UNIT => {
+                // Eject, which the host does before the user unplugs it
+                if cb[4] & 0x03 == 0x02 {
+                    self.disk.eject();
+                }
+                Reply::Done
+            }
+            READ_FORMAT_CAPACITIES => {
+                let mut capacities = [0; 12];
+                capacities[3] = 8;
+                capacities[4..8].copy_from_slice(&self.disk.blocks().to_be_bytes());
+                // Formatted media
+                capacities[8] = 0x02;
+                capacities[9..12].copy_from_slice(&(BLOCK_SIZE as u32).to_be_bytes()[1..]);
+                self.data(&capacities)
+            }
+            READ_CAPACITY_10 => {
+                let mut capacity = [0; 8];
+                capacity[..4].copy_from_slice(&last_block.to_be_bytes());
+                capacity[4..].copy_from_slice(&(BLOCK_SIZE as u32).to_be_bytes());
+                self.data(&capacity)
+            }
+            READ_10 | WRITE_10 => {
+                match lba.checked_add(count) {
+                    Some(end) if end <= self.disk.blocks() => (),
+                    _ => return self.fail(Sense::OUT_OF_RANGE),
+                }
+                // Never move more than the host expects
+                let count = count.min(self.expected / BLOCK_SIZE as u32);
+                if cb[0] == READ_10 {
+                    Reply::Read { start: lba, count }
+                } else {
+                    Reply::Write { start: lba, count }
+                }
+            }
+            _ => self.fail(Sense::INVALID_COMMAND),
+        }
+    }
+
+    /// Handles a command block
+    fn command(&mut self) {
+        let mut cbw = [0; PACKET_SIZE];
+        let len = match self.read_ep.read(&mut cbw) {
+            Ok(len) => len,
+            Err(_) => return,
+        };
+        let word = |i: usize| u32::from_le_bytes([cbw[i], cbw[i + 1], cbw[i + 2], cbw[i + 3]]);
+        if len != CBW_LEN || word(0) != CBW_SIGNATURE {
+            return;
+        }
+        self.tag = word(4);
+        self.expected = word(8);
+        self.transferred = 0;
+        self.failed = false;
+        self.zlp = false;
+        let to_host = cbw[12] & 0x80 != 0;
+        let mut cb = [0; 16];
+        cb.copy_from_slice(&cbw[15..31]);
+
+        self.buf_len = 0;
+        self.buf_pos = 0;
+        self.blocks_left = 0;
+        match self.execute(&cb) {
+            Reply::Data(len) => self.buf_len = len.min(self.expected as usize),
+            Reply::Read { start, count } => {
+                self.block = start;
+                self.blocks_left = count;
+            }
+            Reply::Write { start, count } => {
+                self.block = start;
+                self.blocks_left = count;
+            }
+            Reply::Done => (),
+        }
+
+        self.state = if self.expected == 0 {
+            State::Status
+        } else if to_host {
+            State::DataIn
+        } else {
+            State::DataOut
+        };
+        self.send();
+    }
+
+    /// Receives a packet of data
+    fn data_out(&mut self) {
+        let mut packet = [0; PACKET_SIZE];
+        let len = match self.read_ep.read(&mut packet) {
+            Ok(len) => len,
+            Err(_) => return,
+        };
+        self.transferred += len as u32;
+
+        let block_bytes = self.blocks_left as usize * BLOCK_SIZE;
+        if !self.failed && self.buf_len < block_bytes {
+            let len = len.min(block_bytes - self.buf_len);
+            self.buf[self.buf_len..self.buf_len + len].copy_from_slice(&packet[..len]);
+            self.buf_len += len;
+            // Write once the buffer reaches the end of a sector, or all
+            // blocks are there
+            let sector_end = BUFFER_SIZE - (self.block as usize * BLOCK_SIZE) % BUFFER_SIZE;
+            if self.buf_len == sector_end || self.buf_len == block_bytes {
+                self.flush();
+            }
+        }
+
+        if self.transferred >= self.expected || len < PACKET_SIZE {
+            self.state = State::Status;
+            self.send();
+        }
+    }
+
+    /// Writes the received blocks to the disk
+    fn flush(&mut self) {
+        let blocks = (self.buf_len / BLOCK_SIZE) as u32;
+        let result = self
+            .disk
+            .write_blocks(Owner::Host, self.block, &self.buf[..self.buf_len]);
+        if let Err(e) = result {
+            self.fail(Sense::from_error(e));
+        }
+        self.block += blocks;
+        self.blocks_left -= blocks;
+        self.buf_len = 0;
+    }
+
+    /// Sends the next packet, if the endpoint is free
+    fn send(&mut self) {
+        if self.state == State::DataIn {
+            if self.buf_pos == self.buf_len && self.blocks_left > 0 && !self.failed {
+                let result =
+                    self.disk
+                        .read_blocks(Owner::Host, self.block, &mut self.buf[..BLOCK_SIZE]);
+                match result {
+                    Ok(()) => {
+                        self.block += 1;
+                        self.blocks_left -= 1;
+                        self.buf_pos = 0;
+                        self.buf_len = BLOCK_SIZE;
+                    }
+                    Err(e) => {
+                        self.fail(Sense::from_error(e));
+                    }
+                }
+            }
+
+            if self.buf_pos < self.buf_len {
+                let end = self.buf_len.min(self.buf_pos + PACKET_SIZE);
+                if let Ok(len) = self.write_ep.write(&self.buf[self.buf_pos..end]) {
+                    self.buf_pos += len;
+                    self.transferred += len as u32;
+                }
+                return;
+            }
+
+            // The host waits for more data, unless the last packet was short
+            if self.transferred < self.expected
+                && self.transferred as usize & (PACKET_SIZE - 1) == 0
+                && !self.zlp
+            {
+                if self.write_ep.write(&[]).is_ok() {
+                    self.zlp = true;
+                }
+                return;
+            }
+            self.state = State::Status;
+        }
+
+        if self.state == State::Status {
+            let mut csw = [0; 13];
+            csw[0..4].copy_from_slice(&CSW_SIGNATURE.to_le_bytes());
+            csw[4..8].copy_from_slice(&self.tag.to_le_bytes());
+            let residue = self.expected.saturating_sub(self.transferred);
+            csw[8..12].copy_from_slice(&residue.to_le_bytes());
+            csw[12] = self.failed as u8;
+            if self.write_ep.write(&csw).is_ok() {
+                self.state = State::Command;
+            }
+        }
+    }
+
+    fn reset_transport(&mut self) {
+        self.state = State::Command;
+        self.buf_len = 0;
+        self.buf_pos = 0;
+        self.blocks_left = 0;
+    }
+}
+
+impl<B: UsbBus, F: MultiwriteNorFlash> UsbClass<B> for MscClass<'_, B, F> {
+    fn get_configuration_descriptors(
+        &self,
+        writer: &mut DescriptorWriter,
+    ) -> usb_device::Result<()> {
+        writer.interface(self.iface, CLASS_MSC, SUBCLASS_SCSI, PROTOCOL_BBB)?;
+        writer.endpoint(&self.read_ep)?;
+        writer.endpoint(&self.write_ep)?;
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.reset_transport();
+    }
+
+    fn control_in(&mut self, xfer: ControlIn<B>) {
+        let req = xfer.request();
+        if req.request_type == RequestType::Class
+            && req.recipient == Recipient::Interface
+            && req.index == u16::from(u8::from(self.iface))
+            && req.request == REQ_GET_MAX_LUN
+        {
+            // A single logical unit
+            xfer.accept_with(&[0]).ok();
+        }
+    }
+
+    fn control_out(&mut self, xfer: ControlOut<B>) {
+        let req = xfer.request();
+        if req.request_type == RequestType::Class
+            && req.recipient == Recipient::Interface
+            && req.index == u16::from(u8::from(self.iface))
+            && req.request == REQ_BULK_ONLY_RESET
+        {
+            self.reset_transport();
+            xfer.accept().ok();
+        }
+    }
+
+    fn endpoint_out(&mut self, addr: EndpointAddress) {
+        if addr != self.read_ep.address() {
+            return;
+        }
+        match self.state {
+            State::Command => self.command(),
+            State::DataOut => self.data_out(),
+            // Wait until the status went out
+            State::DataIn | State::Status => (),
+        }
+    }
+
+    fn endpoint_in_complete(&mut self, addr: EndpointAddress) {
+        if addr == self.write_ep.address() {
+            self.send();
+        }
+    }
+
+    fn poll(&mut self) {
+        // Retry a packet which didn't fit into the endpoint before
+        if self.state == State::DataIn || self.state == State::Status {
+            self.send();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::collections::VecDeque;
+    use std::sync::{Arc, Mutex};
+    use std::vec::Vec;
+
+    use usb_device::bus::PollResult;
+    use usb_device::prelude::*;
+    use usb_device::UsbDirection;
+
+    use super::*;
+    use crate::flash::RamFlash;
+
+    type TestFlash = RamFlash<{ 64 * 1024 }>;
+
+    /// Packets on the bulk endpoints
+    #[derive(Default)]
+    struct Packets {
+        /// Sent by the host and not read yet
+        out: VecDeque<Vec<u8>>,
+        /// Written by the class and not received yet
+        written: Option<Vec<u8>>,
+    }
+
+    /// A bus which only carries the packets of the bulk endpoints
+    struct FakeBus(Arc<Mutex<Packets>>);
+
+    impl UsbBus for FakeBus {
+        fn alloc_ep(
+            &mut self,
+            ep_dir: UsbDirection,
+            ep_addr: Option<EndpointAddress>,
+            ep_type: EndpointType,
+            _max_packet_size: u16,
+            _interval: u8,
+        ) -> usb_device::Result<EndpointAddress> {
+            let index = if ep_type == EndpointType::Control {
+                0
+            } else {
+                1
+            };
+            Ok(ep_addr.unwrap_or(EndpointAddress::from_parts(index, ep_dir)))
+        }
+
+        fn enable(&mut self) {}
+
+        fn reset(&self) {}
+
+        fn set_device_address(&self, _addr: u8) {}
+
+        fn write(&self, _ep_addr: EndpointAddress, buf: &[u8]) -> usb_device::Result<usize> {
+            assert!(buf.len() <= PACKET_SIZE);
+            let mut packets = self.0.lock().unwrap();
+            if packets.written.is_some() {
+                return Err(UsbError::WouldBlock);
+            }
+            packets.written = Some(buf.to_vec());
+            Ok(buf.len())
+        }
+
+        fn read(&self, _ep_addr: EndpointAddress, buf: &mut [u8]) -> usb_device::Result<usize> {
+            let packet = self.0.lock().unwrap().out.pop_front();
+            let packet = packet.ok_or(UsbError::WouldBlock)?;
+            buf[..packet.len()].copy_from_slice(&packet);
+            Ok(packet.len())
+        }
+
+        fn set_stalled(&self, _ep_addr: EndpointAddress, _stalled: bool) {}
+
+        fn is_stalled(&self, _ep_addr: EndpointAddress) -> bool {
+            false
+        }
+
+        fn suspend(&self) {}
+
+        fn resume(&self) {}
+
+        fn poll(&self) -> PollResult {
+            PollResult::None
+        }
+    }
+
+    /// The command status
+    #[derive(Debug, PartialEq)]
+    struct Status {
+        residue: u32,
+        failed: bool,
+    }
+
+    const OK: Status = Status {
+        residue: 0,
+        failed: false,
+    };
+
+    const FAILED: Status = Status {
+        residue: 0,
+        failed: true,
+    };
+
+    /// The USB host, talking to the class
+    struct Host<'a> {
+        packets: Arc<Mutex<Packets>>,
+        msc: MscClass<'a, FakeBus, TestFlash>,
+        tag: u32,
+    }
+
+    fn bus() -> (UsbBusAllocator<FakeBus>, Arc<Mutex<Packets>>) {
+        let packets = Arc::new(Mutex::new(Packets::default()));
+        (UsbBusAllocator::new(FakeBus(packets.clone())), packets)
+    }
+
+    fn disk() -> FlashDisk<TestFlash> {
+        FlashDisk::new(TestFlash::new(), 0, 64 * 1024).unwrap()
+    }
+
+    /// A command block of a READ_10, WRITE_10 or another command
+    fn cb(op: u8, lba: u32, count: u16) -> [u8; 10] {
+        let mut cb = [0; 10];
+        cb[0] = op;
+        cb[2..6].copy_from_slice(&lba.to_be_bytes());
+        cb[7..9].copy_from_slice(&count.to_be_bytes());
+        cb
+    }
+
+    fn blocks(count: usize, seed: u8) -> Vec<u8> {
+        (0..count * BLOCK_SIZE)
+            .map(|i| (i as u8).wrapping_mul(7).wrapping_add(seed))
+            .collect()
+    }
+
+    impl<'a> Host<'a> {
+        fn new(
+            alloc: &'a UsbBusAllocator<FakeBus>,
+            packets: Arc<Mutex<Packets>>,
+            disk: &'a FlashDisk<TestFlash>,
+        ) -> Self {
+            let msc = MscClass::new(alloc, disk);
+            // Enables the bus, which the endpoints need
+            UsbDeviceBuilder::new(alloc, UsbVidPid(0x16c0, 0x27dd)).build();
+            Host {
+                packets,
+                msc,
+                tag: 0,
+            }
+        }
+
+        fn send(&mut self, packet: &[u8]) {
+            self.packets.lock().unwrap().out.push_back(packet.to_vec());
+            self.msc.endpoint_out(self.msc.read_ep.address());
+        }
+
+        /// Takes the packets until the class has nothing more to send
+        fn receive(&mut self) -> Vec<Vec<u8>> {
+            let mut received = Vec::new();
+            loop {
+                let packet = self.packets.lock().unwrap().written.take();
+                match packet {
+                    Some(packet) => received.push(packet),
+                    None => return received,
+                }
+                self.msc.endpoint_in_complete(self.msc.write_ep.address());
+            }
+        }
+
+        fn command(&mut self, expected: u32, to_host: bool, cb: &[u8]) {
+            self.tag += 1;
+            let mut cbw = [0; CBW_LEN];
+            cbw[0..4].copy_from_slice(&CBW_SIGNATURE.to_le_bytes());
+            cbw[4..8].copy_from_slice(&self.tag.to_le_bytes());
+            cbw[8..12].copy_from_slice(&expected.to_le_bytes());
+            cbw[12] = if to_host { 0x80 } else { 0 };
+            cbw[14] = cb.len() as u8;
+            cbw[15..15 + cb.len()].copy_from_slice(cb);
+            self.send(&cbw);
+        }
+
+        fn status(&self, csw: &[u8]) -> Status {
+            assert_eq!(csw.len(), 13);
+            assert_eq!(csw[0..4], CSW_SIGNATURE.to_le_bytes());
+            assert_eq!(csw[4..8], self.tag.to_le_bytes());
+            Status {
+                residue: u32::from_le_bytes([csw[8], csw[9], csw[10], csw[11]]),
+                failed: csw[12] != 0,
+            }
+        }
+
+        /// Runs a command which sends the host up to `expected` bytes
+        fn data_in(&mut self, expected: u32, cb: &[u8]) -> (Vec<u8>, Status) {
+            self.command(expected, true, cb);
+            let mut packets = self.receive();
+            let csw = packets.pop().expect("no status");
+            let status = self.status(&csw);
+            (packets.concat(), status)
+        }
+
+        /// Runs a command which sends `data` to the device
+        fn data_out(&mut self, cb: &[u8], data: &[u8]) -> Status {
+            self.command(data.len() as u32, false, cb);
+            for packet in data.chunks(PACKET_SIZE) {
+                assert!(self.receive().is_empty(), "status before the data");
+                self.send(packet);
+            }
+            let packets = self.receive();
+            assert_eq!(packets.len(), 1);
+            self.status(&packets[0])
+        }
+
+        fn run(&mut self, cb: &[u8]) -> Status {
+            self.data_in(0, cb).1
+        }
+
+        fn sense(&mut self) -> Sense {
+            let (sense, status) = self.data_in(18, &[REQUEST_SENSE, 0, 0, 0, 18, 0]);
+            assert_eq!((sense.len(), status), (18, OK));
+            Sense(sense[2], sense[12], sense[13])
+        }
+
+        /// Tells the host about the medium, after the disk was given to it
+        fn mount(&mut self) {
+            assert_eq!(self.run(&[TEST_UNIT_READY; 6]), FAILED);
+            assert_eq!(self.sense(), Sense::MEDIUM_CHANGED);
+            assert_eq!(self.run(&[TEST_UNIT_READY; 6]), OK);
+        }
+    }
+
+    #[test]
+    fn no_medium_while_the_firmware_owns_the_disk() {
+        let disk = disk();
+        let (alloc, packets) = bus();
+        let mut host = Host::new(&alloc, packets, &disk);
+
+        let (inquiry, status) = host.data_in(36, &[INQUIRY, 0, 0, 0, 36, 0]);
+        assert_eq!((inquiry.len(), status), (36, OK));
+        assert_eq!(inquiry[..2], [0x00, 0x80]);
+        assert_eq!(&inquiry[8..32], b"rp-rs   RP2040 Flash    ");
+
+        assert_eq!(host.run(&[TEST_UNIT_READY; 6]), FAILED);
+        assert_eq!(host.sense(), Sense::MEDIUM_NOT_PRESENT);
+        assert_eq!(host.sense(), Sense::NONE);
+        let (_, status) = host.data_in(8, &cb(READ_CAPACITY_10, 0, 0));
+        assert!(status.failed);
+    }
+
+    #[test]
+    fn describes_the_medium() {
+        let disk = disk();
+        let (alloc, packets) = bus();
+        let mut host = Host::new(&alloc, packets, &disk);
+        disk.give_to_host();
+        host.mount();
+
+        let (capacity, status) = host.data_in(8, &cb(READ_CAPACITY_10, 0, 0));
+        assert_eq!(
+            (&capacity[..], status),
+            (&[0, 0, 0, 127, 0, 0, 2, 0][..], OK)
+        );
+        let (capacities, status) = host.data_in(252, &cb(READ_FORMAT_CAPACITIES, 0, 252));
+        assert_eq!(capacities, [0, 0, 0, 8, 0, 0, 0, 128, 2, 0, 2, 0]);
+        assert_eq!(
+            status,
+            Status {
+                residue: 240,
+                failed: false
+            }
+        );
+        let (mode, status) = host.data_in(192, &[MODE_SENSE_6, 0, 0x3f, 0, 192, 0]);
+        assert_eq!((&mode[..], status.failed), (&[3, 0, 0, 0][..], false));
+    }
+
+    #[test]
+    fn writes_and_reads_across_sectors() {
+        let disk = disk();
+        let (alloc, packets) = bus();
+        let mut host = Host::new(&alloc, packets, &disk);
+        disk.give_to_host();
+        host.mount();
+
+        let data = blocks(4, 1);
+        assert_eq!(host.data_out(&cb(WRITE_10, 6, 4), &data), OK);
+        let mut written = std::vec![0; data.len()];
+        disk.read_blocks(Owner::Host, 6, &mut written).unwrap();
+        assert_eq!(written, data);
+
+        let (read, status) = host.data_in(2048, &cb(READ_10, 6, 4));
+        assert_eq!((read, status), (data, OK));
+    }
+
+    #[test]
+    fn short_data_ends_with_a_zero_length_packet() {
+        let disk = disk();
+        let (alloc, packets) = bus();
+        let mut host = Host::new(&alloc, packets, &disk);
+        disk.give_to_host();
+        host.mount();
+
+        host.command(1024, true, &cb(READ_10, 0, 1));
+        let packets = host.receive();
+        assert_eq!(packets.len(), 8 + 2);
+        assert!(packets[8].is_empty());
+        let status = host.status(&packets[9]);
+        assert_eq!(
+            status,
+            Status {
+                residue: 512,
+                failed: false
+            }
+        );
+
+        // The host never gets more than it expects
+        let (read, status) = host.data_in(512, &cb(READ_10, 0, 2));
+        assert_eq!((read.len(), status), (512, OK));
+    }
+
+    #[test]
+    fn blocks_outside_fail() {
+        let disk = disk();
+        let (alloc, packets) = bus();
+        let mut host = Host::new(&alloc, packets, &disk);
+        disk.give_to_host();
+        host.mount();
+
+        let (read, status) = host.data_in(1024, &cb(READ_10, 127, 2));
+        assert!(read.is_empty());
+        assert_eq!(
+            status,
+            Status {
+                residue: 1024,
+                failed: true
+            }
+        );
+        assert_eq!(host.sense(), Sense::OUT_OF_RANGE);
+
+        let status = host.data_out(&cb(WRITE_10, u32::MAX, 1), &blocks(1, 2));
+        assert_eq!((status, host.sense()), (FAILED, Sense::OUT_OF_RANGE));
+    }
+
+    #[test]
+    fn writes_without_medium_are_dropped() {
+        let disk = disk();
+        let (alloc, packets) = bus();
+        let mut host = Host::new(&alloc, packets, &disk);
+
+        assert_eq!(host.data_out(&cb(WRITE_10, 0, 2), &blocks(2, 3)), FAILED);
+        assert_eq!(host.sense(), Sense::MEDIUM_NOT_PRESENT);
+        let mut block = [0; BLOCK_SIZE];
+        disk.read_blocks(Owner::Firmware, 0, &mut block).unwrap();
+        assert!(block.iter().all(|&b| b == 0xff));
+    }
+
+    #[test]
+    fn eject_gives_the_disk_back() {
+        let disk = disk();
+        let (alloc, packets) = bus();
+        let mut host = Host::new(&alloc, packets, &disk);
+        disk.give_to_host();
+        host.mount();
+
+        assert_eq!(host.run(&[PREVENT_ALLOW_MEDIUM_REMOVAL, 0, 0, 0, 0, 0]), OK);
+        assert_eq!(host.run(&[START_STOP_UNIT, 0, 0, 0, 0x02, 0]), OK);
+        assert_eq!(disk.owner(), Owner::Firmware);
+        assert_eq!(host.run(&[TEST_UNIT_READY; 6]), FAILED);
+        assert_eq!(host.sense(), Sense::MEDIUM_NOT_PRESENT);
+
+        // Until the firmware hands it over again
+        disk.give_to_host();
+        host.mount();
+    }
+
+    #[test]
+    fn unknown_commands_fail() {
+        let disk = disk();
+        let (alloc, packets) = bus();
+        let mut host = Host::new(&alloc, packets, &disk);
+        disk.give_to_host();
+        host.mount();
+
+        assert_eq!(host.run(&[0x04, 0, 0, 0, 0, 0]), FAILED);
+        assert_eq!(host.sense(), Sense::INVALID_COMMAND);
+    }
+
+    #[test]
+    fn invalid_command_blocks_are_ignored() {
+        let disk = disk();
+        let (alloc, packets) = bus();
+        let mut host = Host::new(&alloc, packets, &disk);
+
+        host.send(&[0; CBW_LEN]);
+        let mut short = [0; CBW_LEN - 1];
+        short[..4].copy_from_slice(&CBW_SIGNATURE.to_le_bytes());
+        host.send(&short);
+        assert!(host.receive().is_empty());
+
+        let (inquiry, status) = host.data_in(36, &[INQUIRY, 0, 0, 0, 36, 0]);
+        assert_eq!((inquiry.len(), status), (36, OK));
+    }
+
+    #[test]
+    fn reset_aborts_a_transfer() {
+        let disk = disk();
+        let (alloc, packets) = bus();
+        let mut host = Host::new(&alloc, packets.clone(), &disk);
+        disk.give_to_host();
+        host.mount();
+
+        // The host gives up after the first packet
+        host.command(1024, true, &cb(READ_10, 0, 2));
+        assert!(packets.lock().unwrap().written.take().is_some());
+        host.msc.reset();
+
+        assert_eq!(host.run(&[TEST_UNIT_READY; 6]), OK);
+    }
+}
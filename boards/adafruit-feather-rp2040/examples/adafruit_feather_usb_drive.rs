@@ -0,0 +1,140 @@
+//! # Adafruit Feather USB Drive Example
+//!
+//! Keeps a log file in the flash of a Adafruit Feather RP2040, and shows the
+//! flash to the computer as a USB drive.
+//!
+//! The upper 4 MB of the flash hold a FAT16 filesystem, which is created on
+//! the first start. The firmware appends a line to `LOG.TXT`, then hands the
+//! drive to the computer, where the file can be read. Ejecting the drive on
+//! the computer hands it back, and the firmware appends another line before
+//! handing it over again. The red LED is on while the computer has the drive.
+//!
+//! The program must stay clear of the filesystem, so build it with the upper
+//! half of the flash reserved:
+//!
+//! ```sh
+//! FLASH_RESERVED_BYTES=4M cargo run --release --example adafruit_feather_usb_drive
+//! ```
+//!
+//! See the `Cargo.toml` file for Copyright and license details.
+
+#![no_std]
+#![no_main]
+
+// The macro for our start-up function
+use adafruit_feather_rp2040::entry;
+
+// Ensure we halt the program on panic (if we don't mention this crate it won't
+// be linked)
+use panic_halt as _;
+
+use embedded_sdmmc::filesystem::Mode;
+use embedded_sdmmc::{BlockDevice, TimeSource, Timestamp, VolumeIdx, VolumeManager};
+
+use adafruit_feather_rp2040::board_common::disk::Owner;
+use adafruit_feather_rp2040::board_common::{
+    ActiveHigh, Core1, Flash, FlashDisk, MscClass, StatusLed,
+};
+use adafruit_feather_rp2040::hal;
+
+// USB Device support
+use usb_device::{class_prelude::*, prelude::*};
+
+/// The board has no clock, so all files are dated to 1970
+struct DummyTimesource;
+
+impl TimeSource for DummyTimesource {
+    fn get_timestamp(&self) -> Timestamp {
+        Timestamp {
+            year_since_1970: 0,
+            zero_indexed_month: 0,
+            zero_indexed_day: 0,
+            hours: 0,
+            minutes: 0,
+            seconds: 0,
+        }
+    }
+}
+
+/// Entry point to our bare-metal application.
+///
+/// The `#[entry]` macro ensures the Cortex-M start-up code calls this function
+/// as soon as all global variables are initialised.
+///
+/// The function sets up the filesystem and the USB drive, then hands the
+/// drive back and forth between the firmware and the computer.
+#[entry]
+fn main() -> ! {
+    let mut board = adafruit_feather_rp2040::Board::take().unwrap();
+    let mut led = ActiveHigh::new(board.pins.d13.into_push_pull_output());
+
+    // Core 1 isn't started, so nothing else runs from the flash while the
+    // disk writes to it
+    let flash = Flash::new(adafruit_feather_rp2040::FLASH_SIZE_BYTES, Core1::Stopped);
+    let disk = FlashDisk::new(
+        flash,
+        adafruit_feather_rp2040::FLASH_RESERVED_OFFSET,
+        adafruit_feather_rp2040::FLASH_RESERVED_BYTES,
+    )
+    .unwrap();
+    let mut volume_mgr = VolumeManager::new(&disk, DummyTimesource);
+
+    // Create the filesystem on the first start
+    if volume_mgr.open_volume(VolumeIdx(0)).is_err() {
+        disk.format(b"FEATHER").unwrap();
+    }
+    append_log(&mut volume_mgr, b"Started\r\n").unwrap();
+
+    // Set up the USB driver
+    let usb_bus = UsbBusAllocator::new(hal::usb::UsbBus::new(
+        board.peripherals.USBCTRL_REGS,
+        board.peripherals.USBCTRL_DPRAM,
+        board.clocks.usb_clock,
+        true,
+        &mut board.peripherals.RESETS,
+    ));
+
+    // Set up the USB Mass Storage Class driver
+    let mut msc = MscClass::new(&usb_bus, &disk);
+
+    // Create a USB device with a fake VID and PID
+    let mut usb_dev = UsbDeviceBuilder::new(&usb_bus, UsbVidPid(0x16c0, 0x27dd))
+        .strings(&[StringDescriptors::default()
+            .manufacturer("Fake company")
+            .product("Flash drive")
+            .serial_number("TEST")])
+        .unwrap()
+        .build();
+
+    disk.give_to_host();
+    loop {
+        usb_dev.poll(&mut [&mut msc]);
+
+        // The drive was ejected
+        if disk.owner() == Owner::Firmware {
+            led.off();
+            // The computer may have changed anything, so the volume is
+            // opened again
+            let _ = append_log(&mut volume_mgr, b"Handed back\r\n");
+            disk.give_to_host();
+            led.on();
+        }
+    }
+}
+
+/// Appends `line` to `LOG.TXT` in the root directory
+fn append_log<D: BlockDevice, T: TimeSource>(
+    volume_mgr: &mut VolumeManager<D, T>,
+    line: &[u8],
+) -> Result<(), embedded_sdmmc::Error<D::Error>> {
+    let mut volume = volume_mgr.open_volume(VolumeIdx(0))?;
+    let dir = volume_mgr.open_root_dir(&volume)?;
+    let mut file =
+        volume_mgr.open_file_in_dir(&mut volume, &dir, "LOG.TXT", Mode::ReadWriteCreateOrAppend)?;
+    volume_mgr.write(&mut volume, &mut file, line)?;
+    volume_mgr.close_file(&volume, file)?;
+    volume_mgr.close_dir(&volume, dir);
+    Ok(())
+}
+
+// End of file
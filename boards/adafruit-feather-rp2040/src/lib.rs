@@ -17,7 +17,9 @@ pub static BOOT2_FIRMWARE: [u8; 256] = rp2040_boot2::BOOT_LOADER_GD25Q64CS;
 
 pub use hal::pac;
 
-hal::bsp_pins!(
+board_common::bsp_pins!(
+    embassy: crate::embassy::Pins;
+
     Gpio0 {
         name: tx,
         aliases: { FunctionUart, PullNone: UartTx }
@@ -41,7 +43,13 @@ hal::bsp_pins!(
     Gpio10 { name: d10 },
     Gpio11 { name: d11 },
     Gpio12 { name: d12 },
-    Gpio13 { name: d13 },
+    Gpio13 {
+        name: d13,
+        aliases: {
+            /// Output alias for pin [crate::Pins::d13].
+            FunctionSioOutput, PullNone: LedPin
+        }
+    },
     Gpio16 { name: neopixel },
     Gpio18 {
         name: sclk,
@@ -64,3 +72,16 @@ hal::bsp_pins!(
 );
 
 pub const XOSC_CRYSTAL_FREQ: u32 = 12_000_000;
+
+// FLASH_SIZE_BYTES and the reserved end of the flash, see build.rs
+include!(concat!(env!("OUT_DIR"), "/flash.rs"));
+
+#[cfg(feature = "embassy")]
+pub mod embassy;
+
+pub use board_common;
+
+board_common::board_pins!(Pins, XOSC_CRYSTAL_FREQ, activity_led: 13);
+
+/// The red LED on D13, see [board_common::StatusLed]
+pub type Led = board_common::ActiveHigh<LedPin>;
@@ -0,0 +1,8 @@
+//! Generates the memory layout of the board for the linker, and the
+//! `FLASH_*` constants, see [board_build]
+
+use board_build::{Defmt, MB};
+
+fn main() {
+    board_build::generate(8 * MB, Defmt::WithFeature);
+}
@@ -0,0 +1,150 @@
+//! # KB2040 USB Log Example
+//!
+//! Logs with the `log` crate over the USB serial port of the KB2040, without
+//! a debug probe. Build it with the `log-usb` feature:
+//!
+//! ```console
+//! rp-hal-boards/boards/adafruit-kb2040$ cargo run --release --features log-usb --example adafruit_kb2040_usb_log
+//! ```
+//!
+//! The USB driver runs in the USB interrupt, which also sends the logs. Open
+//! the serial port with any terminal, the records of the start-up are still
+//! there:
+//!
+//! ```text
+//! INFO  adafruit_kb2040_usb_log: up and running
+//! DEBUG adafruit_kb2040_usb_log: tick 0, 0 logs dropped
+//! ```
+//!
+//! See the `Cargo.toml` file for Copyright and license details.
+
+#![no_std]
+#![no_main]
+#![allow(static_mut_refs)]
+
+use adafruit_kb2040 as bsp;
+
+// The macro for our start-up function
+use bsp::entry;
+
+// The macro for marking our interrupt functions
+use bsp::hal::pac::interrupt;
+
+// Ensure we halt the program on panic (if we don't mention this crate it won't
+// be linked)
+use panic_halt as _;
+
+// A shorter alias for the Peripheral Access Crate, which provides low-level
+// register access
+use bsp::hal::pac;
+
+// A shorter alias for the Hardware Abstraction Layer, which provides
+// higher-level drivers.
+use bsp::hal;
+
+// The logger over USB serial shared by all boards
+use bsp::board_common::usb_log;
+
+// USB Device support
+use usb_device::{class_prelude::*, prelude::*};
+
+// USB Communications Class Device support
+use usbd_serial::SerialPort;
+
+/// The USB Device Driver (shared with the interrupt).
+static mut USB_DEVICE: Option<UsbDevice<hal::usb::UsbBus>> = None;
+
+/// The USB Bus Driver (shared with the interrupt).
+static mut USB_BUS: Option<UsbBusAllocator<hal::usb::UsbBus>> = None;
+
+/// The USB Serial Device Driver (shared with the interrupt).
+static mut USB_SERIAL: Option<SerialPort<hal::usb::UsbBus>> = None;
+
+/// Entry point to our bare-metal application.
+///
+/// The `#[entry]` macro ensures the Cortex-M start-up code calls this function
+/// as soon as all global variables are initialised.
+///
+/// The function sets up the logger and USB Serial, then logs once a second.
+#[entry]
+fn main() -> ! {
+    // Note (safety): nothing has logged yet
+    unsafe { usb_log::init_log(log::LevelFilter::Debug) };
+
+    let mut board = bsp::Board::take().unwrap();
+
+    // Set up the USB driver
+    let usb_bus = UsbBusAllocator::new(hal::usb::UsbBus::new(
+        board.peripherals.USBCTRL_REGS,
+        board.peripherals.USBCTRL_DPRAM,
+        board.clocks.usb_clock,
+        true,
+        &mut board.peripherals.RESETS,
+    ));
+    unsafe {
+        // Note (safety): This is safe as interrupts haven't been started yet
+        USB_BUS = Some(usb_bus);
+    }
+
+    // Grab a reference to the USB Bus allocator. We are promising to the
+    // compiler not to take mutable access to this global variable whilst this
+    // reference exists!
+    let bus_ref = unsafe { USB_BUS.as_ref().unwrap() };
+
+    // Set up the USB Communications Class Device driver
+    let serial = SerialPort::new(bus_ref);
+    unsafe {
+        USB_SERIAL = Some(serial);
+    }
+
+    // Create a USB device with a fake VID and PID
+    let usb_dev = UsbDeviceBuilder::new(bus_ref, UsbVidPid(0x16c0, 0x27dd))
+        .strings(&[StringDescriptors::default()
+            .manufacturer("Fake company")
+            .product("Serial port")
+            .serial_number("TEST")])
+        .unwrap()
+        .device_class(2) // from: https://www.usb.org/defined-class-codes
+        .build();
+    unsafe {
+        // Note (safety): This is safe as interrupts haven't been started yet
+        USB_DEVICE = Some(usb_dev);
+    }
+
+    // Enable the USB interrupt
+    unsafe {
+        pac::NVIC::unmask(hal::pac::Interrupt::USBCTRL_IRQ);
+    };
+
+    log::info!("up and running");
+
+    // Log from the main thread, the interrupt sends the records
+    let mut tick = 0u32;
+    loop {
+        log::debug!("tick {}, {} logs dropped", tick, usb_log::dropped());
+        tick = tick.wrapping_add(1);
+        board.delay.delay_ms(1000);
+    }
+}
+
+/// This function is called whenever the USB Hardware generates an Interrupt
+/// Request, and after every log.
+#[allow(non_snake_case)]
+#[interrupt]
+unsafe fn USBCTRL_IRQ() {
+    // Grab the global objects. This is OK as we only access them under interrupt.
+    let usb_dev = USB_DEVICE.as_mut().unwrap();
+    let serial = USB_SERIAL.as_mut().unwrap();
+
+    // Poll the USB driver with all of our supported USB Classes, and throw
+    // away whatever the host sends
+    if usb_dev.poll(&mut [serial]) {
+        let mut buf = [0u8; 64];
+        let _ = serial.read(&mut buf);
+    }
+
+    // Send the new logs
+    usb_log::drain(serial);
+}
+
+// End of file
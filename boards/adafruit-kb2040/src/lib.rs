@@ -17,7 +17,9 @@ pub static BOOT2_FIRMWARE: [u8; 256] = rp2040_boot2::BOOT_LOADER_W25Q080;
 
 pub use hal::pac;
 
-hal::bsp_pins!(
+board_common::bsp_pins!(
+    embassy: crate::embassy::Pins;
+
     Gpio0 {
         name: tx,
         aliases: { FunctionUart, PullNone: UartTx }
@@ -64,3 +66,13 @@ hal::bsp_pins!(
 );
 
 pub const XOSC_CRYSTAL_FREQ: u32 = 12_000_000;
+
+// FLASH_SIZE_BYTES and the reserved end of the flash, see build.rs
+include!(concat!(env!("OUT_DIR"), "/flash.rs"));
+
+#[cfg(feature = "embassy")]
+pub mod embassy;
+
+pub use board_common;
+
+board_common::board_pins!(Pins, XOSC_CRYSTAL_FREQ);
@@ -0,0 +1,46 @@
+//! # MacroPad Bootloader Example
+//!
+//! The bootloader of the A/B layout, which swaps in the new images received
+//! by the `adafruit_macropad_dfu` example, and brings back the previous one
+//! if a new image doesn't come up.
+//!
+//! It lives in the first 32K of the flash, so build it with its own layout,
+//! and copy it to the board once, in BOOTSEL mode:
+//!
+//! ```sh
+//! FLASH_LAYOUT=bootloader cargo run --release --example adafruit_macropad_bootloader
+//! ```
+//!
+//! See the `Cargo.toml` file for Copyright and license details.
+
+#![no_std]
+#![no_main]
+
+// The macro for our start-up function
+use adafruit_macropad::entry;
+
+// Ensure we halt the program on panic (if we don't mention this crate it won't
+// be linked)
+use panic_halt as _;
+
+use adafruit_macropad::board_common::{Bootloader, Core1, Flash};
+use adafruit_macropad::hal::{pac, Watchdog};
+
+/// Entry point to our bare-metal application.
+///
+/// The `#[entry]` macro ensures the Cortex-M start-up code calls this function
+/// as soon as all global variables are initialised.
+///
+/// The function finishes a pending update, then jumps to the application in
+/// the active slot. The clocks are left alone, the application sets them up.
+#[entry]
+fn main() -> ! {
+    let pac = pac::Peripherals::take().unwrap();
+    let mut watchdog = Watchdog::new(pac.WATCHDOG);
+
+    let flash = Flash::new(adafruit_macropad::FLASH_SIZE_BYTES, Core1::Stopped);
+    let bootloader = Bootloader::new(flash, adafruit_macropad::FLASH_PARTITIONS).unwrap();
+    bootloader.boot(&mut watchdog)
+}
+
+// End of file
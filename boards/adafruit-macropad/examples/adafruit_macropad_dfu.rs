@@ -0,0 +1,125 @@
+//! # MacroPad DFU Example
+//!
+//! Runs in the active slot of the A/B layout, and takes new firmware over
+//! USB DFU, so a MacroPad in the field can be updated without pressing any
+//! buttons.
+//!
+//! With the `adafruit_macropad_bootloader` example on the board, copy this
+//! one to the board in BOOTSEL mode:
+//!
+//! ```sh
+//! FLASH_LAYOUT=app cargo run --release --example adafruit_macropad_dfu
+//! ```
+//!
+//! Later versions are sent over USB, as UF2 file:
+//!
+//! ```sh
+//! FLASH_LAYOUT=app cargo build --release --example adafruit_macropad_dfu
+//! elf2uf2-rs ../../target/thumbv6m-none-eabi/release/examples/adafruit_macropad_dfu app.uf2
+//! dfu-util -D app.uf2
+//! ```
+//!
+//! The new version blinks the LED until the computer has set up its USB
+//! device, then keeps it on. If that doesn't happen within 5 seconds, the
+//! watchdog resets the board, and the bootloader brings back the previous
+//! version.
+//!
+//! See the `Cargo.toml` file for Copyright and license details.
+
+#![no_std]
+#![no_main]
+
+// The macro for our start-up function
+use adafruit_macropad::entry;
+
+// Ensure we halt the program on panic (if we don't mention this crate it won't
+// be linked)
+use panic_halt as _;
+
+use adafruit_macropad::board_common::boot::UpdateState;
+use adafruit_macropad::board_common::{
+    ActiveHigh, Core1, DfuClass, FirmwareUpdater, Flash, StatusLed,
+};
+use adafruit_macropad::hal;
+use adafruit_macropad::hal::fugit::MicrosDurationU32;
+
+// USB Device support
+use usb_device::{class_prelude::*, prelude::*};
+
+/// Period of the watchdog, once the image is known to work
+const WATCHDOG_PERIOD: MicrosDurationU32 = MicrosDurationU32::secs(4);
+
+/// Entry point to our bare-metal application.
+///
+/// The `#[entry]` macro ensures the Cortex-M start-up code calls this function
+/// as soon as all global variables are initialised.
+///
+/// The function sets up the USB DFU class, marks the image booted once USB
+/// works, and resets after a download for the bootloader to swap it in.
+#[entry]
+fn main() -> ! {
+    let mut board = adafruit_macropad::Board::take().unwrap();
+    let mut led = ActiveHigh::new(board.pins.led.into_push_pull_output());
+
+    // Core 1 isn't started, so nothing else runs from the flash while the
+    // updater writes to it
+    let flash = Flash::new(adafruit_macropad::FLASH_SIZE_BYTES, Core1::Stopped);
+    let mut updater = FirmwareUpdater::new(flash, adafruit_macropad::FLASH_PARTITIONS).unwrap();
+
+    // While the image is tested, the watchdog of the bootloader runs out
+    let mut booted = updater.state() != Ok(UpdateState::Testing);
+    if booted {
+        board.watchdog.start(WATCHDOG_PERIOD);
+    }
+
+    // Set up the USB driver
+    let usb_bus = UsbBusAllocator::new(hal::usb::UsbBus::new(
+        board.peripherals.USBCTRL_REGS,
+        board.peripherals.USBCTRL_DPRAM,
+        board.clocks.usb_clock,
+        true,
+        &mut board.peripherals.RESETS,
+    ));
+
+    // Set up the USB DFU class
+    let mut dfu = DfuClass::new(&usb_bus, updater);
+
+    // Create a USB device with a fake VID and PID
+    let mut usb_dev = UsbDeviceBuilder::new(&usb_bus, UsbVidPid(0x16c0, 0x27dd))
+        .strings(&[StringDescriptors::default()
+            .manufacturer("Fake company")
+            .product("MacroPad")
+            .serial_number("TEST")])
+        .unwrap()
+        .build();
+
+    loop {
+        usb_dev.poll(&mut [&mut dfu]);
+
+        // Working USB is good enough for this example
+        if !booted && usb_dev.state() == UsbDeviceState::Configured {
+            dfu.updater().mark_booted(&mut board.watchdog).unwrap();
+            board.watchdog.start(WATCHDOG_PERIOD);
+            booted = true;
+        }
+
+        if booted {
+            board.watchdog.feed();
+            led.on();
+        } else {
+            // Blink at 2 Hz while being tested
+            led.set(board.timer.get_counter().ticks() / 250_000 % 2 == 0);
+        }
+
+        if dfu.update_ready() {
+            // Let the last status reach the host before the reset
+            let start = board.timer.get_counter();
+            while (board.timer.get_counter() - start).to_millis() < 100 {
+                usb_dev.poll(&mut [&mut dfu]);
+            }
+            cortex_m::peripheral::SCB::sys_reset();
+        }
+    }
+}
+
+// End of file
@@ -19,8 +19,16 @@ pub static BOOT2_FIRMWARE: [u8; 256] = rp2040_boot2::BOOT_LOADER_W25Q080;
 
 pub use hal::pac;
 
-hal::bsp_pins!(
-    Gpio0 { name: button },
+board_common::bsp_pins!(
+    embassy: crate::embassy::Pins;
+
+    Gpio0 {
+        name: button,
+        aliases: {
+            /// Input alias for pin [crate::Pins::button].
+            FunctionSioInput, PullUp: ButtonPin
+        }
+    },
     Gpio1 { name: key1 },
     Gpio2 { name: key2 },
     Gpio3 { name: key3 },
@@ -33,7 +41,13 @@ hal::bsp_pins!(
     Gpio10 { name: key10 },
     Gpio11 { name: key11 },
     Gpio12 { name: key12 },
-    Gpio13 { name: led },
+    Gpio13 {
+        name: led,
+        aliases: {
+            /// Output alias for pin [crate::Pins::led].
+            FunctionSioOutput, PullNone: LedPin
+        }
+    },
     Gpio14 {
         name: speaker_shutdown
     },
@@ -74,3 +88,19 @@ hal::bsp_pins!(
 );
 
 pub const XOSC_CRYSTAL_FREQ: u32 = 12_000_000;
+
+// FLASH_SIZE_BYTES and the reserved end of the flash, see build.rs
+include!(concat!(env!("OUT_DIR"), "/flash.rs"));
+
+#[cfg(feature = "embassy")]
+pub mod embassy;
+
+pub use board_common;
+
+board_common::board_pins!(Pins, XOSC_CRYSTAL_FREQ, activity_led: 13);
+
+/// The button of the rotary encoder, see [board_common::UserButton]
+pub type Button = board_common::ActiveLow<ButtonPin>;
+
+/// The red LED on GPIO 13, see [board_common::StatusLed]
+pub type Led = board_common::ActiveHigh<LedPin>;
@@ -0,0 +1,178 @@
+//! # SD Card Hot-Plug Example
+//!
+//! Logs a line to `LOG.TXT` on the microSD card of a Adafruit Metro RP2040
+//! every second, while cards are inserted and removed.
+//!
+//! The LED is on while a card is ready. Whenever a card is inserted, its
+//! first volume is opened and kept for the whole session. Once the card is
+//! removed, the session ends and the volume is dropped.
+//!
+//! See the `Cargo.toml` file for Copyright and license details.
+
+#![no_std]
+#![no_main]
+
+// The macro for our start-up function
+use adafruit_metro_rp2040::entry;
+
+// Ensure we halt the program on panic (if we don't mention this crate it won't
+// be linked)
+use panic_halt as _;
+
+// Some traits we need
+use embedded_hal::digital::OutputPin;
+
+use embedded_sdmmc::filesystem::Mode;
+use embedded_sdmmc::{BlockDevice, TimeSource, Timestamp, Volume, VolumeIdx, VolumeManager};
+use fugit::ExtU64;
+
+use adafruit_metro_rp2040::{
+    hal::{
+        clocks::{init_clocks_and_plls, Clock},
+        dma::DMAExt,
+        pac,
+        pio::PIOExt,
+        sio::Sio,
+        watchdog::Watchdog,
+        Timer,
+    },
+    sd_card::{SdEvent, SdManager, Session},
+    sdio::{CardDetect, DmaBuffer, Sdio, SdioCard, SdioPins, SdioStateMachines, BUFFER_WORDS},
+    Pins, XOSC_CRYSTAL_FREQ,
+};
+
+/// The board has no clock, so all files are dated to 1970
+struct DummyTimesource;
+
+impl TimeSource for DummyTimesource {
+    fn get_timestamp(&self) -> Timestamp {
+        Timestamp {
+            year_since_1970: 0,
+            zero_indexed_month: 0,
+            zero_indexed_day: 0,
+            hours: 0,
+            minutes: 0,
+            seconds: 0,
+        }
+    }
+}
+
+/// Entry point to our bare-metal application.
+///
+/// The `#[entry]` macro ensures the Cortex-M start-up code calls this function
+/// as soon as all global variables are initialised.
+#[entry]
+fn main() -> ! {
+    // Grab our singleton objects
+    let mut pac = pac::Peripherals::take().unwrap();
+
+    // Set up the watchdog driver - needed by the clock setup code
+    let mut watchdog = Watchdog::new(pac.WATCHDOG);
+
+    // Configure the clocks
+    let clocks = init_clocks_and_plls(
+        XOSC_CRYSTAL_FREQ,
+        pac.XOSC,
+        pac.CLOCKS,
+        pac.PLL_SYS,
+        pac.PLL_USB,
+        &mut pac.RESETS,
+        &mut watchdog,
+    )
+    .ok()
+    .unwrap();
+
+    let timer = Timer::new(pac.TIMER, &mut pac.RESETS, &clocks);
+
+    // The single-cycle I/O block controls our GPIO pins
+    let sio = Sio::new(pac.SIO);
+
+    let pins = Pins::new(
+        pac.IO_BANK0,
+        pac.PADS_BANK0,
+        sio.gpio_bank0,
+        &mut pac.RESETS,
+    );
+    let mut led_pin = pins.d13.into_push_pull_output();
+
+    // Run the SD bus with three PIO state machines and one DMA channel
+    let (mut pio0, sm0_0, _, _, _) = pac.PIO0.split(&mut pac.RESETS);
+    let (mut pio1, sm1_0, sm1_1, _, _) = pac.PIO1.split(&mut pac.RESETS);
+    let dma = pac.DMA.split(&mut pac.RESETS);
+    let sdio = Sdio::new(
+        &mut pio0,
+        &mut pio1,
+        SdioStateMachines {
+            cmd: sm0_0,
+            data_rx: sm1_0,
+            data_tx: sm1_1,
+        },
+        SdioPins {
+            clk: pins.sclk.reconfigure(),
+            cmd: pins.mosi.reconfigure(),
+            d0: pins.miso.reconfigure(),
+            d1: pins.sdio_data1.reconfigure(),
+            d2: pins.sdio_data2.reconfigure(),
+            d3: pins.sd_cs.reconfigure(),
+        },
+        timer,
+        clocks.system_clock.freq(),
+    );
+    let buffer = cortex_m::singleton!(: DmaBuffer = [0; BUFFER_WORDS]).unwrap();
+    let card = SdioCard::new(sdio, dma.ch0, buffer);
+
+    let mut manager = SdManager::new(
+        card,
+        DummyTimesource,
+        CardDetect::new(pins.sd_cd.reconfigure()),
+    );
+
+    // The volume of the current card
+    let mut log: Option<(Session, Volume)> = None;
+    let mut next_line = timer.get_counter();
+
+    loop {
+        match manager.poll(timer.get_counter()) {
+            Some(SdEvent::Inserted { .. }) => {
+                led_pin.set_high().unwrap();
+                let session = manager.session().unwrap();
+                let volume_mgr = manager.volume_mgr(session).unwrap();
+                log = volume_mgr
+                    .get_volume(VolumeIdx(0))
+                    .ok()
+                    .map(|volume| (session, volume));
+            }
+            Some(SdEvent::Removed) => {
+                led_pin.set_low().unwrap();
+                log = None;
+            }
+            Some(SdEvent::Failed(_)) | None => {}
+        }
+
+        if timer.get_counter() < next_line {
+            continue;
+        }
+        next_line = timer.get_counter() + 1.secs();
+
+        if let Some((session, volume)) = log.as_mut() {
+            if let Ok(volume_mgr) = manager.volume_mgr(*session) {
+                let _ = append_line(volume_mgr, volume);
+            }
+        }
+    }
+}
+
+fn append_line<D: BlockDevice, T: TimeSource>(
+    volume_mgr: &mut VolumeManager<D, T>,
+    volume: &mut Volume,
+) -> Result<(), embedded_sdmmc::Error<D::Error>> {
+    let dir = volume_mgr.open_root_dir(volume)?;
+    let mut file =
+        volume_mgr.open_file_in_dir(volume, &dir, "LOG.TXT", Mode::ReadWriteCreateOrAppend)?;
+    volume_mgr.write(volume, &mut file, b"Still logging\r\n")?;
+    volume_mgr.close_file(volume, file)?;
+    volume_mgr.close_dir(volume, dir);
+    Ok(())
+}
+
+// End of file
@@ -0,0 +1,164 @@
+//! # SDIO SD Card Example
+//!
+//! Appends lines to a log file on the microSD card of a Adafruit Metro
+//! RP2040, using the 4 bit SD bus instead of SPI.
+//!
+//! The card has to be formatted with FAT16 or FAT32. The example waits for a
+//! card to be inserted, then appends 1000 lines to `LOG.TXT` in the root
+//! directory. Afterwards the LED blinks slowly, or quickly if anything
+//! failed.
+//!
+//! See the `Cargo.toml` file for Copyright and license details.
+
+#![no_std]
+#![no_main]
+
+// The macro for our start-up function
+use adafruit_metro_rp2040::entry;
+
+// Ensure we halt the program on panic (if we don't mention this crate it won't
+// be linked)
+use panic_halt as _;
+
+// Some traits we need
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::OutputPin;
+
+use embedded_sdmmc::filesystem::Mode;
+use embedded_sdmmc::{TimeSource, Timestamp, VolumeIdx, VolumeManager};
+
+use adafruit_metro_rp2040::{
+    hal::{
+        clocks::{init_clocks_and_plls, Clock},
+        dma::DMAExt,
+        pac,
+        pio::PIOExt,
+        sio::Sio,
+        watchdog::Watchdog,
+        Timer,
+    },
+    sdio::{CardDetect, DmaBuffer, Sdio, SdioCard, SdioPins, SdioStateMachines, BUFFER_WORDS},
+    Pins, XOSC_CRYSTAL_FREQ,
+};
+
+/// The board has no clock, so all files are dated to 1970
+struct DummyTimesource;
+
+impl TimeSource for DummyTimesource {
+    fn get_timestamp(&self) -> Timestamp {
+        Timestamp {
+            year_since_1970: 0,
+            zero_indexed_month: 0,
+            zero_indexed_day: 0,
+            hours: 0,
+            minutes: 0,
+            seconds: 0,
+        }
+    }
+}
+
+const LINE: &[u8] = b"The quick brown fox jumps over the lazy dog\r\n";
+
+/// Entry point to our bare-metal application.
+///
+/// The `#[entry]` macro ensures the Cortex-M start-up code calls this function
+/// as soon as all global variables are initialised.
+#[entry]
+fn main() -> ! {
+    // Grab our singleton objects
+    let mut pac = pac::Peripherals::take().unwrap();
+
+    // Set up the watchdog driver - needed by the clock setup code
+    let mut watchdog = Watchdog::new(pac.WATCHDOG);
+
+    // Configure the clocks
+    let clocks = init_clocks_and_plls(
+        XOSC_CRYSTAL_FREQ,
+        pac.XOSC,
+        pac.CLOCKS,
+        pac.PLL_SYS,
+        pac.PLL_USB,
+        &mut pac.RESETS,
+        &mut watchdog,
+    )
+    .ok()
+    .unwrap();
+
+    let mut timer = Timer::new(pac.TIMER, &mut pac.RESETS, &clocks);
+
+    // The single-cycle I/O block controls our GPIO pins
+    let sio = Sio::new(pac.SIO);
+
+    let pins = Pins::new(
+        pac.IO_BANK0,
+        pac.PADS_BANK0,
+        sio.gpio_bank0,
+        &mut pac.RESETS,
+    );
+    let mut led_pin = pins.d13.into_push_pull_output();
+
+    // Wait for a card, with the LED on
+    let mut card_detect = CardDetect::new(pins.sd_cd.reconfigure());
+    led_pin.set_high().unwrap();
+    while !card_detect.is_inserted() {
+        timer.delay_ms(10);
+    }
+    // Let the card settle in the slot
+    timer.delay_ms(100);
+    led_pin.set_low().unwrap();
+
+    // Run the SD bus with three PIO state machines and one DMA channel
+    let (mut pio0, sm0_0, _, _, _) = pac.PIO0.split(&mut pac.RESETS);
+    let (mut pio1, sm1_0, sm1_1, _, _) = pac.PIO1.split(&mut pac.RESETS);
+    let dma = pac.DMA.split(&mut pac.RESETS);
+    let sdio = Sdio::new(
+        &mut pio0,
+        &mut pio1,
+        SdioStateMachines {
+            cmd: sm0_0,
+            data_rx: sm1_0,
+            data_tx: sm1_1,
+        },
+        SdioPins {
+            clk: pins.sclk.reconfigure(),
+            cmd: pins.mosi.reconfigure(),
+            d0: pins.miso.reconfigure(),
+            d1: pins.sdio_data1.reconfigure(),
+            d2: pins.sdio_data2.reconfigure(),
+            d3: pins.sd_cs.reconfigure(),
+        },
+        timer,
+        clocks.system_clock.freq(),
+    );
+    let buffer = cortex_m::singleton!(: DmaBuffer = [0; BUFFER_WORDS]).unwrap();
+    let card = SdioCard::new(sdio, dma.ch0, buffer);
+
+    let mut volume_mgr = VolumeManager::new(card, DummyTimesource);
+    let ok = append_log(&mut volume_mgr).is_ok();
+    volume_mgr.free();
+
+    let blink_ms = if ok { 500 } else { 100 };
+    loop {
+        led_pin.set_high().unwrap();
+        timer.delay_ms(blink_ms);
+        led_pin.set_low().unwrap();
+        timer.delay_ms(blink_ms);
+    }
+}
+
+fn append_log<D: embedded_sdmmc::BlockDevice, T: TimeSource>(
+    volume_mgr: &mut VolumeManager<D, T>,
+) -> Result<(), embedded_sdmmc::Error<D::Error>> {
+    let mut volume = volume_mgr.get_volume(VolumeIdx(0))?;
+    let dir = volume_mgr.open_root_dir(&volume)?;
+    let mut file =
+        volume_mgr.open_file_in_dir(&mut volume, &dir, "LOG.TXT", Mode::ReadWriteCreateOrAppend)?;
+    for _ in 0..1000 {
+        volume_mgr.write(&mut volume, &mut file, LINE)?;
+    }
+    volume_mgr.close_file(&volume, file)?;
+    volume_mgr.close_dir(&volume, dir);
+    Ok(())
+}
+
+// End of file
@@ -17,7 +17,12 @@ pub static BOOT2_FIRMWARE: [u8; 256] = rp2040_boot2::BOOT_LOADER_W25Q080;
 
 pub use hal::pac;
 
-hal::bsp_pins!(
+pub mod sd_card;
+pub mod sdio;
+
+board_common::bsp_pins!(
+    embassy: crate::embassy::Pins;
+
     Gpio0 {
         name: tx,
         aliases: { FunctionUart, PullNone: UartTx }
@@ -37,9 +42,18 @@ hal::bsp_pins!(
     Gpio10 { name: d10 },
     Gpio11 { name: d11 },
     Gpio12 { name: d12 },
-    Gpio13 { name: d13 },
+    Gpio13 {
+        name: d13,
+        aliases: {
+            /// Output alias for pin [crate::Pins::d13].
+            FunctionSioOutput, PullNone: LedPin
+        }
+    },
     Gpio14 { name: neopixel_data },
-    Gpio15 { name: sd_cd, },
+    Gpio15 {
+        name: sd_cd,
+        aliases: { FunctionSioInput, PullUp: SdCd }
+    },
     Gpio16 {
         name: sda,
         aliases: { FunctionI2C, PullUp: Sda }
@@ -50,19 +64,28 @@ hal::bsp_pins!(
     },
     Gpio18 {
         name: sclk,
-        aliases: { FunctionSpi, PullNone: Sclk }
+        aliases: { FunctionSpi, PullNone: Sclk, FunctionPio0, PullNone: SdClk }
     },
     Gpio19 {
         name: mosi,
-        aliases: { FunctionSpi, PullNone: Mosi }
+        aliases: { FunctionSpi, PullNone: Mosi, FunctionPio0, PullUp: SdCmd }
     },
     Gpio20 {
         name: miso
-        aliases: { FunctionSpi, PullNone: Miso }
+        aliases: { FunctionSpi, PullNone: Miso, FunctionPio1, PullUp: SdData0 }
+    },
+    Gpio21 {
+        name: sdio_data1,
+        aliases: { FunctionPio1, PullUp: SdData1 }
+    },
+    Gpio22 {
+        name: sdio_data2,
+        aliases: { FunctionPio1, PullUp: SdData2 }
+    },
+    Gpio23 {
+        name: sd_cs,
+        aliases: { FunctionPio1, PullUp: SdData3 }
     },
-    Gpio21 { name: sdio_data1 },
-    Gpio22 { name: sdio_data2 },
-    Gpio23 { name: sd_cs },
     Gpio24 { name: d24 },
     Gpio25 { name: d25 },
     Gpio26 { name: a0 },
@@ -72,3 +95,16 @@ hal::bsp_pins!(
 );
 
 pub const XOSC_CRYSTAL_FREQ: u32 = 12_000_000;
+
+// FLASH_SIZE_BYTES and the reserved end of the flash, see build.rs
+include!(concat!(env!("OUT_DIR"), "/flash.rs"));
+
+#[cfg(feature = "embassy")]
+pub mod embassy;
+
+pub use board_common;
+
+board_common::board_pins!(Pins, XOSC_CRYSTAL_FREQ, activity_led: 13);
+
+/// The red LED on D13, see [board_common::StatusLed]
+pub type Led = board_common::ActiveHigh<LedPin>;
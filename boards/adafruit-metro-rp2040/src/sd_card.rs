@@ -0,0 +1,523 @@
+//! Hot-plugging of microSD cards
+//!
+//! [SdManager] watches the card detect switch of the slot, and owns the
+//! [VolumeManager] of the card. The switch is any input pin which is low
+//! while a card is inserted, like the [CardDetect](crate::sdio::CardDetect)
+//! of the Metro's slot.
+//!
+//! Once a card was inserted and the switch settled, the card is initialised
+//! and a new [Session] begins. When the card is removed, the volume manager
+//! is replaced by a fresh one, which forgets about all open volumes,
+//! directories and files.
+//!
+//! The volume manager is only handed out for the current session. Handles
+//! opened for an earlier card can't be used on the next one by accident:
+//! their session is stale, so [SdManager::volume_mgr] fails.
+//!
+//! The card can be driven either by [SdioCard], or over SPI by
+//! [embedded_sdmmc::SdCard].
+//!
+//! ```ignore
+//! let mut manager = SdManager::new(card, time_source, CardDetect::new(pins.sd_cd.reconfigure()));
+//! let mut log = None;
+//! loop {
+//!     match manager.poll(timer.get_counter()) {
+//!         Some(SdEvent::Inserted { .. }) => {
+//!             let session = manager.session().unwrap();
+//!             let volume_mgr = manager.volume_mgr(session).unwrap();
+//!             let volume = volume_mgr.get_volume(VolumeIdx(0)).unwrap();
+//!             log = Some((session, volume));
+//!         }
+//!         Some(SdEvent::Removed) => log = None,
+//!         _ => {}
+//!     }
+//! }
+//! ```
+
+use embedded_hal::digital::InputPin;
+use embedded_sdmmc::{BlockDevice, TimeSource, VolumeManager};
+
+use crate::hal::dma::SingleChannel;
+use crate::hal::timer::Instant;
+use crate::sdio::SdioCard;
+
+/// How long the card detect switch has to be stable, in microseconds
+const DEBOUNCE_US: u64 = 50_000;
+
+/// A card which can be replaced while the device is in use
+pub trait SdDevice: BlockDevice {
+    /// Forget the card, so it is initialised again on next use
+    fn mark_card_uninit(&self);
+
+    /// Initialise the card if needed and return its size in bytes
+    fn num_bytes(&self) -> Result<u64, Self::Error>;
+}
+
+impl<CH: SingleChannel> SdDevice for SdioCard<CH> {
+    fn mark_card_uninit(&self) {
+        SdioCard::mark_card_uninit(self)
+    }
+
+    fn num_bytes(&self) -> Result<u64, Self::Error> {
+        SdioCard::num_bytes(self)
+    }
+}
+
+impl<SPI, CS, DELAYER> SdDevice for embedded_sdmmc::SdCard<SPI, CS, DELAYER>
+where
+    SPI: embedded_hal_0_2::blocking::spi::Transfer<u8> + embedded_hal_0_2::blocking::spi::Write<u8>,
+    CS: embedded_hal_0_2::digital::v2::OutputPin,
+    <SPI as embedded_hal_0_2::blocking::spi::Transfer<u8>>::Error: core::fmt::Debug,
+    <SPI as embedded_hal_0_2::blocking::spi::Write<u8>>::Error: core::fmt::Debug,
+    DELAYER: embedded_hal_0_2::blocking::delay::DelayUs<u8>,
+{
+    fn mark_card_uninit(&self) {
+        embedded_sdmmc::SdCard::mark_card_uninit(self)
+    }
+
+    fn num_bytes(&self) -> Result<u64, Self::Error> {
+        embedded_sdmmc::SdCard::num_bytes(self)
+    }
+}
+
+/// Something that happened to the card, reported by [SdManager::poll]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SdEvent<E> {
+    /// A card was inserted and initialised, a new session began
+    Inserted {
+        /// Size of the card in bytes
+        bytes: u64,
+    },
+    /// The card was removed, the session ended
+    Removed,
+    /// A card was inserted, but couldn't be initialised
+    Failed(E),
+}
+
+/// The time from inserting a card until it is removed
+///
+/// Volumes, directories and files belong to the session they were opened
+/// in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Session(u32);
+
+/// The session has ended, its card was removed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Stale;
+
+/// Debounces a level, which is only taken once it didn't change for
+/// [DEBOUNCE_US]
+#[derive(Debug, Clone, Copy)]
+struct Debounce {
+    stable: bool,
+    level: bool,
+    since: u64,
+}
+
+impl Debounce {
+    /// Returns the new stable level when it changes
+    fn update(&mut self, level: bool, now_us: u64) -> Option<bool> {
+        if level != self.level {
+            self.level = level;
+            self.since = now_us;
+        }
+        if self.level != self.stable && now_us.wrapping_sub(self.since) >= DEBOUNCE_US {
+            self.stable = self.level;
+            return Some(self.stable);
+        }
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Empty,
+    Ready,
+    Failed,
+}
+
+/// Handles inserting and removing the card, see the [module](self)
+/// documentation
+pub struct SdManager<D: SdDevice, T: TimeSource, P: InputPin> {
+    volume_mgr: Option<VolumeManager<D, T>>,
+    detect: P,
+    debounce: Debounce,
+    state: State,
+    session: u32,
+}
+
+impl<D: SdDevice, T: TimeSource, P: InputPin> SdManager<D, T, P> {
+    /// Manage the card in `device`, with the card detect switch `detect`
+    ///
+    /// A card which is already inserted is reported by the first calls to
+    /// [poll](SdManager::poll) like a newly inserted one.
+    pub fn new(device: D, time_source: T, detect: P) -> Self {
+        SdManager {
+            volume_mgr: Some(VolumeManager::new(device, time_source)),
+            detect,
+            debounce: Debounce {
+                stable: false,
+                level: false,
+                since: 0,
+            },
+            state: State::Empty,
+            session: 0,
+        }
+    }
+
+    /// Check the card detect switch and (re)initialise the card
+    ///
+    /// Has to be called regularly, e.g. every few milliseconds, or from the
+    /// `IO_IRQ_BANK0` interrupt and again once the switch has settled.
+    pub fn poll(&mut self, now: Instant) -> Option<SdEvent<D::Error>> {
+        // A pin which can't be read counts as an empty slot
+        let inserted = matches!(self.detect.is_low(), Ok(true));
+        if self.debounce.update(inserted, now.ticks())? {
+            Some(self.mount())
+        } else {
+            self.unmount();
+            Some(SdEvent::Removed)
+        }
+    }
+
+    /// Try to initialise a card which failed before
+    ///
+    /// Returns `None` if there is no such card.
+    pub fn retry(&mut self) -> Option<SdEvent<D::Error>> {
+        if self.state != State::Failed {
+            return None;
+        }
+        Some(self.mount())
+    }
+
+    /// The current session, if a card is ready
+    pub fn session(&self) -> Option<Session> {
+        match self.state {
+            State::Ready => Some(Session(self.session)),
+            _ => None,
+        }
+    }
+
+    /// Whether a card is inserted and initialised
+    pub fn is_ready(&self) -> bool {
+        self.state == State::Ready
+    }
+
+    /// The volume manager of the card, as long as `session` is current
+    pub fn volume_mgr(&mut self, session: Session) -> Result<&mut VolumeManager<D, T>, Stale> {
+        if self.session() != Some(session) {
+            return Err(Stale);
+        }
+        Ok(self.volume_mgr.as_mut().unwrap())
+    }
+
+    /// Release the device, the time source and the card detect switch
+    pub fn free(self) -> (D, T, P) {
+        let (device, time_source) = self.volume_mgr.unwrap().free();
+        (device, time_source, self.detect)
+    }
+
+    fn mount(&mut self) -> SdEvent<D::Error> {
+        let device = self.volume_mgr.as_mut().unwrap().device();
+        device.mark_card_uninit();
+        match device.num_bytes() {
+            Ok(bytes) => {
+                self.session = self.session.wrapping_add(1);
+                self.state = State::Ready;
+                SdEvent::Inserted { bytes }
+            }
+            Err(e) => {
+                self.state = State::Failed;
+                SdEvent::Failed(e)
+            }
+        }
+    }
+
+    fn unmount(&mut self) {
+        // A fresh volume manager has no volumes, directories or files open
+        let (device, time_source) = self.volume_mgr.take().unwrap().free();
+        device.mark_card_uninit();
+        self.volume_mgr = Some(VolumeManager::new(device, time_source));
+        self.state = State::Empty;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::cell::Cell;
+    use core::convert::Infallible;
+    use std::rc::Rc;
+
+    use embedded_hal::digital::ErrorType;
+    use embedded_sdmmc::{Block, BlockCount, BlockIdx, Timestamp};
+
+    use super::*;
+
+    const BYTES: u64 = 32 << 30;
+
+    /// The card detect switch, low while a card is inserted
+    #[derive(Clone, Default)]
+    struct Switch(Rc<Cell<bool>>);
+
+    impl Switch {
+        fn set_inserted(&self, inserted: bool) {
+            self.0.set(inserted)
+        }
+    }
+
+    impl ErrorType for Switch {
+        type Error = Infallible;
+    }
+
+    impl InputPin for Switch {
+        fn is_high(&mut self) -> Result<bool, Infallible> {
+            Ok(!self.0.get())
+        }
+
+        fn is_low(&mut self) -> Result<bool, Infallible> {
+            Ok(self.0.get())
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    struct CardError;
+
+    #[derive(Default)]
+    struct CardState {
+        /// How often the card was forgotten
+        uninits: Cell<u32>,
+        /// Whether the card fails to initialise
+        broken: Cell<bool>,
+    }
+
+    /// A card in the slot, which is never read or written
+    #[derive(Clone, Default)]
+    struct FakeCard(Rc<CardState>);
+
+    impl BlockDevice for FakeCard {
+        type Error = CardError;
+
+        fn read(&self, _: &mut [Block], _: BlockIdx, _: &str) -> Result<(), CardError> {
+            unimplemented!()
+        }
+
+        fn write(&self, _: &[Block], _: BlockIdx) -> Result<(), CardError> {
+            unimplemented!()
+        }
+
+        fn num_blocks(&self) -> Result<BlockCount, CardError> {
+            unimplemented!()
+        }
+    }
+
+    impl SdDevice for FakeCard {
+        fn mark_card_uninit(&self) {
+            self.0.uninits.set(self.0.uninits.get() + 1);
+        }
+
+        fn num_bytes(&self) -> Result<u64, CardError> {
+            if self.0.broken.get() {
+                Err(CardError)
+            } else {
+                Ok(BYTES)
+            }
+        }
+    }
+
+    struct NoTime;
+
+    impl TimeSource for NoTime {
+        fn get_timestamp(&self) -> Timestamp {
+            Timestamp {
+                year_since_1970: 0,
+                zero_indexed_month: 0,
+                zero_indexed_day: 0,
+                hours: 0,
+                minutes: 0,
+                seconds: 0,
+            }
+        }
+    }
+
+    type Manager = SdManager<FakeCard, NoTime, Switch>;
+
+    fn manager() -> (Manager, FakeCard, Switch) {
+        let card = FakeCard::default();
+        let switch = Switch::default();
+        let manager = SdManager::new(card.clone(), NoTime, switch.clone());
+        (manager, card, switch)
+    }
+
+    fn ms(ms: u64) -> Instant {
+        Instant::from_ticks(ms * 1000)
+    }
+
+    /// Inserts a card at `at` milliseconds and lets the switch settle
+    fn insert(manager: &mut Manager, switch: &Switch, at: u64) -> Session {
+        switch.set_inserted(true);
+        assert_eq!(manager.poll(ms(at)), None);
+        assert_eq!(
+            manager.poll(ms(at + 50)),
+            Some(SdEvent::Inserted { bytes: BYTES })
+        );
+        manager.session().unwrap()
+    }
+
+    /// Removes the card at `at` milliseconds and lets the switch settle
+    fn remove(manager: &mut Manager, switch: &Switch, at: u64) {
+        switch.set_inserted(false);
+        assert_eq!(manager.poll(ms(at)), None);
+        assert_eq!(manager.poll(ms(at + 50)), Some(SdEvent::Removed));
+    }
+
+    #[test]
+    fn debounce_waits_for_a_stable_level() {
+        let mut debounce = Debounce {
+            stable: false,
+            level: false,
+            since: 0,
+        };
+        assert_eq!(debounce.update(true, 1_000), None);
+        assert_eq!(debounce.update(true, 50_999), None);
+        assert_eq!(debounce.update(true, 51_000), Some(true));
+        assert_eq!(debounce.update(true, 200_000), None);
+        assert_eq!(debounce.update(false, 300_000), None);
+        assert_eq!(debounce.update(false, 350_000), Some(false));
+    }
+
+    #[test]
+    fn debounce_restarts_on_a_bounce() {
+        let mut debounce = Debounce {
+            stable: false,
+            level: false,
+            since: 0,
+        };
+        assert_eq!(debounce.update(true, 0), None);
+        assert_eq!(debounce.update(false, 20_000), None);
+        // Back at the stable level, which isn't reported again
+        assert_eq!(debounce.update(false, 100_000), None);
+        assert_eq!(debounce.update(true, 110_000), None);
+        assert_eq!(debounce.update(true, 159_999), None);
+        assert_eq!(debounce.update(true, 160_000), Some(true));
+    }
+
+    #[test]
+    fn debounce_survives_the_timer_wrapping() {
+        let mut debounce = Debounce {
+            stable: false,
+            level: false,
+            since: 0,
+        };
+        assert_eq!(debounce.update(true, u64::MAX - 9_999), None);
+        assert_eq!(debounce.update(true, 39_999), None);
+        assert_eq!(debounce.update(true, 40_000), Some(true));
+    }
+
+    #[test]
+    fn an_empty_slot_stays_empty() {
+        let (mut manager, card, _switch) = manager();
+        assert_eq!(manager.poll(ms(0)), None);
+        assert_eq!(manager.poll(ms(1_000)), None);
+        assert_eq!(manager.session(), None);
+        assert!(!manager.is_ready());
+        assert_eq!(manager.retry(), None);
+        assert_eq!(card.0.uninits.get(), 0);
+    }
+
+    #[test]
+    fn an_inserted_card_is_initialised_once_settled() {
+        let (mut manager, card, switch) = manager();
+        switch.set_inserted(true);
+        assert_eq!(manager.poll(ms(0)), None);
+        assert_eq!(manager.poll(ms(49)), None);
+        assert_eq!(manager.session(), None);
+        assert_eq!(
+            manager.poll(ms(50)),
+            Some(SdEvent::Inserted { bytes: BYTES })
+        );
+        assert_eq!(manager.poll(ms(100)), None);
+        let session = manager.session().unwrap();
+        assert!(manager.is_ready());
+        assert!(manager.volume_mgr(session).is_ok());
+        assert_eq!(card.0.uninits.get(), 1);
+    }
+
+    #[test]
+    fn a_bouncing_switch_is_reported_once() {
+        let (mut manager, _card, switch) = manager();
+        let mut events = 0;
+        for (at, inserted) in [(0, true), (5, false), (10, true), (20, false), (25, true)] {
+            switch.set_inserted(inserted);
+            events += manager.poll(ms(at)).into_iter().count();
+        }
+        assert_eq!(events, 0);
+        assert_eq!(manager.poll(ms(74)), None);
+        assert_eq!(
+            manager.poll(ms(75)),
+            Some(SdEvent::Inserted { bytes: BYTES })
+        );
+    }
+
+    #[test]
+    fn removing_the_card_ends_the_session() {
+        let (mut manager, card, switch) = manager();
+        let session = insert(&mut manager, &switch, 0);
+        remove(&mut manager, &switch, 1_000);
+        assert_eq!(manager.session(), None);
+        assert!(!manager.is_ready());
+        assert_eq!(manager.volume_mgr(session).err(), Some(Stale));
+        // Forgotten when mounted and again when removed
+        assert_eq!(card.0.uninits.get(), 2);
+    }
+
+    #[test]
+    fn handles_of_an_earlier_card_are_stale() {
+        let (mut manager, _card, switch) = manager();
+        let first = insert(&mut manager, &switch, 0);
+        remove(&mut manager, &switch, 1_000);
+        let second = insert(&mut manager, &switch, 2_000);
+        assert_ne!(first, second);
+        assert_eq!(manager.volume_mgr(first).err(), Some(Stale));
+        assert!(manager.volume_mgr(second).is_ok());
+    }
+
+    #[test]
+    fn a_failed_card_can_be_retried() {
+        let (mut manager, card, switch) = manager();
+        card.0.broken.set(true);
+        switch.set_inserted(true);
+        assert_eq!(manager.poll(ms(0)), None);
+        assert_eq!(manager.poll(ms(50)), Some(SdEvent::Failed(CardError)));
+        assert_eq!(manager.session(), None);
+        assert_eq!(manager.retry(), Some(SdEvent::Failed(CardError)));
+        card.0.broken.set(false);
+        assert_eq!(manager.retry(), Some(SdEvent::Inserted { bytes: BYTES }));
+        assert!(manager.is_ready());
+        assert_eq!(manager.retry(), None);
+    }
+
+    #[test]
+    fn removing_a_failed_card_empties_the_slot() {
+        let (mut manager, card, switch) = manager();
+        card.0.broken.set(true);
+        switch.set_inserted(true);
+        manager.poll(ms(0));
+        assert_eq!(manager.poll(ms(50)), Some(SdEvent::Failed(CardError)));
+        remove(&mut manager, &switch, 1_000);
+        assert_eq!(manager.retry(), None);
+    }
+
+    #[test]
+    fn free_returns_the_parts() {
+        let (mut manager, _card, switch) = manager();
+        insert(&mut manager, &switch, 0);
+        let (card, _time, mut detect) = manager.free();
+        assert_eq!(card.0.uninits.get(), 1);
+        assert_eq!(detect.is_low(), Ok(true));
+    }
+}
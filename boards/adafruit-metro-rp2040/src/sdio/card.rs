@@ -0,0 +1,255 @@
+//! Commands, responses and registers of SD cards
+
+use super::crc::crc7;
+
+pub const GO_IDLE_STATE: u8 = 0;
+pub const ALL_SEND_CID: u8 = 2;
+pub const SEND_RELATIVE_ADDR: u8 = 3;
+pub const SWITCH_FUNC: u8 = 6;
+pub const SELECT_CARD: u8 = 7;
+pub const SEND_IF_COND: u8 = 8;
+pub const SEND_CSD: u8 = 9;
+pub const STOP_TRANSMISSION: u8 = 12;
+pub const SEND_STATUS: u8 = 13;
+pub const SET_BLOCKLEN: u8 = 16;
+pub const READ_SINGLE_BLOCK: u8 = 17;
+pub const WRITE_BLOCK: u8 = 24;
+pub const WRITE_MULTIPLE_BLOCK: u8 = 25;
+pub const APP_CMD: u8 = 55;
+
+pub const SET_BUS_WIDTH: u8 = 6;
+pub const SET_WR_BLK_ERASE_COUNT: u8 = 23;
+pub const SD_SEND_OP_COND: u8 = 41;
+
+/// Argument of SEND_IF_COND: 2.7-3.6 V and a check pattern
+pub const IF_COND: u32 = 0x1aa;
+
+/// Argument of SD_SEND_OP_COND: 3.2-3.4 V, and high capacity support
+pub const OP_COND: u32 = 0x0030_0000;
+pub const OP_COND_HCS: u32 = 1 << 30;
+pub const OP_COND_BUSY: u32 = 1 << 31;
+
+/// Argument of SWITCH_FUNC, switching function group 1 to high speed
+pub const SWITCH_HIGH_SPEED: u32 = 0x80ff_fff1;
+
+/// Argument of SET_BUS_WIDTH for a 4 bit bus
+pub const BUS_WIDTH_4: u32 = 0b10;
+
+/// Error bits of the card status in an R1 response
+pub const STATUS_ERRORS: u32 = 0xfdf9_8008;
+
+/// `tran` state of the card status, ready for data transfers
+pub const STATE_TRAN: u32 = 4;
+
+/// The response expected for a command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Response {
+    /// No response
+    None,
+    /// Card status, 48 bits
+    R1,
+    /// Card status, followed by busy on DAT0
+    R1b,
+    /// CID or CSD register, 136 bits
+    R2,
+    /// OCR register, 48 bits without CRC
+    R3,
+    /// Relative card address, 48 bits
+    R6,
+    /// Card interface condition, 48 bits
+    R7,
+}
+
+impl Response {
+    /// Length of the response in bits, including start and end bit
+    pub fn bits(self) -> u8 {
+        match self {
+            Response::None => 0,
+            Response::R2 => 136,
+            _ => 48,
+        }
+    }
+
+    /// Number of words the PIO pushes for the response
+    pub fn words(self) -> usize {
+        match self {
+            Response::None => 1,
+            Response::R2 => 5,
+            _ => 2,
+        }
+    }
+}
+
+/// Encode a command as the two words fed to the command state machine
+///
+/// The first word holds the number of bits to send minus one, followed by
+/// the first 24 bits of the command. The second word holds the remaining 24
+/// bits, followed by the number of response bits to receive minus one, or 0
+/// if there is no response.
+pub fn command_words(index: u8, arg: u32, response: Response) -> [u32; 2] {
+    let arg = arg.to_be_bytes();
+    let start = [0x40 | index, arg[0], arg[1], arg[2], arg[3]];
+    let crc = crc7(&start) << 1 | 1;
+    [
+        u32::from_be_bytes([47, start[0], start[1], start[2]]),
+        u32::from_be_bytes([start[3], start[4], crc, response.bits().saturating_sub(1)]),
+    ]
+}
+
+/// Decode a 48 bit response into command index and argument
+///
+/// The PIO pushes the first 32 bits in one word and the last 16 bits in the
+/// lower half of a second one. Returns `None` if the CRC doesn't match.
+pub fn parse_short(words: &[u32], check_crc: bool) -> Option<(u8, u32)> {
+    let index = (words[0] >> 24) as u8 & 0x3f;
+    let arg = words[0] << 8 | (words[1] >> 8) & 0xff;
+    let crc = (words[1] >> 1) as u8 & 0x7f;
+    if check_crc {
+        let head = words[0].to_be_bytes();
+        let start = [head[0], head[1], head[2], head[3], (words[1] >> 8) as u8];
+        if crc7(&start) != crc {
+            return None;
+        }
+    }
+    Some((index, arg))
+}
+
+/// Decode a 136 bit response into the 128 bit register it carries
+///
+/// Returns `None` if the CRC of the register doesn't match.
+pub fn parse_long(words: &[u32]) -> Option<u128> {
+    let register = u128::from(words[0]) << 104
+        | u128::from(words[1]) << 72
+        | u128::from(words[2]) << 40
+        | u128::from(words[3]) << 8
+        | u128::from(words[4] & 0xff);
+    let bytes = register.to_be_bytes();
+    if crc7(&bytes[..15]) != bytes[15] >> 1 {
+        return None;
+    }
+    Some(register)
+}
+
+/// Number of 512 byte blocks of a card, from its CSD register
+pub fn csd_blocks(csd: u128) -> u32 {
+    let bits = |high: u32, low: u32| (csd >> low) as u32 & ((1 << (high - low + 1)) - 1);
+    match bits(127, 126) {
+        // SDHC and SDXC
+        1 => (bits(69, 48) + 1) << 10,
+        // SDSC
+        _ => {
+            let c_size = bits(73, 62);
+            let c_size_mult = bits(49, 47);
+            let read_bl_len = bits(83, 80);
+            (c_size + 1) << (c_size_mult + 2 + read_bl_len - 9)
+        }
+    }
+}
+
+/// The state of the card, from the card status of an R1 response
+pub fn current_state(status: u32) -> u32 {
+    (status >> 9) & 0xf
+}
+
+/// Whether the status block returned by SWITCH_FUNC reports function group
+/// 1 as switched to high speed
+pub fn switched_to_high_speed(status: &[u8; 64]) -> bool {
+    status[16] & 0x0f == 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The words the PIO pushes for a 48 bit response
+    fn short_response(index: u8, arg: u32) -> [u32; 2] {
+        let arg = arg.to_be_bytes();
+        let start = [index, arg[0], arg[1], arg[2], arg[3]];
+        let crc = crc7(&start) << 1 | 1;
+        [
+            u32::from_be_bytes([start[0], start[1], start[2], start[3]]),
+            u32::from_be_bytes([0, 0, start[4], crc]),
+        ]
+    }
+
+    /// The words the PIO pushes for a 136 bit response carrying `register`,
+    /// whose last byte is replaced by its CRC and the end bit
+    fn long_response(register: u128) -> [u32; 5] {
+        let mut bytes = register.to_be_bytes();
+        bytes[15] = crc7(&bytes[..15]) << 1 | 1;
+        let register = u128::from_be_bytes(bytes);
+        [
+            0x3f00_0000 | (register >> 104) as u32,
+            (register >> 72) as u32,
+            (register >> 40) as u32,
+            (register >> 8) as u32,
+            register as u32 & 0xff,
+        ]
+    }
+
+    #[test]
+    fn commands_are_encoded() {
+        // 48 bits to send, no response, and the CRC of GO_IDLE_STATE
+        assert_eq!(
+            command_words(GO_IDLE_STATE, 0, Response::None),
+            [0x2f40_0000, 0x0000_9500]
+        );
+        let crc = crc7(&[0x51, 0x12, 0x34, 0x56, 0x78]) << 1 | 1;
+        assert_eq!(
+            command_words(READ_SINGLE_BLOCK, 0x1234_5678, Response::R1),
+            [0x2f51_1234, u32::from_be_bytes([0x56, 0x78, crc, 47])]
+        );
+        assert_eq!(command_words(SEND_CSD, 0, Response::R2)[1] & 0xff, 135);
+    }
+
+    #[test]
+    fn short_responses_are_parsed() {
+        let words = short_response(SEND_STATUS, 0x0000_0900);
+        assert_eq!(parse_short(&words, true), Some((SEND_STATUS, 0x0000_0900)));
+        assert_eq!(current_state(0x0000_0900), STATE_TRAN);
+
+        // A flipped bit fails the CRC, unless it isn't checked like for R3
+        let corrupted = [words[0] ^ 1, words[1]];
+        assert_eq!(parse_short(&corrupted, true), None);
+        assert_eq!(
+            parse_short(&corrupted, false),
+            Some((SEND_STATUS, 0x0000_0800))
+        );
+        let ocr = [0x3f80_ff80, 0x0000_00ff];
+        assert_eq!(parse_short(&ocr, false), Some((0x3f, 0x80ff_8000)));
+    }
+
+    #[test]
+    fn long_responses_are_parsed() {
+        let csd = 0x400e_0032_5b59_0000_3b37_7f80_0a40_4000;
+        let words = long_response(csd);
+        let register = parse_long(&words).unwrap();
+        assert_eq!(register >> 8, csd >> 8);
+
+        let corrupted = [words[0], words[1] ^ 1, words[2], words[3], words[4]];
+        assert_eq!(parse_long(&corrupted), None);
+    }
+
+    #[test]
+    fn capacity_from_csd() {
+        // CSD version 2, with a C_SIZE of 0x3b37 in bits 69 to 48
+        let sdhc = 0x400e_0032_5b59_0000_3b37_7f80_0a40_4000;
+        assert_eq!(csd_blocks(sdhc), (0x3b37 + 1) << 10);
+
+        // CSD version 1, with 1024 byte blocks and a C_SIZE_MULT of 7
+        let c_size = 0xeffu128;
+        let sdsc = 0xa << 80 | c_size << 62 | 7 << 47;
+        assert_eq!(csd_blocks(sdsc), (0xeff + 1) << 10);
+    }
+
+    #[test]
+    fn high_speed_switch_status() {
+        let mut status = [0; 64];
+        assert!(!switched_to_high_speed(&status));
+        status[16] = 0x01;
+        assert!(switched_to_high_speed(&status));
+        status[16] = 0x0f;
+        assert!(!switched_to_high_speed(&status));
+    }
+}
@@ -0,0 +1,103 @@
+//! Checksums of the SD bus
+
+/// CRC7 of a command or response, polynomial x^7 + x^3 + 1
+pub fn crc7(data: &[u8]) -> u8 {
+    let mut crc = 0u8;
+    for &byte in data {
+        for bit in (0..8).rev() {
+            let feedback = ((byte >> bit) ^ (crc >> 6)) & 1;
+            crc = (crc << 1) & 0x7f;
+            if feedback != 0 {
+                crc ^= 0x09;
+            }
+        }
+    }
+    crc
+}
+
+/// The CRC16 of each of the four data lines, as sent after a data block
+///
+/// `words` are the words shifted in or out by the PIO, 8 nibbles each with
+/// the first nibble in the upper bits. Every line has its own CRC16 with the
+/// polynomial x^16 + x^12 + x^5 + 1. The four are computed at once and
+/// returned interleaved the same way, so the result can be compared with
+/// the two words following the data.
+pub fn crc16_4bit(words: &[u32]) -> u64 {
+    let mut crc = 0u64;
+    for &data_in in words {
+        // Shift out 8 bits of each line
+        let mut data_out = (crc >> 32) as u32;
+        crc <<= 32;
+
+        // The outgoing bits feed back into the taps at x^12, x^5 and x^0.
+        // The x^12 tap is 4 bits (16 nibbles) behind, which is folded in
+        // first for both the outgoing and the incoming bits.
+        data_out ^= data_out >> 16;
+        data_out ^= data_in >> 16;
+
+        let xorred = u64::from(data_out ^ data_in);
+        crc ^= xorred;
+        crc ^= xorred << (5 * 4);
+        crc ^= xorred << (12 * 4);
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The CRC16 of each line, one bit at a time, interleaved like
+    /// [crc16_4bit]
+    fn crc16_by_line(words: &[u32]) -> u64 {
+        let mut crcs = [0u16; 4];
+        for word in words {
+            for nibble in (0..8).rev().map(|n| word >> (4 * n)) {
+                for (line, crc) in crcs.iter_mut().enumerate() {
+                    let feedback = (nibble >> line) as u16 ^ *crc >> 15;
+                    *crc <<= 1;
+                    if feedback & 1 != 0 {
+                        *crc ^= 0x1021;
+                    }
+                }
+            }
+        }
+        (0..16).fold(0, |crc, bit| {
+            let nibble = crcs.iter().enumerate().fold(0, |nibble, (line, crc)| {
+                nibble | u64::from(crc >> (15 - bit) & 1) << line
+            });
+            crc << 4 | nibble
+        })
+    }
+
+    #[test]
+    fn crc7_of_commands() {
+        // GO_IDLE_STATE, SEND_IF_COND and READ_SINGLE_BLOCK, as sent in SPI
+        // mode, where the CRC is followed by the end bit
+        assert_eq!(crc7(&[0x40, 0, 0, 0, 0]) << 1 | 1, 0x95);
+        assert_eq!(crc7(&[0x48, 0, 0, 0x01, 0xaa]) << 1 | 1, 0x87);
+        assert_eq!(crc7(&[0x51, 0, 0, 0, 0]) << 1 | 1, 0x55);
+    }
+
+    #[test]
+    fn crc16_of_erased_block() {
+        // 512 bytes of 0xff on a line give 0x7fa1, from the specification,
+        // which is in bit 0 of each nibble for DAT0
+        let crc = crc16_4bit(&[u32::MAX; 512]);
+        assert_eq!(crc & 0x1111_1111_1111_1111, 0x0111_1111_1010_0001);
+    }
+
+    #[test]
+    fn crc16_matches_each_line() {
+        let mut seed = 1u32;
+        let words: [u32; 128] = core::array::from_fn(|_| {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed
+        });
+        assert_eq!(crc16_4bit(&words), crc16_by_line(&words));
+        assert_eq!(crc16_4bit(&words[..1]), crc16_by_line(&words[..1]));
+        assert_eq!(crc16_4bit(&[]), 0);
+    }
+}
@@ -0,0 +1,914 @@
+//! The microSD card slot, driven as a 4 bit SDIO bus by PIO
+//!
+//! The slot is wired for the SD bus, with CMD, CLK and four data lines.
+//! Compared to SPI, this transfers four bits per clock cycle at up to
+//! 50 MHz, which makes a big difference for data logging.
+//!
+//! | SD   | RP2040 | Alias             |
+//! |------|--------|-------------------|
+//! | CLK  | GPIO18 | [crate::SdClk]    |
+//! | CMD  | GPIO19 | [crate::SdCmd]    |
+//! | DAT0 | GPIO20 | [crate::SdData0]  |
+//! | DAT1 | GPIO21 | [crate::SdData1]  |
+//! | DAT2 | GPIO22 | [crate::SdData2]  |
+//! | DAT3 | GPIO23 | [crate::SdData3]  |
+//! | CD   | GPIO15 | [crate::SdCd]     |
+//!
+//! [Sdio] runs the bus with three PIO state machines. State machine 0 of
+//! PIO0 generates the clock and handles commands. State machines 0 and 1 of
+//! PIO1 receive and send data blocks, which are moved by DMA and protected
+//! by a CRC16 per data line.
+//!
+//! [SdioCard] initialises the card, switches to high speed mode if the card
+//! supports it, and implements [BlockDevice], so it can be used with the
+//! [VolumeManager](embedded_sdmmc::VolumeManager) of `embedded_sdmmc`.
+//! Like [embedded_sdmmc::SdCard], it initialises the card on first use.
+//!
+//! [CardDetect] reports cards being inserted into or removed from the slot.
+//!
+//! ```ignore
+//! let (mut pio0, sm0, _, _, _) = pac.PIO0.split(&mut pac.RESETS);
+//! let (mut pio1, sm1_0, sm1_1, _, _) = pac.PIO1.split(&mut pac.RESETS);
+//! let dma = pac.DMA.split(&mut pac.RESETS);
+//! let sdio = Sdio::new(
+//!     &mut pio0,
+//!     &mut pio1,
+//!     SdioStateMachines { cmd: sm0, data_rx: sm1_0, data_tx: sm1_1 },
+//!     SdioPins {
+//!         clk: pins.sclk.reconfigure(),
+//!         cmd: pins.mosi.reconfigure(),
+//!         d0: pins.miso.reconfigure(),
+//!         d1: pins.sdio_data1.reconfigure(),
+//!         d2: pins.sdio_data2.reconfigure(),
+//!         d3: pins.sd_cs.reconfigure(),
+//!     },
+//!     timer,
+//!     clocks.system_clock.freq(),
+//! );
+//! let buffer = cortex_m::singleton!(: DmaBuffer = [0; BUFFER_WORDS]).unwrap();
+//! let card = SdioCard::new(sdio, dma.ch0, buffer);
+//! let mut volume_mgr = VolumeManager::new(card, time_source);
+//! ```
+
+use core::cell::RefCell;
+use core::convert::Infallible;
+
+use embedded_hal::digital::InputPin;
+use embedded_sdmmc::{Block, BlockCount, BlockDevice, BlockIdx};
+use pio::{Instruction, InstructionOperands, JmpCondition, SetDestination};
+
+use crate::hal::dma::{single_buffer, SingleChannel};
+use crate::hal::fugit::{HertzU32, RateExtU32};
+use crate::hal::gpio::Interrupt;
+use crate::hal::pio::{
+    MovStatusConfig, PIOBuilder, PinDir, Running, Rx, ShiftDirection, StateMachine, Tx,
+    UninitStateMachine, ValidStateMachine, PIO, SM0, SM1,
+};
+use crate::hal::Timer;
+use crate::pac::{PIO0, PIO1};
+use crate::{SdCd, SdClk, SdCmd, SdData0, SdData1, SdData2, SdData3};
+
+mod card;
+mod crc;
+
+use card::*;
+use crc::crc16_4bit;
+
+/// Number of words in the DMA buffer of a [SdioCard]
+///
+/// A block of 512 bytes is sent as 128 words, followed by the two words of
+/// its CRC. Sending needs another word in front, receiving one after.
+pub const BUFFER_WORDS: usize = 131;
+
+/// The DMA buffer of a [SdioCard]
+pub type DmaBuffer = [u32; BUFFER_WORDS];
+
+/// Words in a data block, without CRC
+const BLOCK_WORDS: usize = 128;
+
+/// Nibbles received for a block: data, CRC, end bit and idle bus
+const READ_NIBBLES: u32 = BUFFER_WORDS as u32 * 8;
+
+/// Nibbles sent for a block: data and CRC
+const WRITE_NIBBLES: u32 = (BLOCK_WORDS as u32 + 2) * 8;
+
+/// Words received for the 64 byte status of SWITCH_FUNC, including CRC
+const SWITCH_WORDS: usize = 18;
+
+/// Bus clock while identifying the card
+const INIT_CLOCK_KHZ: u32 = 400;
+
+/// Bus clock of the default speed mode
+const DEFAULT_CLOCK_MHZ: u32 = 25;
+
+/// Bus clock of the high speed mode
+const HIGH_SPEED_CLOCK_MHZ: u32 = 50;
+
+const COMMAND_TIMEOUT_US: u64 = 10_000;
+const READ_TIMEOUT_US: u64 = 100_000;
+const WRITE_TIMEOUT_US: u64 = 500_000;
+const INIT_TIMEOUT_US: u64 = 1_000_000;
+
+/// Pushes the ISR, to feed a DMA transfer waiting for data which never came
+const PUSH_NOBLOCK: Instruction = Instruction {
+    operands: InstructionOperands::PUSH {
+        if_full: false,
+        block: false,
+    },
+    delay: 0,
+    side_set: None,
+};
+
+/// Releases the data lines, after a state machine stopped while sending
+const RELEASE_PINS: Instruction = Instruction {
+    operands: InstructionOperands::SET {
+        destination: SetDestination::PINDIRS,
+        data: 0,
+    },
+    delay: 0,
+    side_set: None,
+};
+
+/// Errors of the card or the bus
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The card didn't respond or send data in time
+    Timeout,
+    /// A command response had a bad CRC or was for another command
+    Response,
+    /// The card reported an error, with its card status
+    Status(u32),
+    /// A data block was corrupted on the bus
+    Crc,
+    /// The card couldn't program a written block
+    WriteFailed,
+    /// The card doesn't support the voltage range of the board
+    Unsupported,
+}
+
+/// The pins of the SD bus
+pub struct SdioPins {
+    /// Clock
+    pub clk: SdClk,
+    /// Command and response
+    pub cmd: SdCmd,
+    /// Data line 0, also used as busy signal
+    pub d0: SdData0,
+    /// Data line 1
+    pub d1: SdData1,
+    /// Data line 2
+    pub d2: SdData2,
+    /// Data line 3
+    pub d3: SdData3,
+}
+
+/// The PIO state machines running the SD bus
+pub struct SdioStateMachines {
+    /// Clock and commands
+    pub cmd: UninitStateMachine<(PIO0, SM0)>,
+    /// Receiving data
+    pub data_rx: UninitStateMachine<(PIO1, SM0)>,
+    /// Sending data
+    pub data_tx: UninitStateMachine<(PIO1, SM1)>,
+}
+
+/// The SD bus, clocked and driven by PIO
+pub struct Sdio {
+    cmd_sm: StateMachine<(PIO0, SM0), Running>,
+    cmd_rx: Rx<(PIO0, SM0)>,
+    cmd_tx: Tx<(PIO0, SM0)>,
+    rx_sm: StateMachine<(PIO1, SM0), Running>,
+    data_rx: Option<Rx<(PIO1, SM0)>>,
+    rx_count: Tx<(PIO1, SM0)>,
+    tx_sm: StateMachine<(PIO1, SM1), Running>,
+    tx_status: Rx<(PIO1, SM1)>,
+    data_tx: Option<Tx<(PIO1, SM1)>>,
+    /// Where the programs of the state machines start
+    cmd_start: u8,
+    rx_start: u8,
+    tx_start: u8,
+    pins: SdioPins,
+    timer: Timer,
+    sys_freq: HertzU32,
+    clock: HertzU32,
+}
+
+impl Sdio {
+    /// Install the PIO programs and start clocking the bus at 400 kHz
+    ///
+    /// The programs take 18 instructions of PIO0 and 30 of PIO1.
+    pub fn new(
+        pio0: &mut PIO<PIO0>,
+        pio1: &mut PIO<PIO1>,
+        state_machines: SdioStateMachines,
+        pins: SdioPins,
+        timer: Timer,
+        sys_freq: HertzU32,
+    ) -> Self {
+        let clk = pins.clk.id().num;
+        let cmd = pins.cmd.id().num;
+        let d0 = pins.d0.id().num;
+
+        let program = pio_proc::pio_file!("./src/sdio.pio", select_program("sdio_cmd_clk"));
+        let program = pio0.install(&program.program).unwrap();
+        let cmd_start = program.offset();
+        let (mut cmd_sm, cmd_rx, cmd_tx) = PIOBuilder::from_installed_program(program)
+            .side_set_pin_base(clk)
+            .set_pins(cmd, 1)
+            .out_pins(cmd, 1)
+            .in_pin_base(cmd)
+            .jmp_pin(cmd)
+            .set_mov_status_config(MovStatusConfig::Tx(2))
+            .out_shift_direction(ShiftDirection::Left)
+            .autopull(true)
+            .pull_threshold(32)
+            .in_shift_direction(ShiftDirection::Left)
+            .autopush(true)
+            .push_threshold(32)
+            .clock_divisor_fixed_point(divisor(sys_freq, INIT_CLOCK_KHZ.kHz()), 0)
+            .build(state_machines.cmd);
+        cmd_sm.set_pindirs([(clk, PinDir::Output), (cmd, PinDir::Input)]);
+
+        let program = pio_proc::pio_file!("./src/sdio.pio", select_program("sdio_data_rx"));
+        let program = pio1.install(&program.program).unwrap();
+        let rx_start = program.offset();
+        let (rx_sm, data_rx, rx_count) = PIOBuilder::from_installed_program(program)
+            .in_pin_base(d0)
+            .in_shift_direction(ShiftDirection::Left)
+            .autopush(true)
+            .push_threshold(32)
+            .autopull(true)
+            .pull_threshold(32)
+            .build(state_machines.data_rx);
+
+        let program = pio_proc::pio_file!("./src/sdio.pio", select_program("sdio_data_tx"));
+        let program = pio1.install(&program.program).unwrap();
+        let tx_start = program.offset();
+        let (mut tx_sm, tx_status, data_tx) = PIOBuilder::from_installed_program(program)
+            .out_pins(d0, 4)
+            .set_pins(d0, 4)
+            .in_pin_base(d0)
+            .out_shift_direction(ShiftDirection::Left)
+            .autopull(true)
+            .pull_threshold(32)
+            .in_shift_direction(ShiftDirection::Left)
+            .build(state_machines.data_tx);
+        tx_sm.set_pindirs((d0..d0 + 4).map(|pin| (pin, PinDir::Input)));
+
+        let mut sdio = Sdio {
+            cmd_sm: cmd_sm.start(),
+            cmd_rx,
+            cmd_tx,
+            rx_sm: rx_sm.start(),
+            data_rx: Some(data_rx),
+            rx_count,
+            tx_sm: tx_sm.start(),
+            tx_status,
+            data_tx: Some(data_tx),
+            cmd_start,
+            rx_start,
+            tx_start,
+            pins,
+            timer,
+            sys_freq,
+            clock: 0.Hz(),
+        };
+        sdio.set_clock(INIT_CLOCK_KHZ.kHz());
+        sdio
+    }
+
+    /// The clock of the bus
+    pub fn clock(&self) -> HertzU32 {
+        self.clock
+    }
+
+    /// Stop the state machines and release them and the pins
+    ///
+    /// The programs stay installed.
+    pub fn free(self) -> (SdioStateMachines, SdioPins, Timer) {
+        let (cmd, _) = self.cmd_sm.uninit(self.cmd_rx, self.cmd_tx);
+        let (data_rx, _) = self.rx_sm.uninit(self.data_rx.unwrap(), self.rx_count);
+        let (data_tx, _) = self.tx_sm.uninit(self.tx_status, self.data_tx.unwrap());
+        (
+            SdioStateMachines {
+                cmd,
+                data_rx,
+                data_tx,
+            },
+            self.pins,
+            self.timer,
+        )
+    }
+
+    /// Set the bus clock to at most `freq`
+    fn set_clock(&mut self, freq: HertzU32) {
+        let divisor = divisor(self.sys_freq, freq);
+        self.cmd_sm.clock_divisor_fixed_point(divisor, 0);
+        self.clock = self.sys_freq / (2 * u32::from(divisor));
+    }
+
+    fn deadline(&self, timeout_us: u64) -> u64 {
+        self.timer.get_counter().ticks() + timeout_us
+    }
+
+    fn expired(&self, deadline: u64) -> bool {
+        self.timer.get_counter().ticks() >= deadline
+    }
+
+    /// Wait for at least `cycles` cycles of the bus clock
+    fn delay_cycles(&self, cycles: u32) {
+        let us = u64::from(cycles) * 1_000_000 / u64::from(self.clock.to_Hz()) + 1;
+        let deadline = self.deadline(us);
+        while !self.expired(deadline) {}
+    }
+
+    /// Send a command and receive the words of its response
+    fn command(&mut self, index: u8, arg: u32, response: Response) -> Result<[u32; 5], Error> {
+        let [first, second] = command_words(index, arg, response);
+        self.cmd_tx.write(first);
+        self.cmd_tx.write(second);
+
+        let mut words = [0; 5];
+        let deadline = self.deadline(COMMAND_TIMEOUT_US);
+        for word in words.iter_mut().take(response.words()) {
+            *word = loop {
+                if let Some(word) = self.cmd_rx.read() {
+                    break word;
+                }
+                if self.expired(deadline) {
+                    // Stop waiting for the response
+                    reset(&mut self.cmd_sm, self.cmd_start);
+                    return Err(Error::Timeout);
+                }
+            };
+        }
+        Ok(words)
+    }
+
+    /// Send a command with a 48 bit response and return its argument
+    fn short(&mut self, index: u8, arg: u32, response: Response) -> Result<u32, Error> {
+        let words = self.command(index, arg, response)?;
+        // R3 carries neither command index nor CRC
+        let check = response != Response::R3;
+        match parse_short(&words, check) {
+            Some((response_index, arg)) if !check || response_index == index => Ok(arg),
+            _ => Err(Error::Response),
+        }
+    }
+
+    /// Send a command with a 136 bit response and return the register
+    fn long(&mut self, index: u8, arg: u32) -> Result<u128, Error> {
+        let words = self.command(index, arg, Response::R2)?;
+        parse_long(&words).ok_or(Error::Response)
+    }
+
+    /// Send a command with an R1 or R1b response and check the card status
+    fn status(&mut self, index: u8, arg: u32, response: Response) -> Result<u32, Error> {
+        let status = self.short(index, arg, response)?;
+        if status & STATUS_ERRORS != 0 {
+            return Err(Error::Status(status));
+        }
+        Ok(status)
+    }
+
+    /// Send a command with an R1 response and check the card status
+    fn r1(&mut self, index: u8, arg: u32) -> Result<u32, Error> {
+        self.status(index, arg, Response::R1)
+    }
+
+    /// Send a command with an R1b response and wait while the card is busy
+    fn r1b(&mut self, index: u8, arg: u32) -> Result<u32, Error> {
+        let status = self.status(index, arg, Response::R1b)?;
+        self.wait_busy()?;
+        Ok(status)
+    }
+
+    /// Poll the card status until the card is back in the `tran` state,
+    /// having programmed all written blocks
+    fn wait_tran(&mut self, rca: u16) -> Result<(), Error> {
+        let deadline = self.deadline(WRITE_TIMEOUT_US);
+        while current_state(self.r1(SEND_STATUS, u32::from(rca) << 16)?) != STATE_TRAN {
+            if self.expired(deadline) {
+                return Err(Error::Timeout);
+            }
+        }
+        Ok(())
+    }
+
+    /// Send an application specific command with an R1 response
+    fn app_r1(&mut self, rca: u16, index: u8, arg: u32) -> Result<u32, Error> {
+        self.r1(APP_CMD, u32::from(rca) << 16)?;
+        self.r1(index, arg)
+    }
+
+    /// Wait while the card holds DAT0 low
+    fn wait_busy(&mut self) -> Result<(), Error> {
+        // Busy starts a few clock cycles after the response or CRC status
+        self.delay_cycles(8);
+        let deadline = self.deadline(WRITE_TIMEOUT_US);
+        while !matches!(self.pins.d0.as_input().is_high(), Ok(true)) {
+            if self.expired(deadline) {
+                return Err(Error::Timeout);
+            }
+        }
+        Ok(())
+    }
+
+    /// Send a command and receive a data block into `buffer` by DMA
+    ///
+    /// The transfer is started before the command, as the card may send the
+    /// block right after its response.
+    fn read_block<CH: SingleChannel>(
+        &mut self,
+        ch: CH,
+        buffer: &'static mut DmaBuffer,
+        index: u8,
+        arg: u32,
+    ) -> (CH, &'static mut DmaBuffer, Result<(), Error>) {
+        self.rx_count.write(READ_NIBBLES - 1);
+        let rx = self.data_rx.take().unwrap();
+        let transfer = single_buffer::Config::new(ch, rx, buffer).start();
+
+        let mut result = self.r1(index, arg).map(drop);
+        if result.is_ok() {
+            let deadline = self.deadline(READ_TIMEOUT_US);
+            while !transfer.is_done() {
+                if self.expired(deadline) {
+                    result = Err(Error::Timeout);
+                    break;
+                }
+            }
+        }
+        if result.is_err() {
+            // Finish the transfer with junk, to get back channel and buffer
+            while !transfer.is_done() {
+                self.rx_sm.exec_instruction(PUSH_NOBLOCK);
+            }
+        }
+
+        let (ch, rx, buffer) = transfer.wait();
+        self.data_rx = Some(rx);
+        if result.is_err() {
+            reset(&mut self.rx_sm, self.rx_start);
+        }
+        (ch, buffer, result)
+    }
+
+    /// Send a data block from `buffer` by DMA and wait until it's programmed
+    ///
+    /// The buffer starts with the number of nibbles to send, minus one. The
+    /// transfer always finishes, as the bus is clocked continuously.
+    fn write_block<CH: SingleChannel>(
+        &mut self,
+        ch: CH,
+        buffer: &'static mut DmaBuffer,
+    ) -> (CH, &'static mut DmaBuffer, Result<(), Error>) {
+        let tx = self.data_tx.take().unwrap();
+        let (ch, buffer, tx) = single_buffer::Config::new(ch, buffer, tx).start().wait();
+        self.data_tx = Some(tx);
+
+        // The 3 status bits of the CRC status token, followed by its end bit
+        let deadline = self.deadline(COMMAND_TIMEOUT_US);
+        let token = loop {
+            if let Some(token) = self.tx_status.read() {
+                break token;
+            }
+            if self.expired(deadline) {
+                self.tx_sm.exec_instruction(RELEASE_PINS);
+                reset(&mut self.tx_sm, self.tx_start);
+                return (ch, buffer, Err(Error::Timeout));
+            }
+        };
+        let result = match (token >> 1) & 0b111 {
+            0b010 => self.wait_busy(),
+            0b101 => Err(Error::Crc),
+            _ => Err(Error::WriteFailed),
+        };
+        (ch, buffer, result)
+    }
+
+    /// Switch the card to high speed mode
+    ///
+    /// Returns whether the card supports it. The 64 byte status of the
+    /// switch is short enough to be read without DMA at the initial clock.
+    fn switch_high_speed(&mut self) -> Result<bool, Error> {
+        self.rx_count.write(SWITCH_WORDS as u32 * 8 - 1);
+        let mut words = [0u32; SWITCH_WORDS];
+        let mut result = self.r1(SWITCH_FUNC, SWITCH_HIGH_SPEED).map(drop);
+        let deadline = self.deadline(READ_TIMEOUT_US);
+        let mut received = words.iter_mut();
+        while let (Ok(()), Some(word)) = (result, received.next()) {
+            loop {
+                if let Some(data) = self.data_rx.as_mut().unwrap().read() {
+                    *word = data;
+                    break;
+                }
+                if self.expired(deadline) {
+                    result = Err(Error::Timeout);
+                    break;
+                }
+            }
+        }
+
+        if let Err(e) = result {
+            reset(&mut self.rx_sm, self.rx_start);
+            return match e {
+                // Cards before version 1.10 don't know the command
+                Error::Status(_) => Ok(false),
+                e => Err(e),
+            };
+        }
+
+        let crc = u64::from(words[16]) << 32 | u64::from(words[17]);
+        if crc16_4bit(&words[..16]) != crc {
+            return Err(Error::Crc);
+        }
+        let mut status = [0u8; 64];
+        for (bytes, word) in status.chunks_exact_mut(4).zip(words.iter()) {
+            bytes.copy_from_slice(&word.to_be_bytes());
+        }
+        // The card switches within 8 clock cycles after the status
+        self.delay_cycles(8);
+        Ok(switched_to_high_speed(&status))
+    }
+}
+
+/// Starts the program of a state machine over, with empty FIFOs
+///
+/// `restart` doesn't touch the program counter, so a state machine waiting
+/// for a response or a start bit which never comes would keep waiting.
+fn reset<SM: ValidStateMachine>(sm: &mut StateMachine<SM, Running>, start: u8) {
+    sm.restart();
+    sm.exec_instruction(Instruction {
+        operands: InstructionOperands::JMP {
+            condition: JmpCondition::Always,
+            address: start,
+        },
+        delay: 0,
+        side_set: None,
+    });
+    sm.clear_fifos();
+}
+
+/// Clock divisor of the command state machine for a bus clock of at most
+/// `freq`, with two instructions per clock cycle
+fn divisor(sys_freq: HertzU32, freq: HertzU32) -> u16 {
+    let divisor = (sys_freq.to_Hz() - 1) / (2 * freq.to_Hz()) + 1;
+    divisor.clamp(1, u32::from(u16::MAX)) as u16
+}
+
+/// The identified card
+#[derive(Debug, Clone, Copy)]
+struct Card {
+    rca: u16,
+    high_capacity: bool,
+    blocks: u32,
+}
+
+impl Card {
+    /// The address of a block in data commands
+    fn address(&self, block: u32) -> u32 {
+        if self.high_capacity {
+            block
+        } else {
+            block * Block::LEN_U32
+        }
+    }
+}
+
+struct Inner<CH> {
+    sdio: Sdio,
+    dma: Option<(CH, &'static mut DmaBuffer)>,
+    card: Option<Card>,
+    high_speed: bool,
+}
+
+impl<CH: SingleChannel> Inner<CH> {
+    /// The card, initialised if it isn't yet
+    fn acquire(&mut self) -> Result<Card, Error> {
+        if let Some(card) = self.card {
+            return Ok(card);
+        }
+        let card = self.init_card();
+        #[cfg(feature = "defmt")]
+        match card {
+            Ok(card) => defmt::info!(
+                "sdio: card of {=u32} blocks, high capacity: {=bool}",
+                card.blocks,
+                card.high_capacity
+            ),
+            Err(e) => defmt::warn!("sdio: the card doesn't start, {}", e),
+        }
+        if card.is_err() {
+            self.sdio.set_clock(INIT_CLOCK_KHZ.kHz());
+        }
+        self.card = card.ok();
+        card
+    }
+
+    fn init_card(&mut self) -> Result<Card, Error> {
+        let sdio = &mut self.sdio;
+        sdio.set_clock(INIT_CLOCK_KHZ.kHz());
+        // The card needs 74 clock cycles to power up
+        sdio.delay_cycles(80);
+        sdio.command(GO_IDLE_STATE, 0, Response::None)?;
+
+        let version2 = match sdio.short(SEND_IF_COND, IF_COND, Response::R7) {
+            Ok(arg) if arg & 0xfff == IF_COND => true,
+            Ok(_) => return Err(Error::Unsupported),
+            // Version 1 cards don't respond
+            Err(Error::Timeout) => false,
+            Err(e) => return Err(e),
+        };
+        let hcs = if version2 { OP_COND_HCS } else { 0 };
+
+        let deadline = sdio.deadline(INIT_TIMEOUT_US);
+        let ocr = loop {
+            // The status may still flag SEND_IF_COND as illegal
+            sdio.short(APP_CMD, 0, Response::R1)?;
+            let ocr = sdio.short(SD_SEND_OP_COND, OP_COND | hcs, Response::R3)?;
+            if ocr & OP_COND_BUSY != 0 {
+                break ocr;
+            }
+            if sdio.expired(deadline) {
+                return Err(Error::Timeout);
+            }
+        };
+        if ocr & OP_COND == 0 {
+            return Err(Error::Unsupported);
+        }
+        let high_capacity = ocr & OP_COND_HCS != 0;
+
+        sdio.long(ALL_SEND_CID, 0)?;
+        let rca = (sdio.short(SEND_RELATIVE_ADDR, 0, Response::R6)? >> 16) as u16;
+        let blocks = csd_blocks(sdio.long(SEND_CSD, u32::from(rca) << 16)?);
+        sdio.r1b(SELECT_CARD, u32::from(rca) << 16)?;
+        sdio.app_r1(rca, SET_BUS_WIDTH, BUS_WIDTH_4)?;
+        if !high_capacity {
+            sdio.r1(SET_BLOCKLEN, Block::LEN_U32)?;
+        }
+
+        let high_speed = self.high_speed && sdio.switch_high_speed()?;
+        sdio.set_clock(if high_speed {
+            HIGH_SPEED_CLOCK_MHZ.MHz()
+        } else {
+            DEFAULT_CLOCK_MHZ.MHz()
+        });
+
+        Ok(Card {
+            rca,
+            high_capacity,
+            blocks,
+        })
+    }
+
+    fn read(
+        &mut self,
+        card: Card,
+        block: u32,
+        contents: &mut [u8; Block::LEN],
+    ) -> Result<(), Error> {
+        let (ch, buffer) = self.dma.take().unwrap();
+        let (ch, buffer, result) =
+            self.sdio
+                .read_block(ch, buffer, READ_SINGLE_BLOCK, card.address(block));
+        let result = result.and_then(|()| {
+            let crc = u64::from(buffer[BLOCK_WORDS]) << 32 | u64::from(buffer[BLOCK_WORDS + 1]);
+            if crc16_4bit(&buffer[..BLOCK_WORDS]) != crc {
+                return Err(Error::Crc);
+            }
+            for (bytes, word) in contents.chunks_exact_mut(4).zip(buffer.iter()) {
+                bytes.copy_from_slice(&word.to_be_bytes());
+            }
+            Ok(())
+        });
+        self.dma = Some((ch, buffer));
+        result
+    }
+
+    fn write(&mut self, contents: &[u8; Block::LEN]) -> Result<(), Error> {
+        let (ch, buffer) = self.dma.take().unwrap();
+        buffer[0] = WRITE_NIBBLES - 1;
+        let data = &mut buffer[1..=BLOCK_WORDS];
+        for (word, bytes) in data.iter_mut().zip(contents.chunks_exact(4)) {
+            *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        let crc = crc16_4bit(data);
+        buffer[BLOCK_WORDS + 1] = (crc >> 32) as u32;
+        buffer[BLOCK_WORDS + 2] = crc as u32;
+
+        let (ch, buffer, result) = self.sdio.write_block(ch, buffer);
+        self.dma = Some((ch, buffer));
+        result
+    }
+
+    fn write_blocks(&mut self, card: Card, blocks: &[Block], start: u32) -> Result<(), Error> {
+        let address = card.address(start);
+        if let [block] = blocks {
+            self.sdio.r1(WRITE_BLOCK, address)?;
+            self.write(&block.contents)?;
+            return self.sdio.wait_tran(card.rca);
+        }
+
+        // Let the card erase all blocks up front
+        self.sdio
+            .app_r1(card.rca, SET_WR_BLK_ERASE_COUNT, blocks.len() as u32)?;
+        self.sdio.r1(WRITE_MULTIPLE_BLOCK, address)?;
+        let result = blocks
+            .iter()
+            .try_for_each(|block| self.write(&block.contents));
+        let stop = self.sdio.r1b(STOP_TRANSMISSION, 0);
+        result.and(stop)?;
+        self.sdio.wait_tran(card.rca)
+    }
+}
+
+/// A microSD card on the SD bus
+///
+/// Blocks are read one at a time, and written with a single multiple block
+/// write.
+pub struct SdioCard<CH: SingleChannel> {
+    inner: RefCell<Inner<CH>>,
+}
+
+impl<CH: SingleChannel> SdioCard<CH> {
+    /// Use the card on `sdio`, with a DMA channel and buffer for data
+    /// blocks
+    ///
+    /// The card is initialised on first use.
+    pub fn new(sdio: Sdio, ch: CH, buffer: &'static mut DmaBuffer) -> Self {
+        SdioCard {
+            inner: RefCell::new(Inner {
+                sdio,
+                dma: Some((ch, buffer)),
+                card: None,
+                high_speed: true,
+            }),
+        }
+    }
+
+    /// Allow or prevent switching to high speed mode, which is allowed by
+    /// default
+    ///
+    /// Only takes effect when the card is initialised.
+    pub fn set_high_speed(&mut self, enabled: bool) {
+        self.inner.get_mut().high_speed = enabled;
+    }
+
+    /// Initialise the card, unless it already is
+    pub fn init(&self) -> Result<(), Error> {
+        self.inner.borrow_mut().acquire().map(drop)
+    }
+
+    /// Mark the card as uninitialised, so it is initialised again on next
+    /// use, e.g. after a card has been inserted
+    pub fn mark_card_uninit(&self) {
+        let mut inner = self.inner.borrow_mut();
+        inner.card = None;
+        inner.sdio.set_clock(INIT_CLOCK_KHZ.kHz());
+    }
+
+    /// Whether the card is initialised
+    pub fn is_initialised(&self) -> bool {
+        self.inner.borrow().card.is_some()
+    }
+
+    /// The size of the card in bytes
+    pub fn num_bytes(&self) -> Result<u64, Error> {
+        let card = self.inner.borrow_mut().acquire()?;
+        Ok(u64::from(card.blocks) * Block::LEN as u64)
+    }
+
+    /// The clock of the bus, which is 25 MHz or 50 MHz at most once the
+    /// card is initialised
+    pub fn clock(&self) -> HertzU32 {
+        self.inner.borrow().sdio.clock()
+    }
+
+    /// Release the bus, the DMA channel and the buffer
+    pub fn free(self) -> (Sdio, CH, &'static mut DmaBuffer) {
+        let inner = self.inner.into_inner();
+        let (ch, buffer) = inner.dma.unwrap();
+        (inner.sdio, ch, buffer)
+    }
+}
+
+impl<CH: SingleChannel> BlockDevice for SdioCard<CH> {
+    type Error = Error;
+
+    fn read(
+        &self,
+        blocks: &mut [Block],
+        start_block_idx: BlockIdx,
+        _reason: &str,
+    ) -> Result<(), Self::Error> {
+        let mut inner = self.inner.borrow_mut();
+        let card = inner.acquire()?;
+        for (block, idx) in blocks.iter_mut().zip(start_block_idx.0..) {
+            inner.read(card, idx, &mut block.contents)?;
+        }
+        Ok(())
+    }
+
+    fn write(&self, blocks: &[Block], start_block_idx: BlockIdx) -> Result<(), Self::Error> {
+        if blocks.is_empty() {
+            return Ok(());
+        }
+        let mut inner = self.inner.borrow_mut();
+        let card = inner.acquire()?;
+        inner.write_blocks(card, blocks, start_block_idx.0)
+    }
+
+    fn num_blocks(&self) -> Result<BlockCount, Self::Error> {
+        let card = self.inner.borrow_mut().acquire()?;
+        Ok(BlockCount(card.blocks))
+    }
+}
+
+/// A card being inserted or removed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CardEvent {
+    /// A card was inserted into the slot
+    Inserted,
+    /// The card was removed from the slot
+    Removed,
+}
+
+/// The card detect switch of the slot
+///
+/// The switch connects the pin to ground while a card is inserted.
+pub struct CardDetect {
+    pin: SdCd,
+    inserted: bool,
+}
+
+impl CardDetect {
+    /// Watch the switch, enabling the `IO_IRQ_BANK0` interrupt of the pin on
+    /// both edges
+    pub fn new(mut pin: SdCd) -> Self {
+        pin.set_interrupt_enabled(Interrupt::EdgeLow, true);
+        pin.set_interrupt_enabled(Interrupt::EdgeHigh, true);
+        let inserted = matches!(pin.is_low(), Ok(true));
+        CardDetect { pin, inserted }
+    }
+
+    /// Whether a card is inserted
+    pub fn is_inserted(&mut self) -> bool {
+        matches!(self.pin.is_low(), Ok(true))
+    }
+
+    /// Check whether the card was inserted or removed since the last call,
+    /// and clear the interrupt of the pin
+    ///
+    /// Can be called from the interrupt handler or polled. The switch
+    /// bounces, so an event may be followed by the opposite one shortly
+    /// after.
+    pub fn event(&mut self) -> Option<CardEvent> {
+        self.clear_interrupt();
+        let inserted = self.is_inserted();
+        if inserted == self.inserted {
+            return None;
+        }
+        self.inserted = inserted;
+        Some(if inserted {
+            CardEvent::Inserted
+        } else {
+            CardEvent::Removed
+        })
+    }
+
+    /// Disable the interrupts and release the pin
+    pub fn free(self) -> SdCd {
+        self.pin.set_interrupt_enabled(Interrupt::EdgeLow, false);
+        self.pin.set_interrupt_enabled(Interrupt::EdgeHigh, false);
+        self.pin
+    }
+
+    fn clear_interrupt(&mut self) {
+        self.pin.clear_interrupt(Interrupt::EdgeLow);
+        self.pin.clear_interrupt(Interrupt::EdgeHigh);
+    }
+}
+
+impl embedded_hal::digital::ErrorType for CardDetect {
+    type Error = Infallible;
+}
+
+/// The level of the switch, low while a card is inserted
+///
+/// Reading it clears the interrupt of the pin, so a
+/// [SdManager](crate::sd_card::SdManager) can be polled from the interrupt
+/// handler.
+impl InputPin for CardDetect {
+    fn is_high(&mut self) -> Result<bool, Infallible> {
+        self.clear_interrupt();
+        self.pin.is_high()
+    }
+
+    fn is_low(&mut self) -> Result<bool, Infallible> {
+        self.clear_interrupt();
+        self.pin.is_low()
+    }
+}
@@ -0,0 +1,66 @@
+//! The pins and drivers of the board on top of `embassy-rp`
+//!
+//! [embassy_pins!](crate::embassy_pins) names the pins of the `embassy_rp`
+//! peripherals like [crate::Pins] does, and the functions of this module set up
+//! the button and NeoPixel of the board with the async drivers of
+//! [board_common::embassy]:
+//!
+//! ```ignore
+//! let p = embassy_rp::init(Default::default());
+//! let pins = adafruit_qt_py_rp2040::embassy_pins!(p);
+//! let mut button = embassy::button(pins.button);
+//! button.wait_for_press().await;
+//! let Pio { mut common, sm0, .. } = Pio::new(p.PIO0, Irqs);
+//! let mut neopixel = embassy::neopixel(
+//!     &mut common,
+//!     sm0,
+//!     p.DMA_CH0,
+//!     pins.neopixel_data,
+//!     pins.neopixel_power,
+//! );
+//! neopixel.write(&[RGB8::new(0, 0, 32); embassy::NEOPIXEL_COUNT]).await;
+//! ```
+//!
+//! `embassy-rp` brings its own runtime, critical section and second stage
+//! bootloader, so build the BSP with `default-features = false`.
+//!
+//! Only available with the `embassy` feature.
+
+use embassy_rp::dma;
+use embassy_rp::gpio::{Input, Pull};
+use embassy_rp::peripherals::*;
+use embassy_rp::pio::{Common, Instance, StateMachine};
+use embassy_rp::Peripheral;
+
+use board_common::embassy;
+use board_common::ActiveLow;
+
+#[doc(inline)]
+pub use crate::EmbassyPins as Pins;
+
+/// The BOOT button, see [AsyncUserButton](board_common::AsyncUserButton)
+pub type Button = ActiveLow<Input<'static, PIN_21>>;
+
+/// Sets up [Button]
+pub fn button(pin: PIN_21) -> Button {
+    ActiveLow(Input::new(pin, Pull::Up))
+}
+
+/// The number of NeoPixels on the board
+pub const NEOPIXEL_COUNT: usize = 1;
+
+/// The NeoPixel of the board, driven by the PIO state machine `S`
+pub type NeoPixel<'d, P, const S: usize> = embassy::NeoPixel<'d, P, S, NEOPIXEL_COUNT>;
+
+/// Sets up [NeoPixel] on `sm` of the PIO of `common`, fed by `dma`
+///
+/// The power of the LED is switched on with `power`.
+pub fn neopixel<'d, P: Instance, const S: usize>(
+    common: &mut Common<'d, P>,
+    sm: StateMachine<'d, P, S>,
+    dma: impl Peripheral<P = impl dma::Channel> + 'd,
+    data: PIN_12,
+    power: PIN_11,
+) -> NeoPixel<'d, P, S> {
+    NeoPixel::new(common, sm, dma, data).with_power(power)
+}
@@ -17,7 +17,9 @@ pub static BOOT2_FIRMWARE: [u8; 256] = rp2040_boot2::BOOT_LOADER_GD25Q64CS;
 
 pub use hal::pac;
 
-hal::bsp_pins!(
+board_common::bsp_pins!(
+    embassy: crate::embassy::Pins;
+
     Gpio3 {
         name: mosi,
         aliases: { FunctionSpi, PullNone: Mosi }
@@ -41,7 +43,11 @@ hal::bsp_pins!(
         aliases: { FunctionUart, PullNone: UartTx }
     },
     Gpio21 {
-        name: button
+        name: button,
+        aliases: {
+            /// Input alias for pin [crate::Pins::button].
+            FunctionSioInput, PullUp: ButtonPin
+        }
     },
     Gpio22 {
         name: sda1,
@@ -66,3 +72,16 @@ hal::bsp_pins!(
 );
 
 pub const XOSC_CRYSTAL_FREQ: u32 = 12_000_000;
+
+// FLASH_SIZE_BYTES and the reserved end of the flash, see build.rs
+include!(concat!(env!("OUT_DIR"), "/flash.rs"));
+
+#[cfg(feature = "embassy")]
+pub mod embassy;
+
+pub use board_common;
+
+board_common::board_pins!(Pins, XOSC_CRYSTAL_FREQ);
+
+/// The BOOT button, see [board_common::UserButton]
+pub type Button = board_common::ActiveLow<ButtonPin>;
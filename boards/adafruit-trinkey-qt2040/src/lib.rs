@@ -17,8 +17,16 @@ pub static BOOT2_FIRMWARE: [u8; 256] = rp2040_boot2::BOOT_LOADER_W25Q080;
 
 pub use hal::pac;
 
-hal::bsp_pins!(
-    Gpio12 { name: button },
+board_common::bsp_pins!(
+    embassy: crate::embassy::Pins;
+
+    Gpio12 {
+        name: button,
+        aliases: {
+            /// Input alias for pin [crate::Pins::button].
+            FunctionSioInput, PullUp: ButtonPin
+        }
+    },
     Gpio16 {
         name: sda,
         aliases: { FunctionI2C, PullUp: Sda }
@@ -31,3 +39,16 @@ hal::bsp_pins!(
 );
 
 pub const XOSC_CRYSTAL_FREQ: u32 = 12_000_000;
+
+// FLASH_SIZE_BYTES and the reserved end of the flash, see build.rs
+include!(concat!(env!("OUT_DIR"), "/flash.rs"));
+
+#[cfg(feature = "embassy")]
+pub mod embassy;
+
+pub use board_common;
+
+board_common::board_pins!(Pins, XOSC_CRYSTAL_FREQ);
+
+/// The BOOT button, see [board_common::UserButton]
+pub type Button = board_common::ActiveLow<ButtonPin>;
@@ -0,0 +1,119 @@
+//! # Nano BLE HCI Example
+//!
+//! Resets the NINA-W102 module of the Arduino Nano Connect into a Bluetooth
+//! HCI controller and talks to it over UART.
+//!
+//! Sends an HCI Reset command and lights the on-board LED once the controller
+//! confirmed it with a Command Complete event. A Bluetooth host stack would
+//! sit on top of the same transport.
+//!
+//! See the `Cargo.toml` file for Copyright and license details.
+
+#![no_std]
+#![no_main]
+
+use embedded_hal::digital::OutputPin;
+
+// Ensure we halt the program on panic (if we don't mention this crate it won't
+// be linked)
+use panic_halt as _;
+
+use arduino_nano_connect as bsp;
+
+// A shorter alias for the Peripheral Access Crate, which provides low-level
+// register access
+use bsp::hal::pac;
+
+// A shorter alias for the Hardware Abstraction Layer, which provides
+// higher-level drivers.
+use bsp::hal;
+
+// The HCI transport
+use bsp::nina::ble::{self, H4Transport, NinaControl, PacketType};
+
+/// Opcode of the HCI Reset command
+const HCI_RESET: u16 = 0x0C03;
+
+/// Event code of the Command Complete event
+const COMMAND_COMPLETE: u8 = 0x0E;
+
+/// Entry point to our bare-metal application.
+///
+/// The `#[arduino_nano_connect::entry]` macro ensures the Cortex-M start-up code calls this function
+/// as soon as all global variables and the spinlock are initialised.
+///
+/// The function configures the RP2040 peripherals, resets the Bluetooth
+/// controller and then waits for its response.
+#[arduino_nano_connect::entry]
+fn main() -> ! {
+    // Grab our singleton objects
+    let mut pac = pac::Peripherals::take().unwrap();
+
+    // Set up the watchdog driver - needed by the clock setup code
+    let mut watchdog = hal::Watchdog::new(pac.WATCHDOG);
+
+    // Configure the clocks
+    //
+    // The default is to generate a 125 MHz system clock
+    let clocks = hal::clocks::init_clocks_and_plls(
+        bsp::XOSC_CRYSTAL_FREQ,
+        pac.XOSC,
+        pac.CLOCKS,
+        pac.PLL_SYS,
+        pac.PLL_USB,
+        &mut pac.RESETS,
+        &mut watchdog,
+    )
+    .ok()
+    .unwrap();
+
+    let mut timer = hal::Timer::new(pac.TIMER, &mut pac.RESETS, &clocks);
+
+    // The single-cycle I/O block controls our GPIO pins
+    let sio = hal::Sio::new(pac.SIO);
+
+    // Set the pins up according to their function on this particular board
+    let pins = bsp::Pins::new(
+        pac.IO_BANK0,
+        pac.PADS_BANK0,
+        sio.gpio_bank0,
+        &mut pac.RESETS,
+    );
+
+    // Set the LED to be an output
+    let mut led_pin = pins.sck0.into_push_pull_output();
+
+    // Boot the module into its Bluetooth controller firmware
+    let mut control = NinaControl::new(pins.nina_reset_n, pins.ble_rts);
+    let rx = control.reset_into_hci(pins.ble_rx_cs, &mut timer);
+
+    let uart = ble::hci_uart(
+        pac.UART1,
+        (
+            pins.ble_tx_cipo.reconfigure(),
+            rx,
+            pins.ble_cts_ack.reconfigure(),
+            pins.nina_copi.reconfigure(),
+        ),
+        &mut pac.RESETS,
+        &clocks.peripheral_clock,
+    );
+    let mut hci = H4Transport::new(uart);
+
+    hci.send_command(HCI_RESET, &[]).unwrap();
+
+    loop {
+        // Unknown or oversized packets are skipped by the transport
+        if let Ok(packet) = hci.read_packet() {
+            // Command Complete: event code, length, number of packets, opcode
+            if packet.kind == PacketType::Event
+                && packet.data[0] == COMMAND_COMPLETE
+                && packet.data[3..5] == HCI_RESET.to_le_bytes()
+            {
+                led_pin.set_high().unwrap();
+            }
+        }
+    }
+}
+
+// End of file
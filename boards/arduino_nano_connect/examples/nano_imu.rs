@@ -0,0 +1,133 @@
+//! # Nano IMU Example
+//!
+//! Reads the on-board LSM6DSOX IMU of the Arduino Nano Connect and lights the
+//! on-board LED while the board is upside down. Tapping the board switches
+//! the LED on and off.
+//!
+//! Accelerometer and gyroscope samples are queued in the FIFO of the IMU,
+//! which raises its INT1 line once enough of them are waiting. The same line
+//! signals detected taps.
+//!
+//! See the `Cargo.toml` file for Copyright and license details.
+
+#![no_std]
+#![no_main]
+
+use embedded_hal::digital::{InputPin, OutputPin};
+
+// Ensure we halt the program on panic (if we don't mention this crate it won't
+// be linked)
+use panic_halt as _;
+
+use arduino_nano_connect as bsp;
+
+// A shorter alias for the Peripheral Access Crate, which provides low-level
+// register access
+use bsp::hal::pac;
+
+// A shorter alias for the Hardware Abstraction Layer, which provides
+// higher-level drivers.
+use bsp::hal;
+
+// The IMU driver
+use bsp::imu::{self, AccelScale, DataRate, FifoSample, GyroScale, Lsm6dsox, TapConfig};
+
+/// Number of FIFO samples to wait for, 8 accelerometer and 8 gyroscope
+const WATERMARK: u16 = 16;
+
+/// Entry point to our bare-metal application.
+///
+/// The `#[arduino_nano_connect::entry]` macro ensures the Cortex-M start-up code calls this function
+/// as soon as all global variables and the spinlock are initialised.
+///
+/// The function configures the RP2040 peripherals, then reads the IMU in an
+/// infinite loop.
+#[arduino_nano_connect::entry]
+fn main() -> ! {
+    // Grab our singleton objects
+    let mut pac = pac::Peripherals::take().unwrap();
+
+    // Set up the watchdog driver - needed by the clock setup code
+    let mut watchdog = hal::Watchdog::new(pac.WATCHDOG);
+
+    // Configure the clocks
+    //
+    // The default is to generate a 125 MHz system clock
+    let clocks = hal::clocks::init_clocks_and_plls(
+        bsp::XOSC_CRYSTAL_FREQ,
+        pac.XOSC,
+        pac.CLOCKS,
+        pac.PLL_SYS,
+        pac.PLL_USB,
+        &mut pac.RESETS,
+        &mut watchdog,
+    )
+    .ok()
+    .unwrap();
+
+    // The single-cycle I/O block controls our GPIO pins
+    let sio = hal::Sio::new(pac.SIO);
+
+    // Set the pins up according to their function on this particular board
+    let pins = bsp::Pins::new(
+        pac.IO_BANK0,
+        pac.PADS_BANK0,
+        sio.gpio_bank0,
+        &mut pac.RESETS,
+    );
+
+    // Set the LED to be an output
+    let mut led_pin = pins.sck0.into_push_pull_output();
+
+    // Set up the IMU, tap detection needs at least 416 Hz
+    let mut imu = Lsm6dsox::on_board(
+        pac.I2C0,
+        pins.a4,
+        pins.a5,
+        &mut pac.RESETS,
+        &clocks.system_clock,
+    )
+    .unwrap();
+    imu.set_accel(DataRate::Hz416, AccelScale::G4).unwrap();
+    imu.set_gyro(DataRate::Hz416, GyroScale::Dps500).unwrap();
+    imu.enable_fifo(DataRate::Hz26, DataRate::Hz26, WATERMARK)
+        .unwrap();
+    imu.enable_tap(TapConfig {
+        threshold: 0.5,
+        double_tap: false,
+    })
+    .unwrap();
+
+    let mut int1 = imu::int1_pin(pins.int1);
+
+    let mut enabled = true;
+    let mut upside_down = false;
+    loop {
+        if int1.is_low().unwrap() {
+            continue;
+        }
+
+        // Tapping the board switches the LED on and off
+        let events = imu.events().unwrap();
+        if events.single_tap {
+            enabled = !enabled;
+        }
+        if events.fifo_watermark {
+            imu.drain_fifo(|sample| {
+                if let FifoSample::Accel([_, _, z]) = sample {
+                    upside_down = z < 0.0;
+                }
+            })
+            .unwrap();
+        }
+
+        // Light the LED while the board is upside down
+        if enabled && upside_down {
+            led_pin.set_high().unwrap();
+        } else {
+            led_pin.set_low().unwrap();
+        }
+    }
+}
+
+// End of file
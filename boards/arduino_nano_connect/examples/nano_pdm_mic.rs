@@ -0,0 +1,135 @@
+//! # Nano PDM Microphone Example
+//!
+//! Records audio from the on-board microphone of the Arduino Nano Connect and
+//! lights the on-board LED while it is loud.
+//!
+//! The microphone is clocked by PIO0 and its bitstream is copied to memory by
+//! two DMA channels, alternating between two buffers. While DMA fills one of
+//! them, the other one is decimated to 16 kHz PCM.
+//!
+//! See the `Cargo.toml` file for Copyright and license details.
+
+#![no_std]
+#![no_main]
+
+use embedded_hal::digital::OutputPin;
+
+// Ensure we halt the program on panic (if we don't mention this crate it won't
+// be linked)
+use panic_halt as _;
+
+use arduino_nano_connect as bsp;
+
+// Pull in any important traits
+use bsp::hal::prelude::*;
+
+// A shorter alias for the Peripheral Access Crate, which provides low-level
+// register access
+use bsp::hal::pac;
+
+// A shorter alias for the Hardware Abstraction Layer, which provides
+// higher-level drivers.
+use bsp::hal;
+
+use hal::dma::{double_buffer, DMAExt};
+use hal::pio::PIOExt;
+
+// The microphone driver
+use bsp::pdm::{Decimator, PcmRing, PdmMic, SampleRate};
+
+/// Number of 32 bit words in each DMA buffer, 5.3 ms of audio
+const BUFFER_WORDS: usize = 256;
+
+/// Number of PCM samples in each block, 16 ms at 16 kHz
+const BLOCK_LEN: usize = 256;
+
+/// Peak level above which the LED is turned on
+const LOUD: i16 = 8_000;
+
+/// Entry point to our bare-metal application.
+///
+/// The `#[arduino_nano_connect::entry]` macro ensures the Cortex-M start-up code calls this function
+/// as soon as all global variables and the spinlock are initialised.
+///
+/// The function configures the RP2040 peripherals, then records audio in an
+/// infinite loop.
+#[arduino_nano_connect::entry]
+fn main() -> ! {
+    // Grab our singleton objects
+    let mut pac = pac::Peripherals::take().unwrap();
+
+    // Set up the watchdog driver - needed by the clock setup code
+    let mut watchdog = hal::Watchdog::new(pac.WATCHDOG);
+
+    // Configure the clocks
+    //
+    // The default is to generate a 125 MHz system clock
+    let clocks = hal::clocks::init_clocks_and_plls(
+        bsp::XOSC_CRYSTAL_FREQ,
+        pac.XOSC,
+        pac.CLOCKS,
+        pac.PLL_SYS,
+        pac.PLL_USB,
+        &mut pac.RESETS,
+        &mut watchdog,
+    )
+    .ok()
+    .unwrap();
+
+    // The single-cycle I/O block controls our GPIO pins
+    let sio = hal::Sio::new(pac.SIO);
+
+    // Set the pins up according to their function on this particular board
+    let pins = bsp::Pins::new(
+        pac.IO_BANK0,
+        pac.PADS_BANK0,
+        sio.gpio_bank0,
+        &mut pac.RESETS,
+    );
+
+    // Set the LED to be an output
+    let mut led_pin = pins.sck0.into_push_pull_output();
+
+    // Start clocking the microphone
+    let (mut pio, sm0, _, _, _) = pac.PIO0.split(&mut pac.RESETS);
+    let (mic, rx) = PdmMic::new(
+        &mut pio,
+        sm0,
+        pins.pdmclk,
+        pins.pdmdin,
+        clocks.system_clock.freq(),
+        SampleRate::Khz16,
+    );
+    let mut decimator = Decimator::new(mic.rate());
+    let mut ring = PcmRing::<4, BLOCK_LEN>::new();
+
+    // Copy the bitstream to memory, alternating between two buffers
+    let dma = pac.DMA.split(&mut pac.RESETS);
+    let buf0 = cortex_m::singleton!(: [u32; BUFFER_WORDS] = [0; BUFFER_WORDS]).unwrap();
+    let buf1 = cortex_m::singleton!(: [u32; BUFFER_WORDS] = [0; BUFFER_WORDS]).unwrap();
+    let transfer = double_buffer::Config::new((dma.ch0, dma.ch1), rx, buf0).start();
+    let mut transfer = transfer.write_next(buf1);
+
+    loop {
+        // Wait for a buffer to be filled, and hand it back to DMA once it has
+        // been decimated
+        let (buf, next) = transfer.wait();
+        decimator.process(&buf[..], |sample| ring.push(sample));
+        transfer = next.write_next(buf);
+
+        while let Some(block) = ring.pop() {
+            let peak = block
+                .iter()
+                .map(|sample| sample.saturating_abs())
+                .max()
+                .unwrap_or(0);
+            if peak > LOUD {
+                led_pin.set_high().unwrap();
+            } else {
+                led_pin.set_low().unwrap();
+            }
+        }
+    }
+}
+
+// End of file
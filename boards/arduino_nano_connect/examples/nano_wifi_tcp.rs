@@ -0,0 +1,144 @@
+//! # Nano WiFi TCP Example
+//!
+//! Joins a WiFi network with the NINA-W102 module of the Arduino Nano Connect,
+//! fetches `http://example.com/` and lights the on-board LED if the server
+//! answered.
+//!
+//! Set `SSID` and `PASSPHRASE` below to match your network.
+//!
+//! See the `Cargo.toml` file for Copyright and license details.
+
+#![no_std]
+#![no_main]
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::OutputPin;
+use embedded_nal::{AddrType, Dns, TcpClientStack};
+
+// Ensure we halt the program on panic (if we don't mention this crate it won't
+// be linked)
+use panic_halt as _;
+
+use arduino_nano_connect as bsp;
+
+// Pull in any important traits
+use bsp::hal::prelude::*;
+
+// A shorter alias for the Peripheral Access Crate, which provides low-level
+// register access
+use bsp::hal::pac;
+
+// A shorter alias for the Hardware Abstraction Layer, which provides
+// higher-level drivers.
+use bsp::hal;
+
+// The WiFi driver
+use bsp::nina::wifi::Status;
+use bsp::nina::{NinaSpi, Wifi};
+
+use core::net::SocketAddr;
+
+/// Name of the network to join
+const SSID: &str = "my-network";
+
+/// Passphrase of the network, use `None` for open networks
+const PASSPHRASE: Option<&str> = Some("my-passphrase");
+
+/// Entry point to our bare-metal application.
+///
+/// The `#[arduino_nano_connect::entry]` macro ensures the Cortex-M start-up code calls this function
+/// as soon as all global variables and the spinlock are initialised.
+///
+/// The function configures the RP2040 peripherals, joins the network and then
+/// sends a HTTP request.
+#[arduino_nano_connect::entry]
+fn main() -> ! {
+    // Grab our singleton objects
+    let mut pac = pac::Peripherals::take().unwrap();
+
+    // Set up the watchdog driver - needed by the clock setup code
+    let mut watchdog = hal::Watchdog::new(pac.WATCHDOG);
+
+    // Configure the clocks
+    //
+    // The default is to generate a 125 MHz system clock
+    let clocks = hal::clocks::init_clocks_and_plls(
+        bsp::XOSC_CRYSTAL_FREQ,
+        pac.XOSC,
+        pac.CLOCKS,
+        pac.PLL_SYS,
+        pac.PLL_USB,
+        &mut pac.RESETS,
+        &mut watchdog,
+    )
+    .ok()
+    .unwrap();
+
+    // The timer is used for the timeouts of the NINA driver
+    let mut timer = hal::Timer::new(pac.TIMER, &mut pac.RESETS, &clocks);
+
+    // The single-cycle I/O block controls our GPIO pins
+    let sio = hal::Sio::new(pac.SIO);
+
+    // Set the pins up according to their function on this particular board
+    let pins = bsp::Pins::new(
+        pac.IO_BANK0,
+        pac.PADS_BANK0,
+        sio.gpio_bank0,
+        &mut pac.RESETS,
+    );
+
+    // Set the LED to be an output
+    let mut led_pin = pins.sck0.into_push_pull_output();
+
+    // The NINA module is connected to SPI1
+    let spi_sclk: bsp::Gp14Spi1Sck = pins.nina_sck.reconfigure();
+    let spi_mosi: bsp::Gp11Spi1Tx = pins.nina_copi.reconfigure();
+    let spi_miso: bsp::Gp8Spi1Rx = pins.ble_tx_cipo.reconfigure();
+    let spi = hal::Spi::<_, _, _, 8>::new(pac.SPI1, (spi_mosi, spi_miso, spi_sclk)).init(
+        &mut pac.RESETS,
+        clocks.peripheral_clock.freq(),
+        8.MHz(),
+        embedded_hal::spi::MODE_0,
+    );
+
+    // Chip select and the ACK handshake line are driven manually
+    let cs = pins.ble_rx_cs.into_push_pull_output();
+    let ack = pins.ble_cts_ack.into_floating_input();
+    let mut nina = NinaSpi::new(spi, cs, ack, timer);
+
+    // Reset the module into its WiFi firmware
+    let mut reset_n = pins.nina_reset_n.into_push_pull_output();
+    let mut gpio0 = pins.ble_rts.into_push_pull_output();
+    nina.reset(&mut reset_n, &mut gpio0).unwrap();
+
+    let mut wifi = Wifi::new(nina);
+    wifi.join(SSID, PASSPHRASE).unwrap();
+    while wifi.status().unwrap() != Status::Connected {
+        timer.delay_ms(100);
+    }
+
+    let address = nb::block!(wifi.get_host_by_name("example.com", AddrType::IPv4)).unwrap();
+    let mut socket = wifi.socket().unwrap();
+    nb::block!(wifi.connect(&mut socket, SocketAddr::new(address, 80))).unwrap();
+
+    let request = b"GET / HTTP/1.0\r\nHost: example.com\r\n\r\n";
+    let mut sent = 0;
+    while sent < request.len() {
+        sent += nb::block!(wifi.send(&mut socket, &request[sent..])).unwrap();
+    }
+
+    // Light the LED if the server responded with a status line
+    let mut response = [0u8; 16];
+    let len = nb::block!(wifi.receive(&mut socket, &mut response)).unwrap();
+    if response[..len].starts_with(b"HTTP/1.") {
+        led_pin.set_high().unwrap();
+    }
+    wifi.close(socket).unwrap();
+
+    loop {
+        cortex_m::asm::wfi();
+    }
+}
+
+// End of file
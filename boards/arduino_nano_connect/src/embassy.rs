@@ -0,0 +1,17 @@
+//! The pins and drivers of the board on top of `embassy-rp`
+//!
+//! [embassy_pins!](crate::embassy_pins) names the pins of the `embassy_rp`
+//! peripherals like [crate::Pins] does:
+//!
+//! ```ignore
+//! let p = embassy_rp::init(Default::default());
+//! let pins = arduino_nano_connect::embassy_pins!(p);
+//! ```
+//!
+//! `embassy-rp` brings its own runtime, critical section and second stage
+//! bootloader, so build the BSP with `default-features = false`.
+//!
+//! Only available with the `embassy` feature.
+
+#[doc(inline)]
+pub use crate::EmbassyPins as Pins;
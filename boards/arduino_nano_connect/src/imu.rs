@@ -0,0 +1,906 @@
+//! Driver for the on-board LSM6DSOX 6-axis IMU
+//!
+//! The IMU sits on I2C0, with SDA on GPIO12 ([crate::Pins::a4]) and SCL on
+//! GPIO13 ([crate::Pins::a5]), so those pins are shared with the header. Its
+//! INT1 output is connected to GPIO24 ([crate::Pins::int1]) and is used to
+//! signal FIFO watermarks and detected events.
+//!
+//! ```ignore
+//! let mut imu = Lsm6dsox::on_board(pac.I2C0, pins.a4, pins.a5, &mut pac.RESETS, &clocks.system_clock)?;
+//! imu.set_accel(DataRate::Hz104, AccelScale::G4)?;
+//! imu.set_gyro(DataRate::Hz104, GyroScale::Dps500)?;
+//! let [x, y, z] = imu.accel()?;
+//! ```
+
+use embedded_hal::i2c::I2c;
+
+use crate::hal;
+use crate::hal::clocks::{Clock, SystemClock};
+use crate::hal::fugit::RateExtU32;
+use crate::hal::gpio::bank0::{Gpio12, Gpio13, Gpio24};
+use crate::hal::gpio::{Function, FunctionSioInput, Interrupt, Pin, PullDown, PullType};
+use crate::pac;
+use crate::{Gp12I2C0Sda, Gp13I2C0Scl};
+
+/// I2C address of the IMU, SA0 is pulled low
+pub const ADDRESS: u8 = 0x6A;
+
+/// Expected value of the `WHO_AM_I` register
+const DEVICE_ID: u8 = 0x6C;
+
+/// Reads of `CTRL3_C` before giving up on the software reset, which takes
+/// about 50 µs, less than a single read
+const RESET_POLLS: u32 = 100;
+
+/// Standard gravity in m/s²
+const GRAVITY: f32 = 9.80665;
+
+mod reg {
+    pub const FIFO_CTRL1: u8 = 0x07;
+    pub const FIFO_CTRL2: u8 = 0x08;
+    pub const FIFO_CTRL3: u8 = 0x09;
+    pub const FIFO_CTRL4: u8 = 0x0A;
+    pub const INT1_CTRL: u8 = 0x0D;
+    pub const WHO_AM_I: u8 = 0x0F;
+    pub const CTRL1_XL: u8 = 0x10;
+    pub const CTRL2_G: u8 = 0x11;
+    pub const CTRL3_C: u8 = 0x12;
+    pub const WAKE_UP_SRC: u8 = 0x1B;
+    pub const TAP_SRC: u8 = 0x1C;
+    pub const OUT_TEMP_L: u8 = 0x20;
+    pub const OUTX_L_G: u8 = 0x22;
+    pub const OUTX_L_A: u8 = 0x28;
+    pub const FIFO_STATUS1: u8 = 0x3A;
+    pub const FIFO_STATUS2: u8 = 0x3B;
+    pub const TAP_CFG0: u8 = 0x56;
+    pub const TAP_CFG1: u8 = 0x57;
+    pub const TAP_CFG2: u8 = 0x58;
+    pub const TAP_THS_6D: u8 = 0x59;
+    pub const INT_DUR2: u8 = 0x5A;
+    pub const WAKE_UP_THS: u8 = 0x5B;
+    pub const WAKE_UP_DUR: u8 = 0x5C;
+    pub const FREE_FALL: u8 = 0x5D;
+    pub const MD1_CFG: u8 = 0x5E;
+    pub const FIFO_DATA_OUT_TAG: u8 = 0x78;
+}
+
+/// The I2C bus the IMU is connected to
+pub type ImuI2c = hal::I2C<pac::I2C0, (Gp12I2C0Sda, Gp13I2C0Scl)>;
+
+/// The INT1 line of the IMU, configured as input
+pub type Int1 = Pin<Gpio24, FunctionSioInput, PullDown>;
+
+/// Configure the INT1 pin as input, interrupting on its rising edge
+///
+/// The IMU drives the line high while an interrupt is pending. The GPIO
+/// interrupt still has to be unmasked in the NVIC.
+pub fn int1_pin<F, P>(pin: Pin<Gpio24, F, P>) -> Int1
+where
+    F: Function,
+    P: PullType,
+{
+    let pin = pin.into_pull_down_input();
+    pin.set_interrupt_enabled(Interrupt::EdgeHigh, true);
+    pin
+}
+
+/// Errors reported by the IMU driver
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    /// The I2C bus reported an error
+    I2c(E),
+    /// `WHO_AM_I` returned an unexpected value
+    WrongDevice(u8),
+    /// The software reset didn't complete
+    ResetTimeout,
+}
+
+/// Output data rate of the accelerometer and gyroscope
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DataRate {
+    /// Powered down
+    Off = 0,
+    /// 12.5 Hz
+    Hz12_5 = 1,
+    /// 26 Hz
+    Hz26 = 2,
+    /// 52 Hz
+    Hz52 = 3,
+    /// 104 Hz
+    Hz104 = 4,
+    /// 208 Hz
+    Hz208 = 5,
+    /// 416 Hz, the minimum for tap detection
+    Hz416 = 6,
+    /// 833 Hz
+    Hz833 = 7,
+    /// 1.66 kHz
+    Hz1666 = 8,
+    /// 3.33 kHz
+    Hz3333 = 9,
+    /// 6.66 kHz
+    Hz6666 = 10,
+}
+
+/// Full scale of the accelerometer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AccelScale {
+    /// ±2 g
+    G2,
+    /// ±4 g
+    G4,
+    /// ±8 g
+    G8,
+    /// ±16 g
+    G16,
+}
+
+impl AccelScale {
+    fn bits(self) -> u8 {
+        match self {
+            AccelScale::G2 => 0b00,
+            AccelScale::G4 => 0b10,
+            AccelScale::G8 => 0b11,
+            AccelScale::G16 => 0b01,
+        }
+    }
+
+    /// Full scale in g
+    pub fn g(self) -> f32 {
+        match self {
+            AccelScale::G2 => 2.0,
+            AccelScale::G4 => 4.0,
+            AccelScale::G8 => 8.0,
+            AccelScale::G16 => 16.0,
+        }
+    }
+
+    /// Sensitivity in m/s² per LSB
+    fn sensitivity(self) -> f32 {
+        // 0.061 mg/LSB at ±2 g, doubling with every range
+        0.061e-3 * GRAVITY * self.g() / 2.0
+    }
+}
+
+/// Full scale of the gyroscope
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum GyroScale {
+    /// ±125 dps
+    Dps125,
+    /// ±250 dps
+    Dps250,
+    /// ±500 dps
+    Dps500,
+    /// ±1000 dps
+    Dps1000,
+    /// ±2000 dps
+    Dps2000,
+}
+
+impl GyroScale {
+    fn bits(self) -> u8 {
+        match self {
+            GyroScale::Dps125 => 0b0010,
+            GyroScale::Dps250 => 0b0000,
+            GyroScale::Dps500 => 0b0100,
+            GyroScale::Dps1000 => 0b1000,
+            GyroScale::Dps2000 => 0b1100,
+        }
+    }
+
+    /// Full scale in degrees per second
+    pub fn dps(self) -> f32 {
+        match self {
+            GyroScale::Dps125 => 125.0,
+            GyroScale::Dps250 => 250.0,
+            GyroScale::Dps500 => 500.0,
+            GyroScale::Dps1000 => 1000.0,
+            GyroScale::Dps2000 => 2000.0,
+        }
+    }
+
+    /// Sensitivity in rad/s per LSB
+    fn sensitivity(self) -> f32 {
+        // 4.375 mdps/LSB at ±125 dps, doubling with every range
+        4.375e-3 * self.dps() / 125.0 * core::f32::consts::PI / 180.0
+    }
+}
+
+/// A sample read from the FIFO, in SI units
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FifoSample {
+    /// Acceleration in m/s²
+    Accel([f32; 3]),
+    /// Angular rate in rad/s
+    Gyro([f32; 3]),
+    /// Any other data the FIFO can hold, with its tag
+    Other(u8),
+}
+
+/// Configuration of tap detection
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TapConfig {
+    /// Threshold in g, rounded to 1/32 of the accelerometer full scale
+    pub threshold: f32,
+    /// Also detect double taps, which delays single tap events
+    pub double_tap: bool,
+}
+
+/// Free-fall threshold, the acceleration below which the IMU is falling
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FreeFallThreshold {
+    /// 156 mg
+    Mg156 = 0,
+    /// 219 mg
+    Mg219 = 1,
+    /// 250 mg
+    Mg250 = 2,
+    /// 312 mg
+    Mg312 = 3,
+    /// 344 mg
+    Mg344 = 4,
+    /// 406 mg
+    Mg406 = 5,
+    /// 469 mg
+    Mg469 = 6,
+    /// 500 mg
+    Mg500 = 7,
+}
+
+/// Interrupt sources that were active, see [Lsm6dsox::events]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Events {
+    /// A single tap was detected
+    pub single_tap: bool,
+    /// A double tap was detected
+    pub double_tap: bool,
+    /// The IMU is in free fall
+    pub free_fall: bool,
+    /// The FIFO reached its watermark
+    pub fifo_watermark: bool,
+    /// The FIFO overflowed and samples were lost
+    pub fifo_overrun: bool,
+}
+
+/// LSM6DSOX accelerometer and gyroscope
+pub struct Lsm6dsox<I2C> {
+    i2c: I2C,
+    address: u8,
+    accel_scale: AccelScale,
+    gyro_scale: GyroScale,
+}
+
+impl Lsm6dsox<ImuI2c> {
+    /// Set up I2C0 and the on-board IMU
+    pub fn on_board<F1, P1, F2, P2>(
+        i2c0: pac::I2C0,
+        sda: Pin<Gpio12, F1, P1>,
+        scl: Pin<Gpio13, F2, P2>,
+        resets: &mut pac::RESETS,
+        system_clock: &SystemClock,
+    ) -> Result<Self, Error<hal::i2c::Error>>
+    where
+        F1: Function,
+        P1: PullType,
+        F2: Function,
+        P2: PullType,
+    {
+        let i2c = hal::I2C::i2c0(
+            i2c0,
+            sda.reconfigure(),
+            scl.reconfigure(),
+            400.kHz(),
+            resets,
+            system_clock.freq(),
+        );
+        Self::new(i2c, ADDRESS)
+    }
+}
+
+impl<I2C: I2c> Lsm6dsox<I2C> {
+    /// Reset the IMU and check its identity
+    ///
+    /// Both sensors are powered down afterwards.
+    pub fn new(i2c: I2C, address: u8) -> Result<Self, Error<I2C::Error>> {
+        let mut imu = Lsm6dsox {
+            i2c,
+            address,
+            accel_scale: AccelScale::G2,
+            gyro_scale: GyroScale::Dps250,
+        };
+
+        let id = imu.read_reg(reg::WHO_AM_I)?;
+        if id != DEVICE_ID {
+            #[cfg(feature = "defmt")]
+            defmt::warn!("imu: WHO_AM_I is {=u8:#04x}, not a LSM6DSOX", id);
+            return Err(Error::WrongDevice(id));
+        }
+
+        // Software reset, the bit clears itself once done
+        imu.write_reg(reg::CTRL3_C, 0x01)?;
+        let mut polls = 0;
+        while imu.read_reg(reg::CTRL3_C)? & 0x01 != 0 {
+            polls += 1;
+            if polls == RESET_POLLS {
+                return Err(Error::ResetTimeout);
+            }
+        }
+
+        // Block data update, so the low and high bytes of a sample always
+        // match, and auto increment of the register address
+        imu.write_reg(reg::CTRL3_C, 0x44)?;
+        #[cfg(feature = "defmt")]
+        defmt::debug!("imu: LSM6DSOX found at {=u8:#04x}", address);
+        Ok(imu)
+    }
+
+    /// Release the I2C bus
+    pub fn free(self) -> I2C {
+        self.i2c
+    }
+
+    fn read_reg(&mut self, reg: u8) -> Result<u8, Error<I2C::Error>> {
+        let mut value = [0];
+        self.i2c
+            .write_read(self.address, &[reg], &mut value)
+            .map_err(Error::I2c)?;
+        Ok(value[0])
+    }
+
+    fn write_reg(&mut self, reg: u8, value: u8) -> Result<(), Error<I2C::Error>> {
+        self.i2c
+            .write(self.address, &[reg, value])
+            .map_err(Error::I2c)
+    }
+
+    fn modify_reg(&mut self, reg: u8, mask: u8, value: u8) -> Result<(), Error<I2C::Error>> {
+        let old = self.read_reg(reg)?;
+        self.write_reg(reg, old & !mask | value & mask)
+    }
+
+    fn read_vector(&mut self, reg: u8) -> Result<[i16; 3], Error<I2C::Error>> {
+        let mut buf = [0; 6];
+        self.i2c
+            .write_read(self.address, &[reg], &mut buf)
+            .map_err(Error::I2c)?;
+        Ok([
+            i16::from_le_bytes([buf[0], buf[1]]),
+            i16::from_le_bytes([buf[2], buf[3]]),
+            i16::from_le_bytes([buf[4], buf[5]]),
+        ])
+    }
+
+    /// Configure the accelerometer
+    pub fn set_accel(
+        &mut self,
+        rate: DataRate,
+        scale: AccelScale,
+    ) -> Result<(), Error<I2C::Error>> {
+        self.write_reg(reg::CTRL1_XL, (rate as u8) << 4 | scale.bits() << 2)?;
+        self.accel_scale = scale;
+        Ok(())
+    }
+
+    /// Configure the gyroscope
+    pub fn set_gyro(&mut self, rate: DataRate, scale: GyroScale) -> Result<(), Error<I2C::Error>> {
+        self.write_reg(reg::CTRL2_G, (rate as u8) << 4 | scale.bits())?;
+        self.gyro_scale = scale;
+        Ok(())
+    }
+
+    /// Read the raw accelerometer output
+    pub fn accel_raw(&mut self) -> Result<[i16; 3], Error<I2C::Error>> {
+        self.read_vector(reg::OUTX_L_A)
+    }
+
+    /// Read the raw gyroscope output
+    pub fn gyro_raw(&mut self) -> Result<[i16; 3], Error<I2C::Error>> {
+        self.read_vector(reg::OUTX_L_G)
+    }
+
+    /// Read the acceleration in m/s²
+    pub fn accel(&mut self) -> Result<[f32; 3], Error<I2C::Error>> {
+        let raw = self.accel_raw()?;
+        Ok(scale(raw, self.accel_scale.sensitivity()))
+    }
+
+    /// Read the angular rate in rad/s
+    pub fn gyro(&mut self) -> Result<[f32; 3], Error<I2C::Error>> {
+        let raw = self.gyro_raw()?;
+        Ok(scale(raw, self.gyro_scale.sensitivity()))
+    }
+
+    /// Read the die temperature in °C
+    pub fn temperature(&mut self) -> Result<f32, Error<I2C::Error>> {
+        let mut buf = [0; 2];
+        self.i2c
+            .write_read(self.address, &[reg::OUT_TEMP_L], &mut buf)
+            .map_err(Error::I2c)?;
+        Ok(25.0 + f32::from(i16::from_le_bytes(buf)) / 256.0)
+    }
+
+    /// Queue samples in the FIFO and signal on INT1 once `watermark` samples
+    /// are waiting
+    ///
+    /// The FIFO runs in continuous mode, overwriting the oldest samples when
+    /// it is full. It holds up to 511 samples, each accelerometer and each
+    /// gyroscope reading counts as one. A rate of [DataRate::Off] leaves that
+    /// sensor out of the FIFO.
+    pub fn enable_fifo(
+        &mut self,
+        accel_rate: DataRate,
+        gyro_rate: DataRate,
+        watermark: u16,
+    ) -> Result<(), Error<I2C::Error>> {
+        let watermark = watermark.min(511);
+        self.write_reg(reg::FIFO_CTRL1, watermark as u8)?;
+        self.modify_reg(reg::FIFO_CTRL2, 0x01, (watermark >> 8) as u8)?;
+        self.write_reg(reg::FIFO_CTRL3, (gyro_rate as u8) << 4 | accel_rate as u8)?;
+        // Continuous mode
+        self.write_reg(reg::FIFO_CTRL4, 0b110)?;
+        // Watermark and overrun on INT1
+        self.modify_reg(reg::INT1_CTRL, 0x18, 0x18)
+    }
+
+    /// Stop queueing samples, discarding the FIFO content
+    pub fn disable_fifo(&mut self) -> Result<(), Error<I2C::Error>> {
+        self.modify_reg(reg::INT1_CTRL, 0x18, 0)?;
+        self.write_reg(reg::FIFO_CTRL4, 0)
+    }
+
+    /// Number of samples waiting in the FIFO
+    pub fn fifo_len(&mut self) -> Result<u16, Error<I2C::Error>> {
+        let mut buf = [0; 2];
+        self.i2c
+            .write_read(self.address, &[reg::FIFO_STATUS1], &mut buf)
+            .map_err(Error::I2c)?;
+        Ok(u16::from_le_bytes(buf) & 0x03FF)
+    }
+
+    /// Read the oldest sample from the FIFO
+    ///
+    /// Check [Self::fifo_len] first, reading an empty FIFO returns garbage.
+    pub fn read_fifo(&mut self) -> Result<FifoSample, Error<I2C::Error>> {
+        let mut buf = [0; 7];
+        self.i2c
+            .write_read(self.address, &[reg::FIFO_DATA_OUT_TAG], &mut buf)
+            .map_err(Error::I2c)?;
+        let raw = [
+            i16::from_le_bytes([buf[1], buf[2]]),
+            i16::from_le_bytes([buf[3], buf[4]]),
+            i16::from_le_bytes([buf[5], buf[6]]),
+        ];
+        Ok(match buf[0] >> 3 {
+            0x01 => FifoSample::Gyro(scale(raw, self.gyro_scale.sensitivity())),
+            0x02 => FifoSample::Accel(scale(raw, self.accel_scale.sensitivity())),
+            tag => FifoSample::Other(tag),
+        })
+    }
+
+    /// Read all samples from the FIFO, handing them to `f`
+    ///
+    /// Returns the number of samples read.
+    pub fn drain_fifo(&mut self, mut f: impl FnMut(FifoSample)) -> Result<u16, Error<I2C::Error>> {
+        let len = self.fifo_len()?;
+        for _ in 0..len {
+            f(self.read_fifo()?);
+        }
+        Ok(len)
+    }
+
+    /// Detect taps on all axes and signal them on INT1
+    ///
+    /// Needs an accelerometer data rate of at least [DataRate::Hz416].
+    pub fn enable_tap(&mut self, config: TapConfig) -> Result<(), Error<I2C::Error>> {
+        let threshold = (config.threshold / self.accel_scale.g() * 32.0) as u8;
+        let threshold = threshold.clamp(1, 31);
+
+        // Latched interrupts, cleared by reading the source registers, with
+        // tap detection on all axes
+        self.modify_reg(reg::TAP_CFG0, 0x4F, 0x4F)?;
+        // X axis has priority, same threshold for all of them
+        self.write_reg(reg::TAP_CFG1, threshold)?;
+        self.modify_reg(reg::TAP_CFG2, 0x9F, 0x80 | threshold)?;
+        self.modify_reg(reg::TAP_THS_6D, 0x1F, threshold)?;
+        // Longest gap between double taps, quiet and shock times, as
+        // recommended for 416 Hz
+        self.write_reg(reg::INT_DUR2, 0x7F)?;
+        let double_tap = if config.double_tap { 0x80 } else { 0 };
+        self.modify_reg(reg::WAKE_UP_THS, 0x80, double_tap)?;
+        // INT1_SINGLE_TAP, and INT1_DOUBLE_TAP if enabled
+        self.modify_reg(reg::MD1_CFG, 0x48, 0x40 | double_tap >> 4)
+    }
+
+    /// Detect free fall and signal it on INT1
+    ///
+    /// `duration` is the number of accelerometer samples the acceleration
+    /// has to stay below the threshold, up to 63.
+    pub fn enable_free_fall(
+        &mut self,
+        threshold: FreeFallThreshold,
+        duration: u8,
+    ) -> Result<(), Error<I2C::Error>> {
+        let duration = duration.min(63);
+        self.modify_reg(reg::TAP_CFG0, 0x41, 0x41)?;
+        self.modify_reg(reg::TAP_CFG2, 0x80, 0x80)?;
+        self.modify_reg(reg::WAKE_UP_DUR, 0x80, (duration & 0x20) << 2)?;
+        self.write_reg(reg::FREE_FALL, (duration & 0x1F) << 3 | threshold as u8)?;
+        self.modify_reg(reg::MD1_CFG, 0x10, 0x10)
+    }
+
+    /// Read and clear the interrupt sources
+    ///
+    /// Call this when INT1 goes high.
+    pub fn events(&mut self) -> Result<Events, Error<I2C::Error>> {
+        let tap = self.read_reg(reg::TAP_SRC)?;
+        let wake_up = self.read_reg(reg::WAKE_UP_SRC)?;
+        let fifo = self.read_reg(reg::FIFO_STATUS2)?;
+        Ok(Events {
+            single_tap: tap & 0x60 == 0x60,
+            double_tap: tap & 0x50 == 0x50,
+            free_fall: wake_up & 0x20 != 0,
+            fifo_watermark: fifo & 0x80 != 0,
+            fifo_overrun: fifo & 0x40 != 0,
+        })
+    }
+}
+
+fn scale(raw: [i16; 3], sensitivity: f32) -> [f32; 3] {
+    raw.map(|value| f32::from(value) * sensitivity)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::convert::Infallible;
+    use std::collections::VecDeque;
+    use std::vec::Vec;
+
+    use embedded_hal::i2c::{ErrorType, Operation};
+
+    use super::*;
+
+    /// Default of `CTRL3_C`, with the auto increment of the address
+    const CTRL3_C_DEFAULT: u8 = 0x04;
+
+    /// A LSM6DSOX on a simulated bus
+    struct FakeImu {
+        regs: [u8; 0x80],
+        /// Samples in the FIFO, tag byte first
+        fifo: VecDeque<[u8; 7]>,
+        /// Reads of `CTRL3_C` until the software reset is done, `None` if
+        /// it never is
+        reset_reads: Option<u32>,
+        /// Reads of `CTRL3_C` while resetting
+        polls: u32,
+        /// Register writes, in order
+        writes: Vec<(u8, u8)>,
+    }
+
+    impl FakeImu {
+        fn new() -> Self {
+            let mut regs = [0; 0x80];
+            regs[usize::from(reg::WHO_AM_I)] = DEVICE_ID;
+            regs[usize::from(reg::CTRL3_C)] = CTRL3_C_DEFAULT;
+            FakeImu {
+                regs,
+                fifo: VecDeque::new(),
+                reset_reads: Some(2),
+                polls: 0,
+                writes: Vec::new(),
+            }
+        }
+
+        fn reg(&self, reg: u8) -> u8 {
+            self.regs[usize::from(reg)]
+        }
+
+        fn set_regs(&mut self, reg: u8, values: &[u8]) {
+            let at = usize::from(reg);
+            self.regs[at..at + values.len()].copy_from_slice(values);
+        }
+
+        fn write(&mut self, reg: u8, value: u8) {
+            self.writes.push((reg, value));
+            if reg == reg::CTRL3_C && value & 0x01 != 0 {
+                // Everything but the identity goes back to its default
+                let id = self.reg(reg::WHO_AM_I);
+                self.regs = [0; 0x80];
+                self.regs[usize::from(reg::WHO_AM_I)] = id;
+                self.regs[usize::from(reg::CTRL3_C)] = CTRL3_C_DEFAULT | 0x01;
+                return;
+            }
+            self.regs[usize::from(reg)] = value;
+        }
+
+        fn read(&mut self, reg: u8) -> u8 {
+            if reg == reg::CTRL3_C && self.reg(reg) & 0x01 != 0 {
+                self.polls += 1;
+                if let Some(reads) = &mut self.reset_reads {
+                    if *reads == 0 {
+                        self.regs[usize::from(reg)] &= !0x01;
+                    } else {
+                        *reads -= 1;
+                    }
+                }
+            }
+            self.reg(reg)
+        }
+    }
+
+    impl ErrorType for FakeImu {
+        type Error = Infallible;
+    }
+
+    impl I2c for FakeImu {
+        fn transaction(
+            &mut self,
+            address: u8,
+            operations: &mut [Operation<'_>],
+        ) -> Result<(), Infallible> {
+            assert_eq!(address, ADDRESS);
+            let mut at = 0;
+            for operation in operations {
+                match operation {
+                    Operation::Write(bytes) => {
+                        at = bytes[0];
+                        for &value in &bytes[1..] {
+                            self.write(at, value);
+                            at += 1;
+                        }
+                    }
+                    Operation::Read(buf) if at == reg::FIFO_DATA_OUT_TAG => {
+                        let sample = self.fifo.pop_front().expect("FIFO is empty");
+                        buf.copy_from_slice(&sample);
+                    }
+                    Operation::Read(buf) => {
+                        for byte in buf.iter_mut() {
+                            *byte = self.read(at);
+                            at += 1;
+                        }
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+
+    fn assert_close(actual: [f32; 3], expected: [f32; 3]) {
+        for (a, e) in actual.iter().zip(expected.iter()) {
+            assert!(
+                (a - e).abs() < 1e-5 * e.abs().max(1.0),
+                "{:?} != {:?}",
+                actual,
+                expected
+            );
+        }
+    }
+
+    fn vector_bytes(vector: [i16; 3]) -> Vec<u8> {
+        vector.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    fn sample(tag: u8, vector: [i16; 3]) -> [u8; 7] {
+        let mut sample = [tag; 7];
+        sample[1..].copy_from_slice(&vector_bytes(vector));
+        sample
+    }
+
+    fn imu() -> Lsm6dsox<FakeImu> {
+        let mut imu = Lsm6dsox::new(FakeImu::new(), ADDRESS).unwrap();
+        imu.i2c.writes.clear();
+        imu
+    }
+
+    #[test]
+    fn init_checks_the_device_and_resets_it() {
+        let mut fake = FakeImu::new();
+        fake.set_regs(reg::CTRL1_XL, &[0x48, 0x44]);
+        let fake = Lsm6dsox::new(fake, ADDRESS).unwrap().free();
+        assert_eq!(fake.writes, [(reg::CTRL3_C, 0x01), (reg::CTRL3_C, 0x44)]);
+        assert_eq!(fake.polls, 3);
+        assert_eq!((fake.reg(reg::CTRL1_XL), fake.reg(reg::CTRL2_G)), (0, 0));
+
+        let mut fake = FakeImu::new();
+        fake.regs[usize::from(reg::WHO_AM_I)] = 0x6B;
+        assert!(matches!(
+            Lsm6dsox::new(fake, ADDRESS),
+            Err(Error::WrongDevice(0x6B))
+        ));
+    }
+
+    #[test]
+    fn stuck_reset_times_out() {
+        let mut fake = FakeImu::new();
+        fake.reset_reads = None;
+        assert!(matches!(
+            Lsm6dsox::new(fake, ADDRESS),
+            Err(Error::ResetTimeout)
+        ));
+    }
+
+    #[test]
+    fn rates_and_scales_are_written() {
+        let mut imu = imu();
+        imu.set_accel(DataRate::Hz104, AccelScale::G4).unwrap();
+        imu.set_gyro(DataRate::Hz12_5, GyroScale::Dps125).unwrap();
+        assert_eq!(imu.i2c.reg(reg::CTRL1_XL), 0x48);
+        assert_eq!(imu.i2c.reg(reg::CTRL2_G), 0x12);
+
+        imu.set_accel(DataRate::Hz6666, AccelScale::G16).unwrap();
+        imu.set_gyro(DataRate::Off, GyroScale::Dps2000).unwrap();
+        assert_eq!(imu.i2c.reg(reg::CTRL1_XL), 0xA4);
+        assert_eq!(imu.i2c.reg(reg::CTRL2_G), 0x0C);
+    }
+
+    #[test]
+    fn readings_are_scaled() {
+        let mut imu = imu();
+        imu.i2c
+            .set_regs(reg::OUTX_L_A, &vector_bytes([16384, -8192, 0]));
+        imu.i2c
+            .set_regs(reg::OUTX_L_G, &vector_bytes([1000, 0, -1000]));
+        imu.i2c.set_regs(reg::OUT_TEMP_L, &[0x80, 0x19]);
+        assert_eq!(imu.accel_raw().unwrap(), [16384, -8192, 0]);
+        assert_eq!(imu.gyro_raw().unwrap(), [1000, 0, -1000]);
+        assert_eq!(imu.temperature().unwrap(), 50.5);
+
+        // The sensitivities of the data sheet, in mg and mdps per LSB
+        for (scale, mg) in [
+            (AccelScale::G2, 0.061),
+            (AccelScale::G4, 0.122),
+            (AccelScale::G8, 0.244),
+            (AccelScale::G16, 0.488),
+        ] {
+            imu.set_accel(DataRate::Hz104, scale).unwrap();
+            let g = mg * 1e-3 * GRAVITY;
+            assert_close(imu.accel().unwrap(), [16384.0 * g, -8192.0 * g, 0.0]);
+        }
+        for (scale, mdps) in [
+            (GyroScale::Dps125, 4.375),
+            (GyroScale::Dps250, 8.75),
+            (GyroScale::Dps500, 17.5),
+            (GyroScale::Dps1000, 35.0),
+            (GyroScale::Dps2000, 70.0),
+        ] {
+            imu.set_gyro(DataRate::Hz104, scale).unwrap();
+            let rad = (mdps * 1e-3f32).to_radians();
+            assert_close(imu.gyro().unwrap(), [1000.0 * rad, 0.0, -1000.0 * rad]);
+        }
+    }
+
+    #[test]
+    fn fifo_samples_are_tagged_and_scaled() {
+        let mut imu = imu();
+        imu.set_accel(DataRate::Hz104, AccelScale::G2).unwrap();
+        imu.set_gyro(DataRate::Hz52, GyroScale::Dps250).unwrap();
+        // Bits the FIFO setup has to keep
+        imu.i2c.set_regs(reg::FIFO_CTRL2, &[0x10]);
+        imu.i2c.set_regs(reg::INT1_CTRL, &[0x01]);
+
+        imu.enable_fifo(DataRate::Hz104, DataRate::Hz52, 600)
+            .unwrap();
+        assert_eq!(imu.i2c.reg(reg::FIFO_CTRL1), 0xFF);
+        assert_eq!(imu.i2c.reg(reg::FIFO_CTRL2), 0x11);
+        assert_eq!(imu.i2c.reg(reg::FIFO_CTRL3), 0x34);
+        assert_eq!(imu.i2c.reg(reg::FIFO_CTRL4), 0b110);
+        assert_eq!(imu.i2c.reg(reg::INT1_CTRL), 0x19);
+
+        // The tag is in the upper 5 bits, below are a counter and parity
+        imu.i2c.fifo.extend([
+            sample(0x01 << 3 | 0b110, [0, 0, 1000]),
+            sample(0x02 << 3 | 0b001, [16384, 0, 0]),
+            sample(0x03 << 3, [0; 3]),
+        ]);
+        imu.i2c.set_regs(reg::FIFO_STATUS1, &[3, 0x80]);
+        assert_eq!(imu.fifo_len().unwrap(), 3);
+
+        let mut samples = Vec::new();
+        assert_eq!(imu.drain_fifo(|s| samples.push(s)).unwrap(), 3);
+        match samples[..] {
+            [FifoSample::Gyro(gyro), FifoSample::Accel(accel), FifoSample::Other(0x03)] => {
+                assert_close(gyro, [0.0, 0.0, 8.75f32.to_radians()]);
+                assert_close(accel, [16384.0 * 0.061e-3 * GRAVITY, 0.0, 0.0]);
+            }
+            _ => panic!("unexpected samples {:?}", samples),
+        }
+        assert!(imu.i2c.fifo.is_empty());
+
+        imu.disable_fifo().unwrap();
+        assert_eq!(imu.i2c.reg(reg::INT1_CTRL), 0x01);
+        assert_eq!(imu.i2c.reg(reg::FIFO_CTRL4), 0);
+    }
+
+    #[test]
+    fn tap_detection_is_configured() {
+        let mut imu = imu();
+        imu.set_accel(DataRate::Hz416, AccelScale::G2).unwrap();
+        imu.enable_tap(TapConfig {
+            threshold: 0.5,
+            double_tap: true,
+        })
+        .unwrap();
+        assert_eq!(imu.i2c.reg(reg::TAP_CFG0), 0x4F);
+        assert_eq!(imu.i2c.reg(reg::TAP_CFG1), 8);
+        assert_eq!(imu.i2c.reg(reg::TAP_CFG2), 0x88);
+        assert_eq!(imu.i2c.reg(reg::TAP_THS_6D), 8);
+        assert_eq!(imu.i2c.reg(reg::INT_DUR2), 0x7F);
+        assert_eq!(imu.i2c.reg(reg::WAKE_UP_THS), 0x80);
+        assert_eq!(imu.i2c.reg(reg::MD1_CFG), 0x48);
+
+        // The threshold is clamped to the 5 bits
+        imu.enable_tap(TapConfig {
+            threshold: 4.0,
+            double_tap: false,
+        })
+        .unwrap();
+        assert_eq!(imu.i2c.reg(reg::TAP_CFG1), 31);
+        assert_eq!(imu.i2c.reg(reg::WAKE_UP_THS), 0);
+        assert_eq!(imu.i2c.reg(reg::MD1_CFG), 0x40);
+        imu.enable_tap(TapConfig {
+            threshold: 0.0,
+            double_tap: false,
+        })
+        .unwrap();
+        assert_eq!(imu.i2c.reg(reg::TAP_CFG1), 1);
+    }
+
+    #[test]
+    fn free_fall_detection_is_configured() {
+        let mut imu = imu();
+        imu.enable_free_fall(FreeFallThreshold::Mg312, 40).unwrap();
+        assert_eq!(imu.i2c.reg(reg::TAP_CFG0), 0x41);
+        assert_eq!(imu.i2c.reg(reg::TAP_CFG2), 0x80);
+        // The 6th bit of the duration is in WAKE_UP_DUR
+        assert_eq!(imu.i2c.reg(reg::WAKE_UP_DUR), 0x80);
+        assert_eq!(imu.i2c.reg(reg::FREE_FALL), 8 << 3 | 3);
+        assert_eq!(imu.i2c.reg(reg::MD1_CFG), 0x10);
+
+        imu.enable_free_fall(FreeFallThreshold::Mg156, 100).unwrap();
+        assert_eq!(imu.i2c.reg(reg::FREE_FALL), 31 << 3);
+        imu.enable_free_fall(FreeFallThreshold::Mg500, 3).unwrap();
+        assert_eq!(imu.i2c.reg(reg::WAKE_UP_DUR), 0);
+        assert_eq!(imu.i2c.reg(reg::FREE_FALL), 3 << 3 | 7);
+    }
+
+    #[test]
+    fn events_are_decoded() {
+        let mut imu = imu();
+        assert_eq!(imu.events().unwrap(), Events::default());
+
+        imu.i2c.set_regs(reg::TAP_SRC, &[0x60]);
+        imu.i2c.set_regs(reg::WAKE_UP_SRC, &[0x20]);
+        imu.i2c.set_regs(reg::FIFO_STATUS2, &[0xC0]);
+        assert_eq!(
+            imu.events().unwrap(),
+            Events {
+                single_tap: true,
+                double_tap: false,
+                free_fall: true,
+                fifo_watermark: true,
+                fifo_overrun: true,
+            }
+        );
+
+        imu.i2c.set_regs(reg::TAP_SRC, &[0x50]);
+        imu.i2c.set_regs(reg::WAKE_UP_SRC, &[0]);
+        imu.i2c.set_regs(reg::FIFO_STATUS2, &[0x80]);
+        assert_eq!(
+            imu.events().unwrap(),
+            Events {
+                double_tap: true,
+                fifo_watermark: true,
+                ..Events::default()
+            }
+        );
+    }
+}
@@ -30,10 +30,16 @@ pub static BOOT2_FIRMWARE: [u8; 256] = rp2040_boot2::BOOT_LOADER_GENERIC_03H;
 
 pub use hal::pac;
 
+pub mod imu;
+pub mod nina;
+pub mod pdm;
+
 // borrowed some pin defs from rp-pico from a dicussion on the bsp_pins! macro
 // stripped out functions from connected lines that are no available through
 // any of the header pins
-hal::bsp_pins!(
+board_common::bsp_pins!(
+    embassy: crate::embassy::Pins;
+
     /// GPIO 0 supports following functions:
     /// | Default      | UART0 TX (arduino nano connect)
     /// | Function     | Alias with applied function |
@@ -764,3 +770,13 @@ hal::bsp_pins!(
 );
 
 pub const XOSC_CRYSTAL_FREQ: u32 = 12_000_000;
+
+// FLASH_SIZE_BYTES and the reserved end of the flash, see build.rs
+include!(concat!(env!("OUT_DIR"), "/flash.rs"));
+
+#[cfg(feature = "embassy")]
+pub mod embassy;
+
+pub use board_common;
+
+board_common::board_pins!(Pins, XOSC_CRYSTAL_FREQ);
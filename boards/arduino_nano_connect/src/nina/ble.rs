@@ -0,0 +1,554 @@
+//! Bluetooth HCI transport of the NINA firmware
+//!
+//! When the chip select line is held low during reset, the NINA firmware
+//! doesn't start its WiFi driver but runs the ESP32 Bluetooth controller,
+//! speaking HCI over UART instead. The UART uses the same pins as the SPI
+//! interface, together with hardware flow control:
+//!
+//! | NINA | RP2040 | Function    | Alias                 |
+//! |------|--------|-------------|-----------------------|
+//! | RXD  | GPIO8  | `UART1 TX`  | [crate::Gp8Uart1Tx]   |
+//! | TXD  | GPIO9  | `UART1 RX`  | [crate::Gp9Uart1Rx]   |
+//! | RTS  | GPIO10 | `UART1 CTS` | [crate::Gp10Uart1Cts] |
+//! | CTS  | GPIO11 | `UART1 RTS` | [crate::Gp11Uart1Rts] |
+//!
+//! Packets are framed with the H4 protocol, a single byte packet indicator
+//! followed by the HCI packet. [H4Transport] does the framing, so a Bluetooth
+//! host stack only has to deal with HCI packets.
+//!
+//! ```ignore
+//! let mut control = NinaControl::new(pins.nina_reset_n, pins.ble_rts);
+//! let rx = control.reset_into_hci(pins.ble_rx_cs, &mut timer);
+//! let uart = hci_uart(
+//!     pac.UART1,
+//!     (pins.ble_tx_cipo.reconfigure(), rx, pins.ble_cts_ack.reconfigure(), pins.nina_copi.reconfigure()),
+//!     &mut pac.RESETS,
+//!     &clocks.peripheral_clock,
+//! );
+//! let mut hci = H4Transport::new(uart);
+//! // HCI_Reset
+//! hci.send_command(0x0C03, &[])?;
+//! ```
+
+use core::convert::TryFrom;
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::OutputPin;
+use embedded_hal_nb::serial::{Read, Write};
+
+use crate::hal::clocks::{Clock, PeripheralClock};
+use crate::hal::fugit::RateExtU32;
+use crate::hal::gpio::bank0::{Gpio2, Gpio3, Gpio9};
+use crate::hal::gpio::{Function, FunctionSioOutput, Pin, PullNone, PullType};
+use crate::hal::uart::{DataBits, Enabled, StopBits, UartConfig, UartPeripheral};
+use crate::pac;
+use crate::{Gp10Uart1Cts, Gp11Uart1Rts, Gp8Uart1Tx, Gp9Uart1Rx};
+
+/// Baud rate used by the NINA firmware in HCI mode
+pub const HCI_BAUD_RATE: u32 = 912_600;
+
+/// Largest HCI packet [H4Transport] can receive, without the packet indicator
+///
+/// Enough for any event and for ACL data of LE controllers.
+pub const MAX_PACKET_LEN: usize = 4 + 255;
+
+/// Reset line of the NINA module, low active
+pub type ResetPin = Pin<Gpio3, FunctionSioOutput, PullNone>;
+
+/// GPIO0 of the NINA module, selects the ESP32 download mode during reset
+pub type Gpio0Pin = Pin<Gpio2, FunctionSioOutput, PullNone>;
+
+/// Pins of the HCI UART, with hardware flow control
+pub type HciUartPins = (Gp8Uart1Tx, Gp9Uart1Rx, Gp10Uart1Cts, Gp11Uart1Rts);
+
+/// The UART connected to the NINA module
+pub type HciUart = UartPeripheral<Enabled, pac::UART1, HciUartPins>;
+
+/// Drives the reset and boot pins of the NINA module
+pub struct NinaControl {
+    reset_n: ResetPin,
+    gpio0: Gpio0Pin,
+}
+
+impl NinaControl {
+    /// Take control of the reset and GPIO0 pins, leaving the module running
+    pub fn new<F1, P1, F2, P2>(reset_n: Pin<Gpio3, F1, P1>, gpio0: Pin<Gpio2, F2, P2>) -> Self
+    where
+        F1: Function,
+        P1: PullType,
+        F2: Function,
+        P2: PullType,
+    {
+        let mut reset_n: ResetPin = reset_n.reconfigure();
+        reset_n.set_high().unwrap();
+        let mut gpio0: Gpio0Pin = gpio0.reconfigure();
+        gpio0.set_high().unwrap();
+        NinaControl { reset_n, gpio0 }
+    }
+
+    /// Reset the module into its Bluetooth HCI firmware
+    ///
+    /// The chip select pin is held low while the module boots, then it is
+    /// handed back as UART RX pin.
+    pub fn reset_into_hci<F, P>(
+        &mut self,
+        cs: Pin<Gpio9, F, P>,
+        delay: &mut impl DelayNs,
+    ) -> Gp9Uart1Rx
+    where
+        F: Function,
+        P: PullType,
+    {
+        #[cfg(feature = "defmt")]
+        defmt::debug!("nina: resetting into the HCI firmware");
+        let mut cs: Pin<Gpio9, FunctionSioOutput, PullNone> = cs.reconfigure();
+        cs.set_low().unwrap();
+
+        // GPIO0 has to be high, or the ESP32 enters its download mode
+        self.gpio0.set_high().unwrap();
+        self.reset_n.set_low().unwrap();
+        delay.delay_ms(100);
+        self.reset_n.set_high().unwrap();
+        delay.delay_ms(750);
+
+        cs.reconfigure()
+    }
+
+    /// Hold the module in reset, e.g. to save power
+    pub fn hold_in_reset(&mut self) {
+        self.reset_n.set_low().unwrap();
+    }
+
+    /// Release the pins
+    pub fn free(self) -> (ResetPin, Gpio0Pin) {
+        (self.reset_n, self.gpio0)
+    }
+}
+
+/// Set up UART1 for talking to the module in HCI mode
+pub fn hci_uart(
+    uart: pac::UART1,
+    pins: HciUartPins,
+    resets: &mut pac::RESETS,
+    peripheral_clock: &PeripheralClock,
+) -> HciUart {
+    UartPeripheral::new(uart, pins, resets)
+        .enable(
+            UartConfig::new(HCI_BAUD_RATE.Hz(), DataBits::Eight, None, StopBits::One),
+            peripheral_clock.freq(),
+        )
+        .unwrap()
+}
+
+/// H4 packet indicator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PacketType {
+    /// HCI command, sent by the host
+    Command = 0x01,
+    /// ACL data
+    AclData = 0x02,
+    /// Synchronous (SCO) data
+    SyncData = 0x03,
+    /// HCI event, sent by the controller
+    Event = 0x04,
+    /// Isochronous data
+    IsoData = 0x05,
+}
+
+impl PacketType {
+    /// Parse a packet indicator
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0x01 => Some(PacketType::Command),
+            0x02 => Some(PacketType::AclData),
+            0x03 => Some(PacketType::SyncData),
+            0x04 => Some(PacketType::Event),
+            0x05 => Some(PacketType::IsoData),
+            _ => None,
+        }
+    }
+
+    /// Length of the packet header, which contains the payload length
+    fn header_len(self) -> usize {
+        match self {
+            PacketType::Event => 2,
+            PacketType::Command | PacketType::SyncData => 3,
+            PacketType::AclData | PacketType::IsoData => 4,
+        }
+    }
+
+    /// Payload length, read from a complete header
+    fn payload_len(self, header: &[u8]) -> usize {
+        match self {
+            PacketType::Event => usize::from(header[1]),
+            PacketType::Command | PacketType::SyncData => usize::from(header[2]),
+            PacketType::AclData => usize::from(u16::from_le_bytes([header[2], header[3]])),
+            // The upper two bits are reserved
+            PacketType::IsoData => usize::from(u16::from_le_bytes([header[2], header[3]]) & 0x3FFF),
+        }
+    }
+}
+
+/// Errors reported by [H4Transport]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    /// The UART reported an error
+    Uart(E),
+    /// Received an unknown packet indicator, the byte is skipped
+    UnknownPacketType(u8),
+    /// A received packet doesn't fit into [MAX_PACKET_LEN], it is skipped
+    PacketTooLarge,
+    /// A packet to be sent is longer than its length field allows
+    InvalidLength,
+}
+
+/// A received HCI packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Packet<'a> {
+    /// Type of the packet
+    pub kind: PacketType,
+    /// The packet including its header, without the packet indicator
+    pub data: &'a [u8],
+}
+
+/// Frames HCI packets with the H4 protocol
+///
+/// Flow control is left to the UART hardware.
+pub struct H4Transport<U> {
+    uart: U,
+    buf: [u8; MAX_PACKET_LEN],
+    kind: Option<PacketType>,
+    len: usize,
+    expected: usize,
+    skip: usize,
+    complete: bool,
+}
+
+impl<U> H4Transport<U>
+where
+    U: Read<u8> + Write<u8>,
+{
+    /// Create a transport on top of a UART connected to the controller
+    pub fn new(uart: U) -> Self {
+        H4Transport {
+            uart,
+            buf: [0; MAX_PACKET_LEN],
+            kind: None,
+            len: 0,
+            expected: 0,
+            skip: 0,
+            complete: false,
+        }
+    }
+
+    /// Release the UART
+    pub fn free(self) -> U {
+        self.uart
+    }
+
+    fn write_all(&mut self, bytes: &[u8]) -> Result<(), Error<U::Error>> {
+        for &byte in bytes {
+            nb::block!(self.uart.write(byte)).map_err(Error::Uart)?;
+        }
+        Ok(())
+    }
+
+    /// Send a complete HCI packet, including its header
+    pub fn write_packet(&mut self, kind: PacketType, packet: &[u8]) -> Result<(), Error<U::Error>> {
+        self.write_all(&[kind as u8])?;
+        self.write_all(packet)?;
+        nb::block!(self.uart.flush()).map_err(Error::Uart)
+    }
+
+    /// Send an HCI command
+    pub fn send_command(&mut self, opcode: u16, params: &[u8]) -> Result<(), Error<U::Error>> {
+        let len = u8::try_from(params.len()).map_err(|_| Error::InvalidLength)?;
+        let [lo, hi] = opcode.to_le_bytes();
+        self.write_all(&[PacketType::Command as u8, lo, hi, len])?;
+        self.write_all(params)?;
+        nb::block!(self.uart.flush()).map_err(Error::Uart)
+    }
+
+    /// Send ACL data for the connection `handle`
+    ///
+    /// `flags` are the packet boundary and broadcast flags, in the upper four
+    /// bits of the handle field.
+    pub fn send_acl(&mut self, handle: u16, flags: u8, data: &[u8]) -> Result<(), Error<U::Error>> {
+        let len = u16::try_from(data.len()).map_err(|_| Error::InvalidLength)?;
+        let [h_lo, h_hi] = (handle & 0x0FFF | u16::from(flags) << 12).to_le_bytes();
+        let [l_lo, l_hi] = len.to_le_bytes();
+        self.write_all(&[PacketType::AclData as u8, h_lo, h_hi, l_lo, l_hi])?;
+        self.write_all(data)?;
+        nb::block!(self.uart.flush()).map_err(Error::Uart)
+    }
+
+    /// Read received bytes until a complete packet is available
+    ///
+    /// Returns [nb::Error::WouldBlock] if the UART runs out of data first.
+    /// The packet stays valid until the next call.
+    pub fn read_packet(&mut self) -> nb::Result<Packet<'_>, Error<U::Error>> {
+        loop {
+            let byte = self.uart.read().map_err(|e| e.map(Error::Uart))?;
+            if self.push(byte)? {
+                break;
+            }
+        }
+        Ok(Packet {
+            // Only complete packets are returned, which have a type
+            kind: self.kind.unwrap(),
+            data: &self.buf[..self.len],
+        })
+    }
+
+    /// Feed a received byte into the packet parser
+    ///
+    /// Returns `true` once a packet is complete.
+    fn push(&mut self, byte: u8) -> Result<bool, Error<U::Error>> {
+        if self.complete {
+            self.complete = false;
+            self.kind = None;
+        }
+
+        if self.skip > 0 {
+            self.skip -= 1;
+            return Ok(false);
+        }
+
+        let kind = match self.kind {
+            Some(kind) => kind,
+            None => {
+                let kind = PacketType::from_u8(byte).ok_or(Error::UnknownPacketType(byte))?;
+                self.kind = Some(kind);
+                self.len = 0;
+                self.expected = kind.header_len();
+                return Ok(false);
+            }
+        };
+
+        self.buf[self.len] = byte;
+        self.len += 1;
+
+        if self.len == kind.header_len() {
+            let payload = kind.payload_len(&self.buf[..self.len]);
+            if self.len + payload > MAX_PACKET_LEN {
+                self.kind = None;
+                self.skip = payload;
+                return Err(Error::PacketTooLarge);
+            }
+            self.expected = self.len + payload;
+        }
+
+        self.complete = self.len == self.expected;
+        Ok(self.complete)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::collections::VecDeque;
+    use std::vec::Vec;
+
+    use embedded_hal_nb::serial::{ErrorKind, ErrorType};
+
+    use super::*;
+
+    /// A UART with queued received bytes, which runs dry once they are read
+    #[derive(Default)]
+    struct FakeUart {
+        rx: VecDeque<Result<u8, ErrorKind>>,
+        tx: Vec<u8>,
+        flushes: usize,
+    }
+
+    impl ErrorType for FakeUart {
+        type Error = ErrorKind;
+    }
+
+    impl Read<u8> for FakeUart {
+        fn read(&mut self) -> nb::Result<u8, ErrorKind> {
+            match self.rx.pop_front() {
+                Some(received) => received.map_err(nb::Error::Other),
+                None => Err(nb::Error::WouldBlock),
+            }
+        }
+    }
+
+    impl Write<u8> for FakeUart {
+        fn write(&mut self, byte: u8) -> nb::Result<(), ErrorKind> {
+            self.tx.push(byte);
+            Ok(())
+        }
+
+        fn flush(&mut self) -> nb::Result<(), ErrorKind> {
+            self.flushes += 1;
+            Ok(())
+        }
+    }
+
+    fn hci(received: &[u8]) -> H4Transport<FakeUart> {
+        let mut hci = H4Transport::new(FakeUart::default());
+        receive(&mut hci, received);
+        hci
+    }
+
+    fn receive(hci: &mut H4Transport<FakeUart>, bytes: &[u8]) {
+        hci.uart.rx.extend(bytes.iter().map(|&b| Ok(b)));
+    }
+
+    /// Reads a packet, copying it out of the transport
+    fn read(
+        hci: &mut H4Transport<FakeUart>,
+    ) -> nb::Result<(PacketType, Vec<u8>), Error<ErrorKind>> {
+        hci.read_packet()
+            .map(|packet| (packet.kind, packet.data.to_vec()))
+    }
+
+    #[test]
+    fn events_are_received() {
+        // Command Complete of HCI_Reset, and an event without parameters
+        let mut hci = hci(&[0x04, 0x0E, 0x04, 0x01, 0x03, 0x0C, 0x00, 0x04, 0xFF, 0x00]);
+        assert_eq!(
+            read(&mut hci),
+            Ok((
+                PacketType::Event,
+                std::vec![0x0E, 0x04, 0x01, 0x03, 0x0C, 0x00]
+            ))
+        );
+        assert_eq!(
+            read(&mut hci),
+            Ok((PacketType::Event, std::vec![0xFF, 0x00]))
+        );
+        assert_eq!(read(&mut hci), Err(nb::Error::WouldBlock));
+    }
+
+    #[test]
+    fn data_packets_have_16_bit_lengths() {
+        let mut hci = hci(&[0x02, 0x40, 0x20, 0x03, 0x00, 1, 2, 3]);
+        assert_eq!(
+            read(&mut hci),
+            Ok((
+                PacketType::AclData,
+                std::vec![0x40, 0x20, 0x03, 0x00, 1, 2, 3]
+            ))
+        );
+
+        // The upper bits of the ISO length are reserved
+        receive(&mut hci, &[0x05, 0x01, 0x00, 0x02, 0xC0, 4, 5]);
+        assert_eq!(
+            read(&mut hci),
+            Ok((PacketType::IsoData, std::vec![0x01, 0x00, 0x02, 0xC0, 4, 5]))
+        );
+
+        // The largest ACL packet which fits
+        let mut acl = std::vec![0x02, 0x01, 0x00, 0xFF, 0x00];
+        acl.extend((0..255).map(|i| i as u8));
+        receive(&mut hci, &acl);
+        assert_eq!(read(&mut hci), Ok((PacketType::AclData, acl[1..].to_vec())));
+    }
+
+    #[test]
+    fn packets_are_read_in_parts() {
+        let packet = [0x04, 0x13, 0x05, 0x01, 0x40, 0x00, 0x01, 0x00];
+        let mut hci = hci(&[]);
+        for &byte in &packet[..packet.len() - 1] {
+            receive(&mut hci, &[byte]);
+            assert_eq!(read(&mut hci), Err(nb::Error::WouldBlock));
+        }
+        receive(&mut hci, &packet[packet.len() - 1..]);
+        assert_eq!(
+            read(&mut hci),
+            Ok((PacketType::Event, packet[1..].to_vec()))
+        );
+
+        // Also when the header is split
+        receive(&mut hci, &[0x02, 0x40]);
+        assert_eq!(read(&mut hci), Err(nb::Error::WouldBlock));
+        receive(&mut hci, &[0x00, 0x01, 0x00, 9]);
+        assert_eq!(
+            read(&mut hci),
+            Ok((PacketType::AclData, std::vec![0x40, 0x00, 0x01, 0x00, 9]))
+        );
+    }
+
+    #[test]
+    fn unknown_packet_types_are_skipped() {
+        let mut hci = hci(&[0x00, 0xFF, 0x04, 0x0F, 0x00]);
+        assert_eq!(
+            read(&mut hci),
+            Err(nb::Error::Other(Error::UnknownPacketType(0x00)))
+        );
+        assert_eq!(
+            read(&mut hci),
+            Err(nb::Error::Other(Error::UnknownPacketType(0xFF)))
+        );
+        assert_eq!(
+            read(&mut hci),
+            Ok((PacketType::Event, std::vec![0x0F, 0x00]))
+        );
+    }
+
+    #[test]
+    fn packets_too_large_are_skipped() {
+        // 256 bytes of ACL data, followed by an event
+        let mut hci = hci(&[0x02, 0x40, 0x00, 0x00, 0x01]);
+        receive(&mut hci, &[0x04; 256]);
+        receive(&mut hci, &[0x04, 0x0F, 0x00]);
+        assert_eq!(read(&mut hci), Err(nb::Error::Other(Error::PacketTooLarge)));
+        assert_eq!(
+            read(&mut hci),
+            Ok((PacketType::Event, std::vec![0x0F, 0x00]))
+        );
+    }
+
+    #[test]
+    fn uart_errors_are_reported() {
+        let mut hci = hci(&[0x04, 0x0F]);
+        hci.uart.rx.push_back(Err(ErrorKind::Overrun));
+        receive(&mut hci, &[0x00]);
+        assert_eq!(
+            read(&mut hci),
+            Err(nb::Error::Other(Error::Uart(ErrorKind::Overrun)))
+        );
+        // The packet continues after the error
+        assert_eq!(
+            read(&mut hci),
+            Ok((PacketType::Event, std::vec![0x0F, 0x00]))
+        );
+    }
+
+    #[test]
+    fn packets_are_framed() {
+        let mut hci = hci(&[]);
+        hci.send_command(0x0C03, &[]).unwrap();
+        hci.send_command(0x2006, &[0xA0, 0x00]).unwrap();
+        hci.send_acl(0x0040, 0b10, &[1, 2, 3]).unwrap();
+        hci.write_packet(PacketType::Command, &[0x01, 0x10, 0x00])
+            .unwrap();
+        assert_eq!(
+            hci.uart.tx,
+            [
+                0x01, 0x03, 0x0C, 0x00, //
+                0x01, 0x06, 0x20, 0x02, 0xA0, 0x00, //
+                0x02, 0x40, 0x20, 0x03, 0x00, 1, 2, 3, //
+                0x01, 0x01, 0x10, 0x00,
+            ]
+        );
+        assert_eq!(hci.uart.flushes, 4);
+
+        // Nothing is sent for packets too long for their length field
+        assert_eq!(
+            hci.send_command(0x0C03, &[0; 256]),
+            Err(Error::InvalidLength)
+        );
+        assert_eq!(
+            hci.send_acl(0x0040, 0, &[0; 0x10000]),
+            Err(Error::InvalidLength)
+        );
+        assert_eq!(hci.uart.tx.len(), 22);
+    }
+}
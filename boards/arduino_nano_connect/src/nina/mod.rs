@@ -0,0 +1,66 @@
+//! Driver for the u-blox NINA-W102 WiFi and Bluetooth module
+//!
+//! The module runs Arduino's [nina-fw], an ESP32 firmware which is controlled
+//! over SPI with the WiFiNINA command protocol. The transport is implemented
+//! in [spi], the WiFi and socket commands in [wifi]. Alternatively the module
+//! can be reset into a Bluetooth controller, see [ble].
+//!
+//! Everything is generic over the `embedded-hal` traits, so it runs against a
+//! simulated module just as well as on the board. On the Nano RP2040 Connect
+//! the module is wired up as follows:
+//!
+//! | NINA    | RP2040 | Pin                         |
+//! |---------|--------|-----------------------------|
+//! | SCK     | GPIO14 | [crate::Pins::nina_sck]     |
+//! | COPI    | GPIO11 | [crate::Pins::nina_copi]    |
+//! | CIPO    | GPIO8  | [crate::Pins::ble_tx_cipo]  |
+//! | CS      | GPIO9  | [crate::Pins::ble_rx_cs]    |
+//! | ACK     | GPIO10 | [crate::Pins::ble_cts_ack]  |
+//! | GPIO0   | GPIO2  | [crate::Pins::ble_rts]      |
+//! | RESET_N | GPIO3  | [crate::Pins::nina_reset_n] |
+//!
+//! [nina-fw]: https://github.com/arduino/nina-fw
+
+pub mod ble;
+#[cfg(test)]
+mod sim;
+pub mod spi;
+pub mod wifi;
+
+pub use spi::NinaSpi;
+pub use wifi::Wifi;
+
+/// Errors reported by the NINA drivers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    /// The SPI bus reported an error
+    Spi(E),
+    /// Setting or reading one of the control pins failed
+    Pin,
+    /// The module didn't become ready or didn't respond in time
+    Timeout,
+    /// The module sent a malformed response or an error reply
+    Protocol,
+    /// The response didn't fit into the buffer
+    BufferTooSmall,
+    /// The module rejected the command
+    Failed,
+    /// All sockets of the module are in use
+    NoSocket,
+    /// The connection has been closed by the remote end
+    Closed,
+    /// The UDP socket has no remote address to send to
+    NotConnected,
+    /// The request isn't supported by the module, e.g. IPv6
+    Unsupported,
+}
+
+impl<E: core::fmt::Debug> embedded_nal::TcpError for Error<E> {
+    fn kind(&self) -> embedded_nal::TcpErrorKind {
+        match self {
+            Error::Closed => embedded_nal::TcpErrorKind::PipeClosed,
+            _ => embedded_nal::TcpErrorKind::Other,
+        }
+    }
+}
@@ -0,0 +1,228 @@
+//! A simulated NINA module for the tests of the drivers
+//!
+//! [Module] implements the SPI bus, the chip select, the ACK line and the
+//! delay. Its replies are either queued byte by byte with [Module::reply],
+//! or produced by a [Firmware] which gets each command decoded, like the
+//! command handler of nina-fw.
+
+extern crate std;
+
+use core::cell::RefCell;
+use core::convert::Infallible;
+use std::boxed::Box;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::vec::Vec;
+
+use embedded_hal::{delay, digital, spi};
+
+use super::spi::NinaSpi;
+
+const START_CMD: u8 = 0xE0;
+const END_CMD: u8 = 0xEE;
+const ERR_CMD: u8 = 0xEF;
+const REPLY_FLAG: u8 = 1 << 7;
+
+/// Byte clocked out by the host while reading
+const DUMMY: u8 = 0xFF;
+
+/// Answers a command with the parameters of its reply, or `None` for an
+/// error reply
+pub type Firmware = Box<dyn FnMut(u8, &[Vec<u8>]) -> Option<Vec<Vec<u8>>>>;
+
+/// What the simulated module sees and answers
+pub struct State {
+    /// Whether the module pulls ACK low while deselected
+    pub ready: bool,
+    /// Whether the module drives ACK high once selected
+    pub acks: bool,
+    pub selected: bool,
+    /// Bytes written in the current transaction
+    written: Vec<u8>,
+    /// Bytes written in each completed transaction
+    pub transactions: Vec<Vec<u8>>,
+    /// Bytes clocked out by the module, idle low once empty
+    pub reply: VecDeque<u8>,
+    /// Microseconds spent waiting
+    pub waited_us: u64,
+    firmware: Option<Firmware>,
+}
+
+/// A NINA module on the other end of the bus
+#[derive(Clone)]
+pub struct Module(pub Rc<RefCell<State>>);
+
+impl Module {
+    pub fn new() -> Self {
+        Module(Rc::new(RefCell::new(State {
+            ready: true,
+            acks: true,
+            selected: false,
+            written: Vec::new(),
+            transactions: Vec::new(),
+            reply: VecDeque::new(),
+            waited_us: 0,
+            firmware: None,
+        })))
+    }
+
+    /// A module which answers every command with `firmware`
+    pub fn with_firmware(
+        firmware: impl FnMut(u8, &[Vec<u8>]) -> Option<Vec<Vec<u8>>> + 'static,
+    ) -> Self {
+        let module = Module::new();
+        module.0.borrow_mut().firmware = Some(Box::new(firmware));
+        module
+    }
+
+    pub fn nina(&self) -> NinaSpi<Module, Module, Module, Module> {
+        NinaSpi::new(self.clone(), self.clone(), self.clone(), self.clone())
+    }
+
+    pub fn reply(&self, bytes: &[u8]) {
+        self.0.borrow_mut().reply.extend(bytes);
+    }
+
+    pub fn transactions(&self) -> Vec<Vec<u8>> {
+        self.0.borrow().transactions.clone()
+    }
+}
+
+/// Whether the parameters of `cmd` have 16 bit lengths, as they carry
+/// socket data
+fn long_params(cmd: u8) -> bool {
+    matches!(cmd, 0x44..=0x46)
+}
+
+/// Whether the reply to `cmd` has 16 bit parameter lengths
+fn long_reply(cmd: u8) -> bool {
+    cmd == 0x45
+}
+
+/// Splits a command into its ID and parameters
+fn decode(bytes: &[u8]) -> (u8, Vec<Vec<u8>>) {
+    assert_eq!(bytes[0], START_CMD, "not a command: {:02x?}", bytes);
+    assert_eq!(bytes.len() % 4, 0, "not padded: {:02x?}", bytes);
+    let cmd = bytes[1];
+    let mut at = 3;
+    let params = (0..bytes[2])
+        .map(|_| {
+            let len = if long_params(cmd) {
+                at += 2;
+                usize::from(u16::from_be_bytes([bytes[at - 2], bytes[at - 1]]))
+            } else {
+                at += 1;
+                usize::from(bytes[at - 1])
+            };
+            at += len;
+            bytes[at - len..at].to_vec()
+        })
+        .collect();
+    assert_eq!(bytes[at], END_CMD, "not terminated: {:02x?}", bytes);
+    (cmd, params)
+}
+
+/// Frames the reply to `cmd`
+fn encode(cmd: u8, params: Option<Vec<Vec<u8>>>) -> Vec<u8> {
+    let params = match params {
+        Some(params) => params,
+        None => return std::vec![ERR_CMD],
+    };
+    let mut bytes = std::vec![START_CMD, cmd | REPLY_FLAG, params.len() as u8];
+    for param in params {
+        if long_reply(cmd) {
+            bytes.extend_from_slice(&(param.len() as u16).to_be_bytes());
+        } else {
+            bytes.push(param.len() as u8);
+        }
+        bytes.extend(param);
+    }
+    bytes.push(END_CMD);
+    bytes
+}
+
+impl spi::ErrorType for Module {
+    type Error = Infallible;
+}
+
+impl spi::SpiBus<u8> for Module {
+    fn read(&mut self, words: &mut [u8]) -> Result<(), Infallible> {
+        let mut state = self.0.borrow_mut();
+        assert!(state.selected, "read while deselected");
+        for word in words {
+            *word = state.reply.pop_front().unwrap_or(0);
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, words: &[u8]) -> Result<(), Infallible> {
+        let mut state = self.0.borrow_mut();
+        assert!(state.selected, "write while deselected");
+        state.written.extend_from_slice(words);
+        Ok(())
+    }
+
+    fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Infallible> {
+        spi::SpiBus::write(self, write)?;
+        spi::SpiBus::read(self, read)
+    }
+
+    fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), Infallible> {
+        assert!(words.iter().all(|&word| word == DUMMY));
+        spi::SpiBus::read(self, words)
+    }
+
+    fn flush(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+impl digital::ErrorType for Module {
+    type Error = Infallible;
+}
+
+/// The chip select
+impl digital::OutputPin for Module {
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        self.0.borrow_mut().selected = true;
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        let mut state = self.0.borrow_mut();
+        let state = &mut *state;
+        if state.selected {
+            let written = core::mem::take(&mut state.written);
+            // Reading a reply only clocks out dummy bytes
+            if let (Some(firmware), Some(&START_CMD)) = (&mut state.firmware, written.first()) {
+                let (cmd, params) = decode(&written);
+                state.reply.extend(encode(cmd, firmware(cmd, &params)));
+            }
+            state.transactions.push(written);
+        }
+        state.selected = false;
+        Ok(())
+    }
+}
+
+/// The ACK line
+impl digital::InputPin for Module {
+    fn is_high(&mut self) -> Result<bool, Infallible> {
+        let state = self.0.borrow();
+        Ok(if state.selected {
+            state.acks
+        } else {
+            !state.ready
+        })
+    }
+
+    fn is_low(&mut self) -> Result<bool, Infallible> {
+        self.is_high().map(|high| !high)
+    }
+}
+
+impl delay::DelayNs for Module {
+    fn delay_ns(&mut self, ns: u32) {
+        self.0.borrow_mut().waited_us += u64::from(ns / 1000);
+    }
+}
@@ -0,0 +1,447 @@
+//! SPI transport of the WiFiNINA command protocol
+//!
+//! Every command is sent in its own SPI transaction:
+//!
+//! | Byte(s)    | Content                                           |
+//! |------------|---------------------------------------------------|
+//! | 1          | `START_CMD` (`0xE0`)                              |
+//! | 1          | Command ID                                        |
+//! | 1          | Number of parameters                              |
+//! | 1 or 2 + n | For each parameter: length, followed by its bytes |
+//! | 1          | `END_CMD` (`0xEE`)                                |
+//!
+//! Commands are padded to a multiple of 4 bytes. The response follows in a
+//! second transaction with the same layout, with the reply flag set in the
+//! command ID. Before each transaction the module signals it is ready by
+//! pulling the ACK line low, and acknowledges the chip select by driving it
+//! high.
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::{InputPin, OutputPin};
+use embedded_hal::spi::SpiBus;
+
+use super::Error;
+
+const START_CMD: u8 = 0xE0;
+const END_CMD: u8 = 0xEE;
+const ERR_CMD: u8 = 0xEF;
+const REPLY_FLAG: u8 = 1 << 7;
+
+/// Byte clocked out while reading
+const DUMMY: u8 = 0xFF;
+
+/// Maximum number of parameters in a response
+pub const MAX_PARAMS: usize = 10;
+
+/// How long to wait for the module to become ready, scanning takes a while
+const READY_TIMEOUT_US: u32 = 10_000_000;
+/// How long to wait for the module to acknowledge the chip select
+const SELECT_TIMEOUT_US: u32 = 5_000;
+/// How many bytes to read while waiting for the start of a response
+const START_TIMEOUT_BYTES: u32 = 1_000;
+/// Granularity of the timeouts above
+const POLL_INTERVAL_US: u32 = 10;
+
+/// Width of the length field of every parameter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ParamLength {
+    /// One byte, used by most commands
+    Short,
+    /// Two bytes, big endian, used by commands transferring socket data
+    Long,
+}
+
+/// Parameters of a response, stored back to back in a caller provided buffer
+#[derive(Debug)]
+pub struct Response<'a> {
+    data: &'a [u8],
+    ends: [usize; MAX_PARAMS],
+    count: usize,
+}
+
+impl<'a> Response<'a> {
+    /// Number of parameters
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// Whether the response has no parameters
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Get the parameter at `index`
+    pub fn get(&self, index: usize) -> Option<&'a [u8]> {
+        if index >= self.count {
+            return None;
+        }
+        let start = if index == 0 { 0 } else { self.ends[index - 1] };
+        Some(&self.data[start..self.ends[index]])
+    }
+
+    /// Get the first byte of the first parameter
+    pub fn byte(&self) -> Option<u8> {
+        self.get(0)?.first().copied()
+    }
+
+    /// Get the first parameter as little endian `u16`
+    ///
+    /// A one byte parameter is zero extended.
+    pub fn u16_le(&self) -> Option<u16> {
+        match *self.get(0)? {
+            [lo] => Some(u16::from(lo)),
+            [lo, hi, ..] => Some(u16::from_le_bytes([lo, hi])),
+            [] => None,
+        }
+    }
+}
+
+/// Talks to the NINA module over SPI
+///
+/// The chip select is driven manually, since it has to be interleaved with the
+/// ACK handshake.
+pub struct NinaSpi<SPI, CS, ACK, D> {
+    spi: SPI,
+    cs: CS,
+    ack: ACK,
+    delay: D,
+}
+
+impl<SPI, CS, ACK, D> NinaSpi<SPI, CS, ACK, D>
+where
+    SPI: SpiBus<u8>,
+    CS: OutputPin,
+    ACK: InputPin,
+    D: DelayNs,
+{
+    /// Create a new transport, the bus has to be configured for SPI mode 0
+    pub fn new(spi: SPI, mut cs: CS, ack: ACK, delay: D) -> Self {
+        cs.set_high().ok();
+        NinaSpi {
+            spi,
+            cs,
+            ack,
+            delay,
+        }
+    }
+
+    /// Reset the module into its SPI WiFi firmware
+    ///
+    /// `gpio0` is held high during reset so the ESP32 doesn't enter its
+    /// download mode. Together with the chip select being high, this boots
+    /// the WiFi firmware. Afterwards `gpio0` can be released.
+    pub fn reset<RST, GPIO0>(
+        &mut self,
+        reset_n: &mut RST,
+        gpio0: &mut GPIO0,
+    ) -> Result<(), Error<SPI::Error>>
+    where
+        RST: OutputPin,
+        GPIO0: OutputPin,
+    {
+        gpio0.set_high().map_err(|_| Error::Pin)?;
+        self.cs.set_high().map_err(|_| Error::Pin)?;
+        reset_n.set_low().map_err(|_| Error::Pin)?;
+        self.delay.delay_ms(10);
+        reset_n.set_high().map_err(|_| Error::Pin)?;
+        self.delay.delay_ms(750);
+        gpio0.set_low().map_err(|_| Error::Pin)?;
+        #[cfg(feature = "defmt")]
+        defmt::debug!("nina: reset into the WiFi firmware");
+        Ok(())
+    }
+
+    /// Release the bus, pins and delay
+    pub fn free(self) -> (SPI, CS, ACK, D) {
+        (self.spi, self.cs, self.ack, self.delay)
+    }
+
+    /// Wait until `ack` is `high`, polling every few microseconds
+    fn wait_ack(&mut self, high: bool, timeout_us: u32) -> Result<(), Error<SPI::Error>> {
+        let mut waited = 0;
+        while self.ack.is_high().map_err(|_| Error::Pin)? != high {
+            if waited >= timeout_us {
+                return Err(Error::Timeout);
+            }
+            self.delay.delay_us(POLL_INTERVAL_US);
+            waited += POLL_INTERVAL_US;
+        }
+        Ok(())
+    }
+
+    fn select(&mut self) -> Result<(), Error<SPI::Error>> {
+        // The module signals it is ready by pulling ACK low
+        self.wait_ack(false, READY_TIMEOUT_US)?;
+        self.cs.set_low().map_err(|_| Error::Pin)?;
+        // and then acknowledges the chip select by driving it high
+        if let Err(e) = self.wait_ack(true, SELECT_TIMEOUT_US) {
+            self.deselect()?;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    fn deselect(&mut self) -> Result<(), Error<SPI::Error>> {
+        self.spi.flush().map_err(Error::Spi)?;
+        self.cs.set_high().map_err(|_| Error::Pin)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Error<SPI::Error>> {
+        self.spi.write(bytes).map_err(Error::Spi)
+    }
+
+    fn read_byte(&mut self) -> Result<u8, Error<SPI::Error>> {
+        let mut byte = [DUMMY];
+        self.spi.transfer_in_place(&mut byte).map_err(Error::Spi)?;
+        Ok(byte[0])
+    }
+
+    /// Send a command with the given parameters
+    pub fn command(
+        &mut self,
+        cmd: u8,
+        params: &[&[u8]],
+        length: ParamLength,
+    ) -> Result<(), Error<SPI::Error>> {
+        self.select()?;
+        let result = self.write_command(cmd, params, length);
+        self.deselect()?;
+        result
+    }
+
+    fn write_command(
+        &mut self,
+        cmd: u8,
+        params: &[&[u8]],
+        length: ParamLength,
+    ) -> Result<(), Error<SPI::Error>> {
+        self.write(&[START_CMD, cmd & !REPLY_FLAG, params.len() as u8])?;
+        let mut size = 3;
+        for param in params {
+            match length {
+                ParamLength::Short => {
+                    self.write(&[param.len() as u8])?;
+                    size += 1;
+                }
+                ParamLength::Long => {
+                    self.write(&(param.len() as u16).to_be_bytes())?;
+                    size += 2;
+                }
+            }
+            self.write(param)?;
+            size += param.len();
+        }
+        self.write(&[END_CMD])?;
+        size += 1;
+
+        // Pad the command to a multiple of 4 bytes
+        while size & 3 != 0 {
+            self.write(&[DUMMY])?;
+            size += 1;
+        }
+        Ok(())
+    }
+
+    /// Wait for the response to `cmd` and store its parameters in `buf`
+    pub fn response<'b>(
+        &mut self,
+        cmd: u8,
+        buf: &'b mut [u8],
+        length: ParamLength,
+    ) -> Result<Response<'b>, Error<SPI::Error>> {
+        self.select()?;
+        let result = self.read_response(cmd, buf, length);
+        self.deselect()?;
+        let (ends, count) = result?;
+        Ok(Response {
+            data: buf,
+            ends,
+            count,
+        })
+    }
+
+    fn read_response(
+        &mut self,
+        cmd: u8,
+        buf: &mut [u8],
+        length: ParamLength,
+    ) -> Result<([usize; MAX_PARAMS], usize), Error<SPI::Error>> {
+        let mut tries = 0;
+        loop {
+            match self.read_byte()? {
+                START_CMD => break,
+                ERR_CMD => return Err(Error::Protocol),
+                _ if tries >= START_TIMEOUT_BYTES => return Err(Error::Timeout),
+                _ => tries += 1,
+            }
+        }
+
+        if self.read_byte()? != cmd | REPLY_FLAG {
+            return Err(Error::Protocol);
+        }
+
+        let count = usize::from(self.read_byte()?);
+        if count > MAX_PARAMS {
+            return Err(Error::Protocol);
+        }
+
+        let mut ends = [0; MAX_PARAMS];
+        let mut offset = 0;
+        for end in ends.iter_mut().take(count) {
+            let len = match length {
+                ParamLength::Short => usize::from(self.read_byte()?),
+                ParamLength::Long => {
+                    let hi = self.read_byte()?;
+                    let lo = self.read_byte()?;
+                    usize::from(u16::from_be_bytes([hi, lo]))
+                }
+            };
+            let target = buf
+                .get_mut(offset..offset + len)
+                .ok_or(Error::BufferTooSmall)?;
+            target.fill(DUMMY);
+            self.spi.transfer_in_place(target).map_err(Error::Spi)?;
+            offset += len;
+            *end = offset;
+        }
+
+        if self.read_byte()? != END_CMD {
+            return Err(Error::Protocol);
+        }
+        Ok((ends, count))
+    }
+
+    /// Send a command and wait for its response
+    pub fn exchange<'b>(
+        &mut self,
+        cmd: u8,
+        params: &[&[u8]],
+        length: ParamLength,
+        buf: &'b mut [u8],
+    ) -> Result<Response<'b>, Error<SPI::Error>> {
+        self.command(cmd, params, length)?;
+        self.response(cmd, buf, length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::nina::sim::Module;
+
+    #[test]
+    fn commands_are_framed_and_padded() {
+        let module = Module::new();
+        let mut nina = module.nina();
+        nina.command(0x10, &[b"ssid", &[1]], ParamLength::Short)
+            .unwrap();
+        nina.command(0x44, &[&[1, 2, 3]], ParamLength::Long)
+            .unwrap();
+        nina.command(0x37, &[], ParamLength::Short).unwrap();
+
+        assert_eq!(
+            module.transactions(),
+            [
+                std::vec![0xE0, 0x10, 2, 4, b's', b's', b'i', b'd', 1, 1, 0xEE, 0xFF],
+                std::vec![0xE0, 0x44, 1, 0, 3, 1, 2, 3, 0xEE, 0xFF, 0xFF, 0xFF],
+                std::vec![0xE0, 0x37, 0, 0xEE],
+            ]
+        );
+        assert!(!module.0.borrow().selected);
+    }
+
+    #[test]
+    fn responses_are_parsed() {
+        let module = Module::new();
+        let mut nina = module.nina();
+        let mut buf = [0; 16];
+
+        // The module may clock out idle bytes before the response
+        module.reply(&[0x00, 0xFF, 0xE0, 0x90, 2, 1, 7, 2, 0x34, 0x12, 0xEE]);
+        let response = nina.response(0x10, &mut buf, ParamLength::Short).unwrap();
+        assert_eq!(response.len(), 2);
+        assert_eq!(response.byte(), Some(7));
+        assert_eq!(response.get(1), Some(&[0x34, 0x12][..]));
+        assert_eq!(response.get(2), None);
+
+        module.reply(&[0xE0, 0xC5, 1, 0, 3, b'a', b'b', b'c', 0xEE]);
+        let response = nina.response(0x45, &mut buf, ParamLength::Long).unwrap();
+        assert_eq!(response.get(0), Some(&b"abc"[..]));
+
+        module.reply(&[0xE0, 0xB7, 1, 2, 0x34, 0x12, 0xEE]);
+        let response = nina
+            .exchange(0x37, &[], ParamLength::Short, &mut buf)
+            .unwrap();
+        assert_eq!(response.u16_le(), Some(0x1234));
+        assert_eq!(module.transactions().len(), 4);
+    }
+
+    #[test]
+    fn waits_for_the_module_to_become_ready() {
+        let module = Module::new();
+        let mut nina = module.nina();
+
+        module.0.borrow_mut().ready = false;
+        assert_eq!(
+            nina.command(0x37, &[], ParamLength::Short),
+            Err(Error::Timeout)
+        );
+        assert_eq!(module.0.borrow().waited_us, u64::from(READY_TIMEOUT_US));
+        // The chip select was never asserted
+        assert!(module.transactions().is_empty());
+    }
+
+    #[test]
+    fn deselects_without_an_ack() {
+        let module = Module::new();
+        let mut nina = module.nina();
+
+        module.0.borrow_mut().acks = false;
+        assert_eq!(
+            nina.command(0x37, &[], ParamLength::Short),
+            Err(Error::Timeout)
+        );
+        assert_eq!(module.0.borrow().waited_us, u64::from(SELECT_TIMEOUT_US));
+        assert!(!module.0.borrow().selected);
+        // Nothing was sent
+        assert_eq!(module.transactions(), [Vec::new()]);
+    }
+
+    #[test]
+    fn rejects_bad_responses() {
+        let module = Module::new();
+        let mut nina = module.nina();
+        let mut buf = [0; 4];
+        let mut response = |bytes: &[u8]| {
+            module.reply(bytes);
+            let result = nina
+                .response(0x10, &mut buf, ParamLength::Short)
+                .map(|response| response.len());
+            // Leftovers must not leak into the next response
+            module.0.borrow_mut().reply.clear();
+            result
+        };
+
+        // An error reply of the module
+        assert_eq!(response(&[0xEF, 0x10, 0]), Err(Error::Protocol));
+        // The reply to another command
+        assert_eq!(response(&[0xE0, 0x91, 0, 0xEE]), Err(Error::Protocol));
+        // A reply without the reply flag
+        assert_eq!(response(&[0xE0, 0x10, 0, 0xEE]), Err(Error::Protocol));
+        assert_eq!(response(&[0xE0, 0x90, 11]), Err(Error::Protocol));
+        assert_eq!(response(&[0xE0, 0x90, 1, 1, 7, 0x00]), Err(Error::Protocol));
+        assert_eq!(
+            response(&[0xE0, 0x90, 1, 5, 1, 2, 3, 4, 5, 0xEE]),
+            Err(Error::BufferTooSmall)
+        );
+        // Never starting a response
+        assert_eq!(response(&[]), Err(Error::Timeout));
+        assert_eq!(response(&[0xE0, 0x90, 0, 0xEE]), Ok(0));
+        assert!(!module.0.borrow().selected);
+    }
+}
@@ -0,0 +1,1144 @@
+//! WiFi and socket commands of the NINA firmware
+//!
+//! [Wifi] joins networks and implements the `embedded-nal` traits on top of
+//! the sockets of the module, so the usual network clients can be used with
+//! it. IPv6 isn't supported by the firmware.
+//!
+//! ```ignore
+//! let mut wifi = Wifi::new(nina);
+//! wifi.join("my-network", Some("my-passphrase"))?;
+//! while wifi.status()? != Status::Connected {
+//!     timer.delay_ms(100);
+//! }
+//! let mut socket = wifi.socket()?;
+//! nb::block!(wifi.connect(&mut socket, remote))?;
+//! ```
+
+use core::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4};
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::{InputPin, OutputPin};
+use embedded_hal::spi::SpiBus;
+use embedded_nal::{AddrType, Dns, TcpClientStack, UdpClientStack, UdpFullStack};
+
+use super::spi::{NinaSpi, ParamLength};
+use super::Error;
+
+const SET_NET: u8 = 0x10;
+const SET_PASSPHRASE: u8 = 0x11;
+const GET_CONN_STATUS: u8 = 0x20;
+const GET_IPADDR: u8 = 0x21;
+const GET_CURR_RSSI: u8 = 0x25;
+const SCAN_NETWORK: u8 = 0x27;
+const START_SERVER_TCP: u8 = 0x28;
+const AVAIL_DATA_TCP: u8 = 0x2B;
+const START_CLIENT_TCP: u8 = 0x2D;
+const STOP_CLIENT_TCP: u8 = 0x2E;
+const GET_CLIENT_STATE_TCP: u8 = 0x2F;
+const DISCONNECT: u8 = 0x30;
+const GET_IDX_RSSI: u8 = 0x32;
+const GET_IDX_ENCT: u8 = 0x33;
+const REQ_HOST_BY_NAME: u8 = 0x34;
+const GET_HOST_BY_NAME: u8 = 0x35;
+const START_SCAN_NETWORKS: u8 = 0x36;
+const GET_FW_VERSION: u8 = 0x37;
+const SEND_DATA_UDP: u8 = 0x39;
+const GET_REMOTE_DATA: u8 = 0x3A;
+const GET_SOCKET: u8 = 0x3F;
+const SEND_DATA_TCP: u8 = 0x44;
+const GET_DATABUF_TCP: u8 = 0x45;
+const INSERT_DATABUF: u8 = 0x46;
+
+/// Parameter of commands which don't need one, but still expect it
+const DUMMY_PARAM: &[u8] = &[0xFF];
+
+/// Returned by `GET_SOCKET` if all sockets are in use
+const NO_SOCKET: u8 = 0xFF;
+
+const TCP_MODE: u8 = 0;
+const UDP_MODE: u8 = 1;
+
+/// TCP states reported by `GET_CLIENT_STATE_TCP`, as used by lwIP
+const TCP_CLOSED: u8 = 0;
+const TCP_LISTEN: u8 = 1;
+const TCP_ESTABLISHED: u8 = 4;
+const TCP_FIN_WAIT_1: u8 = 5;
+const TCP_FIN_WAIT_2: u8 = 6;
+const TCP_CLOSE_WAIT: u8 = 7;
+
+/// Largest chunk of data transferred with a single command
+///
+/// The firmware receives commands into a 4 KiB buffer, leave some space for
+/// the command header.
+const MAX_CHUNK: usize = 4000;
+
+/// First port used for UDP sockets that aren't explicitly bound
+const EPHEMERAL_PORTS: u16 = 49152;
+
+/// Connection status of the module
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Status {
+    /// Not connected and not trying to connect
+    Idle,
+    /// The configured network couldn't be found
+    NoSsid,
+    /// A network scan has finished
+    ScanCompleted,
+    /// Connected and an IP address has been assigned
+    Connected,
+    /// Connecting failed, e.g. because of a wrong passphrase
+    ConnectFailed,
+    /// The connection has been lost
+    ConnectionLost,
+    /// Disconnected on request
+    Disconnected,
+    /// The module didn't respond
+    NoModule,
+    /// A status value unknown to this driver
+    Other(u8),
+}
+
+impl From<u8> for Status {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => Status::Idle,
+            1 => Status::NoSsid,
+            2 => Status::ScanCompleted,
+            3 => Status::Connected,
+            4 => Status::ConnectFailed,
+            5 => Status::ConnectionLost,
+            6 => Status::Disconnected,
+            255 => Status::NoModule,
+            other => Status::Other(other),
+        }
+    }
+}
+
+/// Encryption used by a network
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Encryption {
+    /// Open network
+    None,
+    /// WEP
+    Wep,
+    /// WPA with TKIP
+    WpaTkip,
+    /// WPA2 with CCMP
+    Wpa2Ccmp,
+    /// WPA or WPA2, the module picks
+    Auto,
+    /// Anything else, e.g. WPA2 Enterprise
+    Unknown,
+}
+
+impl From<u8> for Encryption {
+    fn from(value: u8) -> Self {
+        match value {
+            2 => Encryption::WpaTkip,
+            4 => Encryption::Wpa2Ccmp,
+            5 => Encryption::Wep,
+            7 => Encryption::None,
+            8 => Encryption::Auto,
+            _ => Encryption::Unknown,
+        }
+    }
+}
+
+/// A network found by [Wifi::scan]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Network {
+    ssid: [u8; 32],
+    ssid_len: usize,
+    /// Signal strength in dBm
+    pub rssi: i32,
+    /// Encryption used by the network
+    pub encryption: Encryption,
+}
+
+impl Network {
+    /// Name of the network
+    ///
+    /// Empty if the name isn't valid UTF-8.
+    pub fn ssid(&self) -> &str {
+        core::str::from_utf8(&self.ssid[..self.ssid_len]).unwrap_or("")
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Network {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(
+            f,
+            "Network {{ ssid: {=str}, rssi: {=i32}, encryption: {} }}",
+            self.ssid(),
+            self.rssi,
+            self.encryption
+        )
+    }
+}
+
+impl Default for Network {
+    fn default() -> Self {
+        Network {
+            ssid: [0; 32],
+            ssid_len: 0,
+            rssi: 0,
+            encryption: Encryption::Unknown,
+        }
+    }
+}
+
+/// Addresses assigned by DHCP
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpConfig {
+    /// Our own address
+    pub address: Ipv4Addr,
+    /// Netmask of the network
+    pub netmask: Ipv4Addr,
+    /// Default gateway
+    pub gateway: Ipv4Addr,
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for IpConfig {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(
+            f,
+            "IpConfig {{ address: {=[u8]}, netmask: {=[u8]}, gateway: {=[u8]} }}",
+            &self.address.octets()[..],
+            &self.netmask.octets()[..],
+            &self.gateway.octets()[..]
+        )
+    }
+}
+
+/// A TCP socket of the module
+#[derive(Debug)]
+pub struct TcpSocket {
+    sock: u8,
+    started: bool,
+}
+
+/// A UDP socket of the module
+#[derive(Debug)]
+pub struct UdpSocket {
+    sock: u8,
+    remote: Option<SocketAddrV4>,
+}
+
+/// WiFi driver for the NINA module
+pub struct Wifi<SPI, CS, ACK, D> {
+    nina: NinaSpi<SPI, CS, ACK, D>,
+    next_port: u16,
+}
+
+impl<SPI, CS, ACK, D> Wifi<SPI, CS, ACK, D>
+where
+    SPI: SpiBus<u8>,
+    CS: OutputPin,
+    ACK: InputPin,
+    D: DelayNs,
+{
+    /// Create the driver, the module has to be reset into its WiFi firmware
+    ///
+    /// See [NinaSpi::reset].
+    pub fn new(nina: NinaSpi<SPI, CS, ACK, D>) -> Self {
+        Wifi {
+            nina,
+            next_port: EPHEMERAL_PORTS,
+        }
+    }
+
+    /// Release the transport
+    pub fn free(self) -> NinaSpi<SPI, CS, ACK, D> {
+        self.nina
+    }
+
+    /// Send a command with short parameters and return its first response byte
+    fn request(&mut self, cmd: u8, params: &[&[u8]]) -> Result<u8, Error<SPI::Error>> {
+        let mut buf = [0; 4];
+        let response = self
+            .nina
+            .exchange(cmd, params, ParamLength::Short, &mut buf)?;
+        response.byte().ok_or(Error::Protocol)
+    }
+
+    /// Like [Self::request], but fail unless the module responds with `1`
+    fn request_ok(&mut self, cmd: u8, params: &[&[u8]]) -> Result<(), Error<SPI::Error>> {
+        match self.request(cmd, params)? {
+            1 => Ok(()),
+            _ => Err(Error::Failed),
+        }
+    }
+
+    /// Read the firmware version into `buf`, e.g. `1.4.8`
+    pub fn firmware_version<'b>(
+        &mut self,
+        buf: &'b mut [u8],
+    ) -> Result<&'b str, Error<SPI::Error>> {
+        let response = self
+            .nina
+            .exchange(GET_FW_VERSION, &[], ParamLength::Short, buf)?;
+        let version = response.get(0).ok_or(Error::Protocol)?;
+        // The version is NUL terminated
+        let len = version
+            .iter()
+            .position(|&b| b == 0)
+            .unwrap_or(version.len());
+        core::str::from_utf8(&version[..len]).map_err(|_| Error::Protocol)
+    }
+
+    /// Scan for networks, filling in `networks`
+    ///
+    /// Blocks for the duration of the scan, which takes a few seconds. Returns
+    /// the number of networks found, at most `networks.len()`. The module
+    /// reports at most 10 networks.
+    pub fn scan(&mut self, networks: &mut [Network]) -> Result<usize, Error<SPI::Error>> {
+        self.request_ok(START_SCAN_NETWORKS, &[])?;
+
+        let mut buf = [0; 10 * 32];
+        let response = self
+            .nina
+            .exchange(SCAN_NETWORK, &[], ParamLength::Short, &mut buf)?;
+        let count = response.len().min(networks.len());
+        for (network, index) in networks.iter_mut().zip(0..count) {
+            let ssid = response.get(index).ok_or(Error::Protocol)?;
+            let len = ssid.len().min(network.ssid.len());
+            network.ssid[..len].copy_from_slice(&ssid[..len]);
+            network.ssid_len = len;
+        }
+
+        for (index, network) in networks.iter_mut().take(count).enumerate() {
+            let index = [index as u8];
+            network.rssi = self.read_i32(GET_IDX_RSSI, &[&index])?;
+            network.encryption = self.request(GET_IDX_ENCT, &[&index])?.into();
+        }
+        Ok(count)
+    }
+
+    fn read_i32(&mut self, cmd: u8, params: &[&[u8]]) -> Result<i32, Error<SPI::Error>> {
+        let mut buf = [0; 4];
+        let response = self
+            .nina
+            .exchange(cmd, params, ParamLength::Short, &mut buf)?;
+        match response.get(0) {
+            Some(&[a, b, c, d]) => Ok(i32::from_le_bytes([a, b, c, d])),
+            _ => Err(Error::Protocol),
+        }
+    }
+
+    /// Start joining a network, use `None` as passphrase for open networks
+    ///
+    /// Returns as soon as the module accepted the request, poll
+    /// [Self::status] until it reports [Status::Connected].
+    pub fn join(&mut self, ssid: &str, passphrase: Option<&str>) -> Result<(), Error<SPI::Error>> {
+        match passphrase {
+            Some(passphrase) => {
+                self.request_ok(SET_PASSPHRASE, &[ssid.as_bytes(), passphrase.as_bytes()])
+            }
+            None => self.request_ok(SET_NET, &[ssid.as_bytes()]),
+        }
+    }
+
+    /// Disconnect from the current network
+    pub fn leave(&mut self) -> Result<(), Error<SPI::Error>> {
+        self.request_ok(DISCONNECT, &[DUMMY_PARAM])
+    }
+
+    /// Get the connection status
+    pub fn status(&mut self) -> Result<Status, Error<SPI::Error>> {
+        Ok(self.request(GET_CONN_STATUS, &[])?.into())
+    }
+
+    /// Get the addresses assigned by DHCP
+    ///
+    /// All addresses are `0.0.0.0` until the module is connected.
+    pub fn ip_config(&mut self) -> Result<IpConfig, Error<SPI::Error>> {
+        let mut buf = [0; 12];
+        let response =
+            self.nina
+                .exchange(GET_IPADDR, &[DUMMY_PARAM], ParamLength::Short, &mut buf)?;
+        let address = |index| match response.get(index) {
+            Some(&[a, b, c, d]) => Ok(Ipv4Addr::new(a, b, c, d)),
+            _ => Err(Error::Protocol),
+        };
+        Ok(IpConfig {
+            address: address(0)?,
+            netmask: address(1)?,
+            gateway: address(2)?,
+        })
+    }
+
+    /// Signal strength of the current network in dBm
+    pub fn rssi(&mut self) -> Result<i32, Error<SPI::Error>> {
+        self.read_i32(GET_CURR_RSSI, &[DUMMY_PARAM])
+    }
+
+    fn get_socket(&mut self) -> Result<u8, Error<SPI::Error>> {
+        match self.request(GET_SOCKET, &[])? {
+            NO_SOCKET => Err(Error::NoSocket),
+            sock => Ok(sock),
+        }
+    }
+
+    fn start_client(
+        &mut self,
+        sock: u8,
+        remote: SocketAddrV4,
+        mode: u8,
+    ) -> Result<(), Error<SPI::Error>> {
+        self.request_ok(
+            START_CLIENT_TCP,
+            &[
+                &remote.ip().octets(),
+                &remote.port().to_be_bytes(),
+                &[sock],
+                &[mode],
+            ],
+        )
+    }
+
+    fn start_server(&mut self, sock: u8, port: u16, mode: u8) -> Result<(), Error<SPI::Error>> {
+        self.request_ok(START_SERVER_TCP, &[&port.to_be_bytes(), &[sock], &[mode]])
+    }
+
+    fn stop_client(&mut self, sock: u8) -> Result<(), Error<SPI::Error>> {
+        self.request(STOP_CLIENT_TCP, &[&[sock]]).map(|_| ())
+    }
+
+    fn tcp_state(&mut self, sock: u8) -> Result<u8, Error<SPI::Error>> {
+        self.request(GET_CLIENT_STATE_TCP, &[&[sock]])
+    }
+
+    fn available(&mut self, sock: u8) -> Result<usize, Error<SPI::Error>> {
+        let mut buf = [0; 2];
+        let response =
+            self.nina
+                .exchange(AVAIL_DATA_TCP, &[&[sock]], ParamLength::Short, &mut buf)?;
+        response.u16_le().map(usize::from).ok_or(Error::Protocol)
+    }
+
+    /// Read up to `buf.len()` received bytes
+    fn read_data(&mut self, sock: u8, buf: &mut [u8]) -> Result<usize, Error<SPI::Error>> {
+        let len = buf.len().min(MAX_CHUNK) as u16;
+        self.nina.command(
+            GET_DATABUF_TCP,
+            &[&[sock], &len.to_le_bytes()],
+            ParamLength::Long,
+        )?;
+        let response = self
+            .nina
+            .response(GET_DATABUF_TCP, buf, ParamLength::Long)?;
+        Ok(response.get(0).map_or(0, <[u8]>::len))
+    }
+
+    fn send_packet(
+        &mut self,
+        sock: u8,
+        remote: SocketAddrV4,
+        data: &[u8],
+    ) -> Result<(), Error<SPI::Error>> {
+        self.start_client(sock, remote, UDP_MODE)?;
+        for chunk in data.chunks(MAX_CHUNK) {
+            self.nina
+                .command(INSERT_DATABUF, &[&[sock], chunk], ParamLength::Long)?;
+            let mut buf = [0; 1];
+            let response = self
+                .nina
+                .response(INSERT_DATABUF, &mut buf, ParamLength::Short)?;
+            if response.byte() != Some(1) {
+                return Err(Error::Failed);
+            }
+        }
+        self.request_ok(SEND_DATA_UDP, &[&[sock]])
+    }
+
+    fn ephemeral_port(&mut self) -> u16 {
+        let port = self.next_port;
+        self.next_port = self.next_port.checked_add(1).unwrap_or(EPHEMERAL_PORTS);
+        port
+    }
+
+    /// Whether the TCP connection is gone and there's nothing left to read
+    ///
+    /// Mirrors `WiFiClient::connected` of the Arduino library.
+    fn tcp_closed(&mut self, sock: u8) -> Result<bool, Error<SPI::Error>> {
+        Ok(matches!(
+            self.tcp_state(sock)?,
+            TCP_CLOSED | TCP_LISTEN | TCP_FIN_WAIT_1 | TCP_FIN_WAIT_2 | TCP_CLOSE_WAIT
+        ))
+    }
+}
+
+fn ipv4<E>(addr: SocketAddr) -> Result<SocketAddrV4, Error<E>> {
+    match addr {
+        SocketAddr::V4(addr) => Ok(addr),
+        SocketAddr::V6(_) => Err(Error::Unsupported),
+    }
+}
+
+impl<SPI, CS, ACK, D> TcpClientStack for Wifi<SPI, CS, ACK, D>
+where
+    SPI: SpiBus<u8>,
+    CS: OutputPin,
+    ACK: InputPin,
+    D: DelayNs,
+{
+    type TcpSocket = TcpSocket;
+    type Error = Error<SPI::Error>;
+
+    fn socket(&mut self) -> Result<TcpSocket, Self::Error> {
+        Ok(TcpSocket {
+            sock: self.get_socket()?,
+            started: false,
+        })
+    }
+
+    fn connect(
+        &mut self,
+        socket: &mut TcpSocket,
+        remote: SocketAddr,
+    ) -> nb::Result<(), Self::Error> {
+        if !socket.started {
+            self.start_client(socket.sock, ipv4(remote)?, TCP_MODE)?;
+            socket.started = true;
+        }
+        match self.tcp_state(socket.sock)? {
+            TCP_ESTABLISHED => Ok(()),
+            TCP_CLOSED => {
+                socket.started = false;
+                Err(nb::Error::Other(Error::Failed))
+            }
+            _ => Err(nb::Error::WouldBlock),
+        }
+    }
+
+    fn send(&mut self, socket: &mut TcpSocket, buffer: &[u8]) -> nb::Result<usize, Self::Error> {
+        let chunk = &buffer[..buffer.len().min(MAX_CHUNK)];
+        self.nina
+            .command(SEND_DATA_TCP, &[&[socket.sock], chunk], ParamLength::Long)?;
+        let mut buf = [0; 2];
+        let response = self
+            .nina
+            .response(SEND_DATA_TCP, &mut buf, ParamLength::Short)?;
+        match response.u16_le() {
+            Some(0) if self.tcp_closed(socket.sock)? => Err(nb::Error::Other(Error::Closed)),
+            Some(0) => Err(nb::Error::WouldBlock),
+            Some(sent) => Ok(usize::from(sent)),
+            None => Err(nb::Error::Other(Error::Protocol)),
+        }
+    }
+
+    fn receive(
+        &mut self,
+        socket: &mut TcpSocket,
+        buffer: &mut [u8],
+    ) -> nb::Result<usize, Self::Error> {
+        if self.available(socket.sock)? == 0 {
+            if self.tcp_closed(socket.sock)? {
+                return Err(nb::Error::Other(Error::Closed));
+            }
+            return Err(nb::Error::WouldBlock);
+        }
+        Ok(self.read_data(socket.sock, buffer)?)
+    }
+
+    fn close(&mut self, socket: TcpSocket) -> Result<(), Self::Error> {
+        self.stop_client(socket.sock)
+    }
+}
+
+impl<SPI, CS, ACK, D> UdpClientStack for Wifi<SPI, CS, ACK, D>
+where
+    SPI: SpiBus<u8>,
+    CS: OutputPin,
+    ACK: InputPin,
+    D: DelayNs,
+{
+    type UdpSocket = UdpSocket;
+    type Error = Error<SPI::Error>;
+
+    fn socket(&mut self) -> Result<UdpSocket, Self::Error> {
+        Ok(UdpSocket {
+            sock: self.get_socket()?,
+            remote: None,
+        })
+    }
+
+    fn connect(&mut self, socket: &mut UdpSocket, remote: SocketAddr) -> Result<(), Self::Error> {
+        let remote = ipv4(remote)?;
+        // Replies can only be received on a bound socket
+        let port = self.ephemeral_port();
+        self.start_server(socket.sock, port, UDP_MODE)?;
+        socket.remote = Some(remote);
+        Ok(())
+    }
+
+    fn send(&mut self, socket: &mut UdpSocket, buffer: &[u8]) -> nb::Result<(), Self::Error> {
+        let remote = socket.remote.ok_or(Error::NotConnected)?;
+        Ok(self.send_packet(socket.sock, remote, buffer)?)
+    }
+
+    fn receive(
+        &mut self,
+        socket: &mut UdpSocket,
+        buffer: &mut [u8],
+    ) -> nb::Result<(usize, SocketAddr), Self::Error> {
+        let available = self.available(socket.sock)?;
+        if available == 0 {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        let mut buf = [0; 6];
+        let response = self.nina.exchange(
+            GET_REMOTE_DATA,
+            &[&[socket.sock]],
+            ParamLength::Short,
+            &mut buf,
+        )?;
+        let remote = match (response.get(0), response.get(1)) {
+            (Some(&[a, b, c, d]), Some(&[hi, lo])) => SocketAddr::V4(SocketAddrV4::new(
+                Ipv4Addr::new(a, b, c, d),
+                u16::from_be_bytes([hi, lo]),
+            )),
+            _ => return Err(nb::Error::Other(Error::Protocol)),
+        };
+
+        let len = available.min(buffer.len());
+        let len = self.read_data(socket.sock, &mut buffer[..len])?;
+
+        // Drop the rest of a packet that didn't fit, so it isn't mistaken for
+        // the next one
+        let mut remaining = available - len;
+        let mut scratch = [0; 32];
+        while remaining > 0 {
+            let read = self.read_data(socket.sock, &mut scratch[..remaining.min(32)])?;
+            if read == 0 {
+                break;
+            }
+            remaining -= read;
+        }
+
+        Ok((len, remote))
+    }
+
+    fn close(&mut self, socket: UdpSocket) -> Result<(), Self::Error> {
+        self.stop_client(socket.sock)
+    }
+}
+
+impl<SPI, CS, ACK, D> UdpFullStack for Wifi<SPI, CS, ACK, D>
+where
+    SPI: SpiBus<u8>,
+    CS: OutputPin,
+    ACK: InputPin,
+    D: DelayNs,
+{
+    fn bind(&mut self, socket: &mut UdpSocket, local_port: u16) -> Result<(), Self::Error> {
+        self.start_server(socket.sock, local_port, UDP_MODE)
+    }
+
+    fn send_to(
+        &mut self,
+        socket: &mut UdpSocket,
+        remote: SocketAddr,
+        buffer: &[u8],
+    ) -> nb::Result<(), Self::Error> {
+        Ok(self.send_packet(socket.sock, ipv4(remote)?, buffer)?)
+    }
+}
+
+impl<SPI, CS, ACK, D> Dns for Wifi<SPI, CS, ACK, D>
+where
+    SPI: SpiBus<u8>,
+    CS: OutputPin,
+    ACK: InputPin,
+    D: DelayNs,
+{
+    type Error = Error<SPI::Error>;
+
+    fn get_host_by_name(
+        &mut self,
+        hostname: &str,
+        addr_type: AddrType,
+    ) -> nb::Result<IpAddr, Self::Error> {
+        if addr_type == AddrType::IPv6 {
+            return Err(nb::Error::Other(Error::Unsupported));
+        }
+        self.request_ok(REQ_HOST_BY_NAME, &[hostname.as_bytes()])?;
+
+        let mut buf = [0; 4];
+        let response = self
+            .nina
+            .exchange(GET_HOST_BY_NAME, &[], ParamLength::Short, &mut buf)?;
+        match response.get(0) {
+            // The firmware reports failed lookups as 0.0.0.0
+            Some(&[0, 0, 0, 0]) => Err(nb::Error::Other(Error::Failed)),
+            Some(&[a, b, c, d]) => Ok(IpAddr::V4(Ipv4Addr::new(a, b, c, d))),
+            _ => Err(nb::Error::Other(Error::Protocol)),
+        }
+    }
+
+    fn get_host_by_address(
+        &mut self,
+        _addr: IpAddr,
+        _result: &mut [u8],
+    ) -> nb::Result<usize, Self::Error> {
+        Err(nb::Error::Other(Error::Unsupported))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::cell::RefCell;
+    use core::convert::Infallible;
+    use std::collections::VecDeque;
+    use std::rc::Rc;
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::nina::sim::Module;
+
+    /// The state of nina-fw, as far as the driver sees it
+    #[derive(Default)]
+    struct Firmware {
+        /// Every command received, with its parameters
+        commands: Vec<(u8, Vec<Vec<u8>>)>,
+        status: u8,
+        /// SSID, RSSI and encryption of the networks in range
+        networks: Vec<(&'static [u8], i32, u8)>,
+        /// Returned by GET_SOCKET
+        socket: u8,
+        /// Reported by GET_CLIENT_STATE_TCP
+        tcp_state: u8,
+        /// Received data, or the packet for a UDP socket
+        rx: VecDeque<u8>,
+        remote: ([u8; 4], u16),
+        /// How many bytes SEND_DATA_TCP takes at most
+        accept: usize,
+        /// Data sent over TCP, or inserted into the UDP packet
+        tx: Vec<u8>,
+        /// Whether INSERT_DATABUF fails
+        insert_fails: bool,
+        /// Result of the DNS lookup
+        host: [u8; 4],
+    }
+
+    impl Firmware {
+        fn handle(&mut self, cmd: u8, params: &[Vec<u8>]) -> Option<Vec<Vec<u8>>> {
+            self.commands.push((cmd, params.to_vec()));
+            let ok = std::vec![std::vec![1]];
+            Some(match cmd {
+                GET_FW_VERSION => std::vec![b"1.4.8\0".to_vec()],
+                START_SCAN_NETWORKS | SET_NET | SET_PASSPHRASE | DISCONNECT => ok,
+                SCAN_NETWORK => self.networks.iter().map(|n| n.0.to_vec()).collect(),
+                GET_IDX_RSSI => {
+                    let rssi = self.networks[usize::from(params[0][0])].1;
+                    std::vec![rssi.to_le_bytes().to_vec()]
+                }
+                GET_IDX_ENCT => std::vec![std::vec![self.networks[usize::from(params[0][0])].2]],
+                GET_CONN_STATUS => std::vec![std::vec![self.status]],
+                GET_IPADDR => std::vec![
+                    std::vec![192, 168, 1, 23],
+                    std::vec![255, 255, 255, 0],
+                    std::vec![192, 168, 1, 1],
+                ],
+                GET_CURR_RSSI => std::vec![(-58i32).to_le_bytes().to_vec()],
+                GET_SOCKET => std::vec![std::vec![self.socket]],
+                START_CLIENT_TCP | START_SERVER_TCP | STOP_CLIENT_TCP | SEND_DATA_UDP => ok,
+                GET_CLIENT_STATE_TCP => std::vec![std::vec![self.tcp_state]],
+                AVAIL_DATA_TCP => std::vec![(self.rx.len() as u16).to_le_bytes().to_vec()],
+                GET_DATABUF_TCP => {
+                    let len = usize::from(u16::from_le_bytes([params[1][0], params[1][1]]));
+                    let len = len.min(self.rx.len());
+                    std::vec![self.rx.drain(..len).collect()]
+                }
+                SEND_DATA_TCP => {
+                    let len = params[1].len().min(self.accept);
+                    self.tx.extend_from_slice(&params[1][..len]);
+                    std::vec![(len as u16).to_le_bytes().to_vec()]
+                }
+                INSERT_DATABUF => {
+                    self.tx.extend_from_slice(&params[1]);
+                    std::vec![std::vec![u8::from(!self.insert_fails)]]
+                }
+                GET_REMOTE_DATA => {
+                    std::vec![self.remote.0.to_vec(), self.remote.1.to_be_bytes().to_vec()]
+                }
+                REQ_HOST_BY_NAME => ok,
+                GET_HOST_BY_NAME => std::vec![self.host.to_vec()],
+                _ => return None,
+            })
+        }
+
+        /// The IDs of the commands received
+        fn ids(&self) -> Vec<u8> {
+            self.commands.iter().map(|(cmd, _)| *cmd).collect()
+        }
+
+        /// The parameters of the last `cmd` received
+        fn params(&self, cmd: u8) -> Vec<Vec<u8>> {
+            let found = self.commands.iter().rev().find(|(id, _)| *id == cmd);
+            found.expect("command not received").1.clone()
+        }
+    }
+
+    type TestWifi = Wifi<Module, Module, Module, Module>;
+
+    fn wifi(firmware: Firmware) -> (TestWifi, Rc<RefCell<Firmware>>) {
+        let firmware = Rc::new(RefCell::new(firmware));
+        let handler = firmware.clone();
+        let module =
+            Module::with_firmware(move |cmd, params| handler.borrow_mut().handle(cmd, params));
+        (Wifi::new(module.nina()), firmware)
+    }
+
+    fn remote() -> SocketAddr {
+        SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 2), 8080))
+    }
+
+    fn ipv6() -> SocketAddr {
+        "[::1]:80".parse().unwrap()
+    }
+
+    #[test]
+    fn reads_the_firmware_version() {
+        let (mut wifi, _) = wifi(Firmware::default());
+        let mut buf = [0; 16];
+        assert_eq!(wifi.firmware_version(&mut buf), Ok("1.4.8"));
+    }
+
+    #[test]
+    fn scans_for_networks() {
+        let (mut wifi, firmware) = wifi(Firmware {
+            networks: std::vec![
+                (&b"home"[..], -40, 4),
+                (&b"cafe"[..], -71, 7),
+                (&[0xff], -90, 2)
+            ],
+            ..Firmware::default()
+        });
+        let mut networks = [Network::default(); 2];
+        assert_eq!(wifi.scan(&mut networks), Ok(2));
+        assert_eq!(networks[0].ssid(), "home");
+        assert_eq!(networks[0].rssi, -40);
+        assert_eq!(networks[0].encryption, Encryption::Wpa2Ccmp);
+        assert_eq!(networks[1].ssid(), "cafe");
+        assert_eq!(networks[1].encryption, Encryption::None);
+        // Only the networks that fit are queried
+        assert_eq!(
+            firmware.borrow().ids(),
+            [
+                START_SCAN_NETWORKS,
+                SCAN_NETWORK,
+                GET_IDX_RSSI,
+                GET_IDX_ENCT,
+                GET_IDX_RSSI,
+                GET_IDX_ENCT
+            ]
+        );
+
+        let mut networks = [Network::default(); 4];
+        assert_eq!(wifi.scan(&mut networks), Ok(3));
+        assert_eq!(networks[2].ssid(), "");
+        assert_eq!(networks[2].encryption, Encryption::WpaTkip);
+    }
+
+    #[test]
+    fn joins_and_reports_dhcp() {
+        let (mut wifi, firmware) = wifi(Firmware::default());
+        wifi.join("home", Some("secret")).unwrap();
+        assert_eq!(
+            firmware.borrow().params(SET_PASSPHRASE),
+            [b"home".to_vec(), b"secret".to_vec()]
+        );
+        wifi.join("cafe", None).unwrap();
+        assert_eq!(firmware.borrow().params(SET_NET), [b"cafe".to_vec()]);
+
+        assert_eq!(wifi.status(), Ok(Status::Idle));
+        firmware.borrow_mut().status = 3;
+        assert_eq!(wifi.status(), Ok(Status::Connected));
+        firmware.borrow_mut().status = 42;
+        assert_eq!(wifi.status(), Ok(Status::Other(42)));
+
+        assert_eq!(
+            wifi.ip_config(),
+            Ok(IpConfig {
+                address: Ipv4Addr::new(192, 168, 1, 23),
+                netmask: Ipv4Addr::new(255, 255, 255, 0),
+                gateway: Ipv4Addr::new(192, 168, 1, 1),
+            })
+        );
+        assert_eq!(wifi.rssi(), Ok(-58));
+        wifi.leave().unwrap();
+        assert_eq!(firmware.borrow().params(DISCONNECT), [DUMMY_PARAM.to_vec()]);
+    }
+
+    #[test]
+    fn tcp_connects_sends_receives_and_closes() {
+        let (mut wifi, firmware) = wifi(Firmware {
+            socket: 3,
+            tcp_state: 2,
+            accept: usize::MAX,
+            ..Firmware::default()
+        });
+        let mut socket = TcpClientStack::socket(&mut wifi).unwrap();
+
+        // SYN_SENT, then ESTABLISHED
+        assert_eq!(
+            TcpClientStack::connect(&mut wifi, &mut socket, remote()),
+            Err(nb::Error::WouldBlock)
+        );
+        firmware.borrow_mut().tcp_state = TCP_ESTABLISHED;
+        assert_eq!(
+            TcpClientStack::connect(&mut wifi, &mut socket, remote()),
+            Ok(())
+        );
+        // The client is started only once
+        assert_eq!(
+            firmware.borrow().params(START_CLIENT_TCP),
+            [
+                std::vec![10, 0, 0, 2],
+                std::vec![0x1f, 0x90],
+                std::vec![3],
+                std::vec![TCP_MODE]
+            ]
+        );
+        assert_eq!(
+            firmware
+                .borrow()
+                .ids()
+                .iter()
+                .filter(|&&cmd| cmd == START_CLIENT_TCP)
+                .count(),
+            1
+        );
+
+        assert_eq!(
+            TcpClientStack::send(&mut wifi, &mut socket, b"GET /"),
+            Ok(5)
+        );
+        assert_eq!(firmware.borrow().tx, b"GET /");
+        // Large writes are cut into chunks the firmware can buffer
+        let big = [0x55; MAX_CHUNK + 100];
+        assert_eq!(
+            TcpClientStack::send(&mut wifi, &mut socket, &big),
+            Ok(MAX_CHUNK)
+        );
+
+        let mut buf = [0; 8];
+        assert_eq!(
+            TcpClientStack::receive(&mut wifi, &mut socket, &mut buf),
+            Err(nb::Error::WouldBlock)
+        );
+        firmware.borrow_mut().rx.extend(b"HTTP/1.0 200");
+        assert_eq!(
+            TcpClientStack::receive(&mut wifi, &mut socket, &mut buf),
+            Ok(8)
+        );
+        assert_eq!(&buf, b"HTTP/1.0");
+        assert_eq!(
+            TcpClientStack::receive(&mut wifi, &mut socket, &mut buf),
+            Ok(4)
+        );
+        assert_eq!(&buf[..4], b" 200");
+
+        TcpClientStack::close(&mut wifi, socket).unwrap();
+        assert_eq!(firmware.borrow().params(STOP_CLIENT_TCP), [std::vec![3]]);
+    }
+
+    #[test]
+    fn tcp_reports_closed_connections() {
+        let (mut wifi, firmware) = wifi(Firmware {
+            tcp_state: TCP_CLOSED,
+            ..Firmware::default()
+        });
+        let mut socket = TcpClientStack::socket(&mut wifi).unwrap();
+        assert_eq!(
+            TcpClientStack::connect(&mut wifi, &mut socket, remote()),
+            Err(nb::Error::Other(Error::Failed))
+        );
+        // The next attempt starts the client again
+        TcpClientStack::connect(&mut wifi, &mut socket, remote()).ok();
+        assert_eq!(
+            firmware
+                .borrow()
+                .ids()
+                .iter()
+                .filter(|&&cmd| cmd == START_CLIENT_TCP)
+                .count(),
+            2
+        );
+
+        // Nothing taken, with the remote end gone
+        assert_eq!(
+            TcpClientStack::send(&mut wifi, &mut socket, b"data"),
+            Err(nb::Error::Other(Error::Closed))
+        );
+        firmware.borrow_mut().tcp_state = TCP_ESTABLISHED;
+        assert_eq!(
+            TcpClientStack::send(&mut wifi, &mut socket, b"data"),
+            Err(nb::Error::WouldBlock)
+        );
+
+        // Data received before the close is still read
+        firmware.borrow_mut().tcp_state = TCP_CLOSE_WAIT;
+        firmware.borrow_mut().rx.extend(b"bye");
+        let mut buf = [0; 8];
+        assert_eq!(
+            TcpClientStack::receive(&mut wifi, &mut socket, &mut buf),
+            Ok(3)
+        );
+        assert_eq!(
+            TcpClientStack::receive(&mut wifi, &mut socket, &mut buf),
+            Err(nb::Error::Other(Error::Closed))
+        );
+        assert_eq!(
+            TcpClientStack::connect(&mut wifi, &mut socket, ipv6()),
+            Err(nb::Error::Other(Error::Unsupported))
+        );
+    }
+
+    #[test]
+    fn sockets_run_out() {
+        let (mut wifi, _) = wifi(Firmware {
+            socket: NO_SOCKET,
+            ..Firmware::default()
+        });
+        assert_eq!(
+            TcpClientStack::socket(&mut wifi).map(drop),
+            Err(Error::NoSocket)
+        );
+        assert_eq!(
+            UdpClientStack::socket(&mut wifi).map(drop),
+            Err(Error::NoSocket)
+        );
+    }
+
+    #[test]
+    fn udp_sends_and_receives_packets() {
+        let (mut wifi, firmware) = wifi(Firmware {
+            socket: 1,
+            remote: ([10, 0, 0, 2], 53),
+            ..Firmware::default()
+        });
+        let mut socket = UdpClientStack::socket(&mut wifi).unwrap();
+        assert_eq!(
+            UdpClientStack::send(&mut wifi, &mut socket, b"query"),
+            Err(nb::Error::Other(Error::NotConnected))
+        );
+
+        UdpClientStack::connect(&mut wifi, &mut socket, remote()).unwrap();
+        // Bound to an ephemeral port, for the replies
+        assert_eq!(
+            firmware.borrow().params(START_SERVER_TCP),
+            [
+                EPHEMERAL_PORTS.to_be_bytes().to_vec(),
+                std::vec![1],
+                std::vec![UDP_MODE]
+            ]
+        );
+
+        UdpClientStack::send(&mut wifi, &mut socket, b"query").unwrap();
+        assert_eq!(
+            firmware.borrow().ids()[2..],
+            [START_CLIENT_TCP, INSERT_DATABUF, SEND_DATA_UDP]
+        );
+        assert_eq!(firmware.borrow().params(START_CLIENT_TCP)[3], [UDP_MODE]);
+        assert_eq!(firmware.borrow().tx, b"query");
+
+        let mut buf = [0; 4];
+        assert_eq!(
+            UdpClientStack::receive(&mut wifi, &mut socket, &mut buf),
+            Err(nb::Error::WouldBlock)
+        );
+        // The rest of a packet which doesn't fit is dropped
+        firmware
+            .borrow_mut()
+            .rx
+            .extend(b"answer, and more than fits into 32 bytes");
+        let (len, from) = UdpClientStack::receive(&mut wifi, &mut socket, &mut buf).unwrap();
+        assert_eq!(
+            (&buf[..len], from),
+            (&b"answ"[..], "10.0.0.2:53".parse().unwrap())
+        );
+        assert!(firmware.borrow().rx.is_empty());
+
+        UdpClientStack::close(&mut wifi, socket).unwrap();
+        assert_eq!(firmware.borrow().params(STOP_CLIENT_TCP), [std::vec![1]]);
+    }
+
+    #[test]
+    fn udp_binds_and_sends_to() {
+        let (mut wifi, firmware) = wifi(Firmware::default());
+        let mut socket = UdpClientStack::socket(&mut wifi).unwrap();
+        wifi.bind(&mut socket, 5353).unwrap();
+        assert_eq!(firmware.borrow().params(START_SERVER_TCP)[0], [0x14, 0xe9]);
+
+        wifi.send_to(&mut socket, remote(), b"hello").unwrap();
+        assert_eq!(
+            firmware.borrow().params(START_CLIENT_TCP)[..2],
+            [std::vec![10, 0, 0, 2], std::vec![0x1f, 0x90]]
+        );
+        assert_eq!(
+            wifi.send_to(&mut socket, ipv6(), b"hello"),
+            Err(nb::Error::Other(Error::Unsupported))
+        );
+
+        firmware.borrow_mut().insert_fails = true;
+        assert_eq!(
+            wifi.send_to(&mut socket, remote(), b"hello"),
+            Err(nb::Error::Other(Error::Failed))
+        );
+    }
+
+    #[test]
+    fn resolves_host_names() {
+        let (mut wifi, firmware) = wifi(Firmware {
+            host: [93, 184, 216, 34],
+            ..Firmware::default()
+        });
+        assert_eq!(
+            wifi.get_host_by_name("example.com", AddrType::Either),
+            Ok(IpAddr::V4(Ipv4Addr::new(93, 184, 216, 34)))
+        );
+        assert_eq!(
+            firmware.borrow().params(REQ_HOST_BY_NAME),
+            [b"example.com".to_vec()]
+        );
+
+        firmware.borrow_mut().host = [0; 4];
+        assert_eq!(
+            wifi.get_host_by_name("nowhere.invalid", AddrType::IPv4),
+            Err(nb::Error::Other(Error::Failed))
+        );
+        assert_eq!(
+            wifi.get_host_by_name("example.com", AddrType::IPv6),
+            Err(nb::Error::Other(Error::Unsupported))
+        );
+    }
+
+    #[test]
+    fn error_replies_fail_the_request() {
+        let module = Module::with_firmware(|_, _| None);
+        let mut wifi = Wifi::new(module.nina());
+        assert_eq!(wifi.status(), Err(Error::<Infallible>::Protocol));
+        // The transport is usable again afterwards
+        assert_eq!(wifi.status(), Err(Error::Protocol));
+
+        // A reply without parameters
+        let module = Module::with_firmware(|_, _| Some(Vec::new()));
+        let mut wifi = Wifi::new(module.nina());
+        assert_eq!(wifi.status(), Err(Error::Protocol));
+        assert_eq!(wifi.ip_config(), Err(Error::Protocol));
+
+        // A module rejecting the passphrase
+        let module = Module::with_firmware(|_, _| Some(std::vec![std::vec![0]]));
+        let mut wifi = Wifi::new(module.nina());
+        assert_eq!(wifi.join("home", Some("wrong")), Err(Error::Failed));
+    }
+}
@@ -0,0 +1,295 @@
+//! Decimation of the PDM bitstream to 16 bit PCM
+//!
+//! The bitstream is decimated in two stages:
+//!
+//! 1. A fourth order CIC filter, which is cheap enough to run on every single
+//!    bit, decimates to twice the output sample rate.
+//! 2. A 33 tap FIR low pass filter removes what the CIC filter lets through
+//!    above the output band and decimates by another factor of two.
+//!
+//! The passband reaches up to about 30% of the output sample rate, with about
+//! 1 dB of droop at its edge, which isn't compensated. Aliases folding into
+//! the passband are attenuated by about 60 dB. A full scale bitstream results
+//! in about -4 dBFS, leaving some headroom for the FIR filter.
+
+use super::SampleRate;
+
+/// Order of the CIC filter
+const CIC_ORDER: usize = 4;
+
+/// Number of taps of the FIR filter
+const FIR_TAPS: usize = 33;
+
+/// Hamming windowed sinc, cutoff at 0.225 of the FIR input rate, sums to 2^15
+#[rustfmt::skip]
+const FIR_COEFFS: [i32; FIR_TAPS] = [
+    -31, 44, 69, -57, -177, 42, 379, 82, -669, -426, 1007,
+    1173, -1326, -2856, 1555, 10207, 14736, 10207, 1555, -2856, -1326, 1173,
+    1007, -426, -669, 82, 379, 42, -177, -57, 69, 44, -31,
+];
+
+/// Cascaded integrator-comb filter, decimating a 1 bit stream
+///
+/// All arithmetic wraps, which is fine as long as the final output fits into
+/// an `i32`: the gain is `decimation ^ 4`, so decimation factors up to 215
+/// are supported.
+#[derive(Debug, Clone)]
+pub struct Cic {
+    integrators: [i32; CIC_ORDER],
+    combs: [i32; CIC_ORDER],
+    decimation: u32,
+    count: u32,
+}
+
+impl Cic {
+    /// Create a filter decimating by `decimation`
+    pub fn new(decimation: u32) -> Self {
+        Cic {
+            integrators: [0; CIC_ORDER],
+            combs: [0; CIC_ORDER],
+            decimation,
+            count: 0,
+        }
+    }
+
+    /// Gain of the filter, the output for a stream of only ones
+    pub fn gain(&self) -> i64 {
+        i64::from(self.decimation).pow(CIC_ORDER as u32)
+    }
+
+    /// Feed a single bit, returns an output sample every `decimation` bits
+    pub fn push(&mut self, bit: bool) -> Option<i32> {
+        let mut x = if bit { 1 } else { -1 };
+        for integrator in self.integrators.iter_mut() {
+            *integrator = integrator.wrapping_add(x);
+            x = *integrator;
+        }
+
+        self.count += 1;
+        if self.count < self.decimation {
+            return None;
+        }
+        self.count = 0;
+
+        for comb in self.combs.iter_mut() {
+            let delayed = *comb;
+            *comb = x;
+            x = x.wrapping_sub(delayed);
+        }
+        Some(x)
+    }
+}
+
+/// Low pass FIR filter decimating by two
+#[derive(Debug, Clone)]
+pub struct Fir {
+    history: [i32; FIR_TAPS],
+    pos: usize,
+    odd: bool,
+}
+
+impl Fir {
+    /// Create a filter with empty history
+    pub fn new() -> Self {
+        Fir {
+            history: [0; FIR_TAPS],
+            pos: 0,
+            odd: false,
+        }
+    }
+
+    /// Feed a 16 bit sample, returns an output sample for every second input
+    pub fn push(&mut self, sample: i16) -> Option<i16> {
+        self.history[self.pos] = i32::from(sample);
+        self.pos = (self.pos + 1) % FIR_TAPS;
+
+        self.odd = !self.odd;
+        if self.odd {
+            return None;
+        }
+
+        // The sum of the absolute coefficients is below 2^16, so this fits
+        let (newer, older) = self.history.split_at(self.pos);
+        let acc: i32 = older
+            .iter()
+            .chain(newer.iter())
+            .zip(FIR_COEFFS.iter())
+            .map(|(x, c)| x * c)
+            .sum();
+        Some(saturate(acc >> 15))
+    }
+}
+
+impl Default for Fir {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn saturate(value: i32) -> i16 {
+    value.clamp(i32::from(i16::MIN), i32::from(i16::MAX)) as i16
+}
+
+/// Complete decimation pipeline from PDM words to PCM samples
+#[derive(Debug, Clone)]
+pub struct Decimator {
+    cic: Cic,
+    fir: Fir,
+    shift: u32,
+}
+
+impl Decimator {
+    /// Create a decimator for the given output rate
+    pub fn new(rate: SampleRate) -> Self {
+        let cic = Cic::new(rate.cic_decimation());
+        // Scale the CIC output to 16 bits, keeping some headroom for the FIR
+        let bits = 64 - (cic.gain() as u64).leading_zeros();
+        Decimator {
+            cic,
+            fir: Fir::new(),
+            shift: bits.saturating_sub(15),
+        }
+    }
+
+    /// Decimate words as pushed by the PIO, oldest bit in the MSB
+    ///
+    /// Every PCM sample produced is handed to `sink`.
+    pub fn process(&mut self, words: &[u32], mut sink: impl FnMut(i16)) {
+        for &word in words {
+            for bit in (0..32).rev() {
+                if let Some(sample) = self.cic.push(word & (1 << bit) != 0) {
+                    if let Some(pcm) = self.fir.push(saturate(sample >> self.shift)) {
+                        sink(pcm);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec::Vec;
+
+    use super::*;
+
+    const RATES: [SampleRate; 3] = [SampleRate::Khz8, SampleRate::Khz16, SampleRate::Khz32];
+
+    /// Output of the decimators for a full scale bitstream
+    const FULL_SCALE: i16 = 20736;
+
+    /// A sine of `freq` Hz and amplitude `amp` relative to full scale,
+    /// modulated by a second order sigma-delta modulator like the one of the
+    /// microphone
+    fn modulate(freq: f64, amp: f64, rate: SampleRate, words: usize) -> Vec<u32> {
+        let pdm_rate = f64::from(rate.hz() * rate.cic_decimation() * 2);
+        let (mut first, mut second, mut feedback) = (0.0, 0.0, 0.0);
+        let mut bitstream = std::vec![0; words];
+        for n in 0..words * 32 {
+            let phase = 2.0 * core::f64::consts::PI * freq * n as f64 / pdm_rate;
+            first += amp * phase.sin() - feedback;
+            second += first - feedback;
+            let bit = second >= 0.0;
+            feedback = if bit { 1.0 } else { -1.0 };
+            if bit {
+                bitstream[n / 32] |= 1 << (31 - n % 32);
+            }
+        }
+        bitstream
+    }
+
+    fn decimate(rate: SampleRate, words: &[u32]) -> Vec<i16> {
+        let mut decimator = Decimator::new(rate);
+        let mut pcm = Vec::new();
+        decimator.process(words, |sample| pcm.push(sample));
+        pcm
+    }
+
+    /// RMS of the second half of `pcm`, after the filters settled
+    fn rms(pcm: &[i16]) -> f64 {
+        let tail = &pcm[pcm.len() / 2..];
+        let sum: f64 = tail.iter().map(|&x| f64::from(x) * f64::from(x)).sum();
+        (sum / tail.len() as f64).sqrt()
+    }
+
+    #[test]
+    fn cic_passes_dc_with_its_gain() {
+        let mut cic = Cic::new(24);
+        assert_eq!(cic.gain(), 331_776);
+        let ones: Vec<i32> = (0..24 * 10).filter_map(|_| cic.push(true)).collect();
+        assert_eq!(ones.len(), 10);
+        // Settled once the combs are filled
+        assert!(ones[CIC_ORDER..]
+            .iter()
+            .all(|&x| i64::from(x) == cic.gain()));
+
+        let zeros: Vec<i32> = (0..24 * 10).filter_map(|_| cic.push(false)).collect();
+        assert!(zeros[CIC_ORDER..]
+            .iter()
+            .all(|&x| i64::from(x) == -cic.gain()));
+
+        // Half of the bits set is silence
+        let half: Vec<i32> = (0..24 * 10).filter_map(|n| cic.push(n % 2 == 0)).collect();
+        assert!(half[CIC_ORDER..].iter().all(|&x| x == 0));
+    }
+
+    #[test]
+    fn cic_gain_fits_at_the_largest_decimation() {
+        let mut cic = Cic::new(215);
+        let out: Vec<i32> = (0..215 * 10).filter_map(|_| cic.push(true)).collect();
+        assert_eq!(i64::from(out[9]), cic.gain());
+    }
+
+    #[test]
+    fn fir_passes_dc_and_halves_the_rate() {
+        let mut fir = Fir::new();
+        let out: Vec<i16> = (0..200).filter_map(|_| fir.push(10_000)).collect();
+        assert_eq!(out.len(), 100);
+        // The coefficients sum to exactly 2^15
+        assert!(out[FIR_TAPS / 2..].iter().all(|&x| x == 10_000));
+
+        let out: Vec<i16> = (0..200).filter_map(|_| fir.push(i16::MIN)).collect();
+        assert!(out[FIR_TAPS / 2..].iter().all(|&x| x == i16::MIN));
+    }
+
+    #[test]
+    fn decimator_scales_full_scale_with_headroom() {
+        for &rate in RATES.iter() {
+            let ones = decimate(rate, &[u32::MAX; 1024]);
+            let zeros = decimate(rate, &[0; 1024]);
+            assert_eq!(ones.last(), Some(&FULL_SCALE), "{:?}", rate);
+            assert_eq!(zeros.last(), Some(&-FULL_SCALE), "{:?}", rate);
+        }
+    }
+
+    #[test]
+    fn decimator_outputs_one_sample_per_ratio_bits() {
+        for &rate in RATES.iter() {
+            let ratio = 2 * rate.cic_decimation() as usize;
+            let pcm = decimate(rate, &[0xaaaa_aaaa; 1536]);
+            assert_eq!(pcm.len(), 1536 * 32 / ratio, "{:?}", rate);
+        }
+    }
+
+    #[test]
+    fn decimator_passes_the_band_and_rejects_aliases() {
+        for &rate in RATES.iter() {
+            let hz = f64::from(rate.hz());
+            let words = rate.cic_decimation() as usize * 2 * 1024 / 32;
+
+            // A tone at 10% of the sample rate keeps its level
+            let tone = rms(&decimate(rate, &modulate(0.1 * hz, 0.5, rate, words)));
+            let expected = 0.5 * f64::from(FULL_SCALE) / 2f64.sqrt();
+            assert!((tone / expected - 1.0).abs() < 0.05, "{:?}: {}", rate, tone);
+
+            // Ones at 70%, 130% and 170% would alias to 30%, but are
+            // attenuated by about 60 dB
+            for &freq in [0.7, 1.3, 1.7].iter() {
+                let alias = rms(&decimate(rate, &modulate(freq * hz, 0.5, rate, words)));
+                assert!(alias < expected / 800.0, "{:?} {}: {}", rate, freq, alias);
+            }
+        }
+    }
+}
@@ -0,0 +1,158 @@
+//! Capture audio from the on-board MP34DT06 PDM microphone
+//!
+//! A PIO state machine clocks the microphone and shifts its bitstream into
+//! the RX FIFO, 32 bits per word, oldest bit first. The FIFO is meant to be
+//! drained by DMA, e.g. with a double buffered transfer. Each buffer full of
+//! words is then turned into PCM samples by a [Decimator] and collected in a
+//! [PcmRing].
+//!
+//! ```ignore
+//! let (mic, rx) = PdmMic::new(&mut pio, sm0, pins.pdmclk, pins.pdmdin, sys_freq, SampleRate::Khz16);
+//! let mut decimator = Decimator::new(mic.rate());
+//! let mut ring = PcmRing::<4, 256>::new();
+//! // For every buffer filled by DMA from `rx`:
+//! decimator.process(buffer, |sample| ring.push(sample));
+//! while let Some(block) = ring.pop() {
+//!     // 256 samples at 16 kHz
+//! }
+//! ```
+
+use crate::hal::fugit::HertzU32;
+
+use crate::hal::gpio::bank0::{Gpio22, Gpio23};
+use crate::hal::gpio::{Function, Pin, PullNone, PullType, ValidFunction};
+use crate::hal::pio::{
+    Buffers, PIOBuilder, PIOExt, PinDir, Running, Rx, ShiftDirection, StateMachine,
+    StateMachineIndex, Stopped, UninitStateMachine, PIO,
+};
+
+mod filter;
+mod ring;
+
+pub use filter::{Cic, Decimator, Fir};
+pub use ring::PcmRing;
+
+/// PCM sample rates supported by the [Decimator]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SampleRate {
+    /// 8 kHz, e.g. for speech
+    Khz8,
+    /// 16 kHz
+    Khz16,
+    /// 32 kHz
+    Khz32,
+}
+
+impl SampleRate {
+    /// Sample rate in Hz
+    pub fn hz(self) -> u32 {
+        match self {
+            SampleRate::Khz8 => 8_000,
+            SampleRate::Khz16 => 16_000,
+            SampleRate::Khz32 => 32_000,
+        }
+    }
+
+    /// Decimation factor of the CIC filter, the FIR filter adds a factor of 2
+    pub fn cic_decimation(self) -> u32 {
+        match self {
+            SampleRate::Khz8 => 96,
+            SampleRate::Khz16 => 48,
+            SampleRate::Khz32 => 24,
+        }
+    }
+
+    /// Clock of the microphone, 1.536 MHz for all rates
+    ///
+    /// This is within the range of the MP34DT06 in its normal mode.
+    pub fn pdm_clock(self) -> HertzU32 {
+        HertzU32::Hz(self.hz() * self.cic_decimation() * 2)
+    }
+}
+
+/// Clock pin of the microphone, driven by PIO
+pub type PdmClk<P> = Pin<Gpio23, <P as PIOExt>::PinFunction, PullNone>;
+
+/// Data pin of the microphone, sampled by PIO
+pub type PdmData<P> = Pin<Gpio22, <P as PIOExt>::PinFunction, PullNone>;
+
+/// The microphone, clocked by a PIO state machine
+pub struct PdmMic<P: PIOExt, SM: StateMachineIndex> {
+    sm: StateMachine<(P, SM), Running>,
+    clk: PdmClk<P>,
+    data: PdmData<P>,
+    rate: SampleRate,
+}
+
+impl<P: PIOExt, SM: StateMachineIndex> PdmMic<P, SM> {
+    /// Start clocking the microphone
+    ///
+    /// Returns the RX FIFO of the state machine, which receives the
+    /// bitstream.
+    pub fn new<F1, P1, F2, P2>(
+        pio: &mut PIO<P>,
+        sm: UninitStateMachine<(P, SM)>,
+        clk: Pin<Gpio23, F1, P1>,
+        data: Pin<Gpio22, F2, P2>,
+        sys_freq: HertzU32,
+        rate: SampleRate,
+    ) -> (Self, Rx<(P, SM)>)
+    where
+        F1: Function,
+        P1: PullType,
+        F2: Function,
+        P2: PullType,
+        Gpio22: ValidFunction<P::PinFunction>,
+        Gpio23: ValidFunction<P::PinFunction>,
+    {
+        let clk: PdmClk<P> = clk.reconfigure();
+        let data: PdmData<P> = data.reconfigure();
+        let clk_id = clk.id().num;
+        let data_id = data.id().num;
+
+        // Two PIO cycles per PDM clock, as a 16.8 fixed point divisor
+        let pio_freq = u64::from(rate.pdm_clock().to_Hz()) * 2;
+        let divisor = (u64::from(sys_freq.to_Hz()) << 8) / pio_freq;
+
+        let program = pio_proc::pio_file!("./src/pdm.pio");
+        let program = pio.install(&program.program).unwrap();
+        let (mut sm, rx, _tx) = PIOBuilder::from_installed_program(program)
+            .side_set_pin_base(clk_id)
+            .in_pin_base(data_id)
+            .in_shift_direction(ShiftDirection::Left)
+            .autopush(true)
+            .push_threshold(32)
+            .buffers(Buffers::OnlyRx)
+            .clock_divisor_fixed_point((divisor >> 8) as u16, divisor as u8)
+            .build(sm);
+        sm.set_pindirs([(clk_id, PinDir::Output), (data_id, PinDir::Input)]);
+        let sm = sm.start();
+        #[cfg(feature = "defmt")]
+        defmt::debug!(
+            "pdm: microphone clocked at {=u32} Hz, {}",
+            rate.pdm_clock().to_Hz(),
+            rate
+        );
+
+        (
+            PdmMic {
+                sm,
+                clk,
+                data,
+                rate,
+            },
+            rx,
+        )
+    }
+
+    /// The sample rate the microphone is clocked for
+    pub fn rate(&self) -> SampleRate {
+        self.rate
+    }
+
+    /// Stop clocking the microphone and release the pins
+    pub fn stop(self) -> (StateMachine<(P, SM), Stopped>, PdmClk<P>, PdmData<P>) {
+        (self.sm.stop(), self.clk, self.data)
+    }
+}
@@ -0,0 +1,154 @@
+//! Ring buffer handing PCM samples over in fixed size blocks
+
+/// Ring of `BLOCKS` blocks with `LEN` samples each
+///
+/// One block is always being filled, so up to `BLOCKS - 1` complete blocks
+/// can be queued. If the consumer falls behind, the oldest block is dropped.
+/// A ring with fewer than 2 blocks doesn't build.
+pub struct PcmRing<const BLOCKS: usize, const LEN: usize> {
+    blocks: [[i16; LEN]; BLOCKS],
+    write_block: usize,
+    write_pos: usize,
+    read_block: usize,
+    queued: usize,
+    overruns: u32,
+}
+
+impl<const BLOCKS: usize, const LEN: usize> PcmRing<BLOCKS, LEN> {
+    /// Fails the build for a ring which can't queue a single block
+    const AT_LEAST_TWO_BLOCKS: () = assert!(BLOCKS >= 2, "a PcmRing needs at least 2 blocks");
+
+    /// Create an empty ring
+    pub const fn new() -> Self {
+        let () = Self::AT_LEAST_TWO_BLOCKS;
+        PcmRing {
+            blocks: [[0; LEN]; BLOCKS],
+            write_block: 0,
+            write_pos: 0,
+            read_block: 0,
+            queued: 0,
+            overruns: 0,
+        }
+    }
+
+    /// Append a sample, completing a block every `LEN` samples
+    pub fn push(&mut self, sample: i16) {
+        self.blocks[self.write_block][self.write_pos] = sample;
+        self.write_pos += 1;
+        if self.write_pos < LEN {
+            return;
+        }
+        self.write_pos = 0;
+
+        if self.queued == BLOCKS - 1 {
+            // Drop the oldest block to make room
+            self.read_block = (self.read_block + 1) % BLOCKS;
+            self.queued -= 1;
+            self.overruns = self.overruns.wrapping_add(1);
+        }
+        self.write_block = (self.write_block + 1) % BLOCKS;
+        self.queued += 1;
+    }
+
+    /// Take the oldest complete block
+    pub fn pop(&mut self) -> Option<&[i16; LEN]> {
+        if self.queued == 0 {
+            return None;
+        }
+        let block = self.read_block;
+        self.read_block = (block + 1) % BLOCKS;
+        self.queued -= 1;
+        Some(&self.blocks[block])
+    }
+
+    /// Number of complete blocks waiting to be taken
+    pub fn len(&self) -> usize {
+        self.queued
+    }
+
+    /// Whether there is no complete block
+    pub fn is_empty(&self) -> bool {
+        self.queued == 0
+    }
+
+    /// Number of blocks dropped because the consumer fell behind
+    pub fn overruns(&self) -> u32 {
+        self.overruns
+    }
+}
+
+impl<const BLOCKS: usize, const LEN: usize> Default for PcmRing<BLOCKS, LEN> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push_block<const BLOCKS: usize>(ring: &mut PcmRing<BLOCKS, 4>, first: i16) {
+        for sample in first..first + 4 {
+            ring.push(sample);
+        }
+    }
+
+    #[test]
+    fn complete_blocks_are_queued_in_order() {
+        let mut ring = PcmRing::<3, 4>::new();
+        assert!(ring.is_empty());
+        assert_eq!(ring.pop(), None);
+
+        push_block(&mut ring, 0);
+        // A partial block isn't queued yet
+        ring.push(10);
+        assert_eq!(ring.len(), 1);
+        for sample in 11..14 {
+            ring.push(sample);
+        }
+        assert_eq!(ring.len(), 2);
+
+        assert_eq!(ring.pop(), Some(&[0, 1, 2, 3]));
+        assert_eq!(ring.pop(), Some(&[10, 11, 12, 13]));
+        assert_eq!(ring.pop(), None);
+        assert_eq!(ring.overruns(), 0);
+    }
+
+    #[test]
+    fn wraps_around_the_blocks() {
+        let mut ring = PcmRing::<3, 4>::new();
+        for round in 0..10 {
+            push_block(&mut ring, round * 4);
+            assert_eq!(
+                ring.pop(),
+                Some(&[round * 4, round * 4 + 1, round * 4 + 2, round * 4 + 3])
+            );
+            assert!(ring.is_empty());
+        }
+        assert_eq!(ring.overruns(), 0);
+    }
+
+    #[test]
+    fn overrun_drops_the_oldest_block() {
+        let mut ring = PcmRing::<3, 4>::new();
+        for block in 0..5 {
+            push_block(&mut ring, block * 10);
+        }
+        // Two blocks fit next to the one being filled
+        assert_eq!(ring.len(), 2);
+        assert_eq!(ring.overruns(), 3);
+        assert_eq!(ring.pop(), Some(&[30, 31, 32, 33]));
+        assert_eq!(ring.pop(), Some(&[40, 41, 42, 43]));
+        assert_eq!(ring.pop(), None);
+    }
+
+    #[test]
+    fn two_blocks_hand_over_one_at_a_time() {
+        let mut ring = PcmRing::<2, 4>::new();
+        push_block(&mut ring, 0);
+        push_block(&mut ring, 10);
+        assert_eq!(ring.len(), 1);
+        assert_eq!(ring.overruns(), 1);
+        assert_eq!(ring.pop(), Some(&[10, 11, 12, 13]));
+    }
+}
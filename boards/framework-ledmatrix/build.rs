@@ -1,6 +1,8 @@
-//! This build script makes sure the linker flag -Tdefmt.x is added
-//! for the examples.
+//! Generates the memory layout of the board for the linker, and the
+//! `FLASH_*` constants, see [board_build]
+
+use board_build::{Defmt, MB};
 
 fn main() {
-    println!("cargo:rustc-link-arg-examples=-Tdefmt.x");
+    board_build::generate(1 * MB, Defmt::Always);
 }
@@ -0,0 +1,170 @@
+//! # Framework LED Matrix Module Animation Example
+//!
+//! Scrolls a greeting through the matrix and then plays Game of Life, with the
+//! frames timed by the RP2040 timer. The matrix fades out while the laptop is
+//! asleep.
+//!
+//! See the `Cargo.toml` file for Copyright and license details.
+
+#![no_std]
+#![no_main]
+
+// The macro for our start-up function
+use framework_ledmatrix::entry;
+use framework_ledmatrix::{Grid, Pins, HEIGHT, WIDTH, XOSC_CRYSTAL_FREQ};
+
+// Ensure we halt the program on panic (if we don't mention this crate it won't
+// be linked)
+use panic_halt as _;
+
+// Pull in any important traits
+use framework_ledmatrix::hal::prelude::*;
+
+// A shorter alias for the Peripheral Access Crate, which provides low-level
+// register access
+use framework_ledmatrix::hal::pac;
+
+// A shorter alias for the Hardware Abstraction Layer, which provides
+// higher-level drivers.
+use framework_ledmatrix::hal;
+
+// The animation engine
+use framework_ledmatrix::animation::{Animator, GameOfLife, ScrollingText};
+use framework_ledmatrix::font::Font;
+use framework_ledmatrix::render;
+use framework_ledmatrix::sleep::{self, PowerState, SleepHandler, SleepPin};
+
+use fugit::{MicrosDurationU64, RateExtU32};
+
+use is31fl3741::devices::{LedMatrix, CALC_PIXEL};
+
+/// Maximum brightness out of 255
+///
+/// 100/255 results in 250mA current draw and is plenty bright.
+///  50/255 results in 160mA current draw and is plenty bright.
+const MAX_BRIGHTNESS: u8 = 50;
+
+/// Text to scroll through the matrix
+const TEXT: &str = "HELLO FRAMEWORK";
+
+#[entry]
+fn main() -> ! {
+    // Grab our singleton objects
+    let mut pac = pac::Peripherals::take().unwrap();
+    let core = pac::CorePeripherals::take().unwrap();
+
+    // Set up the watchdog driver - needed by the clock setup code
+    let mut watchdog = hal::Watchdog::new(pac.WATCHDOG);
+
+    // Configure the clocks
+    //
+    // The default is to generate a 125 MHz system clock
+    let clocks = hal::clocks::init_clocks_and_plls(
+        XOSC_CRYSTAL_FREQ,
+        pac.XOSC,
+        pac.CLOCKS,
+        pac.PLL_SYS,
+        pac.PLL_USB,
+        &mut pac.RESETS,
+        &mut watchdog,
+    )
+    .ok()
+    .unwrap();
+
+    // The single-cycle I/O block controls our GPIO pins
+    let sio = hal::Sio::new(pac.SIO);
+
+    // Set the pins up according to their function on this particular board
+    let pins = Pins::new(
+        pac.IO_BANK0,
+        pac.PADS_BANK0,
+        sio.gpio_bank0,
+        &mut pac.RESETS,
+    );
+
+    // The delay object lets us wait for specified amounts of time (in
+    // milliseconds)
+    let mut delay = cortex_m::delay::Delay::new(core.SYST, clocks.system_clock.freq().to_Hz());
+
+    let mut timer = hal::Timer::new(pac.TIMER, &mut pac.RESETS, &clocks);
+
+    // Go straight to the bootloader if DIP switch #1 is turned on
+    let _dip1 = sleep::bootloader_if_dip_set(pins.dip1, &mut timer);
+
+    // Enable LED controller, it is shut down while the laptop is asleep
+    let sleep_pin = SleepPin::new(pins.sleep, &mut timer);
+    let mut power = SleepHandler::new(sleep_pin, pins.sdb);
+    // INTB. Currently ignoring
+    pins.intb.into_floating_input();
+
+    let sda_pin: hal::gpio::Pin<_, hal::gpio::FunctionI2C, _> = pins.gpio26.reconfigure();
+    let scl_pin: hal::gpio::Pin<_, hal::gpio::FunctionI2C, _> = pins.gpio27.reconfigure();
+
+    let i2c = hal::I2C::i2c1(
+        pac.I2C1,
+        sda_pin,
+        scl_pin,
+        1000.kHz(),
+        &mut pac.RESETS,
+        &clocks.peripheral_clock,
+    );
+
+    let mut matrix = LedMatrix::new(i2c, CALC_PIXEL);
+    matrix
+        .setup(&mut delay)
+        .expect("failed to setup RGB controller");
+
+    // Enable only the SW pins that we're using.
+    // Otherwise driving the unused pins might result in audible noise.
+    matrix
+        .device
+        .sw_enablement(is31fl3741::SwSetting::Sw1Sw8)
+        .unwrap();
+
+    matrix
+        .set_scaling(MAX_BRIGHTNESS)
+        .expect("failed to set scaling");
+
+    // Push the framebuffer to the LED controller, once per frame
+    let mut show = |grid: &Grid, power: &mut SleepHandler| {
+        power.poll();
+        if power.state() == PowerState::Asleep {
+            return;
+        }
+        for (x, column) in grid.iter().enumerate() {
+            for (y, value) in column.iter().enumerate() {
+                matrix
+                    .device
+                    .pixel(x as u8, y as u8, power.dim(*value))
+                    .expect("couldn't set pixel");
+            }
+        }
+    };
+
+    let mut grid: Grid = [[0; HEIGHT]; WIDTH];
+
+    loop {
+        // Scroll the text through twice
+        let text = ScrollingText::new(TEXT, Font::Large, 0xFF);
+        let scroll_frames = render::text_height_rotated(Font::Large, TEXT) + HEIGHT as i32;
+        let mut animator = Animator::new(text, MicrosDurationU64::millis(60));
+        let mut frames = 0;
+        while frames < 2 * scroll_frames {
+            if animator.poll(timer.get_counter(), &mut grid) {
+                frames += 1;
+                show(&grid, &mut power);
+            }
+        }
+
+        // Then play Game of Life for a while, seeded from the timer
+        let seed = timer.get_counter().ticks() as u32;
+        let mut animator = Animator::new(GameOfLife::random(seed), MicrosDurationU64::millis(200));
+        let mut frames = 0;
+        while frames < 100 {
+            if animator.poll(timer.get_counter(), &mut grid) {
+                frames += 1;
+                show(&grid, &mut power);
+            }
+        }
+    }
+}
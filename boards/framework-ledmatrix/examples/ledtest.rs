@@ -10,9 +10,10 @@
 
 // The macro for our start-up function
 use framework_ledmatrix::entry;
+use framework_ledmatrix::sleep::{self, SleepPin};
 use framework_ledmatrix::{Pins, XOSC_CRYSTAL_FREQ};
 
-use embedded_hal::digital::{InputPin, OutputPin};
+use embedded_hal::digital::OutputPin;
 
 // Ensure we halt the program on panic (if we don't mention this crate it won't
 // be linked)
@@ -97,21 +98,16 @@ fn main() -> ! {
         &clocks.peripheral_clock,
     );
 
-    let mut dip1 = pins.dip1.into_pull_up_input();
-    let _ = dip1.is_high().unwrap();
+    // The timer implements the delay needed to sample the pins
+    let mut timer = hal::Timer::new(pac.TIMER, &mut pac.RESETS, &clocks);
+
+    // Go straight to the bootloader if DIP switch #1 is turned on
+    let _dip1 = sleep::bootloader_if_dip_set(pins.dip1, &mut timer);
 
     // Detect whether the sleep pin is connected
     // Early revisions of the hardware didn't have it wired up, if that is the
     // case we have to ignore its state.
-    let mut sleep_present = false;
-    let mut sleep = pins.sleep.into_pull_up_input();
-    if sleep.is_low().unwrap() {
-        sleep_present = true;
-    }
-    let mut sleep = sleep.into_pull_down_input();
-    if sleep.is_high().unwrap() {
-        sleep_present = true;
-    }
+    let mut sleep = SleepPin::new(pins.sleep, &mut timer);
 
     let mut matrix = LedMatrix::new(i2c, CALC_PIXEL);
     matrix
@@ -138,7 +134,7 @@ fn main() -> ! {
                 matrix.device.pixel(x, y, 0).expect("couldn't turn off");
 
                 // Reset into bootloader if system asleep
-                if sleep_present && sleep.is_low().unwrap() {
+                if sleep.is_asleep() {
                     hal::rom_data::reset_to_usb_boot(0, 0);
                 }
             }
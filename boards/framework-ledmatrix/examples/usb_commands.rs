@@ -0,0 +1,188 @@
+//! # Framework LED Matrix Module USB Command Example
+//!
+//! Creates a USB Serial device that speaks the input module command protocol,
+//! so the LED Matrix can be controlled with the official host tooling.
+//!
+//! For example `inputmodule-control led-matrix --pattern zig-zag`.
+//!
+//! See the `Cargo.toml` file for Copyright and license details.
+
+#![no_std]
+#![no_main]
+
+// The macro for our start-up function
+use framework_ledmatrix::entry;
+use framework_ledmatrix::{Pins, HEIGHT, WIDTH, XOSC_CRYSTAL_FREQ};
+
+use embedded_hal::digital::OutputPin;
+
+// Ensure we halt the program on panic (if we don't mention this crate it won't
+// be linked)
+use panic_halt as _;
+
+// Pull in any important traits
+use framework_ledmatrix::hal::prelude::*;
+
+// A shorter alias for the Peripheral Access Crate, which provides low-level
+// register access
+use framework_ledmatrix::hal::pac;
+
+// A shorter alias for the Hardware Abstraction Layer, which provides
+// higher-level drivers.
+use framework_ledmatrix::hal;
+
+// The input module command protocol
+use framework_ledmatrix::protocol::{self, Command, LedMatrixState};
+
+// USB Device support
+use usb_device::{class_prelude::*, prelude::*};
+
+// USB Communications Class Device support
+use usbd_serial::SerialPort;
+
+use fugit::RateExtU32;
+
+use is31fl3741::devices::{LedMatrix, CALC_PIXEL};
+
+/// Maximum brightness out of 255
+///
+/// The brightness set by the host is applied on top of this.
+const MAX_BRIGHTNESS: u8 = 50;
+
+#[entry]
+fn main() -> ! {
+    // Grab our singleton objects
+    let mut pac = pac::Peripherals::take().unwrap();
+    let core = pac::CorePeripherals::take().unwrap();
+
+    // Set up the watchdog driver - needed by the clock setup code
+    let mut watchdog = hal::Watchdog::new(pac.WATCHDOG);
+
+    // Configure the clocks
+    //
+    // The default is to generate a 125 MHz system clock
+    let clocks = hal::clocks::init_clocks_and_plls(
+        XOSC_CRYSTAL_FREQ,
+        pac.XOSC,
+        pac.CLOCKS,
+        pac.PLL_SYS,
+        pac.PLL_USB,
+        &mut pac.RESETS,
+        &mut watchdog,
+    )
+    .ok()
+    .unwrap();
+
+    // The single-cycle I/O block controls our GPIO pins
+    let sio = hal::Sio::new(pac.SIO);
+
+    // Set the pins up according to their function on this particular board
+    let pins = Pins::new(
+        pac.IO_BANK0,
+        pac.PADS_BANK0,
+        sio.gpio_bank0,
+        &mut pac.RESETS,
+    );
+
+    // The delay object lets us wait for specified amounts of time (in
+    // milliseconds)
+    let mut delay = cortex_m::delay::Delay::new(core.SYST, clocks.system_clock.freq().to_Hz());
+
+    // Set up the USB driver
+    let usb_bus = UsbBusAllocator::new(hal::usb::UsbBus::new(
+        pac.USBCTRL_REGS,
+        pac.USBCTRL_DPRAM,
+        clocks.usb_clock,
+        true,
+        &mut pac.RESETS,
+    ));
+
+    // Set up the USB Communications Class Device driver
+    let mut serial = SerialPort::new(&usb_bus);
+
+    // Use the IDs of the official firmware, so the host tooling can find us
+    let mut usb_dev =
+        UsbDeviceBuilder::new(&usb_bus, UsbVidPid(protocol::USB_VID, protocol::USB_PID))
+            .strings(&[StringDescriptors::default()
+                .manufacturer(protocol::USB_MANUFACTURER)
+                .product(protocol::USB_PRODUCT)
+                .serial_number("EXAMPLE")])
+            .unwrap()
+            .device_class(2) // from: https://www.usb.org/defined-class-codes
+            .build();
+
+    // Enable LED controller
+    // SDB - Gpio29
+    let mut led_enable = pins.sdb.into_push_pull_output();
+    led_enable.set_high().unwrap();
+    // INTB. Currently ignoring
+    pins.intb.into_floating_input();
+
+    let sda_pin: hal::gpio::Pin<_, hal::gpio::FunctionI2C, _> = pins.gpio26.reconfigure();
+    let scl_pin: hal::gpio::Pin<_, hal::gpio::FunctionI2C, _> = pins.gpio27.reconfigure();
+
+    let i2c = hal::I2C::i2c1(
+        pac.I2C1,
+        sda_pin,
+        scl_pin,
+        1000.kHz(),
+        &mut pac.RESETS,
+        &clocks.peripheral_clock,
+    );
+
+    let mut matrix = LedMatrix::new(i2c, CALC_PIXEL);
+    matrix
+        .setup(&mut delay)
+        .expect("failed to setup RGB controller");
+
+    // Enable only the SW pins that we're using.
+    // Otherwise driving the unused pins might result in audible noise.
+    matrix
+        .device
+        .sw_enablement(is31fl3741::SwSetting::Sw1Sw8)
+        .unwrap();
+
+    matrix
+        .set_scaling(MAX_BRIGHTNESS)
+        .expect("failed to set scaling");
+
+    let mut state = LedMatrixState::new();
+
+    loop {
+        // Check for new data
+        if !usb_dev.poll(&mut [&mut serial]) {
+            continue;
+        }
+
+        let mut buf = [0u8; 64];
+        let count = match serial.read(&mut buf) {
+            Ok(count) if count > 0 => count,
+            _ => continue,
+        };
+
+        let command = match protocol::parse_command(&buf[..count]) {
+            Some(command) => command,
+            None => continue,
+        };
+
+        if command == Command::BootloaderReset {
+            hal::rom_data::reset_to_usb_boot(0, 0);
+        }
+
+        if let Some(response) = state.handle_command(&command) {
+            // The response is smaller than the USB buffers, so this only
+            // fails if the host isn't reading. In that case drop it.
+            let _ = serial.write(&response);
+        }
+
+        // Push the new state to the LED controller
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                matrix
+                    .device
+                    .pixel(x as u8, y as u8, state.led_value(x, y))
+                    .expect("couldn't set pixel");
+            }
+        }
+    }
+}
@@ -0,0 +1,464 @@
+//! Frame based animations for the LED Matrix
+//!
+//! An [Animation] renders one frame at a time into a [Grid]. The [Animator]
+//! takes care of calling it at a fixed frame rate, driven by timestamps from
+//! a timer, e.g. [hal::Timer::get_counter](crate::hal::Timer::get_counter).
+//!
+//! ```ignore
+//! let period = MicrosDurationU64::millis(100);
+//! let mut animator = Animator::new(GameOfLife::random(0x1234), period);
+//! loop {
+//!     if animator.poll(timer.get_counter(), &mut grid) {
+//!         // Push grid to the LED controller
+//!     }
+//! }
+//! ```
+
+use fugit::{MicrosDurationU64, TimerInstantU64};
+
+use crate::font::Font;
+use crate::render;
+use crate::{Grid, HEIGHT, WIDTH};
+
+/// Timestamp with microsecond resolution, as returned by the RP2040 timer
+pub type Instant = TimerInstantU64<1_000_000>;
+
+/// Something that can be displayed frame by frame
+pub trait Animation {
+    /// Render the next frame into `grid`
+    fn next_frame(&mut self, grid: &mut Grid);
+}
+
+/// Runs an [Animation] at a fixed frame period
+pub struct Animator<A> {
+    animation: A,
+    period: MicrosDurationU64,
+    next_frame: Option<Instant>,
+}
+
+impl<A: Animation> Animator<A> {
+    /// Create an animator, showing a new frame every `period`
+    pub fn new(animation: A, period: MicrosDurationU64) -> Self {
+        Self {
+            animation,
+            period,
+            next_frame: None,
+        }
+    }
+
+    /// Render the next frame into `grid` if it is due
+    ///
+    /// Returns `true` if a new frame was rendered. If the caller falls behind,
+    /// frames are skipped instead of being rendered in a burst.
+    pub fn poll(&mut self, now: Instant, grid: &mut Grid) -> bool {
+        match self.next_frame {
+            Some(next_frame) if now < next_frame => false,
+            Some(next_frame) if now < next_frame + self.period => {
+                self.next_frame = Some(next_frame + self.period);
+                self.animation.next_frame(grid);
+                true
+            }
+            _ => {
+                self.next_frame = Some(now + self.period);
+                self.animation.next_frame(grid);
+                true
+            }
+        }
+    }
+
+    /// Change the frame period, takes effect after the next frame
+    pub fn set_period(&mut self, period: MicrosDurationU64) {
+        self.period = period;
+    }
+
+    /// Access the running animation
+    pub fn animation(&mut self) -> &mut A {
+        &mut self.animation
+    }
+
+    /// Stop animating and get the animation back
+    pub fn free(self) -> A {
+        self.animation
+    }
+}
+
+/// Text rotated by 90 degrees, scrolling up through the matrix
+///
+/// Starts with the text just below the matrix and wraps around once it has
+/// left it at the top.
+pub struct ScrollingText<'a> {
+    text: &'a str,
+    font: Font,
+    brightness: u8,
+    y: i32,
+}
+
+impl<'a> ScrollingText<'a> {
+    /// Scroll `text` at the given brightness
+    pub fn new(text: &'a str, font: Font, brightness: u8) -> Self {
+        Self {
+            text,
+            font,
+            brightness,
+            y: HEIGHT as i32,
+        }
+    }
+}
+
+impl Animation for ScrollingText<'_> {
+    fn next_frame(&mut self, grid: &mut Grid) {
+        render::clear(grid);
+        render::draw_text_rotated(grid, self.font, self.text, self.y, self.brightness);
+        self.y -= 1;
+        if self.y < -render::text_height_rotated(self.font, self.text) {
+            self.y = HEIGHT as i32;
+        }
+    }
+}
+
+/// Upright characters, one per line, scrolling up through the matrix
+///
+/// Like [ScrollingText], but without rotating the characters, which suits
+/// short words and numbers read with the laptop in its normal orientation.
+pub struct Ticker<'a> {
+    text: &'a str,
+    font: Font,
+    brightness: u8,
+    y: i32,
+}
+
+impl<'a> Ticker<'a> {
+    /// Scroll `text` at the given brightness
+    pub fn new(text: &'a str, font: Font, brightness: u8) -> Self {
+        Self {
+            text,
+            font,
+            brightness,
+            y: HEIGHT as i32,
+        }
+    }
+}
+
+impl Animation for Ticker<'_> {
+    fn next_frame(&mut self, grid: &mut Grid) {
+        render::clear(grid);
+        render::draw_text_vertical(grid, self.font, self.text, self.y, self.brightness);
+        self.y -= 1;
+        if self.y < -render::text_height(self.font, self.text) {
+            self.y = HEIGHT as i32;
+        }
+    }
+}
+
+/// Conway's Game of Life, wrapping around at the edges
+///
+/// Reseeds itself once all cells have died.
+pub struct GameOfLife {
+    cells: [[bool; HEIGHT]; WIDTH],
+    rng: u32,
+    brightness: u8,
+}
+
+impl GameOfLife {
+    /// Start with a random population derived from `seed`
+    pub fn random(seed: u32) -> Self {
+        let mut game = Self {
+            cells: [[false; HEIGHT]; WIDTH],
+            // xorshift gets stuck at zero
+            rng: seed.max(1),
+            brightness: 0xFF,
+        };
+        game.reseed();
+        game
+    }
+
+    /// Start with every lit LED of `grid` as a living cell
+    pub fn from_grid(grid: &Grid, seed: u32) -> Self {
+        let mut cells = [[false; HEIGHT]; WIDTH];
+        for (cells, column) in cells.iter_mut().zip(grid.iter()) {
+            for (cell, led) in cells.iter_mut().zip(column.iter()) {
+                *cell = *led != 0;
+            }
+        }
+        Self {
+            cells,
+            rng: seed.max(1),
+            brightness: 0xFF,
+        }
+    }
+
+    /// Set the brightness of living cells
+    pub fn set_brightness(&mut self, brightness: u8) {
+        self.brightness = brightness;
+    }
+
+    fn reseed(&mut self) {
+        for cell in self.cells.iter_mut().flatten() {
+            // Roughly a third of the cells start alive
+            *cell = xorshift32(&mut self.rng) < u32::MAX / 3;
+        }
+    }
+
+    fn living_neighbours(&self, x: usize, y: usize) -> usize {
+        let mut count = 0;
+        for dx in [WIDTH - 1, 0, 1] {
+            for dy in [HEIGHT - 1, 0, 1] {
+                if (dx, dy) == (0, 0) {
+                    continue;
+                }
+                if self.cells[(x + dx) % WIDTH][(y + dy) % HEIGHT] {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    /// Advance the simulation by one generation
+    pub fn step(&mut self) {
+        let mut next = [[false; HEIGHT]; WIDTH];
+        for (x, column) in next.iter_mut().enumerate() {
+            for (y, cell) in column.iter_mut().enumerate() {
+                *cell = matches!(
+                    (self.cells[x][y], self.living_neighbours(x, y)),
+                    (true, 2) | (_, 3)
+                );
+            }
+        }
+        self.cells = next;
+
+        if self.cells.iter().flatten().all(|cell| !cell) {
+            self.reseed();
+        }
+    }
+}
+
+impl Animation for GameOfLife {
+    fn next_frame(&mut self, grid: &mut Grid) {
+        for (column, cells) in grid.iter_mut().zip(self.cells.iter()) {
+            for (led, cell) in column.iter_mut().zip(cells.iter()) {
+                *led = if *cell { self.brightness } else { 0 };
+            }
+        }
+        self.step();
+    }
+}
+
+fn xorshift32(state: &mut u32) -> u32 {
+    *state ^= *state << 13;
+    *state ^= *state >> 17;
+    *state ^= *state << 5;
+    *state
+}
+
+/// Slowly fades an image in and out
+pub struct Breathing {
+    image: Grid,
+    level: u8,
+    step: u8,
+    rising: bool,
+}
+
+impl Breathing {
+    /// Breathe `image`, changing the brightness level by `step` every frame
+    pub fn new(image: Grid, step: u8) -> Self {
+        Self {
+            image,
+            level: 0,
+            step: step.max(1),
+            rising: true,
+        }
+    }
+}
+
+impl Animation for Breathing {
+    fn next_frame(&mut self, grid: &mut Grid) {
+        // Square the level, our eyes are more sensitive to changes of dim LEDs
+        let scale = u32::from(self.level) * u32::from(self.level) / 0xFF;
+        for (column, image) in grid.iter_mut().zip(self.image.iter()) {
+            for (led, value) in column.iter_mut().zip(image.iter()) {
+                *led = (u32::from(*value) * scale / 0xFF) as u8;
+            }
+        }
+
+        if self.rising {
+            self.level = self.level.saturating_add(self.step);
+            self.rising = self.level < 0xFF;
+        } else {
+            self.level = self.level.saturating_sub(self.step);
+            self.rising = self.level == 0;
+        }
+    }
+}
+
+/// The [render::zigzag] pattern moving down the matrix
+pub struct ZigZag {
+    offset: usize,
+    brightness: u8,
+}
+
+impl ZigZag {
+    /// Animate the zigzag pattern at the given brightness
+    pub fn new(brightness: u8) -> Self {
+        Self {
+            offset: 0,
+            brightness,
+        }
+    }
+}
+
+impl Animation for ZigZag {
+    fn next_frame(&mut self, grid: &mut Grid) {
+        render::zigzag(grid, self.offset, self.brightness);
+        self.offset = self.offset.wrapping_add(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec::Vec;
+
+    use super::*;
+
+    /// Counts its frames into the top left LED
+    struct Counter(u8);
+
+    impl Animation for Counter {
+        fn next_frame(&mut self, grid: &mut Grid) {
+            self.0 += 1;
+            grid[0][0] = self.0;
+        }
+    }
+
+    fn at(millis: u64) -> Instant {
+        Instant::from_ticks(millis * 1000)
+    }
+
+    fn frames(animation: &mut impl Animation, count: usize) -> Vec<Grid> {
+        (0..count)
+            .map(|_| {
+                let mut grid = [[0; HEIGHT]; WIDTH];
+                animation.next_frame(&mut grid);
+                grid
+            })
+            .collect()
+    }
+
+    fn game(cells: &[(usize, usize)]) -> GameOfLife {
+        let mut grid = [[0; HEIGHT]; WIDTH];
+        for &(x, y) in cells {
+            grid[x][y] = 1;
+        }
+        GameOfLife::from_grid(&grid, 1)
+    }
+
+    fn living(game: &GameOfLife) -> Vec<(usize, usize)> {
+        (0..WIDTH)
+            .flat_map(|x| (0..HEIGHT).map(move |y| (x, y)))
+            .filter(|&(x, y)| game.cells[x][y])
+            .collect()
+    }
+
+    #[test]
+    fn animator_keeps_the_frame_period() {
+        let mut animator = Animator::new(Counter(0), MicrosDurationU64::millis(100));
+        let mut grid = [[0; HEIGHT]; WIDTH];
+
+        // The first poll renders right away
+        assert!(animator.poll(at(5), &mut grid));
+        assert!(!animator.poll(at(104), &mut grid));
+        assert!(animator.poll(at(105), &mut grid));
+        // A late poll doesn't shift the following frames
+        assert!(animator.poll(at(230), &mut grid));
+        assert!(!animator.poll(at(304), &mut grid));
+        assert!(animator.poll(at(305), &mut grid));
+        assert_eq!(grid[0][0], 4);
+
+        // Falling behind skips frames instead of catching up in a burst
+        assert!(animator.poll(at(1000), &mut grid));
+        assert!(!animator.poll(at(1000), &mut grid));
+        assert!(!animator.poll(at(1099), &mut grid));
+        assert!(animator.poll(at(1100), &mut grid));
+        assert_eq!(animator.free().0, 6);
+    }
+
+    #[test]
+    fn scrolling_text_enters_at_the_bottom_and_wraps() {
+        let font = Font::Small;
+        let mut text = ScrollingText::new("HI", font, 0xFF);
+        let period = HEIGHT + render::text_height_rotated(font, "HI") as usize + 1;
+        let frames = frames(&mut text, 2 * period);
+
+        // Just below the matrix, then moving up one row per frame
+        assert_eq!(frames[0], [[0; HEIGHT]; WIDTH]);
+        let mut expected = [[0; HEIGHT]; WIDTH];
+        render::draw_text_rotated(&mut expected, font, "HI", HEIGHT as i32 - 5, 0xFF);
+        assert_eq!(frames[5], expected);
+        assert_eq!(frames[period - 1], [[0; HEIGHT]; WIDTH]);
+        assert_eq!(frames[..period], frames[period..]);
+    }
+
+    #[test]
+    fn ticker_scrolls_upright_characters() {
+        let font = Font::Large;
+        let mut ticker = Ticker::new("42", font, 0x80);
+        let period = HEIGHT + render::text_height(font, "42") as usize + 1;
+        let frames = frames(&mut ticker, 2 * period);
+
+        let mut expected = [[0; HEIGHT]; WIDTH];
+        render::draw_text_vertical(&mut expected, font, "42", 0, 0x80);
+        assert_eq!(frames[HEIGHT], expected);
+        assert_eq!(frames[..period], frames[period..]);
+    }
+
+    #[test]
+    fn game_of_life_follows_the_rules() {
+        // A blinker oscillates, also across the edges of the matrix
+        let mut blinker = game(&[(WIDTH - 1, 5), (0, 5), (1, 5)]);
+        blinker.step();
+        assert_eq!(living(&blinker), [(0, 4), (0, 5), (0, 6)]);
+        blinker.step();
+        assert_eq!(living(&blinker), [(0, 5), (1, 5), (WIDTH - 1, 5)]);
+
+        // A block stays as it is
+        let block = [(3, 0), (3, HEIGHT - 1), (4, 0), (4, HEIGHT - 1)];
+        let mut still = game(&block);
+        still.set_brightness(0x33);
+        let mut expected = [[0; HEIGHT]; WIDTH];
+        for &(x, y) in block.iter() {
+            expected[x][y] = 0x33;
+        }
+        assert!(frames(&mut still, 3).iter().all(|frame| *frame == expected));
+
+        // Once everything died, it starts over
+        let mut lonely = game(&[(4, 17)]);
+        lonely.step();
+        assert!(living(&lonely).len() > WIDTH * HEIGHT / 5);
+    }
+
+    #[test]
+    fn breathing_fades_in_and_out() {
+        let mut breathing = Breathing::new([[0xFF; HEIGHT]; WIDTH], 0x80);
+        let levels: Vec<u8> = frames(&mut breathing, 6)
+            .iter()
+            .map(|frame| {
+                assert!(frame.iter().flatten().all(|&led| led == frame[0][0]));
+                frame[0][0]
+            })
+            .collect();
+        // The levels 0, 128, 255, 127, 0 and 128, squared
+        assert_eq!(levels, [0, 64, 255, 63, 0, 64]);
+    }
+
+    #[test]
+    fn zigzag_moves_down() {
+        let mut zigzag = ZigZag::new(0xFF);
+        for (offset, frame) in frames(&mut zigzag, 20).iter().enumerate() {
+            let mut expected = [[0; HEIGHT]; WIDTH];
+            render::zigzag(&mut expected, offset, 0xFF);
+            assert_eq!(*frame, expected);
+        }
+    }
+}
@@ -0,0 +1,201 @@
+//! Bitmap fonts for the LED Matrix
+//!
+//! Both fonts cover the printable ASCII characters from `' '` up to `'Z'`.
+//! Lowercase letters are drawn as uppercase, anything else falls back to
+//! `'?'`.
+
+/// First character contained in the glyph tables
+const FIRST_CHAR: u8 = b' ';
+/// Last character contained in the glyph tables
+const LAST_CHAR: u8 = b'Z';
+
+/// A fixed width bitmap font
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Font {
+    /// 3x5 pixel font, fits two characters next to each other
+    Small,
+    /// 5x6 pixel font, fits one character per line
+    Large,
+}
+
+impl Font {
+    /// Width of every glyph in pixels
+    pub const fn width(self) -> usize {
+        match self {
+            Font::Small => 3,
+            Font::Large => 5,
+        }
+    }
+
+    /// Height of every glyph in pixels
+    pub const fn height(self) -> usize {
+        match self {
+            Font::Small => 5,
+            Font::Large => 6,
+        }
+    }
+
+    /// Rows of the glyph for `c`, from top to bottom
+    ///
+    /// The rightmost pixel of a row is stored in the least significant bit.
+    pub fn glyph(self, c: char) -> &'static [u8] {
+        let index = glyph_index(c);
+        match self {
+            Font::Small => &FONT_3X5[index],
+            Font::Large => &FONT_5X6[index],
+        }
+    }
+
+    /// Whether the pixel at `x`, `y` of the glyph for `c` is lit
+    ///
+    /// Coordinates outside of the glyph are never lit.
+    pub fn pixel(self, c: char, x: usize, y: usize) -> bool {
+        if x >= self.width() {
+            return false;
+        }
+        match self.glyph(c).get(y) {
+            Some(row) => row & (1 << (self.width() - 1 - x)) != 0,
+            None => false,
+        }
+    }
+}
+
+fn glyph_index(c: char) -> usize {
+    let c = c.to_ascii_uppercase();
+    let c = if c.is_ascii() { c as u8 } else { b'?' };
+    let c = if (FIRST_CHAR..=LAST_CHAR).contains(&c) {
+        c
+    } else {
+        b'?'
+    };
+    usize::from(c - FIRST_CHAR)
+}
+
+const GLYPHS: usize = (LAST_CHAR - FIRST_CHAR + 1) as usize;
+
+#[rustfmt::skip]
+static FONT_3X5: [[u8; 5]; GLYPHS] = [
+    [0b000, 0b000, 0b000, 0b000, 0b000], // ' '
+    [0b010, 0b010, 0b010, 0b000, 0b010], // '!'
+    [0b101, 0b101, 0b000, 0b000, 0b000], // '"'
+    [0b101, 0b111, 0b101, 0b111, 0b101], // '#'
+    [0b011, 0b110, 0b010, 0b011, 0b110], // '$'
+    [0b100, 0b001, 0b010, 0b100, 0b001], // '%'
+    [0b010, 0b101, 0b010, 0b101, 0b011], // '&'
+    [0b010, 0b010, 0b000, 0b000, 0b000], // '\''
+    [0b001, 0b010, 0b010, 0b010, 0b001], // '('
+    [0b100, 0b010, 0b010, 0b010, 0b100], // ')'
+    [0b000, 0b101, 0b010, 0b101, 0b000], // '*'
+    [0b000, 0b010, 0b111, 0b010, 0b000], // '+'
+    [0b000, 0b000, 0b000, 0b010, 0b100], // ','
+    [0b000, 0b000, 0b111, 0b000, 0b000], // '-'
+    [0b000, 0b000, 0b000, 0b000, 0b010], // '.'
+    [0b001, 0b001, 0b010, 0b100, 0b100], // '/'
+    [0b111, 0b101, 0b101, 0b101, 0b111], // '0'
+    [0b010, 0b110, 0b010, 0b010, 0b111], // '1'
+    [0b111, 0b001, 0b111, 0b100, 0b111], // '2'
+    [0b111, 0b001, 0b011, 0b001, 0b111], // '3'
+    [0b101, 0b101, 0b111, 0b001, 0b001], // '4'
+    [0b111, 0b100, 0b111, 0b001, 0b111], // '5'
+    [0b111, 0b100, 0b111, 0b101, 0b111], // '6'
+    [0b111, 0b001, 0b010, 0b010, 0b010], // '7'
+    [0b111, 0b101, 0b111, 0b101, 0b111], // '8'
+    [0b111, 0b101, 0b111, 0b001, 0b111], // '9'
+    [0b000, 0b010, 0b000, 0b010, 0b000], // ':'
+    [0b000, 0b010, 0b000, 0b010, 0b100], // ';'
+    [0b001, 0b010, 0b100, 0b010, 0b001], // '<'
+    [0b000, 0b111, 0b000, 0b111, 0b000], // '='
+    [0b100, 0b010, 0b001, 0b010, 0b100], // '>'
+    [0b111, 0b001, 0b011, 0b000, 0b010], // '?'
+    [0b010, 0b101, 0b111, 0b100, 0b011], // '@'
+    [0b010, 0b101, 0b111, 0b101, 0b101], // 'A'
+    [0b110, 0b101, 0b110, 0b101, 0b110], // 'B'
+    [0b011, 0b100, 0b100, 0b100, 0b011], // 'C'
+    [0b110, 0b101, 0b101, 0b101, 0b110], // 'D'
+    [0b111, 0b100, 0b111, 0b100, 0b111], // 'E'
+    [0b111, 0b100, 0b111, 0b100, 0b100], // 'F'
+    [0b011, 0b100, 0b101, 0b101, 0b011], // 'G'
+    [0b101, 0b101, 0b111, 0b101, 0b101], // 'H'
+    [0b111, 0b010, 0b010, 0b010, 0b111], // 'I'
+    [0b001, 0b001, 0b001, 0b101, 0b010], // 'J'
+    [0b101, 0b101, 0b110, 0b101, 0b101], // 'K'
+    [0b100, 0b100, 0b100, 0b100, 0b111], // 'L'
+    [0b101, 0b111, 0b111, 0b101, 0b101], // 'M'
+    [0b110, 0b101, 0b101, 0b101, 0b101], // 'N'
+    [0b010, 0b101, 0b101, 0b101, 0b010], // 'O'
+    [0b110, 0b101, 0b110, 0b100, 0b100], // 'P'
+    [0b010, 0b101, 0b101, 0b111, 0b011], // 'Q'
+    [0b110, 0b101, 0b110, 0b101, 0b101], // 'R'
+    [0b011, 0b100, 0b010, 0b001, 0b110], // 'S'
+    [0b111, 0b010, 0b010, 0b010, 0b010], // 'T'
+    [0b101, 0b101, 0b101, 0b101, 0b011], // 'U'
+    [0b101, 0b101, 0b101, 0b010, 0b010], // 'V'
+    [0b101, 0b101, 0b111, 0b111, 0b101], // 'W'
+    [0b101, 0b101, 0b010, 0b101, 0b101], // 'X'
+    [0b101, 0b101, 0b010, 0b010, 0b010], // 'Y'
+    [0b111, 0b001, 0b010, 0b100, 0b111], // 'Z'
+];
+
+#[rustfmt::skip]
+static FONT_5X6: [[u8; 6]; GLYPHS] = [
+    [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000], // ' '
+    [0b00100, 0b00100, 0b00100, 0b00100, 0b00000, 0b00100], // '!'
+    [0b01010, 0b01010, 0b00000, 0b00000, 0b00000, 0b00000], // '"'
+    [0b01010, 0b11111, 0b01010, 0b11111, 0b01010, 0b00000], // '#'
+    [0b01111, 0b10100, 0b01110, 0b00101, 0b11110, 0b00000], // '$'
+    [0b11001, 0b11010, 0b00100, 0b01011, 0b10011, 0b00000], // '%'
+    [0b01100, 0b10010, 0b01100, 0b10010, 0b01101, 0b00000], // '&'
+    [0b00100, 0b00100, 0b00000, 0b00000, 0b00000, 0b00000], // '\''
+    [0b00010, 0b00100, 0b00100, 0b00100, 0b00100, 0b00010], // '('
+    [0b01000, 0b00100, 0b00100, 0b00100, 0b00100, 0b01000], // ')'
+    [0b00000, 0b10101, 0b01110, 0b10101, 0b00000, 0b00000], // '*'
+    [0b00000, 0b00100, 0b00100, 0b11111, 0b00100, 0b00100], // '+'
+    [0b00000, 0b00000, 0b00000, 0b00000, 0b00100, 0b01000], // ','
+    [0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000], // '-'
+    [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00100], // '.'
+    [0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b00000], // '/'
+    [0b01110, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110], // '0'
+    [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b01110], // '1'
+    [0b01110, 0b10001, 0b00010, 0b00100, 0b01000, 0b11111], // '2'
+    [0b11110, 0b00001, 0b00110, 0b00001, 0b00001, 0b11110], // '3'
+    [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010], // '4'
+    [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b11110], // '5'
+    [0b01110, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110], // '6'
+    [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000], // '7'
+    [0b01110, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110], // '8'
+    [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b01110], // '9'
+    [0b00000, 0b00100, 0b00000, 0b00000, 0b00100, 0b00000], // ':'
+    [0b00000, 0b00100, 0b00000, 0b00000, 0b00100, 0b01000], // ';'
+    [0b00010, 0b00100, 0b01000, 0b01000, 0b00100, 0b00010], // '<'
+    [0b00000, 0b11111, 0b00000, 0b11111, 0b00000, 0b00000], // '='
+    [0b01000, 0b00100, 0b00010, 0b00010, 0b00100, 0b01000], // '>'
+    [0b01110, 0b10001, 0b00010, 0b00100, 0b00000, 0b00100], // '?'
+    [0b01110, 0b10001, 0b10111, 0b10110, 0b10000, 0b01110], // '@'
+    [0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001], // 'A'
+    [0b11110, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110], // 'B'
+    [0b01110, 0b10001, 0b10000, 0b10000, 0b10001, 0b01110], // 'C'
+    [0b11110, 0b10001, 0b10001, 0b10001, 0b10001, 0b11110], // 'D'
+    [0b11111, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111], // 'E'
+    [0b11111, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000], // 'F'
+    [0b01110, 0b10000, 0b10111, 0b10001, 0b10001, 0b01110], // 'G'
+    [0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001], // 'H'
+    [0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110], // 'I'
+    [0b00111, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100], // 'J'
+    [0b10001, 0b10010, 0b11100, 0b10010, 0b10001, 0b10001], // 'K'
+    [0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111], // 'L'
+    [0b10001, 0b11011, 0b10101, 0b10001, 0b10001, 0b10001], // 'M'
+    [0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001], // 'N'
+    [0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110], // 'O'
+    [0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000], // 'P'
+    [0b01110, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101], // 'Q'
+    [0b11110, 0b10001, 0b10001, 0b11110, 0b10010, 0b10001], // 'R'
+    [0b01111, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110], // 'S'
+    [0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100], // 'T'
+    [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110], // 'U'
+    [0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100], // 'V'
+    [0b10001, 0b10001, 0b10001, 0b10101, 0b11011, 0b10001], // 'W'
+    [0b10001, 0b01010, 0b00100, 0b00100, 0b01010, 0b10001], // 'X'
+    [0b10001, 0b01010, 0b00100, 0b00100, 0b00100, 0b00100], // 'Y'
+    [0b11111, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111], // 'Z'
+];
@@ -24,7 +24,15 @@ pub static BOOT2_FIRMWARE: [u8; 256] = rp2040_boot2::BOOT_LOADER_W25Q080;
 
 pub use hal::pac;
 
-hal::bsp_pins!(
+pub mod animation;
+pub mod font;
+pub mod protocol;
+pub mod render;
+pub mod sleep;
+
+board_common::bsp_pins!(
+    embassy: crate::embassy::Pins;
+
     /// GPIO 0 is connected to the SLEEP# pin of the EC
     Gpio0 { name: sleep },
     /// GPIO 25 is connected to the DIP Switch #1
@@ -52,3 +60,21 @@ hal::bsp_pins!(
 );
 
 pub const XOSC_CRYSTAL_FREQ: u32 = 12_000_000;
+
+// FLASH_SIZE_BYTES and the reserved end of the flash, see build.rs
+include!(concat!(env!("OUT_DIR"), "/flash.rs"));
+
+#[cfg(feature = "embassy")]
+pub mod embassy;
+
+pub use board_common;
+
+board_common::board_pins!(Pins, XOSC_CRYSTAL_FREQ);
+
+/// Number of LED columns
+pub const WIDTH: usize = 9;
+/// Number of LED rows
+pub const HEIGHT: usize = 34;
+
+/// Brightness of every LED, indexed by `[x][y]` with `[0][0]` in the top left
+pub type Grid = [[u8; HEIGHT]; WIDTH];
@@ -0,0 +1,453 @@
+//! Command protocol of the Framework input modules
+//!
+//! The host talks to the LED Matrix over a USB CDC-ACM serial port. Every
+//! command is sent as a single write and starts with the two magic bytes
+//! `0x32 0xAC`, followed by a command ID and its parameters. Commands that
+//! return data answer with a fixed size [Response].
+//!
+//! This module only parses packets and applies them to a [LedMatrixState], it
+//! does not touch any hardware. This keeps it usable on the host, where a
+//! harness can feed it byte streams and inspect the resulting framebuffer.
+
+use crate::render;
+use crate::{Grid, HEIGHT, WIDTH};
+
+/// USB Vendor ID of Framework Computer Inc
+pub const USB_VID: u16 = 0x32AC;
+/// USB Product ID of the LED Matrix input module
+pub const USB_PID: u16 = 0x0020;
+/// USB manufacturer string expected by the host tooling
+pub const USB_MANUFACTURER: &str = "Framework Computer Inc";
+/// USB product string expected by the host tooling
+pub const USB_PRODUCT: &str = "LED Matrix Input Module";
+
+/// Magic bytes every command has to start with
+pub const MAGIC: [u8; 2] = [0x32, 0xAC];
+
+/// Number of bytes needed to transfer a black/white image, one bit per LED
+pub const DRAW_BYTES: usize = (WIDTH * HEIGHT).div_ceil(8);
+
+/// Size of every response sent back to the host
+pub const RESPONSE_SIZE: usize = 32;
+
+/// Response sent back to the host
+pub type Response = [u8; RESPONSE_SIZE];
+
+/// Command IDs as defined by the input module protocol
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum CommandId {
+    Brightness = 0x00,
+    Pattern = 0x01,
+    BootloaderReset = 0x02,
+    Sleep = 0x03,
+    DrawBw = 0x06,
+    StageCol = 0x07,
+    FlushCols = 0x08,
+    Version = 0x20,
+}
+
+impl CommandId {
+    /// Look up a command ID, returns `None` for IDs that are not supported
+    pub fn from_u8(id: u8) -> Option<Self> {
+        use CommandId::*;
+        Some(match id {
+            0x00 => Brightness,
+            0x01 => Pattern,
+            0x02 => BootloaderReset,
+            0x03 => Sleep,
+            0x06 => DrawBw,
+            0x07 => StageCol,
+            0x08 => FlushCols,
+            0x20 => Version,
+            _ => return None,
+        })
+    }
+}
+
+/// Built-in patterns that can be selected by the host
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Pattern {
+    /// Fill the matrix from the bottom up to the given percentage
+    Percentage(u8),
+    /// Brightness increasing from top to bottom
+    Gradient,
+    /// Brightness increasing towards the middle row
+    DoubleGradient,
+    /// One LED per row, bouncing between the left and right edge
+    ZigZag,
+    /// Every LED at full brightness
+    FullBrightness,
+}
+
+/// A parsed command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Command {
+    /// Query the global brightness
+    GetBrightness,
+    /// Set the global brightness
+    SetBrightness(u8),
+    /// Display one of the built-in patterns
+    Pattern(Pattern),
+    /// Reboot into the ROM USB bootloader
+    BootloaderReset,
+    /// Query whether the display is asleep
+    GetSleep,
+    /// Put the display to sleep or wake it up
+    SetSleep(bool),
+    /// Draw a black/white image, one bit per LED, in row-major order
+    DrawBw([u8; DRAW_BYTES]),
+    /// Stage a column of greyscale values, shown on the next [Command::FlushCols]
+    StageCol(u8, [u8; HEIGHT]),
+    /// Show all staged columns
+    FlushCols,
+    /// Query the firmware version
+    Version,
+}
+
+/// Parse a single command packet
+///
+/// Returns `None` if the packet doesn't start with [MAGIC], the command is
+/// unknown or the packet is too short for the command's parameters.
+pub fn parse_command(buf: &[u8]) -> Option<Command> {
+    if buf.len() < 3 || buf[..2] != MAGIC {
+        return None;
+    }
+    let arg = buf.get(3).copied();
+    let params = &buf[3..];
+
+    match CommandId::from_u8(buf[2])? {
+        CommandId::Brightness => Some(match arg {
+            Some(brightness) => Command::SetBrightness(brightness),
+            None => Command::GetBrightness,
+        }),
+        CommandId::Pattern => {
+            let pattern = match arg? {
+                0x00 => Pattern::Percentage(*buf.get(4)?),
+                0x01 => Pattern::Gradient,
+                0x02 => Pattern::DoubleGradient,
+                0x04 => Pattern::ZigZag,
+                0x05 => Pattern::FullBrightness,
+                _ => return None,
+            };
+            Some(Command::Pattern(pattern))
+        }
+        CommandId::BootloaderReset => Some(Command::BootloaderReset),
+        CommandId::Sleep => Some(match arg {
+            Some(sleep) => Command::SetSleep(sleep != 0),
+            None => Command::GetSleep,
+        }),
+        CommandId::DrawBw => {
+            let bits = params.get(..DRAW_BYTES)?;
+            let mut bytes = [0; DRAW_BYTES];
+            bytes.copy_from_slice(bits);
+            Some(Command::DrawBw(bytes))
+        }
+        CommandId::StageCol => {
+            let col = arg?;
+            if usize::from(col) >= WIDTH {
+                return None;
+            }
+            let vals = params.get(1..1 + HEIGHT)?;
+            let mut column = [0; HEIGHT];
+            column.copy_from_slice(vals);
+            Some(Command::StageCol(col, column))
+        }
+        CommandId::FlushCols => Some(Command::FlushCols),
+        CommandId::Version => Some(Command::Version),
+    }
+}
+
+/// State of the LED Matrix as controlled by the host
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LedMatrixState {
+    /// What is currently supposed to be displayed
+    pub grid: Grid,
+    /// Columns staged by [Command::StageCol]
+    pub col_buffer: Grid,
+    /// Global brightness, every LED value is scaled by it
+    pub brightness: u8,
+    /// Whether the display has been put to sleep
+    pub sleeping: bool,
+}
+
+impl Default for LedMatrixState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LedMatrixState {
+    /// Create a blank state at half brightness
+    pub const fn new() -> Self {
+        Self {
+            grid: [[0; HEIGHT]; WIDTH],
+            col_buffer: [[0; HEIGHT]; WIDTH],
+            brightness: 0x80,
+            sleeping: false,
+        }
+    }
+
+    /// Value to program into the LED controller for the LED at `x`, `y`
+    ///
+    /// Takes the global brightness and sleep state into account.
+    pub fn led_value(&self, x: usize, y: usize) -> u8 {
+        if self.sleeping {
+            return 0;
+        }
+        (u16::from(self.grid[x][y]) * u16::from(self.brightness) / 0xFF) as u8
+    }
+
+    /// Apply a command to the state
+    ///
+    /// Returns the response to send back to the host, if the command has one.
+    /// [Command::BootloaderReset] is not handled here, since it needs to act on
+    /// the hardware, the caller has to call
+    /// [hal::rom_data::reset_to_usb_boot](crate::hal::rom_data::reset_to_usb_boot) itself.
+    pub fn handle_command(&mut self, command: &Command) -> Option<Response> {
+        let mut response = [0; RESPONSE_SIZE];
+        match *command {
+            Command::GetBrightness => {
+                response[0] = self.brightness;
+                return Some(response);
+            }
+            Command::SetBrightness(brightness) => self.brightness = brightness,
+            Command::Pattern(pattern) => self.grid = pattern_grid(pattern),
+            Command::BootloaderReset => {}
+            Command::GetSleep => {
+                response[0] = self.sleeping as u8;
+                return Some(response);
+            }
+            Command::SetSleep(sleeping) => self.sleeping = sleeping,
+            Command::DrawBw(ref bytes) => self.grid = draw_bw(bytes),
+            Command::StageCol(col, ref column) => self.col_buffer[usize::from(col)] = *column,
+            Command::FlushCols => self.grid = self.col_buffer,
+            Command::Version => {
+                response[..3].copy_from_slice(&version());
+                return Some(response);
+            }
+        }
+        None
+    }
+}
+
+/// Firmware version as reported by [Command::Version]
+///
+/// The first byte is the major version, the second byte the minor version in
+/// the upper and the patch version in the lower nibble. The third byte is 1
+/// for pre-release versions.
+pub fn version() -> [u8; 3] {
+    let major = env!("CARGO_PKG_VERSION_MAJOR").parse::<u8>().unwrap_or(0);
+    let minor = env!("CARGO_PKG_VERSION_MINOR").parse::<u8>().unwrap_or(0);
+    let patch = env!("CARGO_PKG_VERSION_PATCH").parse::<u8>().unwrap_or(0);
+    let pre_release = !env!("CARGO_PKG_VERSION_PRE").is_empty();
+    [
+        major,
+        ((minor & 0x0F) << 4) | (patch & 0x0F),
+        pre_release as u8,
+    ]
+}
+
+/// Convert a black/white image into a grid
+///
+/// Bit `n` of the image corresponds to the LED at `x = n % WIDTH`,
+/// `y = n / WIDTH`, starting with the least significant bit of the first byte.
+pub fn draw_bw(bytes: &[u8; DRAW_BYTES]) -> Grid {
+    let mut grid = [[0; HEIGHT]; WIDTH];
+    for (x, column) in grid.iter_mut().enumerate() {
+        for (y, led) in column.iter_mut().enumerate() {
+            let index = x + WIDTH * y;
+            if bytes[index / 8] & (1 << (index % 8)) != 0 {
+                *led = 0xFF;
+            }
+        }
+    }
+    grid
+}
+
+/// Render one of the built-in patterns
+pub fn pattern_grid(pattern: Pattern) -> Grid {
+    let mut grid = [[0; HEIGHT]; WIDTH];
+    match pattern {
+        Pattern::Percentage(percent) => render::percentage(&mut grid, percent),
+        Pattern::Gradient => {
+            for column in grid.iter_mut() {
+                for (y, led) in column.iter_mut().enumerate() {
+                    *led = (y * 0xFF / (HEIGHT - 1)) as u8;
+                }
+            }
+        }
+        Pattern::DoubleGradient => {
+            let half = HEIGHT / 2;
+            for column in grid.iter_mut() {
+                for (y, led) in column.iter_mut().enumerate() {
+                    let distance = if y < half { y } else { HEIGHT - 1 - y };
+                    *led = (distance * 0xFF / (half - 1)) as u8;
+                }
+            }
+        }
+        Pattern::ZigZag => render::zigzag(&mut grid, 0, 0xFF),
+        Pattern::FullBrightness => grid = [[0xFF; HEIGHT]; WIDTH],
+    }
+    grid
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec::Vec;
+
+    use super::*;
+
+    /// Build the packet the host tooling sends for `command`
+    fn encode(command: &Command) -> Vec<u8> {
+        let mut packet = MAGIC.to_vec();
+        match *command {
+            Command::GetBrightness => packet.push(CommandId::Brightness as u8),
+            Command::SetBrightness(brightness) => {
+                packet.extend([CommandId::Brightness as u8, brightness])
+            }
+            Command::Pattern(pattern) => {
+                packet.push(CommandId::Pattern as u8);
+                match pattern {
+                    Pattern::Percentage(percent) => packet.extend([0x00, percent]),
+                    Pattern::Gradient => packet.push(0x01),
+                    Pattern::DoubleGradient => packet.push(0x02),
+                    Pattern::ZigZag => packet.push(0x04),
+                    Pattern::FullBrightness => packet.push(0x05),
+                }
+            }
+            Command::BootloaderReset => packet.push(CommandId::BootloaderReset as u8),
+            Command::GetSleep => packet.push(CommandId::Sleep as u8),
+            Command::SetSleep(sleep) => packet.extend([CommandId::Sleep as u8, sleep as u8]),
+            Command::DrawBw(ref bytes) => {
+                packet.push(CommandId::DrawBw as u8);
+                packet.extend_from_slice(bytes);
+            }
+            Command::StageCol(col, ref column) => {
+                packet.extend([CommandId::StageCol as u8, col]);
+                packet.extend_from_slice(column);
+            }
+            Command::FlushCols => packet.push(CommandId::FlushCols as u8),
+            Command::Version => packet.push(CommandId::Version as u8),
+        }
+        packet
+    }
+
+    fn commands() -> Vec<Command> {
+        let mut image = [0; DRAW_BYTES];
+        for (i, byte) in image.iter_mut().enumerate() {
+            *byte = (i as u8).wrapping_mul(37);
+        }
+        let mut column = [0; HEIGHT];
+        for (y, led) in column.iter_mut().enumerate() {
+            *led = y as u8 * 7;
+        }
+        std::vec![
+            Command::GetBrightness,
+            Command::SetBrightness(0x42),
+            Command::Pattern(Pattern::Percentage(75)),
+            Command::Pattern(Pattern::Gradient),
+            Command::Pattern(Pattern::DoubleGradient),
+            Command::Pattern(Pattern::ZigZag),
+            Command::Pattern(Pattern::FullBrightness),
+            Command::BootloaderReset,
+            Command::GetSleep,
+            Command::SetSleep(true),
+            Command::SetSleep(false),
+            Command::DrawBw(image),
+            Command::StageCol(0, column),
+            Command::StageCol(WIDTH as u8 - 1, column),
+            Command::FlushCols,
+            Command::Version,
+        ]
+    }
+
+    #[test]
+    fn packets_round_trip() {
+        for command in commands() {
+            assert_eq!(parse_command(&encode(&command)), Some(command));
+        }
+    }
+
+    #[test]
+    fn malformed_packets_are_rejected() {
+        let rejected: [&[u8]; 10] = [
+            &[],
+            &[0x32, 0xAC],
+            // Wrong magic
+            &[0xAC, 0x32, 0x20],
+            &[0x32, 0xAD, 0x00, 0x10],
+            // Unknown commands
+            &[0x32, 0xAC, 0x04],
+            &[0x32, 0xAC, 0xFF, 0x00],
+            // Unknown pattern, and a pattern missing its parameters
+            &[0x32, 0xAC, 0x01, 0x03],
+            &[0x32, 0xAC, 0x01],
+            &[0x32, 0xAC, 0x01, 0x00],
+            // Column out of range
+            &[0x32, 0xAC, 0x07, WIDTH as u8, 0, 0, 0],
+        ];
+        for packet in rejected.iter() {
+            assert_eq!(parse_command(packet), None, "{:02x?}", packet);
+        }
+
+        // Images and columns missing their last byte
+        for command in commands() {
+            if let Command::DrawBw(_) | Command::StageCol(..) = command {
+                let packet = encode(&command);
+                assert_eq!(parse_command(&packet[..packet.len() - 1]), None);
+            }
+        }
+    }
+
+    #[test]
+    fn commands_update_the_state() {
+        let mut state = LedMatrixState::new();
+
+        assert_eq!(state.handle_command(&Command::SetBrightness(0x40)), None);
+        let response = state.handle_command(&Command::GetBrightness).unwrap();
+        assert_eq!(response[0], 0x40);
+        assert!(response[1..].iter().all(|&byte| byte == 0));
+
+        state.handle_command(&Command::Pattern(Pattern::FullBrightness));
+        assert_eq!(state.led_value(3, 20), 0x40);
+        state.handle_command(&Command::SetSleep(true));
+        assert_eq!(state.handle_command(&Command::GetSleep).unwrap()[0], 1);
+        assert_eq!(state.led_value(3, 20), 0);
+        state.handle_command(&Command::SetSleep(false));
+
+        // Staged columns only show up once flushed
+        let mut column = [0; HEIGHT];
+        column[5] = 0x99;
+        state.handle_command(&Command::StageCol(2, column));
+        assert_eq!(state.grid, [[0xFF; HEIGHT]; WIDTH]);
+        state.handle_command(&Command::FlushCols);
+        assert_eq!(state.grid[2], column);
+        assert_eq!(state.grid[1], [0; HEIGHT]);
+
+        let response = state.handle_command(&Command::Version).unwrap();
+        assert_eq!(response[..3], version());
+    }
+
+    #[test]
+    fn images_are_row_major_lsb_first() {
+        let mut image = [0; DRAW_BYTES];
+        // Bit 0 is the top left LED, bit WIDTH the first LED of the next row
+        image[0] = 0b0000_0001;
+        image[WIDTH / 8] |= 1 << (WIDTH % 8);
+        let last = WIDTH * HEIGHT - 1;
+        image[last / 8] |= 1 << (last % 8);
+
+        let grid = draw_bw(&image);
+        let lit: Vec<(usize, usize)> = (0..WIDTH)
+            .flat_map(|x| (0..HEIGHT).map(move |y| (x, y)))
+            .filter(|&(x, y)| grid[x][y] == 0xFF)
+            .collect();
+        assert_eq!(lit, [(0, 0), (0, 1), (WIDTH - 1, HEIGHT - 1)]);
+    }
+}
@@ -0,0 +1,241 @@
+//! Drawing primitives for the LED Matrix framebuffer
+//!
+//! Everything in here draws into a [Grid], which can then be pushed to the LED
+//! controller. Positions are signed and everything is clipped to the matrix,
+//! so text can be partially scrolled in and out of view.
+
+use crate::font::Font;
+use crate::{Grid, HEIGHT, WIDTH};
+
+/// Turn off every LED
+pub fn clear(grid: &mut Grid) {
+    *grid = [[0; HEIGHT]; WIDTH];
+}
+
+/// Set a single LED, ignoring positions outside of the matrix
+pub fn set_pixel(grid: &mut Grid, x: i32, y: i32, brightness: u8) {
+    if (0..WIDTH as i32).contains(&x) && (0..HEIGHT as i32).contains(&y) {
+        grid[x as usize][y as usize] = brightness;
+    }
+}
+
+/// Draw a single character with its top left corner at `x`, `y`
+pub fn draw_char(grid: &mut Grid, font: Font, c: char, x: i32, y: i32, brightness: u8) {
+    for gy in 0..font.height() {
+        for gx in 0..font.width() {
+            if font.pixel(c, gx, gy) {
+                set_pixel(grid, x + gx as i32, y + gy as i32, brightness);
+            }
+        }
+    }
+}
+
+/// Draw a single character rotated by 90 degrees clockwise
+///
+/// `x`, `y` is the top left corner of the rotated character, which is
+/// `font.height()` LEDs wide and `font.width()` LEDs high.
+pub fn draw_char_rotated(grid: &mut Grid, font: Font, c: char, x: i32, y: i32, brightness: u8) {
+    for gy in 0..font.height() {
+        for gx in 0..font.width() {
+            if font.pixel(c, gx, gy) {
+                let rx = (font.height() - 1 - gy) as i32;
+                set_pixel(grid, x + rx, y + gx as i32, brightness);
+            }
+        }
+    }
+}
+
+/// Number of rows taken up by `text` drawn with [draw_text_vertical]
+pub fn text_height(font: Font, text: &str) -> i32 {
+    text.chars().count() as i32 * (font.height() as i32 + 1)
+}
+
+/// Number of rows taken up by `text` drawn with [draw_text_rotated]
+pub fn text_height_rotated(font: Font, text: &str) -> i32 {
+    text.chars().count() as i32 * (font.width() as i32 + 1)
+}
+
+/// Draw text with one character per line, horizontally centered
+///
+/// `y` is the top row of the first character.
+pub fn draw_text_vertical(grid: &mut Grid, font: Font, text: &str, y: i32, brightness: u8) {
+    let x = (WIDTH - font.width()) as i32 / 2;
+    let advance = font.height() as i32 + 1;
+    for (i, c) in text.chars().enumerate() {
+        draw_char(grid, font, c, x, y + i as i32 * advance, brightness);
+    }
+}
+
+/// Draw text rotated by 90 degrees clockwise, reading from top to bottom
+///
+/// This is how text reads when the laptop is turned on its side. `y` is the
+/// top row of the first character.
+pub fn draw_text_rotated(grid: &mut Grid, font: Font, text: &str, y: i32, brightness: u8) {
+    let x = (WIDTH - font.height()) as i32 / 2;
+    let advance = font.width() as i32 + 1;
+    for (i, c) in text.chars().enumerate() {
+        draw_char_rotated(grid, font, c, x, y + i as i32 * advance, brightness);
+    }
+}
+
+/// Fill the matrix from the bottom up to `percent`
+///
+/// The topmost row is dimmed according to how much of it would be covered, so
+/// small changes are still visible.
+pub fn percentage(grid: &mut Grid, percent: u8) {
+    // Work in 1/255 of a row, to dim the partially filled row
+    let filled = HEIGHT * 0xFF * usize::from(percent.min(100)) / 100;
+    for column in grid.iter_mut() {
+        for (y, led) in column.iter_mut().enumerate() {
+            let from_bottom = HEIGHT - 1 - y;
+            let covered = filled.saturating_sub(from_bottom * 0xFF).min(0xFF);
+            *led = covered as u8;
+        }
+    }
+}
+
+/// Draw a line bouncing between the left and right edge, one LED per row
+///
+/// `offset` shifts the pattern down by that many rows, which animates it.
+pub fn zigzag(grid: &mut Grid, offset: usize, brightness: u8) {
+    // Bounce back and forth, spending one row at each edge
+    let period = 2 * (WIDTH - 1);
+    for (x, column) in grid.iter_mut().enumerate() {
+        for (y, led) in column.iter_mut().enumerate() {
+            let pos = (y + period - offset % period) % period;
+            let lit = if pos < WIDTH { pos } else { period - pos };
+            *led = if x == lit { brightness } else { 0 };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec::Vec;
+
+    use super::*;
+
+    fn lit(grid: &Grid) -> Vec<(i32, i32)> {
+        (0..WIDTH)
+            .flat_map(|x| (0..HEIGHT).map(move |y| (x, y)))
+            .filter(|&(x, y)| grid[x][y] != 0)
+            .map(|(x, y)| (x as i32, y as i32))
+            .collect()
+    }
+
+    fn glyph_pixels(font: Font, c: char) -> Vec<(i32, i32)> {
+        (0..font.width())
+            .flat_map(|x| (0..font.height()).map(move |y| (x, y)))
+            .filter(|&(x, y)| font.pixel(c, x, y))
+            .map(|(x, y)| (x as i32, y as i32))
+            .collect()
+    }
+
+    #[test]
+    fn pixels_outside_are_clipped() {
+        let mut grid = [[0; HEIGHT]; WIDTH];
+        for &(x, y) in [(-1, 0), (0, -1), (WIDTH as i32, 0), (0, HEIGHT as i32)].iter() {
+            set_pixel(&mut grid, x, y, 0xFF);
+        }
+        assert!(lit(&grid).is_empty());
+        set_pixel(&mut grid, WIDTH as i32 - 1, HEIGHT as i32 - 1, 0x10);
+        assert_eq!(grid[WIDTH - 1][HEIGHT - 1], 0x10);
+    }
+
+    #[test]
+    fn characters_are_drawn_upright_and_rotated() {
+        let font = Font::Small;
+        let glyph = glyph_pixels(font, 'A');
+        assert_eq!(glyph.len(), 10);
+
+        let mut grid = [[0; HEIGHT]; WIDTH];
+        draw_char(&mut grid, font, 'A', 2, 10, 0xFF);
+        let mut expected: Vec<_> = glyph.iter().map(|&(x, y)| (2 + x, 10 + y)).collect();
+        expected.sort_unstable();
+        assert_eq!(lit(&grid), expected);
+
+        // Turned clockwise, the top row of the glyph ends up on the right
+        let mut grid = [[0; HEIGHT]; WIDTH];
+        draw_char_rotated(&mut grid, font, 'A', 2, 10, 0xFF);
+        let height = font.height() as i32;
+        let mut expected: Vec<_> = glyph
+            .iter()
+            .map(|&(x, y)| (2 + height - 1 - y, 10 + x))
+            .collect();
+        expected.sort_unstable();
+        assert_eq!(lit(&grid), expected);
+
+        // Half scrolled out at the top
+        let mut grid = [[0; HEIGHT]; WIDTH];
+        draw_char(&mut grid, font, 'A', 0, -3, 0xFF);
+        let expected: Vec<_> = glyph
+            .iter()
+            .map(|&(x, y)| (x, y - 3))
+            .filter(|&(_, y)| y >= 0)
+            .collect();
+        assert_eq!(lit(&grid), expected);
+    }
+
+    #[test]
+    fn text_advances_one_character_plus_a_gap() {
+        let font = Font::Large;
+        assert_eq!(text_height(font, "HI"), 14);
+        assert_eq!(text_height_rotated(font, "HI"), 12);
+
+        let mut grid = [[0; HEIGHT]; WIDTH];
+        draw_text_vertical(&mut grid, font, "II", 0, 0xFF);
+        let mut first = [[0; HEIGHT]; WIDTH];
+        draw_char(&mut first, font, 'I', 2, 0, 0xFF);
+        let mut second = [[0; HEIGHT]; WIDTH];
+        draw_char(&mut second, font, 'I', 2, 7, 0xFF);
+        let mut expected = lit(&first);
+        expected.extend(lit(&second));
+        expected.sort_unstable();
+        assert_eq!(lit(&grid), expected);
+    }
+
+    #[test]
+    fn percentage_fills_from_the_bottom() {
+        let mut grid = [[0; HEIGHT]; WIDTH];
+        percentage(&mut grid, 0);
+        assert_eq!(grid, [[0; HEIGHT]; WIDTH]);
+        percentage(&mut grid, 100);
+        assert_eq!(grid, [[0xFF; HEIGHT]; WIDTH]);
+        percentage(&mut grid, 200);
+        assert_eq!(grid, [[0xFF; HEIGHT]; WIDTH]);
+
+        // 8.5 rows, the half covered one is dimmed
+        percentage(&mut grid, 25);
+        for column in grid.iter() {
+            assert!(column[..HEIGHT - 9].iter().all(|&led| led == 0));
+            assert_eq!(column[HEIGHT - 9], 0x7F);
+            assert!(column[HEIGHT - 8..].iter().all(|&led| led == 0xFF));
+        }
+    }
+
+    #[test]
+    fn zigzag_bounces_between_the_edges() {
+        let mut grid = [[0; HEIGHT]; WIDTH];
+        zigzag(&mut grid, 0, 0x80);
+        let xs: Vec<usize> = (0..HEIGHT)
+            .map(|y| {
+                let lit: Vec<usize> = (0..WIDTH).filter(|&x| grid[x][y] != 0).collect();
+                assert_eq!(lit.len(), 1, "row {}", y);
+                lit[0]
+            })
+            .collect();
+        assert_eq!(
+            xs[..18],
+            [0, 1, 2, 3, 4, 5, 6, 7, 8, 7, 6, 5, 4, 3, 2, 1, 0, 1]
+        );
+
+        // An offset moves the pattern down
+        let mut moved = [[0; HEIGHT]; WIDTH];
+        zigzag(&mut moved, 3, 0x80);
+        for x in 0..WIDTH {
+            assert_eq!(moved[x][3..], grid[x][..HEIGHT - 3]);
+        }
+    }
+}
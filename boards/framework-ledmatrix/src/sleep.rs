@@ -0,0 +1,245 @@
+//! Sleep handling and bootloader entry
+//!
+//! The EC pulls the SLEEP# line low when the laptop goes to sleep. The LED
+//! Matrix should then fade out and shut the LED controller down via its SDB
+//! pin, so it doesn't draw any current. Early revisions of the hardware don't
+//! have the line wired up, which [SleepPin] detects so that floating pin isn't
+//! mistaken for a sleep signal.
+//!
+//! Call [SleepHandler::poll] at a fixed rate, e.g. once per frame, and scale
+//! every LED value with [SleepHandler::dim] before pushing it to the
+//! controller.
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::{InputPin, OutputPin};
+
+use crate::hal::gpio::bank0::{Gpio0, Gpio25, Gpio29};
+use crate::hal::gpio::{
+    Function, FunctionSioInput, FunctionSioOutput, Pin, PullDown, PullNone, PullType, PullUp,
+};
+
+/// Time to let the pull resistor settle before sampling a pin
+const SETTLE_TIME_US: u32 = 100;
+
+/// Number of samples taken with each pull resistor when probing SLEEP#
+const PROBE_SAMPLES: u8 = 8;
+
+/// Number of consecutive polls the SLEEP# line has to agree on a new state
+const DEBOUNCE_POLLS: u8 = 3;
+
+/// Fade level change per poll
+const FADE_STEP: u8 = 16;
+
+/// The SLEEP# line from the EC
+pub type SleepLine = Pin<Gpio0, FunctionSioInput, PullDown>;
+
+/// The SDB pin of the LED controller, driving it low shuts the controller down
+pub type Sdb = Pin<Gpio29, FunctionSioOutput, PullNone>;
+
+/// DIP switch #1, configured as input
+pub type Dip1 = Pin<Gpio25, FunctionSioInput, PullUp>;
+
+/// The SLEEP# line, if it is connected on this hardware revision
+pub struct SleepPin {
+    pin: SleepLine,
+    present: bool,
+}
+
+impl SleepPin {
+    /// Detect whether the SLEEP# line is connected
+    ///
+    /// The pin is sampled a few times with the pull-up and with the pull-down
+    /// enabled. A floating pin follows the pull resistor, while a connected
+    /// line is driven by the EC and ignores it at least once.
+    pub fn new<F, P>(pin: Pin<Gpio0, F, P>, delay: &mut impl DelayNs) -> Self
+    where
+        F: Function,
+        P: PullType,
+    {
+        let mut present = false;
+
+        let mut pin = pin.into_pull_up_input();
+        for _ in 0..PROBE_SAMPLES {
+            delay.delay_us(SETTLE_TIME_US);
+            present |= pin.is_low().unwrap();
+        }
+
+        let mut pin = pin.into_pull_down_input();
+        for _ in 0..PROBE_SAMPLES {
+            delay.delay_us(SETTLE_TIME_US);
+            present |= pin.is_high().unwrap();
+        }
+
+        #[cfg(feature = "defmt")]
+        defmt::debug!("sleep: SLEEP# line connected: {=bool}", present);
+        SleepPin { pin, present }
+    }
+
+    /// Whether the SLEEP# line is connected
+    pub fn is_present(&self) -> bool {
+        self.present
+    }
+
+    /// Whether the EC currently signals sleep
+    ///
+    /// Always `false` if the line isn't connected.
+    pub fn is_asleep(&mut self) -> bool {
+        self.present && self.pin.is_low().unwrap()
+    }
+
+    /// Release the pin
+    pub fn free(self) -> SleepLine {
+        self.pin
+    }
+}
+
+/// Power state of the LED Matrix
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PowerState {
+    /// Displaying normally
+    Awake,
+    /// Going to sleep, LEDs are being dimmed
+    FadingOut,
+    /// LED controller is shut down
+    Asleep,
+    /// Woken up, LEDs are being brought back to full brightness
+    FadingIn,
+}
+
+/// State changes reported by [SleepHandler::poll]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SleepEvent {
+    /// The EC signalled sleep, fading out has started
+    Sleeping,
+    /// Fading out has finished and the LED controller has been shut down
+    Shutdown,
+    /// The EC signalled wake up, the LED controller has been enabled again
+    ///
+    /// The controller keeps its configuration while shut down, but the
+    /// current frame should be pushed again to start fading in.
+    Waking,
+    /// Fading in has finished
+    Awake,
+}
+
+/// Fades out and shuts down the LED controller while the laptop is asleep
+pub struct SleepHandler {
+    sleep: SleepPin,
+    sdb: Sdb,
+    state: PowerState,
+    debounce: u8,
+    level: u8,
+}
+
+impl SleepHandler {
+    /// Take control of the LED controller's SDB pin and enable the controller
+    pub fn new<F, P>(sleep: SleepPin, sdb: Pin<Gpio29, F, P>) -> Self
+    where
+        F: Function,
+        P: PullType,
+    {
+        let mut sdb: Sdb = sdb.reconfigure();
+        sdb.set_high().unwrap();
+        SleepHandler {
+            sleep,
+            sdb,
+            state: PowerState::Awake,
+            debounce: 0,
+            level: 0xFF,
+        }
+    }
+
+    /// Current power state
+    pub fn state(&self) -> PowerState {
+        self.state
+    }
+
+    /// Current fade level, `0xFF` while awake and `0` while asleep
+    pub fn level(&self) -> u8 {
+        self.level
+    }
+
+    /// Scale an LED value by the current fade level
+    pub fn dim(&self, value: u8) -> u8 {
+        (u16::from(value) * u16::from(self.level) / 0xFF) as u8
+    }
+
+    /// Sample the SLEEP# line and advance any fade in progress
+    ///
+    /// Has to be called at a fixed rate, the fade takes 16 polls. Returns an
+    /// event whenever the power state changes.
+    pub fn poll(&mut self) -> Option<SleepEvent> {
+        let asleep = self.sleep.is_asleep();
+        let going_to_sleep = matches!(self.state, PowerState::FadingOut | PowerState::Asleep);
+
+        if asleep == going_to_sleep {
+            self.debounce = 0;
+        } else {
+            self.debounce += 1;
+            if self.debounce >= DEBOUNCE_POLLS {
+                self.debounce = 0;
+                return Some(self.switch(asleep));
+            }
+        }
+
+        match self.state {
+            PowerState::FadingOut => {
+                self.level = self.level.saturating_sub(FADE_STEP);
+                if self.level == 0 {
+                    self.sdb.set_low().unwrap();
+                    self.state = PowerState::Asleep;
+                    return Some(SleepEvent::Shutdown);
+                }
+            }
+            PowerState::FadingIn => {
+                self.level = self.level.saturating_add(FADE_STEP);
+                if self.level == 0xFF {
+                    self.state = PowerState::Awake;
+                    return Some(SleepEvent::Awake);
+                }
+            }
+            PowerState::Awake | PowerState::Asleep => {}
+        }
+        None
+    }
+
+    fn switch(&mut self, asleep: bool) -> SleepEvent {
+        #[cfg(feature = "defmt")]
+        defmt::debug!("sleep: SLEEP# line changed, asleep: {=bool}", asleep);
+        if asleep {
+            self.state = PowerState::FadingOut;
+            SleepEvent::Sleeping
+        } else {
+            self.sdb.set_high().unwrap();
+            self.state = PowerState::FadingIn;
+            SleepEvent::Waking
+        }
+    }
+
+    /// Release the pins, leaving the LED controller enabled
+    pub fn free(mut self) -> (SleepPin, Sdb) {
+        self.sdb.set_high().unwrap();
+        (self.sleep, self.sdb)
+    }
+}
+
+/// Reboot into the ROM USB bootloader if DIP switch #1 is turned on
+///
+/// Meant to be called right after power-up. The switch connects the pin to
+/// ground when turned on. Returns the configured pin if the switch is off.
+pub fn bootloader_if_dip_set<F, P>(dip1: Pin<Gpio25, F, P>, delay: &mut impl DelayNs) -> Dip1
+where
+    F: Function,
+    P: PullType,
+{
+    let mut dip1 = dip1.into_pull_up_input();
+    delay.delay_us(SETTLE_TIME_US);
+    if dip1.is_low().unwrap() {
+        #[cfg(feature = "defmt")]
+        defmt::info!("DIP switch 1 is on, rebooting into BOOTSEL");
+        crate::hal::rom_data::reset_to_usb_boot(0, 0);
+    }
+    dip1
+}
@@ -0,0 +1,117 @@
+//! # Pico Explorer Embassy Example
+//!
+//! Counts the presses of the four switches of the Pico Explorer on its LCD,
+//! with `embassy-rp` instead of `rp2040-hal`. The switches are waited for
+//! without polling, and the screen is drawn into a framebuffer which is sent
+//! to the LCD by DMA.
+//!
+//! `embassy-rp` brings its own runtime, so build it without the default
+//! features of the BSP:
+//!
+//! ```console
+//! rp-hal-boards/boards/pimoroni-pico-explorer$ cargo run --release --no-default-features --features embassy --example pimoroni_pico_explorer_embassy
+//! ```
+//!
+//! See the `Cargo.toml` file for Copyright and license details.
+
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use core::ptr::addr_of_mut;
+
+use arrayvec::ArrayString;
+use embassy_executor::Spawner;
+use embassy_futures::select::{select4, Either4};
+use embedded_graphics::{
+    mono_font::{ascii::FONT_10X20, MonoTextStyle},
+    pixelcolor::Rgb565,
+    prelude::*,
+    text::{Alignment, Text},
+};
+use panic_halt as _;
+use pimoroni_pico_explorer::board_common::embassy::Framebuffer;
+use pimoroni_pico_explorer::board_common::AsyncUserButton;
+use pimoroni_pico_explorer::embassy;
+
+/// The pixels of the LCD, 2 bytes each
+static mut FRAMEBUFFER: [u8; 240 * 240 * 2] = [0; 240 * 240 * 2];
+
+/// The names of the switches, in the order of the counters
+const NAMES: [&str; 4] = ["A", "B", "X", "Y"];
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    let p = embassy_rp::init(Default::default());
+    let pins = pimoroni_pico_explorer::embassy_pins!(p);
+
+    let mut a = embassy::switch_a(pins.switch_a);
+    let mut b = embassy::switch_b(pins.switch_b);
+    let mut x = embassy::switch_x(pins.switch_x);
+    let mut y = embassy::switch_y(pins.switch_y);
+
+    let mut lcd = embassy::lcd(
+        p.SPI0,
+        embassy::LcdPins {
+            dc: pins.spi_miso,
+            cs: pins.lcd_cs,
+            clk: pins.spi_sclk,
+            mosi: pins.spi_mosi,
+        },
+        p.DMA_CH0,
+    )
+    .await;
+
+    // Note (safety): this is the only reference to the framebuffer
+    let buf = unsafe { &mut *addr_of_mut!(FRAMEBUFFER) };
+    let mut framebuffer = Framebuffer::new(buf, embassy::LCD_SIZE);
+
+    let mut presses = [0u32; 4];
+    loop {
+        draw(&mut framebuffer, &presses);
+        lcd.flush(&framebuffer).await;
+
+        // Sleep until one of the switches is pressed, and count it once it
+        // is released again
+        match select4(
+            a.wait_for_press(),
+            b.wait_for_press(),
+            x.wait_for_press(),
+            y.wait_for_press(),
+        )
+        .await
+        {
+            Either4::First(()) => {
+                a.wait_for_release().await;
+                presses[0] += 1;
+            }
+            Either4::Second(()) => {
+                b.wait_for_release().await;
+                presses[1] += 1;
+            }
+            Either4::Third(()) => {
+                x.wait_for_release().await;
+                presses[2] += 1;
+            }
+            Either4::Fourth(()) => {
+                y.wait_for_release().await;
+                presses[3] += 1;
+            }
+        }
+    }
+}
+
+/// Draws a line with the number of presses of each switch
+fn draw(framebuffer: &mut Framebuffer<'_>, presses: &[u32; 4]) {
+    let style = MonoTextStyle::new(&FONT_10X20, Rgb565::GREEN);
+    // Drawing into the framebuffer can't fail
+    let _ = framebuffer.clear(Rgb565::BLACK);
+    for (row, (name, count)) in NAMES.iter().zip(presses).enumerate() {
+        let mut text = ArrayString::<32>::new();
+        let _ = write!(text, "{}: {} presses", name, count);
+        let position = Point::new(120, 60 + 40 * row as i32);
+        let _ = Text::with_alignment(&text, position, style, Alignment::Center).draw(framebuffer);
+    }
+}
+
+// End of file
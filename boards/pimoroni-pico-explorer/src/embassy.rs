@@ -0,0 +1,122 @@
+//! The pins and drivers of the board on top of `embassy-rp`
+//!
+//! [embassy_pins!](crate::embassy_pins) names the pins of the `embassy_rp`
+//! peripherals like [crate::all_pins::Pins] does, and the functions of this
+//! module set up the buttons and display of the board with the async drivers of
+//! [board_common::embassy]:
+//!
+//! ```ignore
+//! let p = embassy_rp::init(Default::default());
+//! let pins = pimoroni_pico_explorer::embassy_pins!(p);
+//! let mut switch_a = embassy::switch_a(pins.switch_a);
+//! switch_a.wait_for_press().await;
+//! let mut lcd = embassy::lcd(
+//!     p.SPI0,
+//!     embassy::LcdPins {
+//!         dc: pins.spi_miso,
+//!         cs: pins.lcd_cs,
+//!         clk: pins.spi_sclk,
+//!         mosi: pins.spi_mosi,
+//!     },
+//!     p.DMA_CH1,
+//! ).await;
+//! ```
+//!
+//! `embassy-rp` brings its own runtime, critical section and second stage
+//! bootloader, so build the BSP with `default-features = false`.
+//!
+//! Only available with the `embassy` feature.
+
+use embassy_rp::dma;
+use embassy_rp::gpio::{Input, Pull};
+use embassy_rp::peripherals::*;
+use embassy_rp::spi::{self, Spi};
+use embassy_rp::Peripheral;
+use embedded_graphics::prelude::Size;
+
+use board_common::embassy::{dcs, InitStep, SpiDisplay};
+use board_common::ActiveLow;
+
+#[doc(inline)]
+pub use crate::all_pins::EmbassyPins as Pins;
+
+/// Switch A, see [AsyncUserButton](board_common::AsyncUserButton)
+pub type SwitchA = ActiveLow<Input<'static, PIN_12>>;
+
+/// Sets up [SwitchA]
+pub fn switch_a(pin: PIN_12) -> SwitchA {
+    ActiveLow(Input::new(pin, Pull::Up))
+}
+
+/// Switch B, see [AsyncUserButton](board_common::AsyncUserButton)
+pub type SwitchB = ActiveLow<Input<'static, PIN_13>>;
+
+/// Sets up [SwitchB]
+pub fn switch_b(pin: PIN_13) -> SwitchB {
+    ActiveLow(Input::new(pin, Pull::Up))
+}
+
+/// Switch X, see [AsyncUserButton](board_common::AsyncUserButton)
+pub type SwitchX = ActiveLow<Input<'static, PIN_14>>;
+
+/// Sets up [SwitchX]
+pub fn switch_x(pin: PIN_14) -> SwitchX {
+    ActiveLow(Input::new(pin, Pull::Up))
+}
+
+/// Switch Y, see [AsyncUserButton](board_common::AsyncUserButton)
+pub type SwitchY = ActiveLow<Input<'static, PIN_15>>;
+
+/// Sets up [SwitchY]
+pub fn switch_y(pin: PIN_15) -> SwitchY {
+    ActiveLow(Input::new(pin, Pull::Up))
+}
+
+/// The size of the display in pixels
+pub const LCD_SIZE: Size = Size::new(240, 240);
+
+/// SPI clock of the display
+const LCD_SPI_FREQ: u32 = 16_000_000;
+
+/// The start-up of the ST7789 controller, in portrait orientation
+pub const LCD_INIT: &[InitStep] = &[
+    InitStep::new(dcs::SWRESET, &[]).delay(150),
+    InitStep::new(dcs::SLPOUT, &[]).delay(10),
+    InitStep::new(dcs::COLMOD, &[0x55]).delay(10),
+    InitStep::new(dcs::MADCTL, &[0x00]),
+    InitStep::new(dcs::INVON, &[]).delay(10),
+    InitStep::new(dcs::NORON, &[]).delay(10),
+    InitStep::new(dcs::DISPON, &[]).delay(10),
+];
+
+/// The ST7789 display of the board, see [SpiDisplay]
+pub type Lcd<'d> = SpiDisplay<'d, SPI0>;
+
+/// The pins connected to the display
+pub struct LcdPins {
+    /// Data/command select
+    pub dc: PIN_16,
+    /// Chip select
+    pub cs: PIN_17,
+    /// SPI clock
+    pub clk: PIN_18,
+    /// SPI data
+    pub mosi: PIN_19,
+}
+
+/// Sets up SPI0 and the display, fed by `dma`
+///
+/// Draw into a [Framebuffer](board_common::embassy::Framebuffer) of [LCD_SIZE],
+/// and send it with [SpiDisplay::flush].
+pub async fn lcd<'d>(
+    spi: SPI0,
+    pins: LcdPins,
+    dma: impl Peripheral<P = impl dma::Channel> + 'd,
+) -> Lcd<'d> {
+    let mut config = spi::Config::default();
+    config.frequency = LCD_SPI_FREQ;
+    let spi = Spi::new_txonly(spi, pins.clk, pins.mosi, dma, config);
+    let mut lcd = SpiDisplay::new(spi, pins.dc, pins.cs, LCD_SIZE);
+    lcd.init(LCD_INIT).await;
+    lcd
+}
@@ -19,6 +19,14 @@ pub use hal::entry;
 #[used]
 pub static BOOT2_FIRMWARE: [u8; 256] = rp2040_boot2::BOOT_LOADER_W25Q080;
 
+#[cfg(feature = "panic-screen")]
+mod panic_screen;
+
+#[cfg(feature = "embassy")]
+pub mod embassy;
+
+pub use board_common;
+pub use board_common::DummyPin;
 use display_interface_spi::SPIInterface;
 use embedded_graphics::{
     draw_target::DrawTarget,
@@ -27,7 +35,7 @@ use embedded_graphics::{
 use embedded_hal_0_2::{
     adc::{Channel, OneShot},
     blocking::delay::DelayUs,
-    digital::v2::{InputPin, OutputPin},
+    digital::v2::InputPin,
     spi::MODE_0,
 };
 use fugit::RateExtU32;
@@ -48,7 +56,9 @@ use hal::{
 use st7789::ST7789;
 
 pub mod all_pins {
-    hal::bsp_pins!(
+    board_common::bsp_pins!(
+        embassy: crate::embassy::Pins;
+
         Gpio0 { name: gpio0 },
         Gpio1 { name: gpio1 },
         Gpio2 { name: gpio2 },
@@ -137,6 +147,14 @@ pub struct Pins {
 }
 
 pub const XOSC_CRYSTAL_FREQ: u32 = 12_000_000;
+
+// FLASH_SIZE_BYTES and the reserved end of the flash, see build.rs
+include!(concat!(env!("OUT_DIR"), "/flash.rs"));
+
+board_common::board_pins!(all_pins::Pins, XOSC_CRYSTAL_FREQ, activity_led: 25);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Button {
     A,
     B,
@@ -144,11 +162,15 @@ pub enum Button {
     Y,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Motor {
     _1,
     _2,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum MotorAction {
     Forward(f32),
     Reverse(f32),
@@ -173,18 +195,6 @@ pub struct PicoExplorer {
     pub screen: Screen,
 }
 
-pub struct DummyPin;
-
-impl OutputPin for DummyPin {
-    type Error = ();
-    fn set_high(&mut self) -> Result<(), Self::Error> {
-        Ok(())
-    }
-    fn set_low(&mut self) -> Result<(), Self::Error> {
-        Ok(())
-    }
-}
-
 impl PicoExplorer {
     pub fn new(
         io: pac::IO_BANK0,
@@ -196,7 +206,17 @@ impl PicoExplorer {
         delay: &mut impl DelayUs<u32>,
     ) -> (Self, Pins) {
         let internal_pins = all_pins::Pins::new(io, pads, sio, resets);
+        Self::with_pins(internal_pins, spi0, adc, resets, delay)
+    }
 
+    /// Like [PicoExplorer::new], for the pins of a [Board]
+    pub fn with_pins(
+        internal_pins: all_pins::Pins,
+        spi0: SPI0,
+        adc: Adc,
+        resets: &mut RESETS,
+        delay: &mut impl DelayUs<u32>,
+    ) -> (Self, Pins) {
         let a = internal_pins.switch_a.into_pull_up_input();
         let b = internal_pins.switch_b.into_pull_up_input();
         let x = internal_pins.switch_x.into_pull_up_input();
@@ -224,6 +244,8 @@ impl PicoExplorer {
             .set_orientation(st7789::Orientation::Portrait)
             .unwrap();
         screen.clear(Rgb565::BLACK).unwrap();
+        #[cfg(feature = "defmt")]
+        defmt::debug!("explorer: screen ready");
 
         (
             PicoExplorer {
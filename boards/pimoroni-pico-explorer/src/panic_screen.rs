@@ -0,0 +1,43 @@
+//! The panic handler of the `panic-screen` feature
+//!
+//! Shows the panic on the LCD, in white on red, and reboots into the USB
+//! bootloader once A is pressed.
+
+use core::panic::PanicInfo;
+
+use board_common::panic_screen::{self, PanicScreen};
+use board_common::BoardPins;
+use embedded_graphics::pixelcolor::{Rgb565, RgbColor};
+
+use crate::{all_pins, hal, Board, Button, PicoExplorer};
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    if !panic_screen::first_panic() {
+        panic_screen::halt();
+    }
+
+    // SAFETY: the application never runs again
+    let mut board = unsafe { Board::steal() };
+    // Keep the message on the screen
+    board.watchdog.disable();
+
+    let resets = &mut board.peripherals.RESETS;
+    let adc = hal::Adc::new(board.peripherals.ADC, resets);
+    let (mut explorer, _pins) = PicoExplorer::with_pins(
+        board.pins,
+        board.peripherals.SPI0,
+        adc,
+        resets,
+        &mut board.delay,
+    );
+
+    let _ = PanicScreen::new(Rgb565::WHITE, Rgb565::RED)
+        .with_hint("Press A for the USB bootloader")
+        .draw(&mut explorer.screen, info);
+
+    panic_screen::wait_for_bootsel(
+        || explorer.is_pressed(Button::A),
+        all_pins::Pins::ACTIVITY_LED,
+    )
+}
@@ -0,0 +1,57 @@
+//! The pins and drivers of the board on top of `embassy-rp`
+//!
+//! [embassy_pins!](crate::embassy_pins) names the pins of the `embassy_rp`
+//! peripherals like [crate::Pins] does, and the functions of this module set up
+//! the button, LEDs and battery of the board with the async drivers of
+//! [board_common::embassy]:
+//!
+//! ```ignore
+//! let p = embassy_rp::init(Default::default());
+//! let pins = pimoroni_pico_lipo_16mb::embassy_pins!(p);
+//! let mut led = embassy::led(pins.led);
+//! let mut user_sw = embassy::user_sw(pins.user_sw);
+//! user_sw.wait_for_press().await;
+//! led.set(true);
+//! let mut adc = Adc::new(p.ADC, Irqs, adc::Config::default());
+//! let mut battery = embassy::battery(pins.batt_sense);
+//! let millivolts = battery.millivolts(&mut adc).await;
+//! ```
+//!
+//! `embassy-rp` brings its own runtime, critical section and second stage
+//! bootloader, so build the BSP with `default-features = false`.
+//!
+//! Only available with the `embassy` feature.
+
+use embassy_rp::adc::Channel;
+use embassy_rp::gpio::{Input, Level, Output, Pull};
+use embassy_rp::peripherals::*;
+
+use board_common::{ActiveHigh, ActiveLow, VoltageDivider};
+
+#[doc(inline)]
+pub use crate::EmbassyPins as Pins;
+
+/// The user LED of the board, see [StatusLed](board_common::StatusLed)
+pub type Led = ActiveHigh<Output<'static, PIN_25>>;
+
+/// Sets up [Led], switched off
+pub fn led(pin: PIN_25) -> Led {
+    ActiveHigh(Output::new(pin, Level::Low))
+}
+
+/// The BOOT button, which can also be read as user switch, see
+/// [AsyncUserButton](board_common::AsyncUserButton)
+pub type UserSw = ActiveLow<Input<'static, PIN_23>>;
+
+/// Sets up [UserSw]
+pub fn user_sw(pin: PIN_23) -> UserSw {
+    ActiveLow(Input::new(pin, Pull::Up))
+}
+
+/// The battery voltage, behind a divider by 3, read with the async ADC
+pub type Battery = VoltageDivider<Channel<'static>>;
+
+/// Sets up [Battery]
+pub fn battery(pin: PIN_29) -> Battery {
+    VoltageDivider::new(Channel::new_pin(pin, Pull::None), 3)
+}
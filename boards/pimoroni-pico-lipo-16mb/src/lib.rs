@@ -17,7 +17,9 @@ pub static BOOT2_FIRMWARE: [u8; 256] = rp2040_boot2::BOOT_LOADER_W25Q080;
 
 pub use hal::pac;
 
-hal::bsp_pins!(
+board_common::bsp_pins!(
+    embassy: crate::embassy::Pins;
+
     Gpio0 { name: gpio0 },
     Gpio1 { name: gpio1 },
     Gpio2 { name: gpio2 },
@@ -41,13 +43,57 @@ hal::bsp_pins!(
     Gpio20 { name: gpio20 },
     Gpio21 { name: gpio21 },
     Gpio22 { name: gpio22 },
-    Gpio23 { name: user_sw },
+    Gpio23 {
+        name: user_sw,
+        aliases: {
+            /// Input alias for pin [crate::Pins::user_sw].
+            FunctionSioInput, PullUp: UserSwPin
+        }
+    },
     Gpio24 { name: vbus_detect },
-    Gpio25 { name: led },
+    Gpio25 {
+        name: led,
+        aliases: {
+            /// Output alias for pin [crate::Pins::led].
+            FunctionSioOutput, PullNone: LedPin
+        }
+    },
     Gpio26 { name: gpio26 },
     Gpio27 { name: gpio27 },
     Gpio28 { name: gpio28 },
-    Gpio29 { name: batt_sense },
+    Gpio29 {
+        name: batt_sense,
+        aliases: {
+            /// Input alias for pin [crate::Pins::batt_sense].
+            FunctionSioInput, PullNone: BattSensePin
+        }
+    },
 );
 
 pub const XOSC_CRYSTAL_FREQ: u32 = 12_000_000;
+
+// FLASH_SIZE_BYTES and the reserved end of the flash, see build.rs
+include!(concat!(env!("OUT_DIR"), "/flash.rs"));
+
+#[cfg(feature = "embassy")]
+pub mod embassy;
+
+pub use board_common;
+
+board_common::board_pins!(Pins, XOSC_CRYSTAL_FREQ, activity_led: 25);
+
+/// The user LED of the board, see [board_common::StatusLed]
+pub type Led = board_common::ActiveHigh<LedPin>;
+
+/// The BOOT button, which can also be read as user switch, see
+/// [board_common::UserButton]
+pub type UserSw = board_common::ActiveLow<UserSwPin>;
+
+/// The voltage on VSYS, powered by USB or the battery, see
+/// [board_common::BatteryMonitor]
+pub type Battery = board_common::VoltageDivider<hal::adc::AdcPin<BattSensePin>>;
+
+/// Measures VSYS, which is divided by 3 on the board
+pub fn battery(pin: hal::adc::AdcPin<BattSensePin>) -> Battery {
+    board_common::VoltageDivider::new(pin, 3)
+}
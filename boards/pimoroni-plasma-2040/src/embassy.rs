@@ -0,0 +1,44 @@
+//! The pins and drivers of the board on top of `embassy-rp`
+//!
+//! [embassy_pins!](crate::embassy_pins) names the pins of the `embassy_rp`
+//! peripherals like [crate::Pins] does, and the functions of this module set up
+//! the buttons of the board with the async drivers of [board_common::embassy]:
+//!
+//! ```ignore
+//! let p = embassy_rp::init(Default::default());
+//! let pins = pimoroni_plasma_2040::embassy_pins!(p);
+//! let mut button_a = embassy::button_a(pins.button_a);
+//! button_a.wait_for_press().await;
+//! ```
+//!
+//! `embassy-rp` brings its own runtime, critical section and second stage
+//! bootloader, so build the BSP with `default-features = false`.
+//!
+//! The length of the LED strip is up to you, drive it with
+//! [NeoPixel](board_common::embassy::NeoPixel) on [Pins::data].
+//!
+//! Only available with the `embassy` feature.
+
+use embassy_rp::gpio::{Input, Pull};
+use embassy_rp::peripherals::*;
+
+use board_common::ActiveLow;
+
+#[doc(inline)]
+pub use crate::EmbassyPins as Pins;
+
+/// Button A, see [AsyncUserButton](board_common::AsyncUserButton)
+pub type ButtonA = ActiveLow<Input<'static, PIN_12>>;
+
+/// Sets up [ButtonA]
+pub fn button_a(pin: PIN_12) -> ButtonA {
+    ActiveLow(Input::new(pin, Pull::Up))
+}
+
+/// Button B, see [AsyncUserButton](board_common::AsyncUserButton)
+pub type ButtonB = ActiveLow<Input<'static, PIN_13>>;
+
+/// Sets up [ButtonB]
+pub fn button_b(pin: PIN_13) -> ButtonB {
+    ActiveLow(Input::new(pin, Pull::Up))
+}
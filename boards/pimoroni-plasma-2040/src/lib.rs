@@ -15,7 +15,9 @@ pub static BOOT2_FIRMWARE: [u8; 256] = rp2040_boot2::BOOT_LOADER_W25Q080;
 
 pub use hal::pac;
 
-hal::bsp_pins!(
+board_common::bsp_pins!(
+    embassy: crate::embassy::Pins;
+
     /// GPIO 0 is connected to I2C0_SDA
     Gpio0 { name: i2c0_sda },
     /// GPIO 1 is connected to I2C0_SCL
@@ -68,5 +70,15 @@ hal::bsp_pins!(
 
 pub const XOSC_CRYSTAL_FREQ: u32 = 12_000_000;
 
+// FLASH_SIZE_BYTES and the reserved end of the flash, see build.rs
+include!(concat!(env!("OUT_DIR"), "/flash.rs"));
+
+#[cfg(feature = "embassy")]
+pub mod embassy;
+
+pub use board_common;
+
+board_common::board_pins!(Pins, XOSC_CRYSTAL_FREQ);
+
 pub const ADC_GAIN: u32 = 50;
 pub const SHUNT_RESISTOR: f32 = 0.015;
@@ -35,7 +35,9 @@ pub const CURRENT_OFFSET: f32 = -0.02;
 
 pub use hal::pac;
 
-hal::bsp_pins!(
+board_common::bsp_pins!(
+    embassy: crate::embassy::Pins;
+
     /// GPIO 0 supports following functions:
     ///
     /// | Function     | Alias with applied function |
@@ -485,6 +487,8 @@ hal::bsp_pins!(
     Gpio23 {
         name: user_sw,
         aliases: {
+            /// Input alias for pin [crate::Pins::user_sw].
+            FunctionSioInput, PullUp: UserSwPin,
             /// UART Function alias for pin [crate::Pins::user_sw].
             FunctionUart, PullNone: Gp23Uart1Rts,
             /// SPI Function alias for pin [crate::Pins::user_sw].
@@ -601,3 +605,16 @@ hal::bsp_pins!(
 );
 
 pub const XOSC_CRYSTAL_FREQ: u32 = 12_000_000;
+
+// FLASH_SIZE_BYTES and the reserved end of the flash, see build.rs
+include!(concat!(env!("OUT_DIR"), "/flash.rs"));
+
+#[cfg(feature = "embassy")]
+pub mod embassy;
+
+pub use board_common;
+
+board_common::board_pins!(Pins, XOSC_CRYSTAL_FREQ);
+
+/// The user switch, see [board_common::UserButton]
+pub type UserSw = board_common::ActiveLow<UserSwPin>;
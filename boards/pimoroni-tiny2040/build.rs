@@ -1,6 +1,9 @@
-//! This build script makes sure the linker flag -Tdefmt.x is added
-//! for the examples.
+//! Generates the memory layout of the board for the linker, and the
+//! `FLASH_*` constants, see [board_build]
+
+use board_build::{Defmt, MB};
 
 fn main() {
-    println!("cargo:rustc-link-arg-examples=-Tdefmt.x");
+    let flash_size = board_build::flash_size_from_features(8 * MB, &[("flash-2mb", 2 * MB)]);
+    board_build::generate(flash_size, Defmt::Always);
 }
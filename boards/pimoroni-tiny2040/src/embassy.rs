@@ -0,0 +1,61 @@
+//! The pins and drivers of the board on top of `embassy-rp`
+//!
+//! [embassy_pins!](crate::embassy_pins) names the pins of the `embassy_rp`
+//! peripherals like [crate::Pins] does, and the functions of this module set up
+//! the button and LEDs of the board with the async drivers of
+//! [board_common::embassy]:
+//!
+//! ```ignore
+//! let p = embassy_rp::init(Default::default());
+//! let pins = pimoroni_tiny2040::embassy_pins!(p);
+//! let mut led_red = embassy::led_red(pins.led_red);
+//! let mut bootsel = embassy::bootsel(pins.bootsel);
+//! bootsel.wait_for_press().await;
+//! led_red.set(true);
+//! ```
+//!
+//! `embassy-rp` brings its own runtime, critical section and second stage
+//! bootloader, so build the BSP with `default-features = false`.
+//!
+//! Only available with the `embassy` feature.
+
+use embassy_rp::gpio::{Input, Level, Output, Pull};
+use embassy_rp::peripherals::*;
+
+use board_common::ActiveLow;
+
+#[doc(inline)]
+pub use crate::EmbassyPins as Pins;
+
+/// The red part of the RGB LED, see [StatusLed](board_common::StatusLed)
+pub type LedRed = ActiveLow<Output<'static, PIN_18>>;
+
+/// Sets up [LedRed], switched off
+pub fn led_red(pin: PIN_18) -> LedRed {
+    ActiveLow(Output::new(pin, Level::High))
+}
+
+/// The green part of the RGB LED, see [StatusLed](board_common::StatusLed)
+pub type LedGreen = ActiveLow<Output<'static, PIN_19>>;
+
+/// Sets up [LedGreen], switched off
+pub fn led_green(pin: PIN_19) -> LedGreen {
+    ActiveLow(Output::new(pin, Level::High))
+}
+
+/// The blue part of the RGB LED, see [StatusLed](board_common::StatusLed)
+pub type LedBlue = ActiveLow<Output<'static, PIN_20>>;
+
+/// Sets up [LedBlue], switched off
+pub fn led_blue(pin: PIN_20) -> LedBlue {
+    ActiveLow(Output::new(pin, Level::High))
+}
+
+/// The BOOT button, which can also be read as user switch, see
+/// [AsyncUserButton](board_common::AsyncUserButton)
+pub type Bootsel = ActiveLow<Input<'static, PIN_23>>;
+
+/// Sets up [Bootsel]
+pub fn bootsel(pin: PIN_23) -> Bootsel {
+    ActiveLow(Input::new(pin, Pull::Up))
+}
@@ -17,7 +17,9 @@ pub static BOOT2_FIRMWARE: [u8; 256] = rp2040_boot2::BOOT_LOADER_W25Q080;
 
 pub use hal::pac;
 
-hal::bsp_pins!(
+board_common::bsp_pins!(
+    embassy: crate::embassy::Pins;
+
     /// GPIO 0 supports following functions:
     ///
     /// | Function     | Alias with applied function |
@@ -244,22 +246,38 @@ hal::bsp_pins!(
 
     /// GPIO 18 is red LED, active low
     Gpio18 {
-        name: led_red
+        name: led_red,
+        aliases: {
+            /// Output alias for pin [crate::Pins::led_red].
+            FunctionSioOutput, PullNone: LedRedPin
+        }
     },
 
     /// GPIO 19 is green LED, active low
     Gpio19 {
-        name: led_green
+        name: led_green,
+        aliases: {
+            /// Output alias for pin [crate::Pins::led_green].
+            FunctionSioOutput, PullNone: LedGreenPin
+        }
     },
 
     /// GPIO 20 is blue LED, active low
     Gpio20 {
-        name: led_blue
+        name: led_blue,
+        aliases: {
+            /// Output alias for pin [crate::Pins::led_blue].
+            FunctionSioOutput, PullNone: LedBluePin
+        }
     },
 
     /// GPIO 23 is connected to bootsel button, active low
     Gpio23 {
-        name: bootsel
+        name: bootsel,
+        aliases: {
+            /// Input alias for pin [crate::Pins::bootsel].
+            FunctionSioInput, PullUp: BootselPin
+        }
     },
 
     /// GPIO 26 supports following functions:
@@ -376,3 +394,25 @@ hal::bsp_pins!(
 );
 
 pub const XOSC_CRYSTAL_FREQ: u32 = 12_000_000;
+
+// FLASH_SIZE_BYTES and the reserved end of the flash, see build.rs
+include!(concat!(env!("OUT_DIR"), "/flash.rs"));
+
+#[cfg(feature = "embassy")]
+pub mod embassy;
+
+pub use board_common;
+
+board_common::board_pins!(Pins, XOSC_CRYSTAL_FREQ);
+
+/// The red part of the RGB LED, see [board_common::StatusLed]
+pub type LedRed = board_common::ActiveLow<LedRedPin>;
+
+/// The green part of the RGB LED, see [board_common::StatusLed]
+pub type LedGreen = board_common::ActiveLow<LedGreenPin>;
+
+/// The blue part of the RGB LED, see [board_common::StatusLed]
+pub type LedBlue = board_common::ActiveLow<LedBluePin>;
+
+/// The BOOT button, see [board_common::UserButton]
+pub type Bootsel = board_common::ActiveLow<BootselPin>;
@@ -0,0 +1,99 @@
+//! The pins and drivers of the board on top of `embassy-rp`
+//!
+//! [embassy_pins!](crate::embassy_pins) names the pins of the `embassy_rp`
+//! peripherals like [crate::Pins] does, and the functions of this module set up
+//! the buttons, LEDs and battery of the board with the async drivers of
+//! [board_common::embassy]:
+//!
+//! ```ignore
+//! let p = embassy_rp::init(Default::default());
+//! let pins = pimoroni_tufty2040::embassy_pins!(p);
+//! let mut led = embassy::led(pins.led);
+//! let mut sw_a = embassy::sw_a(pins.sw_a);
+//! sw_a.wait_for_press().await;
+//! led.set(true);
+//! let mut adc = Adc::new(p.ADC, Irqs, adc::Config::default());
+//! let mut battery = embassy::battery(pins.vbat_sense);
+//! let millivolts = battery.millivolts(&mut adc).await;
+//! ```
+//!
+//! `embassy-rp` brings its own runtime, critical section and second stage
+//! bootloader, so build the BSP with `default-features = false`.
+//!
+//! The parallel LCD of the board has no async driver yet.
+//!
+//! Only available with the `embassy` feature.
+
+use embassy_rp::adc::Channel;
+use embassy_rp::gpio::{Input, Level, Output, Pull};
+use embassy_rp::peripherals::*;
+
+use board_common::{ActiveHigh, ActiveLow, VoltageDivider};
+
+#[doc(inline)]
+pub use crate::EmbassyPins as Pins;
+
+/// Button A, see [AsyncUserButton](board_common::AsyncUserButton)
+pub type SwA = ActiveHigh<Input<'static, PIN_7>>;
+
+/// Sets up [SwA]
+pub fn sw_a(pin: PIN_7) -> SwA {
+    ActiveHigh(Input::new(pin, Pull::Down))
+}
+
+/// Button B, see [AsyncUserButton](board_common::AsyncUserButton)
+pub type SwB = ActiveHigh<Input<'static, PIN_8>>;
+
+/// Sets up [SwB]
+pub fn sw_b(pin: PIN_8) -> SwB {
+    ActiveHigh(Input::new(pin, Pull::Down))
+}
+
+/// Button C, see [AsyncUserButton](board_common::AsyncUserButton)
+pub type SwC = ActiveHigh<Input<'static, PIN_9>>;
+
+/// Sets up [SwC]
+pub fn sw_c(pin: PIN_9) -> SwC {
+    ActiveHigh(Input::new(pin, Pull::Down))
+}
+
+/// The up button, see [AsyncUserButton](board_common::AsyncUserButton)
+pub type SwUp = ActiveHigh<Input<'static, PIN_22>>;
+
+/// Sets up [SwUp]
+pub fn sw_up(pin: PIN_22) -> SwUp {
+    ActiveHigh(Input::new(pin, Pull::Down))
+}
+
+/// The down button, see [AsyncUserButton](board_common::AsyncUserButton)
+pub type SwDown = ActiveHigh<Input<'static, PIN_6>>;
+
+/// Sets up [SwDown]
+pub fn sw_down(pin: PIN_6) -> SwDown {
+    ActiveHigh(Input::new(pin, Pull::Down))
+}
+
+/// The BOOT button, which can also be read as user switch, see
+/// [AsyncUserButton](board_common::AsyncUserButton)
+pub type UserSw = ActiveLow<Input<'static, PIN_23>>;
+
+/// Sets up [UserSw]
+pub fn user_sw(pin: PIN_23) -> UserSw {
+    ActiveLow(Input::new(pin, Pull::Up))
+}
+
+/// The activity LED, see [StatusLed](board_common::StatusLed)
+pub type Led = ActiveHigh<Output<'static, PIN_25>>;
+
+/// Sets up [Led], switched off
+pub fn led(pin: PIN_25) -> Led {
+    ActiveHigh(Output::new(pin, Level::Low))
+}
+
+/// The battery voltage, behind a divider by 3, read with the async ADC
+pub type Battery = VoltageDivider<Channel<'static>>;
+
+/// Sets up [Battery]
+pub fn battery(pin: PIN_29) -> Battery {
+    VoltageDivider::new(Channel::new_pin(pin, Pull::None), 3)
+}
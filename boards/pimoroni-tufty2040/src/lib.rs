@@ -25,7 +25,9 @@ pub use rp2040_hal::entry;
 #[used]
 pub static BOOT2_FIRMWARE: [u8; 256] = rp2040_boot2::BOOT_LOADER_W25Q080;
 
-hal::bsp_pins!(
+board_common::bsp_pins!(
+    embassy: crate::embassy::Pins;
+
     Gpio0 {
         name: gpio0,
         aliases: {
@@ -56,10 +58,34 @@ hal::bsp_pins!(
             FunctionI2C, PullUp: I2cScl
         }
     },
-    Gpio6 { name: sw_down },
-    Gpio7 { name: sw_a },
-    Gpio8 { name: sw_b },
-    Gpio9 { name: sw_c },
+    Gpio6 {
+        name: sw_down,
+        aliases: {
+            /// Input alias for pin [crate::Pins::sw_down].
+            FunctionSioInput, PullDown: SwDownPin
+        }
+    },
+    Gpio7 {
+        name: sw_a,
+        aliases: {
+            /// Input alias for pin [crate::Pins::sw_a].
+            FunctionSioInput, PullDown: SwAPin
+        }
+    },
+    Gpio8 {
+        name: sw_b,
+        aliases: {
+            /// Input alias for pin [crate::Pins::sw_b].
+            FunctionSioInput, PullDown: SwBPin
+        }
+    },
+    Gpio9 {
+        name: sw_c,
+        aliases: {
+            /// Input alias for pin [crate::Pins::sw_c].
+            FunctionSioInput, PullDown: SwCPin
+        }
+    },
     Gpio10 { name: lcd_cs },
     Gpio11 { name: lcd_dc },
     Gpio12 { name: lcd_wr },
@@ -72,18 +98,86 @@ hal::bsp_pins!(
     Gpio19 { name: lcd_db5 },
     Gpio20 { name: lcd_db6 },
     Gpio21 { name: lcd_db7 },
-    Gpio22 { name: sw_up },
-    Gpio23 { name: user_sw },
+    Gpio22 {
+        name: sw_up,
+        aliases: {
+            /// Input alias for pin [crate::Pins::sw_up].
+            FunctionSioInput, PullDown: SwUpPin
+        }
+    },
+    Gpio23 {
+        name: user_sw,
+        aliases: {
+            /// Input alias for pin [crate::Pins::user_sw].
+            FunctionSioInput, PullUp: UserSwPin
+        }
+    },
     Gpio24 { name: vbus_detect },
-    Gpio25 { name: led },
+    Gpio25 {
+        name: led,
+        aliases: {
+            /// Output alias for pin [crate::Pins::led].
+            FunctionSioOutput, PullNone: LedPin
+        }
+    },
     Gpio26 { name: light_sense },
     Gpio27 { name: sensor_power },
     Gpio28 { name: vref_1v24 },
-    Gpio29 { name: vbat_sense },
+    Gpio29 {
+        name: vbat_sense,
+        aliases: {
+            /// Input alias for pin [crate::Pins::vbat_sense].
+            FunctionSioInput, PullNone: VbatSensePin
+        }
+    },
 );
 
 pub const XOSC_CRYSTAL_FREQ: u32 = 12_000_000;
 
+// FLASH_SIZE_BYTES and the reserved end of the flash, see build.rs
+include!(concat!(env!("OUT_DIR"), "/flash.rs"));
+
+#[cfg(feature = "panic-screen")]
+mod panic_screen;
+
+#[cfg(feature = "embassy")]
+pub mod embassy;
+
+pub use board_common;
+pub use board_common::DummyPin;
+
+board_common::board_pins!(Pins, XOSC_CRYSTAL_FREQ, activity_led: 25);
+
+/// Button A, see [board_common::UserButton]
+pub type SwA = board_common::ActiveHigh<SwAPin>;
+
+/// Button B, see [board_common::UserButton]
+pub type SwB = board_common::ActiveHigh<SwBPin>;
+
+/// Button C, see [board_common::UserButton]
+pub type SwC = board_common::ActiveHigh<SwCPin>;
+
+/// The up button, see [board_common::UserButton]
+pub type SwUp = board_common::ActiveHigh<SwUpPin>;
+
+/// The down button, see [board_common::UserButton]
+pub type SwDown = board_common::ActiveHigh<SwDownPin>;
+
+/// The BOOT button, which can also be read as user switch, see
+/// [board_common::UserButton]
+pub type UserSw = board_common::ActiveLow<UserSwPin>;
+
+/// The activity LED, see [board_common::StatusLed]
+pub type Led = board_common::ActiveHigh<LedPin>;
+
+/// The battery voltage, see [board_common::BatteryMonitor]
+pub type Battery = board_common::VoltageDivider<hal::adc::AdcPin<VbatSensePin>>;
+
+/// Measures the battery, which is divided by 3 on the board
+pub fn battery(pin: hal::adc::AdcPin<VbatSensePin>) -> Battery {
+    board_common::VoltageDivider::new(pin, 3)
+}
+
 #[inline]
 fn set_pin_bit<P: OutputPin>(pin: &mut P, bit: u8, value: u8) -> Result<(), DisplayError> {
     pin.set_state(((bit & value) != 0).into())
@@ -274,6 +368,13 @@ impl<P: PIOExt, SM: StateMachineIndex, CH: ChannelIndex> PioDataLines<P, SM, CH>
         ]);
         sm.set_pins([(wr, PinState::High)]);
         sm.start();
+        #[cfg(feature = "defmt")]
+        defmt::debug!(
+            "display: PIO data lines on GPIO{=u8}..=GPIO{=u8}, clock divider {=u32}",
+            d0,
+            d0 + 7,
+            divider
+        );
 
         PioDataLines { tx: Some((tx, ch)) }
     }
@@ -343,17 +444,3 @@ impl<CS: OutputPin, DC: OutputPin, D: DisplayDataLines> WriteOnlyDataCommand
         err
     }
 }
-
-pub struct DummyPin;
-
-impl OutputPin for DummyPin {
-    type Error = ();
-
-    fn set_high(&mut self) -> Result<(), Self::Error> {
-        Ok(())
-    }
-
-    fn set_low(&mut self) -> Result<(), Self::Error> {
-        Ok(())
-    }
-}
@@ -0,0 +1,61 @@
+//! The panic handler of the `panic-screen` feature
+//!
+//! Shows the panic on the LCD, in white on red, and reboots into the USB
+//! bootloader once the BOOT button is pressed. The display is driven through
+//! the GPIOs, so the PIO and DMA the application used are left alone.
+
+use core::panic::PanicInfo;
+
+use board_common::panic_screen::{self, PanicScreen};
+use board_common::{BoardPins, UserButton};
+use embedded_graphics::pixelcolor::{Rgb565, RgbColor};
+use embedded_hal::digital::PinState;
+use st7789::{Orientation, ST7789};
+
+use crate::{Board, DummyPin, GpioDataLines, ParallelDisplayInterface, Pins, UserSw};
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    if !panic_screen::first_panic() {
+        panic_screen::halt();
+    }
+
+    // SAFETY: the application never runs again
+    let mut board = unsafe { Board::steal() };
+    // Keep the message on the screen
+    board.watchdog.disable();
+
+    let pins = board.pins;
+    pins.lcd_backlight
+        .into_push_pull_output_in_state(PinState::High);
+    pins.lcd_rd.into_push_pull_output_in_state(PinState::High);
+
+    let data_lines = GpioDataLines {
+        wr: pins.lcd_wr.into_push_pull_output_in_state(PinState::High),
+        d0: pins.lcd_db0.into_push_pull_output(),
+        d1: pins.lcd_db1.into_push_pull_output(),
+        d2: pins.lcd_db2.into_push_pull_output(),
+        d3: pins.lcd_db3.into_push_pull_output(),
+        d4: pins.lcd_db4.into_push_pull_output(),
+        d5: pins.lcd_db5.into_push_pull_output(),
+        d6: pins.lcd_db6.into_push_pull_output(),
+        d7: pins.lcd_db7.into_push_pull_output(),
+    };
+    let display_interface = ParallelDisplayInterface::new(
+        pins.lcd_cs.into_push_pull_output_in_state(PinState::High),
+        pins.lcd_dc.into_push_pull_output_in_state(PinState::High),
+        data_lines,
+    );
+
+    let mut display = ST7789::new(display_interface, DummyPin, 320, 240);
+    if display.init(&mut board.delay).is_ok()
+        && display.set_orientation(Orientation::Landscape).is_ok()
+    {
+        let _ = PanicScreen::new(Rgb565::WHITE, Rgb565::RED)
+            .with_hint("Press BOOT for the USB bootloader")
+            .draw(&mut display, info);
+    }
+
+    let mut boot = UserSw::new(pins.user_sw.reconfigure());
+    panic_screen::wait_for_bootsel(|| boot.is_pressed(), Pins::ACTIVITY_LED)
+}
@@ -15,7 +15,9 @@ pub use rp2040_hal::entry;
 #[used]
 pub static BOOT2_FIRMWARE: [u8; 256] = rp2040_boot2::BOOT_LOADER_W25Q080;
 
-hal::bsp_pins!(
+board_common::bsp_pins!(
+    embassy: crate::embassy::Pins;
+
     Gpio0 {
         name: gpio0,
         aliases: {
@@ -46,11 +48,41 @@ hal::bsp_pins!(
         }
     },
     Gpio10 { name: p3v3_en },
-    Gpio11 { name: sw_down },
-    Gpio12 { name: sw_a },
-    Gpio13 { name: sw_b },
-    Gpio14 { name: sw_c },
-    Gpio15 { name: sw_up },
+    Gpio11 {
+        name: sw_down,
+        aliases: {
+            /// Input alias for pin [crate::Pins::sw_down].
+            FunctionSioInput, PullDown: SwDownPin
+        }
+    },
+    Gpio12 {
+        name: sw_a,
+        aliases: {
+            /// Input alias for pin [crate::Pins::sw_a].
+            FunctionSioInput, PullDown: SwAPin
+        }
+    },
+    Gpio13 {
+        name: sw_b,
+        aliases: {
+            /// Input alias for pin [crate::Pins::sw_b].
+            FunctionSioInput, PullDown: SwBPin
+        }
+    },
+    Gpio14 {
+        name: sw_c,
+        aliases: {
+            /// Input alias for pin [crate::Pins::sw_c].
+            FunctionSioInput, PullDown: SwCPin
+        }
+    },
+    Gpio15 {
+        name: sw_up,
+        aliases: {
+            /// Input alias for pin [crate::Pins::sw_up].
+            FunctionSioInput, PullDown: SwUpPin
+        }
+    },
     Gpio16 {
         name: miso,
         aliases: {
@@ -81,16 +113,77 @@ hal::bsp_pins!(
     },
     Gpio20 { name: inky_dc },
     Gpio21 { name: inky_res },
-    Gpio23 { name: user_sw },
+    Gpio23 {
+        name: user_sw,
+        aliases: {
+            /// Input alias for pin [crate::Pins::user_sw].
+            FunctionSioInput, PullUp: UserSwPin
+        }
+    },
     /// GPIO 24 is connected to vbus_detect of the badger2040.
     Gpio24 { name: vbus_detect },
     /// GPIO 25 is connected to activity LED of the badger2040.
-    Gpio25 { name: led },
+    Gpio25 {
+        name: led,
+        aliases: {
+            /// Output alias for pin [crate::Pins::led].
+            FunctionSioOutput, PullNone: LedPin
+        }
+    },
     Gpio26 { name: inky_busy },
     Gpio27 { name: vref_power },
     Gpio28 { name: vref_1v24 },
     /// GPIO 29 is connected to battery monitor of the badger2040
-    Gpio29 { name: vbat_sense },
+    Gpio29 {
+        name: vbat_sense,
+        aliases: {
+            /// Input alias for pin [crate::Pins::vbat_sense].
+            FunctionSioInput, PullNone: VbatSensePin
+        }
+    },
 );
 
 pub const XOSC_CRYSTAL_FREQ: u32 = 12_000_000;
+
+// FLASH_SIZE_BYTES and the reserved end of the flash, see build.rs
+include!(concat!(env!("OUT_DIR"), "/flash.rs"));
+
+#[cfg(feature = "panic-screen")]
+mod panic_screen;
+
+#[cfg(feature = "embassy")]
+pub mod embassy;
+
+pub use board_common;
+
+board_common::board_pins!(Pins, XOSC_CRYSTAL_FREQ, activity_led: 25);
+
+/// Button A, see [board_common::UserButton]
+pub type SwA = board_common::ActiveHigh<SwAPin>;
+
+/// Button B, see [board_common::UserButton]
+pub type SwB = board_common::ActiveHigh<SwBPin>;
+
+/// Button C, see [board_common::UserButton]
+pub type SwC = board_common::ActiveHigh<SwCPin>;
+
+/// The up button, see [board_common::UserButton]
+pub type SwUp = board_common::ActiveHigh<SwUpPin>;
+
+/// The down button, see [board_common::UserButton]
+pub type SwDown = board_common::ActiveHigh<SwDownPin>;
+
+/// The BOOT button, which can also be read as user switch, see
+/// [board_common::UserButton]
+pub type UserSw = board_common::ActiveLow<UserSwPin>;
+
+/// The activity LED, see [board_common::StatusLed]
+pub type Led = board_common::ActiveHigh<LedPin>;
+
+/// The battery voltage, see [board_common::BatteryMonitor]
+pub type Battery = board_common::VoltageDivider<hal::adc::AdcPin<VbatSensePin>>;
+
+/// Measures the battery, which is divided by 3 on the board
+pub fn battery(pin: hal::adc::AdcPin<VbatSensePin>) -> Battery {
+    board_common::VoltageDivider::new(pin, 3)
+}
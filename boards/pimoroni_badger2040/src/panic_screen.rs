@@ -0,0 +1,204 @@
+//! The panic handler of the `panic-screen` feature
+//!
+//! Shows the panic on the e-paper display, with a full refresh, and reboots
+//! into the USB bootloader once the BOOT button is pressed. The message stays
+//! on the display after a reset, or when the battery runs out.
+//!
+//! The display is driven by an UC8151 controller on SPI0. Only what is needed
+//! to show a single image is implemented here.
+
+use core::convert::Infallible;
+use core::panic::PanicInfo;
+
+use board_common::panic_screen::{self, PanicScreen};
+use board_common::{BoardPins, UserButton};
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::*;
+use embedded_hal::digital::{InputPin, OutputPin, PinState};
+use embedded_hal::spi::SpiBus;
+use fugit::RateExtU32;
+
+use crate::hal::clocks::Clock;
+use crate::hal::{self, Timer};
+use crate::{Board, Mosi, Pins, Sclk, UserSw};
+
+/// Width of the display in pixels, in landscape orientation
+const WIDTH: u32 = 296;
+
+/// Height of the display in pixels, in landscape orientation
+const HEIGHT: u32 = 128;
+
+/// Bytes of the image, one bit per pixel
+const BUFFER_LEN: usize = (WIDTH * HEIGHT / 8) as usize;
+
+/// Longest wait for the display, a full refresh takes about 4 seconds
+const BUSY_TIMEOUT_MS: u64 = 10_000;
+
+// Commands of the UC8151
+const PSR: u8 = 0x00;
+const PWR: u8 = 0x01;
+const POF: u8 = 0x02;
+const PFS: u8 = 0x03;
+const PON: u8 = 0x04;
+const BTST: u8 = 0x06;
+const DSP: u8 = 0x11;
+const DRF: u8 = 0x12;
+const DTM2: u8 = 0x13;
+const PLL: u8 = 0x30;
+const TSE: u8 = 0x41;
+const CDI: u8 = 0x50;
+const TCON: u8 = 0x60;
+
+/// The image, in the pixel order of the controller: each byte is a part of
+/// a column, and a set bit is white
+struct Framebuffer([u8; BUFFER_LEN]);
+
+impl OriginDimensions for Framebuffer {
+    fn size(&self) -> Size {
+        Size::new(WIDTH, HEIGHT)
+    }
+}
+
+impl DrawTarget for Framebuffer {
+    type Color = BinaryColor;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            if !(0..WIDTH as i32).contains(&point.x) || !(0..HEIGHT as i32).contains(&point.y) {
+                continue;
+            }
+            let (x, y) = (point.x as usize, point.y as usize);
+            let index = x * (HEIGHT as usize / 8) + y / 8;
+            let bit = 0x80 >> (y % 8);
+            // On is ink, so black
+            match color {
+                BinaryColor::On => self.0[index] &= !bit,
+                BinaryColor::Off => self.0[index] |= bit,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The pins and bus of the display
+struct Uc8151<SPI, CS, DC, BUSY> {
+    spi: SPI,
+    cs: CS,
+    dc: DC,
+    busy: BUSY,
+}
+
+impl<SPI, CS, DC, BUSY> Uc8151<SPI, CS, DC, BUSY>
+where
+    SPI: SpiBus,
+    CS: OutputPin,
+    DC: OutputPin,
+    BUSY: InputPin,
+{
+    fn command(&mut self, command: u8, data: &[u8]) {
+        // Neither the GPIOs nor the SPI bus of the RP2040 can fail
+        self.cs.set_low().ok();
+        self.dc.set_low().ok();
+        self.spi.write(&[command]).ok();
+        self.spi.flush().ok();
+        if !data.is_empty() {
+            self.dc.set_high().ok();
+            self.spi.write(data).ok();
+            self.spi.flush().ok();
+        }
+        self.cs.set_high().ok();
+    }
+
+    /// Waits while the busy pin is low
+    fn wait(&mut self, timer: &Timer) {
+        let start = timer.get_counter();
+        while self.busy.is_low().unwrap_or(false)
+            && (timer.get_counter() - start).to_millis() < BUSY_TIMEOUT_MS
+        {}
+    }
+
+    /// Sets up the controller for black and white, with the waveforms in its
+    /// OTP memory
+    fn init(&mut self, timer: &Timer) {
+        // 128x296, black and white, scanning up and shifting right, booster
+        // on, no soft reset
+        self.command(PSR, &[0x9f]);
+        // Internal voltages, +-16V
+        self.command(PWR, &[0x03, 0x00, 0x2b, 0x2b, 0x2b]);
+        self.command(PON, &[]);
+        self.wait(timer);
+        // Soft start of the booster
+        self.command(BTST, &[0x17, 0x17, 0x17]);
+        self.command(PFS, &[0x00]);
+        // Internal temperature sensor
+        self.command(TSE, &[0x00]);
+        self.command(TCON, &[0x22]);
+        self.command(CDI, &[0x4c]);
+        // 100 Hz frame rate
+        self.command(PLL, &[0x3a]);
+    }
+
+    /// Sends the image and refreshes the whole display
+    fn show(&mut self, image: &Framebuffer, timer: &Timer) {
+        self.command(PON, &[]);
+        self.wait(timer);
+        self.command(DTM2, &image.0);
+        self.command(DSP, &[]);
+        self.command(DRF, &[]);
+        self.wait(timer);
+        self.command(POF, &[]);
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    if !panic_screen::first_panic() {
+        panic_screen::halt();
+    }
+
+    // SAFETY: the application never runs again
+    let mut board = unsafe { Board::steal() };
+    // Don't reset in the middle of the refresh
+    board.watchdog.disable();
+
+    let pins = board.pins;
+    // Keep the power on while running from the battery
+    pins.p3v3_en.into_push_pull_output_in_state(PinState::High);
+
+    let mosi: Mosi = pins.mosi.reconfigure();
+    let sclk: Sclk = pins.sclk.reconfigure();
+    let spi = hal::Spi::<_, _, _, 8>::new(board.peripherals.SPI0, (mosi, sclk)).init(
+        &mut board.peripherals.RESETS,
+        board.clocks.peripheral_clock.freq(),
+        12.MHz(),
+        embedded_hal::spi::MODE_0,
+    );
+    let mut display = Uc8151 {
+        spi,
+        cs: pins
+            .inky_cs_gpio
+            .into_push_pull_output_in_state(PinState::High),
+        dc: pins.inky_dc.into_push_pull_output(),
+        busy: pins.inky_busy.into_pull_up_input(),
+    };
+
+    let mut reset = pins.inky_res.into_push_pull_output_in_state(PinState::Low);
+    board.delay.delay_ms(10);
+    reset.set_high().ok();
+    board.delay.delay_ms(10);
+    display.wait(&board.timer);
+
+    let mut image = Framebuffer([0xff; BUFFER_LEN]);
+    let _ = PanicScreen::new(BinaryColor::On, BinaryColor::Off)
+        .with_hint("Press BOOT for the USB bootloader")
+        .draw(&mut image, info);
+    display.init(&board.timer);
+    display.show(&image, &board.timer);
+
+    let mut boot = UserSw::new(pins.user_sw.reconfigure());
+    panic_screen::wait_for_bootsel(|| boot.is_pressed(), Pins::ACTIVITY_LED)
+}
@@ -0,0 +1,99 @@
+//! # Pico Crash Log Example
+//!
+//! Creates a USB Serial device on a Pico board, which panics when it receives
+//! a `p`, and tells the host why after the reboot.
+//!
+//! Instead of `panic_halt`, the panic handler keeps a report of the panic in
+//! RAM and reboots the Pico through the watchdog. On the next start, the
+//! report is printed whenever the serial port is opened:
+//!
+//! ```text
+//! Crash before the last reset:
+//! panicked at boards/rp-pico/examples/pico_crash_log.rs:88:21:
+//! You asked for it
+//! PC 0x10001a3d  SP 0x2003ff10
+//! 2003ff10: ...
+//! ```
+//!
+//! See the `Cargo.toml` file for Copyright and license details.
+
+#![no_std]
+#![no_main]
+
+use core::panic::PanicInfo;
+
+// The macro for our start-up function
+use rp_pico::entry;
+
+// A shorter alias for the Hardware Abstraction Layer, which provides
+// higher-level drivers.
+use rp_pico::hal;
+
+// The crash log shared by all boards
+use rp_pico::board_common::crash::{self, CrashReporter};
+
+// USB Device support
+use usb_device::{class_prelude::*, prelude::*};
+
+// USB Communications Class Device support
+use usbd_serial::SerialPort;
+
+/// Keeps a report of the panic, and reboots
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    crash::log_panic(info)
+}
+
+/// Entry point to our bare-metal application.
+///
+/// The `#[entry]` macro ensures the Cortex-M start-up code calls this function
+/// as soon as all global variables are initialised.
+///
+/// The function sets up USB Serial, prints the report of the last crash, if
+/// any, and panics on request.
+#[entry]
+fn main() -> ! {
+    let mut board = rp_pico::Board::take().unwrap();
+
+    // Read the report before anything can panic again
+    let mut reporter = crash::last_crash().map(CrashReporter::new);
+
+    // Set up the USB driver
+    let usb_bus = UsbBusAllocator::new(hal::usb::UsbBus::new(
+        board.peripherals.USBCTRL_REGS,
+        board.peripherals.USBCTRL_DPRAM,
+        board.clocks.usb_clock,
+        true,
+        &mut board.peripherals.RESETS,
+    ));
+
+    // Set up the USB Communications Class Device driver
+    let mut serial = SerialPort::new(&usb_bus);
+
+    // Create a USB device with a fake VID and PID
+    let mut usb_dev = UsbDeviceBuilder::new(&usb_bus, UsbVidPid(0x16c0, 0x27dd))
+        .strings(&[StringDescriptors::default()
+            .manufacturer("Fake company")
+            .product("Serial port")
+            .serial_number("TEST")])
+        .unwrap()
+        .device_class(2) // from: https://www.usb.org/defined-class-codes
+        .build();
+
+    loop {
+        if usb_dev.poll(&mut [&mut serial]) {
+            let mut buf = [0u8; 64];
+            if let Ok(count) = serial.read(&mut buf) {
+                if buf[..count].contains(&b'p') {
+                    panic!("You asked for it");
+                }
+            }
+        }
+
+        if let Some(reporter) = &mut reporter {
+            reporter.poll(&mut serial);
+        }
+    }
+}
+
+// End of file
@@ -0,0 +1,45 @@
+//! # Pico Overclock Example
+//!
+//! Blinks the LED on a Pico board, with the system clock at 200 MHz.
+//!
+//! Instead of setting up the clocks, pins and delay one by one, this example
+//! lets `Board::take_with` do it. The LED blinks at the same rate as in
+//! `pico_blinky`, as the delay follows the faster clock.
+//!
+//! See the `Cargo.toml` file for Copyright and license details.
+
+#![no_std]
+#![no_main]
+
+// The macro for our start-up function
+use rp_pico::entry;
+
+// Ensure we halt the program on panic (if we don't mention this crate it won't
+// be linked)
+use panic_halt as _;
+
+// The traits and presets shared by all boards
+use rp_pico::board_common::{ActiveHigh, ClockPreset, StatusLed};
+
+/// Entry point to our bare-metal application.
+///
+/// The `#[entry]` macro ensures the Cortex-M start-up code calls this function
+/// as soon as all global variables are initialised.
+///
+/// The function sets up the board, then blinks the LED in an infinite loop.
+#[entry]
+fn main() -> ! {
+    // Take the peripherals, raise the core voltage and run at 200 MHz
+    let mut board = rp_pico::Board::take_with(ClockPreset::Overclock200).unwrap();
+
+    // Set the LED to be an output
+    let mut led: rp_pico::Led = ActiveHigh::new(board.pins.led.reconfigure());
+
+    // Blink the LED at 1 Hz
+    loop {
+        led.toggle();
+        board.delay.delay_ms(500);
+    }
+}
+
+// End of file
@@ -0,0 +1,223 @@
+//! # Pico RTIC Dual Core Example
+//!
+//! Runs RTIC 2 on core 0 of a Pico board, and hands the heavy work to core 1
+//! through the inter-core FIFO.
+//!
+//! - An async task polls the USB Serial device every millisecond, echoes
+//!   what the host sends and prints the results of core 1.
+//! - An async task debounces a push button attached to GPIO 15, like in
+//!   `pico_gpio_in_out`. Each press sends a job to core 1: counting the
+//!   primes below a limit, which grows with every press.
+//! - Core 1 loops outside of RTIC, reading the jobs from the FIFO and writing
+//!   back the results. They wake up a hardware task on core 0, which forwards
+//!   them to the USB task through a channel.
+//!
+//! Needs the `rtic` feature of the BSP:
+//!
+//! ```console
+//! rp-hal-boards/boards/rp-pico$ cargo run --release --features rtic --example pico_rtic_dual_core
+//! ```
+//!
+//! See the `Cargo.toml` file for Copyright and license details.
+
+#![no_std]
+#![no_main]
+
+use panic_halt as _;
+
+use rp_pico::hal::{self, pac, Sio};
+
+// The interrupt handler of the monotonic
+rp_pico::board_common::rtic_monotonic!();
+
+/// The stack of core 1
+static mut CORE1_STACK: hal::multicore::Stack<4096> = hal::multicore::Stack::new();
+
+#[rtic::app(device = rp_pico::hal::pac, peripherals = true, dispatchers = [I2C0_IRQ])]
+mod app {
+
+    use core::fmt::Write;
+    use core::ptr::addr_of_mut;
+
+    use rp_pico::{
+        board_common::{
+            rtic::{Duration, Mono},
+            ActiveLow, ClockPreset, UserButton,
+        },
+        hal::{
+            self,
+            gpio::{bank0::Gpio15, FunctionSioInput, Pin, PullUp},
+            multicore::Multicore,
+            sio::SioFifo,
+        },
+    };
+    use rtic_sync::{
+        channel::{Receiver, Sender},
+        make_channel,
+    };
+    use usb_device::{class_prelude::*, prelude::*};
+    use usbd_serial::SerialPort;
+
+    /// How many results can wait for the USB task
+    const RESULTS: usize = 4;
+
+    /// How often the USB device is polled
+    const USB_POLL: Duration = Duration::millis(1);
+
+    /// How long the button has to stay pressed or released
+    const DEBOUNCE: Duration = Duration::millis(10);
+
+    #[shared]
+    struct Shared {
+        fifo: SioFifo,
+    }
+
+    #[local]
+    struct Local {
+        usb_dev: UsbDevice<'static, hal::usb::UsbBus>,
+        serial: SerialPort<'static, hal::usb::UsbBus>,
+        results: Receiver<'static, (u32, u32), RESULTS>,
+        finished: Sender<'static, (u32, u32), RESULTS>,
+        button: ActiveLow<Pin<Gpio15, FunctionSioInput, PullUp>>,
+    }
+
+    #[init(local = [usb_bus: Option<UsbBusAllocator<hal::usb::UsbBus>> = None])]
+    fn init(c: init::Context) -> (Shared, Local) {
+        // Soft-reset does not release the hardware spinlocks
+        // Release them now to avoid a deadlock after debug or watchdog reset
+        unsafe {
+            hal::sio::spinlock_reset();
+        }
+        let mut board = rp_pico::Board::from_peripherals(c.device, c.core, ClockPreset::Standard);
+        Mono::start(board.timer.alarm_0().unwrap());
+
+        // Start core 1, which waits for its first job
+        let mut mc = Multicore::new(
+            &mut board.peripherals.PSM,
+            &mut board.peripherals.PPB,
+            &mut board.fifo,
+        );
+        let cores = mc.cores();
+        // Note (safety): only core 1 uses its stack
+        let stack = unsafe { &mut *addr_of_mut!(crate::CORE1_STACK.mem) };
+        cores[1].spawn(stack, || crate::core1_task()).unwrap();
+
+        // Set up the USB driver
+        let usb_bus = UsbBusAllocator::new(hal::usb::UsbBus::new(
+            board.peripherals.USBCTRL_REGS,
+            board.peripherals.USBCTRL_DPRAM,
+            board.clocks.usb_clock,
+            true,
+            &mut board.peripherals.RESETS,
+        ));
+        let usb_bus: &'static _ = c.local.usb_bus.insert(usb_bus);
+        // Set up the USB Communications Class Device driver
+        let serial = SerialPort::new(usb_bus);
+        // Create a USB device with a fake VID and PID
+        let usb_dev = UsbDeviceBuilder::new(usb_bus, UsbVidPid(0x16c0, 0x27dd))
+            .strings(&[StringDescriptors::default()
+                .manufacturer("Fake company")
+                .product("Serial port")
+                .serial_number("TEST")])
+            .unwrap()
+            .device_class(2) // from: https://www.usb.org/defined-class-codes
+            .build();
+
+        let button = ActiveLow::new(board.pins.gpio15.into_pull_up_input());
+        let (finished, results) = make_channel!((u32, u32), RESULTS);
+
+        usb::spawn().unwrap();
+        button::spawn().unwrap();
+
+        (
+            Shared { fifo: board.fifo },
+            Local {
+                usb_dev,
+                serial,
+                results,
+                finished,
+                button,
+            },
+        )
+    }
+
+    #[task(local = [usb_dev, serial, results])]
+    async fn usb(c: usb::Context) {
+        let serial = c.local.serial;
+        loop {
+            if c.local.usb_dev.poll(&mut [serial]) {
+                let mut buf = [0u8; 64];
+                if let Ok(count) = serial.read(&mut buf) {
+                    let _ = serial.write(&buf[..count]);
+                }
+            }
+
+            while let Ok((limit, count)) = c.local.results.try_recv() {
+                let mut text = heapless::String::<64>::new();
+                let _ = write!(text, "{} primes below {}\r\n", count, limit);
+                let _ = serial.write(text.as_bytes());
+            }
+
+            Mono::delay(USB_POLL).await;
+        }
+    }
+
+    #[task(local = [button], shared = [fifo])]
+    async fn button(mut c: button::Context) {
+        let mut jobs = 0;
+        loop {
+            // A press counts once the button stays down for DEBOUNCE
+            while !c.local.button.is_pressed() {
+                Mono::delay(DEBOUNCE).await;
+            }
+            Mono::delay(DEBOUNCE).await;
+            if !c.local.button.is_pressed() {
+                continue;
+            }
+
+            jobs += 1;
+            let limit = 10_000 * jobs;
+            c.shared.fifo.lock(|fifo| {
+                // Drop the job rather than wait while core 1 is behind
+                if fifo.is_write_ready() {
+                    fifo.write(limit);
+                }
+            });
+
+            while c.local.button.is_pressed() {
+                Mono::delay(DEBOUNCE).await;
+            }
+        }
+    }
+
+    #[task(binds = SIO_IRQ_PROC0, priority = 2, local = [finished], shared = [fifo])]
+    fn sio_irq(mut c: sio_irq::Context) {
+        c.shared.fifo.lock(|fifo| {
+            while let Some(limit) = fifo.read() {
+                // Core 1 writes the count right after the limit
+                let count = fifo.read_blocking();
+                let _ = c.local.finished.try_send((limit, count));
+            }
+        });
+    }
+}
+
+/// The loop of core 1, counting primes for core 0
+fn core1_task() -> ! {
+    // Note (safety): core 1 only uses its side of the FIFO
+    let pac = unsafe { pac::Peripherals::steal() };
+    let mut sio = Sio::new(pac.SIO);
+    loop {
+        let limit = sio.fifo.read_blocking();
+        let count = count_primes(limit);
+        sio.fifo.write_blocking(limit);
+        sio.fifo.write_blocking(count);
+    }
+}
+
+/// The number of primes below `limit`, the slow way
+fn count_primes(limit: u32) -> u32 {
+    (2..limit)
+        .filter(|&n| (2..).take_while(|d| d * d <= n).all(|d| n % d != 0))
+        .count() as u32
+}
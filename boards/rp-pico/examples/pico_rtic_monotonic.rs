@@ -1,93 +1,67 @@
+//! # Pico RTIC Monotonic Example
+//!
+//! Blinks the LED on a Pico board from an async RTIC 2 task, which sleeps on
+//! the monotonic of the board between the toggles.
+//!
+//! Needs the `rtic` feature of the BSP:
+//!
+//! ```console
+//! rp-hal-boards/boards/rp-pico$ cargo run --release --features rtic --example pico_rtic_monotonic
+//! ```
+//!
+//! See the `Cargo.toml` file for Copyright and license details.
+
 #![no_std]
 #![no_main]
 
 use panic_halt as _;
 
+// The interrupt handler of the monotonic
+rp_pico::board_common::rtic_monotonic!();
+
 #[rtic::app(device = rp_pico::hal::pac, peripherals = true, dispatchers = [I2C0_IRQ])]
 mod app {
 
-    use embedded_hal::digital::OutputPin;
-    use fugit::ExtU64;
     use rp_pico::{
-        hal::{
-            self,
-            clocks::init_clocks_and_plls,
-            timer::{monotonic::Monotonic, Alarm0},
-            watchdog::Watchdog,
-            Sio,
+        board_common::{
+            rtic::{ExtU64, Mono},
+            ActiveHigh, ClockPreset, StatusLed,
         },
-        XOSC_CRYSTAL_FREQ,
+        hal,
     };
 
     #[shared]
-    struct Shared {
-        led: hal::gpio::Pin<
-            hal::gpio::bank0::Gpio25,
-            hal::gpio::FunctionSioOutput,
-            hal::gpio::PullNone,
-        >,
-    }
-
-    #[monotonic(binds = TIMER_IRQ_0, default = true)]
-    type MyMono = Monotonic<Alarm0>;
+    struct Shared {}
 
     #[local]
-    struct Local {}
+    struct Local {
+        led: rp_pico::Led,
+    }
 
     #[init]
-    fn init(c: init::Context) -> (Shared, Local, init::Monotonics) {
+    fn init(c: init::Context) -> (Shared, Local) {
         // Soft-reset does not release the hardware spinlocks
         // Release them now to avoid a deadlock after debug or watchdog reset
         unsafe {
             hal::sio::spinlock_reset();
         }
-        let mut resets = c.device.RESETS;
-        let mut watchdog = Watchdog::new(c.device.WATCHDOG);
-        let clocks = init_clocks_and_plls(
-            XOSC_CRYSTAL_FREQ,
-            c.device.XOSC,
-            c.device.CLOCKS,
-            c.device.PLL_SYS,
-            c.device.PLL_USB,
-            &mut resets,
-            &mut watchdog,
-        )
-        .ok()
-        .unwrap();
-
-        let sio = Sio::new(c.device.SIO);
-        let pins = rp_pico::Pins::new(
-            c.device.IO_BANK0,
-            c.device.PADS_BANK0,
-            sio.gpio_bank0,
-            &mut resets,
-        );
-        let mut led = pins.led.reconfigure();
-        led.set_low().unwrap();
+        let mut board = rp_pico::Board::from_peripherals(c.device, c.core, ClockPreset::Standard);
+        Mono::start(board.timer.alarm_0().unwrap());
 
-        let mut timer = hal::Timer::new(c.device.TIMER, &mut resets, &clocks);
-        let alarm = timer.alarm_0().unwrap();
-        blink_led::spawn_after(500.millis()).unwrap();
+        let led = ActiveHigh::new(board.pins.led.reconfigure());
+        blink_led::spawn().unwrap();
 
-        (
-            Shared { led },
-            Local {},
-            init::Monotonics(Monotonic::new(timer, alarm)),
-        )
+        (Shared {}, Local { led })
     }
 
-    #[task(
-        shared = [led],
-        local = [tog: bool = true],
-    )]
-    fn blink_led(mut c: blink_led::Context) {
-        if *c.local.tog {
-            c.shared.led.lock(|l| l.set_high().unwrap());
-        } else {
-            c.shared.led.lock(|l| l.set_low().unwrap());
+    #[task(local = [led])]
+    async fn blink_led(c: blink_led::Context) {
+        // Toggle every 500 ms, without drifting by the time spent toggling
+        let mut next = Mono::now();
+        loop {
+            c.local.led.toggle();
+            next += 500.millis();
+            Mono::delay_until(next).await;
         }
-        *c.local.tog = !*c.local.tog;
-
-        blink_led::spawn_after(500.millis()).unwrap();
     }
 }
@@ -0,0 +1,72 @@
+//! # Pico Settings Example
+//!
+//! Counts how often the Pico was started, in a key-value store in the flash,
+//! and blinks the LED that many times.
+//!
+//! The store needs the end of the flash to itself, so build the example with
+//! some of the flash reserved:
+//!
+//! ```sh
+//! FLASH_RESERVED_BYTES=16K cargo run --release --example pico_settings
+//! ```
+//!
+//! See the `Cargo.toml` file for Copyright and license details.
+
+#![no_std]
+#![no_main]
+
+// The macro for our start-up function
+use rp_pico::entry;
+
+// Ensure we halt the program on panic (if we don't mention this crate it won't
+// be linked)
+use panic_halt as _;
+
+// The traits, the flash and the store shared by all boards
+use rp_pico::board_common::{ActiveHigh, Core1, Flash, KvStore, StatusLed};
+
+/// Key of the number of starts in the store
+const BOOTS: &[u8] = b"boots";
+
+/// Entry point to our bare-metal application.
+///
+/// The `#[entry]` macro ensures the Cortex-M start-up code calls this function
+/// as soon as all global variables are initialised.
+///
+/// The function counts the start in the store, then blinks the LED once for
+/// every start so far, and repeats that every few seconds.
+#[entry]
+fn main() -> ! {
+    let mut board = rp_pico::Board::take().unwrap();
+    let mut led: rp_pico::Led = ActiveHigh::new(board.pins.led.reconfigure());
+
+    // Core 1 isn't started, so nothing else runs from the flash while the
+    // store writes to it
+    let flash = Flash::new(rp_pico::FLASH_SIZE_BYTES, Core1::Stopped);
+    let mut settings = KvStore::mount(
+        flash,
+        rp_pico::FLASH_RESERVED_OFFSET,
+        rp_pico::FLASH_RESERVED_BYTES,
+    )
+    .unwrap();
+
+    // The count is missing on the first start
+    let mut buf = [0; 4];
+    let boots = match settings.get(BOOTS, &mut buf).unwrap() {
+        Some(&[a, b, c, d]) => u32::from_le_bytes([a, b, c, d]) + 1,
+        _ => 1,
+    };
+    settings.set(BOOTS, &boots.to_le_bytes()).unwrap();
+
+    loop {
+        for _ in 0..boots {
+            led.on();
+            board.delay.delay_ms(200);
+            led.off();
+            board.delay.delay_ms(200);
+        }
+        board.delay.delay_ms(2000);
+    }
+}
+
+// End of file
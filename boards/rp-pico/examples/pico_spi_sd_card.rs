@@ -49,7 +49,9 @@
 //!
 //! If there are 4 different error patterns, all with short blinking pulses:
 //!
-//! - **3 short blink (in a loop)**: Card size could not be retrieved.
+//! - **3 short blink (until a card responds)**: Card size could not be retrieved,
+//!   e.g. because no card is inserted. The card is initialised again after
+//!   every attempt, so a card can be inserted at any time.
 //! - **4 short blink (in a loop)**: Error getting volume/partition 0.
 //! - **5 short blink (in a loop)**: Error opening root directory.
 //! - **6 short blink (in a loop)**: Could not open file 'O.TST'.
@@ -230,11 +232,18 @@ fn main() -> ! {
     blink_signals(&mut led_pin, &mut delay, &BLINK_OK_LONG);
 
     info!("Init SD card controller and retrieve card size...");
-    match volume_mgr.device().num_bytes() {
-        Ok(size) => info!("card size is {} bytes", size),
-        Err(e) => {
-            error!("Error retrieving card size: {}", defmt::Debug2Format(&e));
-            blink_signals_loop(&mut led_pin, &mut delay, &BLINK_ERR_3_SHORT);
+    // There is no card detect pin, so wait until a card responds
+    loop {
+        match volume_mgr.device().num_bytes() {
+            Ok(size) => {
+                info!("card size is {} bytes", size);
+                break;
+            }
+            Err(e) => {
+                error!("Error retrieving card size: {}", defmt::Debug2Format(&e));
+                volume_mgr.device().mark_card_uninit();
+                blink_signals(&mut led_pin, &mut delay, &BLINK_ERR_3_SHORT);
+            }
         }
     }
 
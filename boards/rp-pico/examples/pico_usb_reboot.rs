@@ -0,0 +1,90 @@
+//! # Pico USB Reboot Example
+//!
+//! Creates a USB Serial device on a Pico board, which reboots into the USB
+//! bootloader when the host "touches" it at 1200 baud, like an Arduino.
+//!
+//! Anything received is echoed back. Opening the port at 1200 baud and closing
+//! it again, for example with
+//!
+//! ```sh
+//! stty -F /dev/ttyACM0 1200
+//! ```
+//!
+//! on Linux, reboots the Pico into BOOTSEL mode with the green LED blinking
+//! while the bootloader is busy, ready for the next `cargo run`.
+//!
+//! See the `Cargo.toml` file for Copyright and license details.
+
+#![no_std]
+#![no_main]
+
+// The macro for our start-up function
+use rp_pico::entry;
+
+// Ensure we halt the program on panic (if we don't mention this crate it won't
+// be linked)
+use panic_halt as _;
+
+// A shorter alias for the Hardware Abstraction Layer, which provides
+// higher-level drivers.
+use rp_pico::hal;
+
+// The 1200 baud touch shared by all boards
+use rp_pico::board_common::BaudTouch;
+
+// USB Device support
+use usb_device::{class_prelude::*, prelude::*};
+
+// USB Communications Class Device support
+use usbd_serial::SerialPort;
+
+/// Entry point to our bare-metal application.
+///
+/// The `#[entry]` macro ensures the Cortex-M start-up code calls this function
+/// as soon as all global variables are initialised.
+///
+/// The function sets up USB Serial, then echoes any characters received until
+/// the host asks for a reboot.
+#[entry]
+fn main() -> ! {
+    let mut board = rp_pico::Board::take().unwrap();
+
+    // Set up the USB driver
+    let usb_bus = UsbBusAllocator::new(hal::usb::UsbBus::new(
+        board.peripherals.USBCTRL_REGS,
+        board.peripherals.USBCTRL_DPRAM,
+        board.clocks.usb_clock,
+        true,
+        &mut board.peripherals.RESETS,
+    ));
+
+    // Set up the USB Communications Class Device driver
+    let mut serial = SerialPort::new(&usb_bus);
+
+    // Create a USB device with a fake VID and PID
+    let mut usb_dev = UsbDeviceBuilder::new(&usb_bus, UsbVidPid(0x16c0, 0x27dd))
+        .strings(&[StringDescriptors::default()
+            .manufacturer("Fake company")
+            .product("Serial port")
+            .serial_number("TEST")])
+        .unwrap()
+        .device_class(2) // from: https://www.usb.org/defined-class-codes
+        .build();
+
+    // Reboot with the green LED of the Pico as activity LED
+    let touch = BaudTouch::for_board::<rp_pico::Pins>();
+
+    loop {
+        if usb_dev.poll(&mut [&mut serial]) {
+            let mut buf = [0u8; 64];
+            if let Ok(count) = serial.read(&mut buf) {
+                // Send back to the host, dropping what doesn't fit
+                let _ = serial.write(&buf[..count]);
+            }
+        }
+
+        touch.poll(&serial);
+    }
+}
+
+// End of file
@@ -67,7 +67,9 @@ pub static BOOT2_FIRMWARE: [u8; 256] = rp2040_boot2::BOOT_LOADER_W25Q080;
 
 pub use hal::pac;
 
-hal::bsp_pins!(
+board_common::bsp_pins!(
+    embassy: crate::embassy::Pins;
+
     /// GPIO 0 supports following functions:
     ///
     /// | Function     | Alias with applied function |
@@ -725,6 +727,10 @@ hal::bsp_pins!(
     /// GPIO 25 is connected to led of the Raspberry Pi Pico board.
     Gpio25 {
         name: led,
+        aliases: {
+            /// Output alias for pin [crate::Pins::led].
+            FunctionSioOutput, PullNone: LedPin
+        }
     },
 
     /// GPIO 26 supports following functions:
@@ -814,7 +820,32 @@ hal::bsp_pins!(
     /// GPIO 29 is connected to voltage_monitor of the Raspberry Pi Pico board.
     Gpio29 {
         name: voltage_monitor,
+        aliases: {
+            /// Input alias for pin [crate::Pins::voltage_monitor].
+            FunctionSioInput, PullNone: VoltageMonitorPin
+        }
     },
 );
 
 pub const XOSC_CRYSTAL_FREQ: u32 = 12_000_000;
+
+// FLASH_SIZE_BYTES and the reserved end of the flash, see build.rs
+include!(concat!(env!("OUT_DIR"), "/flash.rs"));
+
+#[cfg(feature = "embassy")]
+pub mod embassy;
+
+pub use board_common;
+
+board_common::board_pins!(Pins, XOSC_CRYSTAL_FREQ, activity_led: 25);
+
+/// The green LED of the board, see [board_common::StatusLed]
+pub type Led = board_common::ActiveHigh<LedPin>;
+
+/// The voltage on VSYS, see [board_common::BatteryMonitor]
+pub type Battery = board_common::VoltageDivider<hal::adc::AdcPin<VoltageMonitorPin>>;
+
+/// Measures VSYS, which is divided by 3 on the board
+pub fn battery(pin: hal::adc::AdcPin<VoltageMonitorPin>) -> Battery {
+    board_common::VoltageDivider::new(pin, 3)
+}
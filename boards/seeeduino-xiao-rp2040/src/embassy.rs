@@ -0,0 +1,82 @@
+//! The pins and drivers of the board on top of `embassy-rp`
+//!
+//! [embassy_pins!](crate::embassy_pins) names the pins of the `embassy_rp`
+//! peripherals like [crate::Pins] does, and the functions of this module set up
+//! the LEDs and NeoPixel of the board with the async drivers of
+//! [board_common::embassy]:
+//!
+//! ```ignore
+//! let p = embassy_rp::init(Default::default());
+//! let pins = seeeduino_xiao_rp2040::embassy_pins!(p);
+//! let mut led_green = embassy::led_green(pins.led_green);
+//! led_green.set(true);
+//! let Pio { mut common, sm0, .. } = Pio::new(p.PIO0, Irqs);
+//! let mut neopixel = embassy::neopixel(
+//!     &mut common,
+//!     sm0,
+//!     p.DMA_CH0,
+//!     pins.neopixel_data,
+//!     pins.neopixel_power,
+//! );
+//! neopixel.write(&[RGB8::new(0, 0, 32); embassy::NEOPIXEL_COUNT]).await;
+//! ```
+//!
+//! `embassy-rp` brings its own runtime, critical section and second stage
+//! bootloader, so build the BSP with `default-features = false`.
+//!
+//! Only available with the `embassy` feature.
+
+use embassy_rp::dma;
+use embassy_rp::gpio::{Level, Output};
+use embassy_rp::peripherals::*;
+use embassy_rp::pio::{Common, Instance, StateMachine};
+use embassy_rp::Peripheral;
+
+use board_common::embassy;
+use board_common::ActiveLow;
+
+#[doc(inline)]
+pub use crate::EmbassyPins as Pins;
+
+/// The green LED of the board, see [StatusLed](board_common::StatusLed)
+pub type LedGreen = ActiveLow<Output<'static, PIN_16>>;
+
+/// Sets up [LedGreen], switched off
+pub fn led_green(pin: PIN_16) -> LedGreen {
+    ActiveLow(Output::new(pin, Level::High))
+}
+
+/// The red LED of the board, see [StatusLed](board_common::StatusLed)
+pub type LedRed = ActiveLow<Output<'static, PIN_17>>;
+
+/// Sets up [LedRed], switched off
+pub fn led_red(pin: PIN_17) -> LedRed {
+    ActiveLow(Output::new(pin, Level::High))
+}
+
+/// The blue LED of the board, see [StatusLed](board_common::StatusLed)
+pub type LedBlue = ActiveLow<Output<'static, PIN_25>>;
+
+/// Sets up [LedBlue], switched off
+pub fn led_blue(pin: PIN_25) -> LedBlue {
+    ActiveLow(Output::new(pin, Level::High))
+}
+
+/// The number of NeoPixels on the board
+pub const NEOPIXEL_COUNT: usize = 1;
+
+/// The NeoPixel of the board, driven by the PIO state machine `S`
+pub type NeoPixel<'d, P, const S: usize> = embassy::NeoPixel<'d, P, S, NEOPIXEL_COUNT>;
+
+/// Sets up [NeoPixel] on `sm` of the PIO of `common`, fed by `dma`
+///
+/// The power of the LED is switched on with `power`.
+pub fn neopixel<'d, P: Instance, const S: usize>(
+    common: &mut Common<'d, P>,
+    sm: StateMachine<'d, P, S>,
+    dma: impl Peripheral<P = impl dma::Channel> + 'd,
+    data: PIN_12,
+    power: PIN_11,
+) -> NeoPixel<'d, P, S> {
+    NeoPixel::new(common, sm, dma, data).with_power(power)
+}
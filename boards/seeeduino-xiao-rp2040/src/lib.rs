@@ -17,7 +17,9 @@ pub static BOOT2_FIRMWARE: [u8; 256] = rp2040_boot2::BOOT_LOADER_W25Q080;
 
 pub use hal::pac;
 
-hal::bsp_pins!(
+board_common::bsp_pins!(
+    embassy: crate::embassy::Pins;
+
     Gpio0 {
         name: tx,
         aliases: { FunctionUart, PullNone: UartTx }
@@ -50,14 +52,26 @@ hal::bsp_pins!(
     Gpio12 { name: neopixel_data },
     Gpio16 {
         name: led_green,
+        aliases: {
+            /// Output alias for pin [crate::Pins::led_green].
+            FunctionSioOutput, PullNone: LedGreenPin
+        }
         aliases: { FunctionPwm, PullNone: LedGreenPwm }
     },
     Gpio17 {
         name: led_red,
+        aliases: {
+            /// Output alias for pin [crate::Pins::led_red].
+            FunctionSioOutput, PullNone: LedRedPin
+        }
         aliases: { FunctionPwm, PullNone: LedRedPwm }
     },
     Gpio25 {
         name: led_blue,
+        aliases: {
+            /// Output alias for pin [crate::Pins::led_blue].
+            FunctionSioOutput, PullNone: LedBluePin
+        }
         aliases: { FunctionPwm, PullNone: LedBluePwm }
     },
     Gpio26 { name: a0 },
@@ -67,3 +81,22 @@ hal::bsp_pins!(
 );
 
 pub const XOSC_CRYSTAL_FREQ: u32 = 12_000_000;
+
+// FLASH_SIZE_BYTES and the reserved end of the flash, see build.rs
+include!(concat!(env!("OUT_DIR"), "/flash.rs"));
+
+#[cfg(feature = "embassy")]
+pub mod embassy;
+
+pub use board_common;
+
+board_common::board_pins!(Pins, XOSC_CRYSTAL_FREQ);
+
+/// The green part of the RGB LED, see [board_common::StatusLed]
+pub type LedGreen = board_common::ActiveLow<LedGreenPin>;
+
+/// The red part of the RGB LED, see [board_common::StatusLed]
+pub type LedRed = board_common::ActiveLow<LedRedPin>;
+
+/// The blue part of the RGB LED, see [board_common::StatusLed]
+pub type LedBlue = board_common::ActiveLow<LedBluePin>;
@@ -37,6 +37,7 @@ use bsp::hal;
 use hal::pac;
 
 // Pull in any important traits
+use bsp::board_common::BatteryMonitor;
 use hal::prelude::*;
 
 /// Entry point to our bare-metal application.
@@ -88,13 +89,12 @@ fn main() -> ! {
     );
 
     let mut adc = hal::Adc::new(pac.ADC, &mut pac.RESETS);
-    let mut battery_voltage = bsp::BatteryVoltage::new(
-        hal::adc::AdcPin::new(pins.batt_vin.into_floating_input()).unwrap(),
-    );
+    let mut battery_voltage =
+        bsp::battery_voltage(hal::adc::AdcPin::new(pins.batt_vin.into_floating_input()).unwrap());
 
     // Print battery voltage once per second
     loop {
-        println!("Battery: {} mV", battery_voltage.read(&mut adc));
+        println!("Battery: {} mV", battery_voltage.millivolts(&mut adc));
         delay.delay_ms(1000);
     }
 }
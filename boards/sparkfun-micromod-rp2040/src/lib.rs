@@ -68,7 +68,9 @@ pub static BOOT2_FIRMWARE: [u8; 256] = rp2040_boot2::BOOT_LOADER_W25Q080;
 use hal::gpio::{bank0::Gpio29, FunctionSioInput, PullNone};
 pub use hal::pac;
 
-hal::bsp_pins!(
+board_common::bsp_pins!(
+    embassy: crate::embassy::Pins;
+
     /// GPIO 0 can serve as:
     /// - `UART_TX1`
     Gpio0 {
@@ -336,6 +338,8 @@ hal::bsp_pins!(
     Gpio25 {
         name: led,
         aliases: {
+            /// Output alias for pin [crate::Pins::led].
+            FunctionSioOutput, PullNone: LedPin,
             FunctionPwm, PullNone: G10Pwm,
             FunctionPio0, PullNone: G10Pio0,
             FunctionPio1, PullNone: G10Pio1
@@ -370,45 +374,26 @@ hal::bsp_pins!(
 
 pub const XOSC_CRYSTAL_FREQ: u32 = 12_000_000;
 
-/// Alias for a configured pin
-pub type BattVin = hal::adc::AdcPin<hal::gpio::Pin<Gpio29, FunctionSioInput, PullNone>>;
-/// Driver for reading the battery volatage
-pub struct BatteryVoltage {
-    pin: BattVin,
-}
+// FLASH_SIZE_BYTES and the reserved end of the flash, see build.rs
+include!(concat!(env!("OUT_DIR"), "/flash.rs"));
 
-impl BatteryVoltage {
-    /// Creates a new battery voltage reader
-    pub fn new(pin: BattVin) -> Self {
-        Self { pin }
-    }
+#[cfg(feature = "embassy")]
+pub mod embassy;
 
-    /// Reads the current battery voltage
-    ///
-    /// # Return
-    ///
-    /// The current voltage in millivolts
-    pub fn read(&mut self, adc: &mut hal::Adc) -> u16 {
-        use embedded_hal_0_2::adc::OneShot;
+pub use board_common;
 
-        let raw_value: u32 = loop {
-            match adc.read(&mut self.pin) {
-                Ok(val) => break val,
-                Err(nb::Error::WouldBlock) => (),
-                Err(nb::Error::Other(_)) => unreachable!(),
-            }
-        };
+board_common::board_pins!(Pins, XOSC_CRYSTAL_FREQ, activity_led: 25);
+
+/// The blue status LED of the board, see [board_common::StatusLed]
+pub type Led = board_common::ActiveHigh<LedPin>;
+
+/// Alias for a configured pin
+pub type BattVin = hal::adc::AdcPin<hal::gpio::Pin<Gpio29, FunctionSioInput, PullNone>>;
 
-        // Convert value to millivolts
-        // The raw ADC value is in in the range of 0..4096,
-        // where 0 = 0V and 4096 = 3.3V.
-        // The MicroMod interface defines that the voltage is divided by 3,
-        // so the conversion formula:
-        // value / 4096 * 3300(mV) * 3
-        let value = (raw_value * 3300 * 3) / 4096;
+/// The battery voltage, see [board_common::BatteryMonitor]
+pub type BatteryVoltage = board_common::VoltageDivider<BattVin>;
 
-        // The maximum possible value is 9900, so it's safe to convert
-        // back to u16.
-        value as u16
-    }
+/// Measures the battery voltage, which the MicroMod interface divides by 3
+pub fn battery_voltage(pin: BattVin) -> BatteryVoltage {
+    board_common::VoltageDivider::new(pin, 3)
 }
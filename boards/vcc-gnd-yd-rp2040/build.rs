@@ -0,0 +1,12 @@
+//! Generates the memory layout of the board for the linker, and the
+//! `FLASH_*` constants, see [board_build]
+
+use board_build::{Defmt, MB};
+
+fn main() {
+    let flash_size = board_build::flash_size_from_features(
+        4 * MB,
+        &[("flash-8mb", 8 * MB), ("flash-16mb", 16 * MB)],
+    );
+    board_build::generate(flash_size, Defmt::WithFeature);
+}
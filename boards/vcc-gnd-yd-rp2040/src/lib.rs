@@ -17,7 +17,9 @@ pub static BOOT2_FIRMWARE: [u8; 256] = rp2040_boot2::BOOT_LOADER_W25Q080;
 
 pub use hal::pac;
 
-hal::bsp_pins!(
+board_common::bsp_pins!(
+    embassy: crate::embassy::Pins;
+
     /// GPIO 0 supports following functions:
     ///
     /// | Function     | Alias with applied function |
@@ -670,11 +672,19 @@ hal::bsp_pins!(
     /// GPIO 24 is connected to the user key of the YD-RP2040 board.
     Gpio24 {
         name: user_key,
+        aliases: {
+            /// Input alias for pin [crate::Pins::user_key].
+            FunctionSioInput, PullUp: UserKeyPin
+        }
     },
 
     /// GPIO 25 is connected to the blue LED of the YD-RP2040 board.
     Gpio25 {
         name: led,
+        aliases: {
+            /// Output alias for pin [crate::Pins::led].
+            FunctionSioOutput, PullNone: LedPin
+        }
     },
 
     /// GPIO 26 supports following functions:
@@ -782,3 +792,19 @@ hal::bsp_pins!(
 );
 
 pub const XOSC_CRYSTAL_FREQ: u32 = 12_000_000;
+
+// FLASH_SIZE_BYTES and the reserved end of the flash, see build.rs
+include!(concat!(env!("OUT_DIR"), "/flash.rs"));
+
+#[cfg(feature = "embassy")]
+pub mod embassy;
+
+pub use board_common;
+
+board_common::board_pins!(Pins, XOSC_CRYSTAL_FREQ, activity_led: 25);
+
+/// The blue LED of the board, see [board_common::StatusLed]
+pub type Led = board_common::ActiveHigh<LedPin>;
+
+/// The user key, see [board_common::UserButton]
+pub type UserKey = board_common::ActiveLow<UserKeyPin>;
@@ -8,19 +8,18 @@
 
 use cortex_m::delay::Delay;
 use embedded_graphics::primitives::Line;
-use fugit::RateExtU32;
 use panic_halt as _;
 
 use waveshare_rp2040_lcd_0_96::entry;
 use waveshare_rp2040_lcd_0_96::{
     hal::{
-        self,
         clocks::{init_clocks_and_plls, Clock},
         pac,
-        pio::PIOExt,
+        pwm::Slices,
         watchdog::Watchdog,
         Sio,
     },
+    lcd::{self, LcdPins, LCD_HEIGHT, LCD_WIDTH},
     Pins, XOSC_CRYSTAL_FREQ,
 };
 
@@ -29,10 +28,7 @@ use embedded_graphics::{
     prelude::*,
     primitives::{PrimitiveStyle, PrimitiveStyleBuilder, Rectangle},
 };
-use st7735_lcd::{Orientation, ST7735};
-
-const LCD_WIDTH: u32 = 160;
-const LCD_HEIGHT: u32 = 80;
+use st7735_lcd::Orientation;
 
 #[entry]
 fn main() -> ! {
@@ -65,40 +61,26 @@ fn main() -> ! {
     let sys_freq = clocks.system_clock.freq().to_Hz();
     let mut delay = Delay::new(core.SYST, sys_freq);
 
-    let (mut _pio, _sm0, _, _, _) = pac.PIO0.split(&mut pac.RESETS);
-
     // https://www.waveshare.com/wiki/RP2040-LCD-0.96
-    // ST7735S LCD
-    let lcd_dc = pins.gp8.into_push_pull_output();
-    let lcd_clk = pins.gp10.into_function::<hal::gpio::FunctionSpi>();
-    let lcd_mosi = pins.gp11.into_function::<hal::gpio::FunctionSpi>();
-    let lcd_rst = pins
-        .gp12
-        .into_push_pull_output_in_state(hal::gpio::PinState::High);
-    let mut _lcd_bl = pins
-        .gp25
-        .into_push_pull_output_in_state(hal::gpio::PinState::High);
-
-    let spi = hal::Spi::<_, _, _, 8>::new(pac.SPI1, (lcd_mosi, lcd_clk));
-
-    // Exchange the uninitialised SPI driver for an initialised one
-    let spi = spi.init(
+    // ST7735S LCD, with its backlight dimmed by PWM
+    let pwm_slices = Slices::new(pac.PWM, &mut pac.RESETS);
+    let (mut display, mut backlight) = lcd::init(
+        pac.SPI1,
+        LcdPins {
+            dc: pins.lcd_dc.reconfigure(),
+            cs: pins.lcd_cs.reconfigure(),
+            clk: pins.lcd_clk.reconfigure(),
+            din: pins.lcd_din.reconfigure(),
+            rst: pins.lcd_rst.reconfigure(),
+            bl: pins.lcd_bl.reconfigure(),
+        },
+        pwm_slices.pwm4,
         &mut pac.RESETS,
-        clocks.peripheral_clock.freq(),
-        10.MHz(),
-        embedded_hal::spi::MODE_0,
+        &clocks.peripheral_clock,
+        &mut delay,
+        Orientation::Landscape,
     );
-
-    // LCD is a 65K IPS LCD 160x80, color order is BGR and a offset 1,26 pixel.
-    // LCD controller can correct this by settings the order bit (bit 3) in MADCTL register.
-    // Also the colours are inverted, LCD controller can also correct this by writing to INVON register with no paramters.
-    // All this is handled by the ST7735 crate.
-    let mut display = ST7735::new(spi, lcd_dc, lcd_rst, false, true, LCD_WIDTH, LCD_HEIGHT);
-
-    display.init(&mut delay).unwrap();
-    display.set_orientation(&Orientation::Landscape).unwrap();
-
-    display.set_offset(1, 26);
+    backlight.set_brightness(50);
 
     let lcd_zero = Point::zero();
     let lcd_max_corner = Point::new((LCD_WIDTH - 1) as i32, (LCD_HEIGHT - 1) as i32);
@@ -0,0 +1,133 @@
+//! The pins and drivers of the board on top of `embassy-rp`
+//!
+//! [embassy_pins!](crate::embassy_pins) names the pins of the `embassy_rp`
+//! peripherals like [crate::Pins] does, and the functions of this module set up
+//! the NeoPixel and display of the board with the async drivers of
+//! [board_common::embassy]:
+//!
+//! ```ignore
+//! let p = embassy_rp::init(Default::default());
+//! let pins = waveshare_rp2040_lcd_0_96::embassy_pins!(p);
+//! let Pio { mut common, sm0, .. } = Pio::new(p.PIO0, Irqs);
+//! let mut neopixel = embassy::neopixel(
+//!     &mut common,
+//!     sm0,
+//!     p.DMA_CH0,
+//!     pins.neopixel,
+//! );
+//! neopixel.write(&[RGB8::new(0, 0, 32); embassy::NEOPIXEL_COUNT]).await;
+//! let mut lcd = embassy::lcd(
+//!     p.SPI1,
+//!     embassy::LcdPins {
+//!         dc: pins.lcd_dc,
+//!         cs: pins.lcd_cs,
+//!         clk: pins.lcd_clk,
+//!         din: pins.lcd_din,
+//!         rst: pins.lcd_rst,
+//!         bl: pins.lcd_bl,
+//!     },
+//!     p.DMA_CH1,
+//! ).await;
+//! ```
+//!
+//! `embassy-rp` brings its own runtime, critical section and second stage
+//! bootloader, so build the BSP with `default-features = false`.
+//!
+//! Only available with the `embassy` feature.
+
+use embassy_rp::dma;
+use embassy_rp::peripherals::*;
+use embassy_rp::pio::{Common, Instance, StateMachine};
+use embassy_rp::spi::{self, Spi};
+use embassy_rp::Peripheral;
+use embedded_graphics::prelude::{Point, Size};
+
+use board_common::embassy::{self, dcs, InitStep, SpiDisplay};
+
+#[doc(inline)]
+pub use crate::EmbassyPins as Pins;
+
+/// The number of NeoPixels on the board
+pub const NEOPIXEL_COUNT: usize = 1;
+
+/// The NeoPixel of the board, driven by the PIO state machine `S`
+pub type NeoPixel<'d, P, const S: usize> = embassy::NeoPixel<'d, P, S, NEOPIXEL_COUNT>;
+
+/// Sets up [NeoPixel] on `sm` of the PIO of `common`, fed by `dma`
+pub fn neopixel<'d, P: Instance, const S: usize>(
+    common: &mut Common<'d, P>,
+    sm: StateMachine<'d, P, S>,
+    dma: impl Peripheral<P = impl dma::Channel> + 'd,
+    data: PIN_16,
+) -> NeoPixel<'d, P, S> {
+    NeoPixel::new(common, sm, dma, data)
+}
+
+/// The size of the display in pixels
+pub const LCD_SIZE: Size = Size::new(160, 80);
+
+/// SPI clock of the display
+const LCD_SPI_FREQ: u32 = 10_000_000;
+
+/// The start-up of the ST7735S controller, in landscape orientation
+///
+/// The panel has its colours inverted and in BGR order.
+pub const LCD_INIT: &[InitStep] = &[
+    InitStep::new(dcs::SWRESET, &[]).delay(150),
+    InitStep::new(dcs::SLPOUT, &[]).delay(120),
+    // Frame rate, power and VCOM settings of the panel
+    InitStep::new(0xb1, &[0x01, 0x2c, 0x2d]),
+    InitStep::new(0xb2, &[0x01, 0x2c, 0x2d]),
+    InitStep::new(0xb3, &[0x01, 0x2c, 0x2d, 0x01, 0x2c, 0x2d]),
+    InitStep::new(0xb4, &[0x07]),
+    InitStep::new(0xc0, &[0xa2, 0x02, 0x84]),
+    InitStep::new(0xc1, &[0xc5]),
+    InitStep::new(0xc2, &[0x0a, 0x00]),
+    InitStep::new(0xc3, &[0x8a, 0x2a]),
+    InitStep::new(0xc4, &[0x8a, 0xee]),
+    InitStep::new(0xc5, &[0x0e]),
+    InitStep::new(dcs::INVON, &[]),
+    InitStep::new(dcs::MADCTL, &[0x68]),
+    InitStep::new(dcs::COLMOD, &[0x05]),
+    InitStep::new(dcs::NORON, &[]).delay(10),
+    InitStep::new(dcs::DISPON, &[]).delay(100),
+];
+
+/// The ST7735S display of the board, see [SpiDisplay]
+pub type Lcd<'d> = SpiDisplay<'d, SPI1>;
+
+/// The pins connected to the display
+pub struct LcdPins {
+    /// Data/command select
+    pub dc: PIN_8,
+    /// Chip select
+    pub cs: PIN_9,
+    /// SPI clock
+    pub clk: PIN_10,
+    /// SPI data
+    pub din: PIN_11,
+    /// Reset
+    pub rst: PIN_12,
+    /// Backlight enable
+    pub bl: PIN_25,
+}
+
+/// Sets up SPI1 and the display, fed by `dma`, and switches its backlight on
+///
+/// Draw into a [Framebuffer](board_common::embassy::Framebuffer) of [LCD_SIZE],
+/// and send it with [SpiDisplay::flush].
+pub async fn lcd<'d>(
+    spi: SPI1,
+    pins: LcdPins,
+    dma: impl Peripheral<P = impl dma::Channel> + 'd,
+) -> Lcd<'d> {
+    let mut config = spi::Config::default();
+    config.frequency = LCD_SPI_FREQ;
+    let spi = Spi::new_txonly(spi, pins.clk, pins.din, dma, config);
+    let mut lcd = SpiDisplay::new(spi, pins.dc, pins.cs, LCD_SIZE)
+        .with_reset(pins.rst)
+        .with_backlight(pins.bl)
+        .with_offset(Point::new(1, 26));
+    lcd.init(LCD_INIT).await;
+    lcd
+}
@@ -0,0 +1,153 @@
+//! The 0.96" 160x80 LCD of the board
+//!
+//! The display is driven by an ST7735S controller, connected to SPI1. Its
+//! backlight is dimmed by PWM slice 4.
+//!
+//! | LCD | RP2040 | Pin                    |
+//! |-----|--------|------------------------|
+//! | DC  | GPIO8  | [crate::Pins::lcd_dc]  |
+//! | CS  | GPIO9  | [crate::Pins::lcd_cs]  |
+//! | CLK | GPIO10 | [crate::Pins::lcd_clk] |
+//! | DIN | GPIO11 | [crate::Pins::lcd_din] |
+//! | RST | GPIO12 | [crate::Pins::lcd_rst] |
+//! | BL  | GPIO25 | [crate::Pins::lcd_bl]  |
+//!
+//! The panel only covers part of the memory of the controller, and has its
+//! colours inverted and in BGR order. [init] takes care of all of this.
+//!
+//! ```ignore
+//! let pwm_slices = hal::pwm::Slices::new(pac.PWM, &mut pac.RESETS);
+//! let (mut display, mut backlight) = lcd::init(
+//!     pac.SPI1,
+//!     LcdPins {
+//!         dc: pins.lcd_dc.reconfigure(),
+//!         cs: pins.lcd_cs.reconfigure(),
+//!         clk: pins.lcd_clk.reconfigure(),
+//!         din: pins.lcd_din.reconfigure(),
+//!         rst: pins.lcd_rst.reconfigure(),
+//!         bl: pins.lcd_bl.reconfigure(),
+//!     },
+//!     pwm_slices.pwm4,
+//!     &mut pac.RESETS,
+//!     &clocks.peripheral_clock,
+//!     &mut delay,
+//!     Orientation::Landscape,
+//! );
+//! backlight.set_brightness(50);
+//! ```
+
+use embedded_graphics::pixelcolor::Rgb565;
+use embedded_graphics::prelude::*;
+use embedded_hal::digital::OutputPin;
+use embedded_hal_0_2::blocking::delay::DelayMs;
+use st7735_lcd::{Orientation, ST7735};
+
+use crate::hal;
+use crate::hal::clocks::{Clock, PeripheralClock};
+use crate::hal::fugit::RateExtU32;
+use crate::hal::pwm::{FreeRunning, Pwm4, Slice};
+use crate::hal::spi::Enabled;
+use crate::pac;
+use crate::{LcdBl, LcdClk, LcdCs, LcdDc, LcdDin, LcdRst};
+
+/// Width of the display in pixels, in landscape orientation
+pub const LCD_WIDTH: u32 = 160;
+
+/// Height of the display in pixels, in landscape orientation
+pub const LCD_HEIGHT: u32 = 80;
+
+/// SPI clock of the display
+const SPI_FREQ_MHZ: u32 = 10;
+
+/// The SPI bus the display is connected to
+pub type LcdSpi = hal::Spi<Enabled, pac::SPI1, (LcdDin, LcdClk), 8>;
+
+/// The display driver, as set up by [init]
+pub type Display = ST7735<LcdSpi, LcdDc, LcdRst>;
+
+/// The pins connected to the display
+pub struct LcdPins {
+    /// Data/command select
+    pub dc: LcdDc,
+    /// Chip select, held low since the display is alone on the bus
+    pub cs: LcdCs,
+    /// SPI clock
+    pub clk: LcdClk,
+    /// SPI data
+    pub din: LcdDin,
+    /// Reset
+    pub rst: LcdRst,
+    /// Backlight enable
+    pub bl: LcdBl,
+}
+
+/// Column and row offset of the panel within the controller memory
+pub fn offset(orientation: Orientation) -> (u16, u16) {
+    match orientation {
+        Orientation::Landscape | Orientation::LandscapeSwapped => (1, 26),
+        Orientation::Portrait | Orientation::PortraitSwapped => (26, 1),
+    }
+}
+
+/// Width and height of the display in pixels
+pub fn size(orientation: Orientation) -> (u32, u32) {
+    match orientation {
+        Orientation::Landscape | Orientation::LandscapeSwapped => (LCD_WIDTH, LCD_HEIGHT),
+        Orientation::Portrait | Orientation::PortraitSwapped => (LCD_HEIGHT, LCD_WIDTH),
+    }
+}
+
+/// Change the orientation of the display, along with its offset
+///
+/// The display has to be redrawn afterwards. Its size stays the one of the
+/// orientation passed to [init], so it can only be turned upside down.
+pub fn set_orientation(display: &mut Display, orientation: Orientation) {
+    // Writing to the SPI bus of the RP2040 can't fail
+    display.set_orientation(&orientation).ok();
+    let (dx, dy) = offset(orientation);
+    display.set_offset(dx, dy);
+}
+
+/// Set up SPI1 and the display, and the PWM slice for the backlight
+///
+/// The display is cleared to black while the backlight is still off, so no
+/// garbage is visible. Afterwards the backlight is turned on at full
+/// brightness.
+pub fn init(
+    spi1: pac::SPI1,
+    pins: LcdPins,
+    pwm4: Slice<Pwm4, FreeRunning>,
+    resets: &mut pac::RESETS,
+    peripheral_clock: &PeripheralClock,
+    delay: &mut impl DelayMs<u8>,
+    orientation: Orientation,
+) -> (Display, Backlight) {
+    let mut backlight = Backlight::new(pwm4, pins.bl);
+    backlight.off();
+
+    // The chip select stays low, the pin keeps its state once dropped
+    let mut cs = pins.cs;
+    cs.set_low().unwrap();
+
+    let spi = hal::Spi::<_, _, _, 8>::new(spi1, (pins.din, pins.clk)).init(
+        resets,
+        peripheral_clock.freq(),
+        SPI_FREQ_MHZ.MHz(),
+        embedded_hal::spi::MODE_0,
+    );
+
+    // BGR colour order and inverted colours
+    let (width, height) = size(orientation);
+    let mut display = ST7735::new(spi, pins.dc, pins.rst, false, true, width, height);
+    display.init(delay).unwrap();
+    set_orientation(&mut display, orientation);
+    display.clear(Rgb565::BLACK).unwrap();
+
+    backlight.on();
+    #[cfg(feature = "defmt")]
+    defmt::debug!("lcd: ST7735 ready, backlight on");
+    (display, backlight)
+}
+
+/// The display backlight, dimmed by PWM slice 4
+pub type Backlight = board_common::Backlight<Pwm4, LcdBl>;
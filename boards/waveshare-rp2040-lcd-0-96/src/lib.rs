@@ -17,7 +17,13 @@ pub static BOOT2_FIRMWARE: [u8; 256] = rp2040_boot2::BOOT_LOADER_W25Q080;
 
 pub use hal::pac;
 
-hal::bsp_pins!(
+pub mod lcd;
+#[cfg(feature = "panic-screen")]
+mod panic_screen;
+
+board_common::bsp_pins!(
+    embassy: crate::embassy::Pins;
+
     /// GPIO 0 supports following functions:
     ///
     /// | Function     | Alias with applied function |
@@ -252,9 +258,13 @@ hal::bsp_pins!(
     /// | `PWM4 A`     | [crate::Gp8Pwm4A]           |
     /// | `PIO0`       | [crate::Gp8Pio0]            |
     /// | `PIO1`       | [crate::Gp8Pio1]            |
+    ///
+    /// LCD data/command select, see [crate::LcdDc]
     Gpio8 {
-        name: gp8,
+        name: lcd_dc,
         aliases: {
+            /// LCD data/command select, alias for pin [crate::Pins::lcd_dc].
+            FunctionSioOutput, PullNone: LcdDc,
             /// UART Function alias for pin [crate::Pins::gpio8].
             FunctionUart, PullNone: Gp8Uart1Tx,
             /// SPI Function alias for pin [crate::Pins::gpio8].
@@ -280,9 +290,13 @@ hal::bsp_pins!(
     /// | `PWM4 B`     | [crate::Gp9Pwm4B]           |
     /// | `PIO0`       | [crate::Gp9Pio0]            |
     /// | `PIO1`       | [crate::Gp9Pio1]            |
+    ///
+    /// LCD chip select, see [crate::LcdCs]
     Gpio9 {
-        name: gp9,
+        name: lcd_cs,
         aliases: {
+            /// LCD chip select, alias for pin [crate::Pins::lcd_cs].
+            FunctionSioOutput, PullNone: LcdCs,
             /// UART Function alias for pin [crate::Pins::gpio9].
             FunctionUart, PullNone: Gp9Uart1Rx,
             /// SPI Function alias for pin [crate::Pins::gpio9].
@@ -308,9 +322,13 @@ hal::bsp_pins!(
     /// | `PWM5 A`     | [crate::Gp10Pwm5A]          |
     /// | `PIO0`       | [crate::Gp10Pio0]           |
     /// | `PIO1`       | [crate::Gp10Pio1]           |
+    ///
+    /// LCD SPI clock, see [crate::LcdClk]
     Gpio10 {
-        name: gp10,
+        name: lcd_clk,
         aliases: {
+            /// LCD SPI clock, alias for pin [crate::Pins::lcd_clk].
+            FunctionSpi, PullNone: LcdClk,
             /// UART Function alias for pin [crate::Pins::gpio10].
             FunctionUart, PullNone: Gp10Uart1Cts,
             /// SPI Function alias for pin [crate::Pins::gpio10].
@@ -336,9 +354,13 @@ hal::bsp_pins!(
     /// | `PWM5 B`     | [crate::Gp11Pwm5B]          |
     /// | `PIO0`       | [crate::Gp11Pio0]           |
     /// | `PIO1`       | [crate::Gp11Pio1]           |
+    ///
+    /// LCD SPI data, see [crate::LcdDin]
     Gpio11 {
-        name: gp11,
+        name: lcd_din,
         aliases: {
+            /// LCD SPI data, alias for pin [crate::Pins::lcd_din].
+            FunctionSpi, PullNone: LcdDin,
             /// UART Function alias for pin [crate::Pins::gpio11].
             FunctionUart, PullNone: Gp11Uart1Rts,
             /// SPI Function alias for pin [crate::Pins::gpio11].
@@ -364,9 +386,13 @@ hal::bsp_pins!(
     /// | `PWM6 A`     | [crate::Gp12Pwm6A]          |
     /// | `PIO0`       | [crate::Gp12Pio0]           |
     /// | `PIO1`       | [crate::Gp12Pio1]           |
+    ///
+    /// LCD reset, low active, see [crate::LcdRst]
     Gpio12 {
-        name: gp12,
+        name: lcd_rst,
         aliases: {
+            /// LCD reset, low active, alias for pin [crate::Pins::lcd_rst].
+            FunctionSioOutput, PullNone: LcdRst,
             /// UART Function alias for pin [crate::Pins::gpio12].
             FunctionUart, PullNone: Gp12Uart0Tx,
             /// SPI Function alias for pin [crate::Pins::gpio12].
@@ -664,9 +690,12 @@ hal::bsp_pins!(
     },
 
     /// GPIO 25
+    ///
+    /// LCD backlight, driven by PWM, see [crate::LcdBl]
     Gpio25 {
-        name: gp25,
+        name: lcd_bl,
         aliases: {
+            FunctionPwm, PullNone: LcdBl,
             FunctionUart, PullNone: Gp25Uart1Rx,
             FunctionSpi, PullNone: Gp25Spi1Csn,
             FunctionI2C, PullUp: Gp25I2C0Scl,
@@ -783,3 +812,13 @@ hal::bsp_pins!(
 );
 
 pub const XOSC_CRYSTAL_FREQ: u32 = 12_000_000;
+
+// FLASH_SIZE_BYTES and the reserved end of the flash, see build.rs
+include!(concat!(env!("OUT_DIR"), "/flash.rs"));
+
+#[cfg(feature = "embassy")]
+pub mod embassy;
+
+pub use board_common;
+
+board_common::board_pins!(Pins, XOSC_CRYSTAL_FREQ);
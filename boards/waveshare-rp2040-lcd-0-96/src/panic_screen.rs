@@ -0,0 +1,49 @@
+//! The panic handler of the `panic-screen` feature
+//!
+//! Shows the panic on the LCD, in white on red. The board has no button the
+//! application can read, so it stays there until it is reset.
+
+use core::panic::PanicInfo;
+
+use board_common::panic_screen::{self, PanicScreen};
+use embedded_graphics::pixelcolor::{Rgb565, RgbColor};
+use st7735_lcd::Orientation;
+
+use crate::lcd::{self, LcdPins};
+use crate::{hal, Board};
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    if !panic_screen::first_panic() {
+        panic_screen::halt();
+    }
+
+    // SAFETY: the application never runs again
+    let mut board = unsafe { Board::steal() };
+    // Keep the message on the screen
+    board.watchdog.disable();
+
+    let pins = board.pins;
+    let resets = &mut board.peripherals.RESETS;
+    let pwm_slices = hal::pwm::Slices::new(board.peripherals.PWM, resets);
+    let (mut display, _backlight) = lcd::init(
+        board.peripherals.SPI1,
+        LcdPins {
+            dc: pins.lcd_dc.reconfigure(),
+            cs: pins.lcd_cs.reconfigure(),
+            clk: pins.lcd_clk.reconfigure(),
+            din: pins.lcd_din.reconfigure(),
+            rst: pins.lcd_rst.reconfigure(),
+            bl: pins.lcd_bl.reconfigure(),
+        },
+        pwm_slices.pwm4,
+        resets,
+        &board.clocks.peripheral_clock,
+        &mut board.delay,
+        Orientation::Landscape,
+    );
+
+    let _ = PanicScreen::new(Rgb565::WHITE, Rgb565::RED).draw(&mut display, info);
+
+    panic_screen::halt()
+}
@@ -6,21 +6,13 @@
 #![no_std]
 #![no_main]
 
-use cortex_m::delay::Delay;
-use fugit::RateExtU32;
-use gc9a01a_driver::{Orientation, GC9A01A};
+use embedded_hal::delay::DelayNs;
 use panic_halt as _;
 
 use waveshare_rp2040_lcd_1_28::entry;
 use waveshare_rp2040_lcd_1_28::{
-    hal::{
-        self,
-        clocks::{init_clocks_and_plls, Clock},
-        pac,
-        pio::PIOExt,
-        watchdog::Watchdog,
-        Sio,
-    },
+    hal::{clocks::init_clocks_and_plls, pac, pwm::Slices, timer::Timer, watchdog::Watchdog, Sio},
+    lcd::{self, LcdPins, RoundClip, LCD_HEIGHT, LCD_WIDTH},
     Pins, XOSC_CRYSTAL_FREQ,
 };
 
@@ -30,16 +22,11 @@ use embedded_graphics::{
     primitives::{Line, PrimitiveStyle, PrimitiveStyleBuilder, Rectangle},
 };
 
-const LCD_WIDTH: u32 = 240;
-const LCD_HEIGHT: u32 = 240;
-// Define static buffers
-
 /// Main entry point for the application
 #[entry]
 fn main() -> ! {
     // Take ownership of peripheral instances
     let mut pac = pac::Peripherals::take().unwrap();
-    let core = pac::CorePeripherals::take().unwrap();
 
     // Initialize watchdog
     let mut watchdog = Watchdog::new(pac.WATCHDOG);
@@ -66,41 +53,29 @@ fn main() -> ! {
         &mut pac.RESETS,
     );
 
-    // Set up the delay for the first core
-    let sys_freq = clocks.system_clock.freq().to_Hz();
-    let mut delay = Delay::new(core.SYST, sys_freq);
-
-    let (mut _pio, _sm0, _, _, _) = pac.PIO0.split(&mut pac.RESETS);
-
-    // Initialize LCD pins
-    let lcd_dc = pins.gp8.into_push_pull_output();
-    let lcd_cs = pins.gp9.into_push_pull_output();
-    let lcd_clk = pins.gp10.into_function::<hal::gpio::FunctionSpi>();
-    let lcd_mosi = pins.gp11.into_function::<hal::gpio::FunctionSpi>();
-    let lcd_rst = pins
-        .gp12
-        .into_push_pull_output_in_state(hal::gpio::PinState::High);
-    let mut _lcd_bl = pins
-        .gp25
-        .into_push_pull_output_in_state(hal::gpio::PinState::Low);
-
-    // Initialize SPI
-    let spi = hal::Spi::<_, _, _, 8>::new(pac.SPI1, (lcd_mosi, lcd_clk));
-    let spi = spi.init(
+    // Set up the timer, used for delays
+    let mut delay = Timer::new(pac.TIMER, &mut pac.RESETS, &clocks);
+
+    // Initialize the display and its backlight
+    let pwm_slices = Slices::new(pac.PWM, &mut pac.RESETS);
+    let (display, _backlight) = lcd::init(
+        pac.SPI1,
+        LcdPins {
+            dc: pins.gp8.reconfigure(),
+            cs: pins.gp9.reconfigure(),
+            clk: pins.gp10.reconfigure(),
+            mosi: pins.gp11.reconfigure(),
+            rst: pins.gp12.reconfigure(),
+            backlight: pins.gp25.reconfigure(),
+        },
+        pwm_slices.pwm4,
         &mut pac.RESETS,
-        clocks.peripheral_clock.freq(),
-        40.MHz(),
-        embedded_hal::spi::MODE_0,
+        &clocks.peripheral_clock,
+        &mut delay,
     );
 
-    // Initialize the display
-    let mut display = GC9A01A::new(spi, lcd_dc, lcd_cs, lcd_rst, false, LCD_WIDTH, LCD_HEIGHT);
-    display.init(&mut delay).unwrap();
-    display.set_orientation(&Orientation::Landscape).unwrap();
-
-    // Clear the screen before turning on the backlight
-    display.clear(Rgb565::BLACK).unwrap();
-    _lcd_bl.into_push_pull_output_in_state(hal::gpio::PinState::High);
+    // Only draw the pixels visible on the round panel
+    let mut display = RoundClip::new(display);
     delay.delay_ms(1000);
 
     let lcd_zero = Point::zero();
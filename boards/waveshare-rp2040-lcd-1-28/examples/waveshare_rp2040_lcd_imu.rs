@@ -0,0 +1,119 @@
+//! Example of the IMU of the Waveshare RP2040-LCD-1.28
+//!
+//! Draws an arrow pointing to the top of the display, and keeps it upright
+//! while the board is turned, using the accelerometer of the QMI8658.
+#![no_std]
+#![no_main]
+
+use embedded_hal::delay::DelayNs;
+use panic_halt as _;
+
+use waveshare_rp2040_lcd_1_28::entry;
+use waveshare_rp2040_lcd_1_28::{
+    hal::{clocks::init_clocks_and_plls, pac, pwm::Slices, timer::Timer, watchdog::Watchdog, Sio},
+    imu::{AccelScale, AutoRotate, DataRate, Qmi8658},
+    lcd::{self, LcdPins, RoundClip, LCD_WIDTH},
+    Pins, XOSC_CRYSTAL_FREQ,
+};
+
+use embedded_graphics::{
+    pixelcolor::Rgb565,
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle, Triangle},
+};
+
+/// How often to check the orientation
+const POLL_INTERVAL_MS: u32 = 100;
+
+/// Main entry point for the application
+#[entry]
+fn main() -> ! {
+    // Take ownership of peripheral instances
+    let mut pac = pac::Peripherals::take().unwrap();
+
+    // Initialize watchdog
+    let mut watchdog = Watchdog::new(pac.WATCHDOG);
+
+    // Initialize clocks and PLLs
+    let clocks = init_clocks_and_plls(
+        XOSC_CRYSTAL_FREQ,
+        pac.XOSC,
+        pac.CLOCKS,
+        pac.PLL_SYS,
+        pac.PLL_USB,
+        &mut pac.RESETS,
+        &mut watchdog,
+    )
+    .ok()
+    .unwrap();
+
+    // Initialize SIO
+    let sio = Sio::new(pac.SIO);
+    let pins = Pins::new(
+        pac.IO_BANK0,
+        pac.PADS_BANK0,
+        sio.gpio_bank0,
+        &mut pac.RESETS,
+    );
+
+    // Set up the timer, used for delays
+    let mut delay = Timer::new(pac.TIMER, &mut pac.RESETS, &clocks);
+
+    // Initialize the display and its backlight
+    let pwm_slices = Slices::new(pac.PWM, &mut pac.RESETS);
+    let (display, _backlight) = lcd::init(
+        pac.SPI1,
+        LcdPins {
+            dc: pins.gp8.reconfigure(),
+            cs: pins.gp9.reconfigure(),
+            clk: pins.gp10.reconfigure(),
+            mosi: pins.gp11.reconfigure(),
+            rst: pins.gp12.reconfigure(),
+            backlight: pins.gp25.reconfigure(),
+        },
+        pwm_slices.pwm4,
+        &mut pac.RESETS,
+        &clocks.peripheral_clock,
+        &mut delay,
+    );
+    let mut display = RoundClip::new(display);
+
+    // Initialize the IMU, only the accelerometer is needed
+    let mut imu = Qmi8658::on_board(
+        pac.I2C1,
+        pins.gp6,
+        pins.gp7,
+        &mut pac.RESETS,
+        &clocks.system_clock,
+    )
+    .unwrap();
+    imu.set_accel(AccelScale::G2, DataRate::Hz31_25).unwrap();
+
+    let mut rotate = AutoRotate::default();
+    draw_arrow(&mut display);
+    loop {
+        let accel = imu.accel().unwrap();
+        if rotate.apply(accel, display.inner_mut()) {
+            draw_arrow(&mut display);
+        }
+        delay.delay_ms(POLL_INTERVAL_MS);
+    }
+}
+
+/// Clear the display and draw an arrow pointing up
+fn draw_arrow<D: DrawTarget<Color = Rgb565>>(display: &mut D) {
+    let center = (LCD_WIDTH / 2) as i32;
+    display.clear(Rgb565::BLACK).ok();
+    Triangle::new(
+        Point::new(center, 30),
+        Point::new(center - 50, 100),
+        Point::new(center + 50, 100),
+    )
+    .into_styled(PrimitiveStyle::with_fill(Rgb565::GREEN))
+    .draw(display)
+    .ok();
+    Rectangle::new(Point::new(center - 20, 100), Size::new(40, 100))
+        .into_styled(PrimitiveStyle::with_fill(Rgb565::GREEN))
+        .draw(display)
+        .ok();
+}
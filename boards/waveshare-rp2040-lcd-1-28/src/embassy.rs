@@ -0,0 +1,182 @@
+//! The pins and drivers of the board on top of `embassy-rp`
+//!
+//! [embassy_pins!](crate::embassy_pins) names the pins of the `embassy_rp`
+//! peripherals like [crate::Pins] does, and the functions of this module set up
+//! the NeoPixel and display of the board with the async drivers of
+//! [board_common::embassy]:
+//!
+//! ```ignore
+//! let p = embassy_rp::init(Default::default());
+//! let pins = waveshare_rp2040_lcd_1_28::embassy_pins!(p);
+//! let Pio { mut common, sm0, .. } = Pio::new(p.PIO0, Irqs);
+//! let mut neopixel = embassy::neopixel(
+//!     &mut common,
+//!     sm0,
+//!     p.DMA_CH0,
+//!     pins.neopixel,
+//! );
+//! neopixel.write(&[RGB8::new(0, 0, 32); embassy::NEOPIXEL_COUNT]).await;
+//! let mut lcd = embassy::lcd(
+//!     p.SPI1,
+//!     embassy::LcdPins {
+//!         dc: pins.gp8,
+//!         cs: pins.gp9,
+//!         clk: pins.gp10,
+//!         mosi: pins.gp11,
+//!         rst: pins.gp12,
+//!         backlight: pins.gp25,
+//!     },
+//!     p.DMA_CH1,
+//! ).await;
+//! ```
+//!
+//! `embassy-rp` brings its own runtime, critical section and second stage
+//! bootloader, so build the BSP with `default-features = false`.
+//!
+//! Only available with the `embassy` feature.
+
+use embassy_rp::dma;
+use embassy_rp::peripherals::*;
+use embassy_rp::pio::{Common, Instance, StateMachine};
+use embassy_rp::spi::{self, Spi};
+use embassy_rp::Peripheral;
+use embedded_graphics::prelude::Size;
+
+use board_common::embassy::{self, dcs, InitStep, SpiDisplay};
+
+#[doc(inline)]
+pub use crate::EmbassyPins as Pins;
+
+/// The number of NeoPixels on the board
+pub const NEOPIXEL_COUNT: usize = 1;
+
+/// The NeoPixel of the board, driven by the PIO state machine `S`
+pub type NeoPixel<'d, P, const S: usize> = embassy::NeoPixel<'d, P, S, NEOPIXEL_COUNT>;
+
+/// Sets up [NeoPixel] on `sm` of the PIO of `common`, fed by `dma`
+pub fn neopixel<'d, P: Instance, const S: usize>(
+    common: &mut Common<'d, P>,
+    sm: StateMachine<'d, P, S>,
+    dma: impl Peripheral<P = impl dma::Channel> + 'd,
+    data: PIN_16,
+) -> NeoPixel<'d, P, S> {
+    NeoPixel::new(common, sm, dma, data)
+}
+
+/// The size of the display in pixels
+pub const LCD_SIZE: Size = Size::new(240, 240);
+
+/// SPI clock of the display
+const LCD_SPI_FREQ: u32 = 40_000_000;
+
+/// The start-up of the GC9A01A controller, in its native orientation
+pub const LCD_INIT: &[InitStep] = &[
+    // The vendor sequence of the panel
+    InitStep::new(0xef, &[]),
+    InitStep::new(0xeb, &[0x14]),
+    InitStep::new(0xfe, &[]),
+    InitStep::new(0xef, &[]),
+    InitStep::new(0xeb, &[0x14]),
+    InitStep::new(0x84, &[0x40]),
+    InitStep::new(0x85, &[0xff]),
+    InitStep::new(0x86, &[0xff]),
+    InitStep::new(0x87, &[0xff]),
+    InitStep::new(0x88, &[0x0a]),
+    InitStep::new(0x89, &[0x21]),
+    InitStep::new(0x8a, &[0x00]),
+    InitStep::new(0x8b, &[0x80]),
+    InitStep::new(0x8c, &[0x01]),
+    InitStep::new(0x8d, &[0x01]),
+    InitStep::new(0x8e, &[0xff]),
+    InitStep::new(0x8f, &[0xff]),
+    InitStep::new(0xb6, &[0x00, 0x20]),
+    InitStep::new(dcs::MADCTL, &[0x08]),
+    InitStep::new(dcs::COLMOD, &[0x05]),
+    InitStep::new(0x90, &[0x08, 0x08, 0x08, 0x08]),
+    InitStep::new(0xbd, &[0x06]),
+    InitStep::new(0xbc, &[0x00]),
+    InitStep::new(0xff, &[0x60, 0x01, 0x04]),
+    InitStep::new(0xc3, &[0x13]),
+    InitStep::new(0xc4, &[0x13]),
+    InitStep::new(0xc9, &[0x22]),
+    InitStep::new(0xbe, &[0x11]),
+    InitStep::new(0xe1, &[0x10, 0x0e]),
+    InitStep::new(0xdf, &[0x21, 0x0c, 0x02]),
+    InitStep::new(0xf0, &[0x45, 0x09, 0x08, 0x08, 0x26, 0x2a]),
+    InitStep::new(0xf1, &[0x43, 0x70, 0x72, 0x36, 0x37, 0x6f]),
+    InitStep::new(0xf2, &[0x45, 0x09, 0x08, 0x08, 0x26, 0x2a]),
+    InitStep::new(0xf3, &[0x43, 0x70, 0x72, 0x36, 0x37, 0x6f]),
+    InitStep::new(0xed, &[0x1b, 0x0b]),
+    InitStep::new(0xae, &[0x77]),
+    InitStep::new(0xcd, &[0x63]),
+    InitStep::new(
+        0x70,
+        &[0x07, 0x07, 0x04, 0x0e, 0x0f, 0x09, 0x07, 0x08, 0x03],
+    ),
+    InitStep::new(0xe8, &[0x34]),
+    InitStep::new(
+        0x62,
+        &[
+            0x18, 0x0d, 0x71, 0xed, 0x70, 0x70, 0x18, 0x0f, 0x71, 0xef, 0x70, 0x70,
+        ],
+    ),
+    InitStep::new(
+        0x63,
+        &[
+            0x18, 0x11, 0x71, 0xf1, 0x70, 0x70, 0x18, 0x13, 0x71, 0xf3, 0x70, 0x70,
+        ],
+    ),
+    InitStep::new(0x64, &[0x28, 0x29, 0xf1, 0x01, 0xf1, 0x00, 0x07]),
+    InitStep::new(
+        0x66,
+        &[0x3c, 0x00, 0xcd, 0x67, 0x45, 0x45, 0x10, 0x00, 0x00, 0x00],
+    ),
+    InitStep::new(
+        0x67,
+        &[0x00, 0x3c, 0x00, 0x00, 0x00, 0x01, 0x54, 0x10, 0x32, 0x98],
+    ),
+    InitStep::new(0x74, &[0x10, 0x85, 0x80, 0x00, 0x00, 0x4e, 0x00]),
+    InitStep::new(0x98, &[0x3e, 0x07]),
+    InitStep::new(0x35, &[]),
+    InitStep::new(dcs::INVON, &[]),
+    InitStep::new(dcs::SLPOUT, &[]).delay(120),
+    InitStep::new(dcs::DISPON, &[]).delay(20),
+];
+
+/// The GC9A01A display of the board, see [SpiDisplay]
+pub type Lcd<'d> = SpiDisplay<'d, SPI1>;
+
+/// The pins connected to the display
+pub struct LcdPins {
+    /// Data/command select
+    pub dc: PIN_8,
+    /// Chip select
+    pub cs: PIN_9,
+    /// SPI clock
+    pub clk: PIN_10,
+    /// SPI data
+    pub mosi: PIN_11,
+    /// Reset
+    pub rst: PIN_12,
+    /// Backlight enable
+    pub backlight: PIN_25,
+}
+
+/// Sets up SPI1 and the display, fed by `dma`, and switches its backlight on
+///
+/// Draw into a [Framebuffer](board_common::embassy::Framebuffer) of [LCD_SIZE],
+/// and send it with [SpiDisplay::flush].
+pub async fn lcd<'d>(
+    spi: SPI1,
+    pins: LcdPins,
+    dma: impl Peripheral<P = impl dma::Channel> + 'd,
+) -> Lcd<'d> {
+    let mut config = spi::Config::default();
+    config.frequency = LCD_SPI_FREQ;
+    let spi = Spi::new_txonly(spi, pins.clk, pins.mosi, dma, config);
+    let mut lcd = SpiDisplay::new(spi, pins.dc, pins.cs, LCD_SIZE)
+        .with_reset(pins.rst)
+        .with_backlight(pins.backlight);
+    lcd.init(LCD_INIT).await;
+    lcd
+}
@@ -0,0 +1,831 @@
+//! Driver for the on-board QMI8658 6-axis IMU
+//!
+//! The IMU is connected to I2C1, with SDA on GPIO6 ([crate::ImuSda]) and SCL
+//! on GPIO7 ([crate::ImuScl]). Its interrupt lines INT1 and INT2 are
+//! connected to GPIO23 ([crate::ImuInt1]) and GPIO24 ([crate::ImuInt2]).
+//!
+//! The driver is generic over the `embedded-hal` I2C traits, so it can be
+//! exercised against a simulated bus as well.
+//!
+//! ```ignore
+//! let mut imu = Qmi8658::on_board(pac.I2C1, pins.gp6, pins.gp7, &mut pac.RESETS, &clocks.system_clock)?;
+//! imu.set_accel(AccelScale::G4, DataRate::Hz125)?;
+//! imu.set_gyro(GyroScale::Dps512, DataRate::Hz125)?;
+//! let [x, y, z] = imu.accel()?;
+//! ```
+
+use embedded_hal::i2c::I2c;
+
+use crate::hal;
+use crate::hal::clocks::{Clock, SystemClock};
+use crate::hal::fugit::RateExtU32;
+use crate::hal::gpio::bank0::{Gpio6, Gpio7};
+use crate::hal::gpio::{Function, Pin, PullType};
+use crate::lcd::Display;
+use crate::pac;
+use crate::{ImuScl, ImuSda};
+
+/// I2C address of the IMU, SA0 is pulled high
+pub const ADDRESS: u8 = 0x6B;
+
+/// Expected value of the `WHO_AM_I` register
+const DEVICE_ID: u8 = 0x05;
+
+/// Standard gravity in m/s²
+const GRAVITY: f32 = 9.80665;
+
+/// How often to poll for a command to complete
+const COMMAND_TIMEOUT_POLLS: u32 = 1000;
+
+/// Size of a FIFO entry of a single sensor in bytes
+const FIFO_ENTRY_LEN: usize = 6;
+
+mod reg {
+    pub const WHO_AM_I: u8 = 0x00;
+    pub const CTRL1: u8 = 0x02;
+    pub const CTRL2: u8 = 0x03;
+    pub const CTRL3: u8 = 0x04;
+    pub const CTRL7: u8 = 0x08;
+    pub const CTRL9: u8 = 0x0A;
+    pub const FIFO_WTM_TH: u8 = 0x13;
+    pub const FIFO_CTRL: u8 = 0x14;
+    pub const FIFO_SMPL_CNT: u8 = 0x15;
+    pub const FIFO_DATA: u8 = 0x17;
+    pub const STATUSINT: u8 = 0x2D;
+    pub const TEMP_L: u8 = 0x33;
+    pub const AX_L: u8 = 0x35;
+    pub const GX_L: u8 = 0x3B;
+}
+
+mod cmd {
+    pub const ACK: u8 = 0x00;
+    pub const RST_FIFO: u8 = 0x04;
+    pub const REQ_FIFO: u8 = 0x05;
+}
+
+/// `CTRL1`: serial interface and interrupt configuration
+const CTRL1_ADDR_AI: u8 = 1 << 6;
+const CTRL1_INT2_EN: u8 = 1 << 4;
+const CTRL1_INT1_EN: u8 = 1 << 3;
+const CTRL1_FIFO_INT_SEL: u8 = 1 << 2;
+
+/// `CTRL7`: sensor enables
+const CTRL7_G_EN: u8 = 1 << 1;
+const CTRL7_A_EN: u8 = 1 << 0;
+
+/// `FIFO_CTRL`: read mode flag, set by the IMU while the FIFO is read
+const FIFO_CTRL_RD_MODE: u8 = 1 << 7;
+
+/// `STATUSINT`: set once a `CTRL9` command has been executed
+const STATUSINT_CMD_DONE: u8 = 1 << 7;
+
+/// The I2C bus the IMU is connected to
+pub type ImuI2c = hal::I2C<pac::I2C1, (ImuSda, ImuScl)>;
+
+/// Errors reported by the IMU driver
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    /// The I2C bus reported an error
+    I2c(E),
+    /// `WHO_AM_I` returned an unexpected value
+    WrongDevice(u8),
+    /// The IMU didn't complete a command in time
+    Timeout,
+}
+
+/// Output data rate of the accelerometer and gyroscope
+///
+/// With both sensors enabled, the rates are slightly lower than named.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DataRate {
+    /// 8 kHz
+    Hz8000 = 0,
+    /// 4 kHz
+    Hz4000 = 1,
+    /// 2 kHz
+    Hz2000 = 2,
+    /// 1 kHz
+    Hz1000 = 3,
+    /// 500 Hz
+    Hz500 = 4,
+    /// 250 Hz
+    Hz250 = 5,
+    /// 125 Hz
+    Hz125 = 6,
+    /// 62.5 Hz
+    Hz62_5 = 7,
+    /// 31.25 Hz
+    Hz31_25 = 8,
+}
+
+/// Full scale of the accelerometer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AccelScale {
+    /// ±2 g
+    G2 = 0,
+    /// ±4 g
+    G4 = 1,
+    /// ±8 g
+    G8 = 2,
+    /// ±16 g
+    G16 = 3,
+}
+
+impl AccelScale {
+    /// Full scale in g
+    pub fn g(self) -> f32 {
+        f32::from(2u8 << self as u8)
+    }
+
+    /// Sensitivity in m/s² per LSB
+    fn sensitivity(self) -> f32 {
+        self.g() * GRAVITY / 32768.0
+    }
+}
+
+/// Full scale of the gyroscope
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum GyroScale {
+    /// ±16 dps
+    Dps16 = 0,
+    /// ±32 dps
+    Dps32 = 1,
+    /// ±64 dps
+    Dps64 = 2,
+    /// ±128 dps
+    Dps128 = 3,
+    /// ±256 dps
+    Dps256 = 4,
+    /// ±512 dps
+    Dps512 = 5,
+    /// ±1024 dps
+    Dps1024 = 6,
+    /// ±2048 dps
+    Dps2048 = 7,
+}
+
+impl GyroScale {
+    /// Full scale in degrees per second
+    pub fn dps(self) -> f32 {
+        f32::from(16u16 << self as u8)
+    }
+
+    /// Sensitivity in rad/s per LSB
+    fn sensitivity(self) -> f32 {
+        self.dps().to_radians() / 32768.0
+    }
+}
+
+/// Number of samples the FIFO holds, per sensor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FifoSize {
+    /// 16 samples
+    Samples16 = 0,
+    /// 32 samples
+    Samples32 = 1,
+    /// 64 samples
+    Samples64 = 2,
+    /// 128 samples
+    Samples128 = 3,
+}
+
+/// Interrupt line signalling the FIFO watermark
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Interrupt {
+    /// INT1, GPIO23
+    Int1,
+    /// INT2, GPIO24
+    Int2,
+}
+
+/// A set of samples taken at the same time, read from the FIFO
+///
+/// Sensors which are disabled are `None`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FifoFrame {
+    /// Acceleration in m/s²
+    pub accel: Option<[f32; 3]>,
+    /// Angular rate in rad/s
+    pub gyro: Option<[f32; 3]>,
+}
+
+/// Flags of the FIFO status register
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FifoStatus {
+    /// Number of bytes waiting in the FIFO
+    pub bytes: u16,
+    /// The FIFO reached its watermark
+    pub watermark: bool,
+    /// The FIFO overflowed and samples were lost
+    pub overflow: bool,
+    /// The FIFO is full
+    pub full: bool,
+}
+
+/// QMI8658 accelerometer and gyroscope
+pub struct Qmi8658<I2C> {
+    i2c: I2C,
+    address: u8,
+    accel: Option<AccelScale>,
+    gyro: Option<GyroScale>,
+    fifo_ctrl: u8,
+}
+
+impl Qmi8658<ImuI2c> {
+    /// Set up I2C1 and the on-board IMU
+    pub fn on_board<F1, P1, F2, P2>(
+        i2c1: pac::I2C1,
+        sda: Pin<Gpio6, F1, P1>,
+        scl: Pin<Gpio7, F2, P2>,
+        resets: &mut pac::RESETS,
+        system_clock: &SystemClock,
+    ) -> Result<Self, Error<hal::i2c::Error>>
+    where
+        F1: Function,
+        P1: PullType,
+        F2: Function,
+        P2: PullType,
+    {
+        let i2c = hal::I2C::i2c1(
+            i2c1,
+            sda.reconfigure(),
+            scl.reconfigure(),
+            400.kHz(),
+            resets,
+            system_clock.freq(),
+        );
+        Self::new(i2c, ADDRESS)
+    }
+}
+
+impl<I2C: I2c> Qmi8658<I2C> {
+    /// Check the identity of the IMU and configure its interface
+    ///
+    /// Both sensors are disabled afterwards.
+    pub fn new(i2c: I2C, address: u8) -> Result<Self, Error<I2C::Error>> {
+        let mut imu = Qmi8658 {
+            i2c,
+            address,
+            accel: None,
+            gyro: None,
+            fifo_ctrl: 0,
+        };
+
+        let id = imu.read_reg(reg::WHO_AM_I)?;
+        if id != DEVICE_ID {
+            #[cfg(feature = "defmt")]
+            defmt::warn!("imu: WHO_AM_I is {=u8:#04x}, not a QMI8658", id);
+            return Err(Error::WrongDevice(id));
+        }
+
+        // Auto increment of the register address, little endian data
+        imu.write_reg(reg::CTRL1, CTRL1_ADDR_AI)?;
+        imu.write_reg(reg::CTRL7, 0)?;
+        #[cfg(feature = "defmt")]
+        defmt::debug!("imu: QMI8658 found at {=u8:#04x}", address);
+        Ok(imu)
+    }
+
+    /// Release the I2C bus
+    pub fn free(self) -> I2C {
+        self.i2c
+    }
+
+    fn read_reg(&mut self, reg: u8) -> Result<u8, Error<I2C::Error>> {
+        let mut value = [0];
+        self.read_regs(reg, &mut value)?;
+        Ok(value[0])
+    }
+
+    fn read_regs(&mut self, reg: u8, buf: &mut [u8]) -> Result<(), Error<I2C::Error>> {
+        self.i2c
+            .write_read(self.address, &[reg], buf)
+            .map_err(Error::I2c)
+    }
+
+    fn write_reg(&mut self, reg: u8, value: u8) -> Result<(), Error<I2C::Error>> {
+        self.i2c
+            .write(self.address, &[reg, value])
+            .map_err(Error::I2c)
+    }
+
+    fn modify_reg(&mut self, reg: u8, mask: u8, value: u8) -> Result<(), Error<I2C::Error>> {
+        let old = self.read_reg(reg)?;
+        self.write_reg(reg, old & !mask | value & mask)
+    }
+
+    fn read_vector(&mut self, reg: u8) -> Result<[i16; 3], Error<I2C::Error>> {
+        let mut buf = [0; 6];
+        self.read_regs(reg, &mut buf)?;
+        Ok(vector(&buf))
+    }
+
+    /// Execute a `CTRL9` command and acknowledge its completion
+    fn command(&mut self, command: u8) -> Result<(), Error<I2C::Error>> {
+        self.write_reg(reg::CTRL9, command)?;
+        self.wait_command_done(true)?;
+        self.write_reg(reg::CTRL9, cmd::ACK)?;
+        self.wait_command_done(false)
+    }
+
+    fn wait_command_done(&mut self, done: bool) -> Result<(), Error<I2C::Error>> {
+        for _ in 0..COMMAND_TIMEOUT_POLLS {
+            if (self.read_reg(reg::STATUSINT)? & STATUSINT_CMD_DONE != 0) == done {
+                return Ok(());
+            }
+        }
+        Err(Error::Timeout)
+    }
+
+    /// Configure and enable the accelerometer
+    pub fn set_accel(
+        &mut self,
+        scale: AccelScale,
+        rate: DataRate,
+    ) -> Result<(), Error<I2C::Error>> {
+        self.write_reg(reg::CTRL2, (scale as u8) << 4 | rate as u8)?;
+        self.modify_reg(reg::CTRL7, CTRL7_A_EN, CTRL7_A_EN)?;
+        self.accel = Some(scale);
+        Ok(())
+    }
+
+    /// Configure and enable the gyroscope
+    pub fn set_gyro(&mut self, scale: GyroScale, rate: DataRate) -> Result<(), Error<I2C::Error>> {
+        self.write_reg(reg::CTRL3, (scale as u8) << 4 | rate as u8)?;
+        self.modify_reg(reg::CTRL7, CTRL7_G_EN, CTRL7_G_EN)?;
+        self.gyro = Some(scale);
+        Ok(())
+    }
+
+    /// Disable the accelerometer
+    pub fn disable_accel(&mut self) -> Result<(), Error<I2C::Error>> {
+        self.modify_reg(reg::CTRL7, CTRL7_A_EN, 0)?;
+        self.accel = None;
+        Ok(())
+    }
+
+    /// Disable the gyroscope
+    pub fn disable_gyro(&mut self) -> Result<(), Error<I2C::Error>> {
+        self.modify_reg(reg::CTRL7, CTRL7_G_EN, 0)?;
+        self.gyro = None;
+        Ok(())
+    }
+
+    /// Read the raw accelerometer output
+    pub fn accel_raw(&mut self) -> Result<[i16; 3], Error<I2C::Error>> {
+        self.read_vector(reg::AX_L)
+    }
+
+    /// Read the raw gyroscope output
+    pub fn gyro_raw(&mut self) -> Result<[i16; 3], Error<I2C::Error>> {
+        self.read_vector(reg::GX_L)
+    }
+
+    /// Read the acceleration in m/s²
+    ///
+    /// Reads zero while the accelerometer is disabled.
+    pub fn accel(&mut self) -> Result<[f32; 3], Error<I2C::Error>> {
+        let raw = self.accel_raw()?;
+        Ok(scale(raw, self.accel.map_or(0.0, AccelScale::sensitivity)))
+    }
+
+    /// Read the angular rate in rad/s
+    ///
+    /// Reads zero while the gyroscope is disabled.
+    pub fn gyro(&mut self) -> Result<[f32; 3], Error<I2C::Error>> {
+        let raw = self.gyro_raw()?;
+        Ok(scale(raw, self.gyro.map_or(0.0, GyroScale::sensitivity)))
+    }
+
+    /// Read the die temperature in °C
+    pub fn temperature(&mut self) -> Result<f32, Error<I2C::Error>> {
+        let mut buf = [0; 2];
+        self.read_regs(reg::TEMP_L, &mut buf)?;
+        Ok(f32::from(i16::from_le_bytes(buf)) / 256.0)
+    }
+
+    /// Queue samples of the enabled sensors in the FIFO, and signal on
+    /// `interrupt` once `watermark` samples per sensor are waiting
+    ///
+    /// The FIFO runs in stream mode, overwriting the oldest samples when it
+    /// is full.
+    pub fn enable_fifo(
+        &mut self,
+        size: FifoSize,
+        watermark: u8,
+        interrupt: Interrupt,
+    ) -> Result<(), Error<I2C::Error>> {
+        let (enable, select) = match interrupt {
+            Interrupt::Int1 => (CTRL1_INT1_EN, CTRL1_FIFO_INT_SEL),
+            Interrupt::Int2 => (CTRL1_INT2_EN, 0),
+        };
+        self.modify_reg(reg::CTRL1, CTRL1_FIFO_INT_SEL | enable, select | enable)?;
+        self.write_reg(reg::FIFO_WTM_TH, watermark)?;
+        // Stream mode
+        self.fifo_ctrl = (size as u8) << 2 | 0b10;
+        self.write_reg(reg::FIFO_CTRL, self.fifo_ctrl)?;
+        self.command(cmd::RST_FIFO)
+    }
+
+    /// Stop queueing samples, discarding the FIFO content
+    pub fn disable_fifo(&mut self) -> Result<(), Error<I2C::Error>> {
+        self.fifo_ctrl = 0;
+        self.write_reg(reg::FIFO_CTRL, 0)?;
+        self.command(cmd::RST_FIFO)
+    }
+
+    /// Read the FIFO status
+    pub fn fifo_status(&mut self) -> Result<FifoStatus, Error<I2C::Error>> {
+        let mut buf = [0; 2];
+        self.read_regs(reg::FIFO_SMPL_CNT, &mut buf)?;
+        let [count, status] = buf;
+        Ok(FifoStatus {
+            // The count is in units of two bytes
+            bytes: 2 * (u16::from(status & 0x03) << 8 | u16::from(count)),
+            full: status & 0x80 != 0,
+            watermark: status & 0x40 != 0,
+            overflow: status & 0x20 != 0,
+        })
+    }
+
+    /// Read all frames from the FIFO, handing them to `f`
+    ///
+    /// The FIFO has to be switched into read mode first, which blocks new
+    /// samples until all of them have been read. Returns the number of
+    /// frames read.
+    pub fn drain_fifo(&mut self, mut f: impl FnMut(FifoFrame)) -> Result<u16, Error<I2C::Error>> {
+        let entries = usize::from(self.accel.is_some()) + usize::from(self.gyro.is_some());
+        if entries == 0 {
+            return Ok(0);
+        }
+
+        self.command(cmd::REQ_FIFO)?;
+        let frame_len = entries * FIFO_ENTRY_LEN;
+        let frames = usize::from(self.fifo_status()?.bytes) / frame_len;
+
+        let mut buf = [0; 2 * FIFO_ENTRY_LEN];
+        let accel_sensitivity = self.accel.map(AccelScale::sensitivity);
+        let gyro_sensitivity = self.gyro.map(GyroScale::sensitivity);
+        for _ in 0..frames {
+            self.read_regs(reg::FIFO_DATA, &mut buf[..frame_len])?;
+            // The accelerometer comes first if both are enabled
+            let mut entries = buf[..frame_len].chunks_exact(FIFO_ENTRY_LEN);
+            let accel = accel_sensitivity.map(|s| scale(vector(entries.next().unwrap()), s));
+            let gyro = gyro_sensitivity.map(|s| scale(vector(entries.next().unwrap()), s));
+            f(FifoFrame { accel, gyro });
+        }
+
+        // Leave the read mode
+        self.write_reg(reg::FIFO_CTRL, self.fifo_ctrl & !FIFO_CTRL_RD_MODE)?;
+        Ok(frames as u16)
+    }
+}
+
+fn vector(buf: &[u8]) -> [i16; 3] {
+    [
+        i16::from_le_bytes([buf[0], buf[1]]),
+        i16::from_le_bytes([buf[2], buf[3]]),
+        i16::from_le_bytes([buf[4], buf[5]]),
+    ]
+}
+
+fn scale(raw: [i16; 3], sensitivity: f32) -> [f32; 3] {
+    raw.map(|value| f32::from(value) * sensitivity)
+}
+
+/// Rotation of the board around the axis perpendicular to the display,
+/// clockwise from the USB connector pointing down
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Rotation {
+    /// USB connector pointing down
+    Deg0,
+    /// USB connector pointing left
+    Deg90,
+    /// USB connector pointing up
+    Deg180,
+    /// USB connector pointing right
+    Deg270,
+}
+
+impl Rotation {
+    /// Determine the rotation from the direction of gravity
+    ///
+    /// Returns `None` if the board lies too flat, or is tilted close to
+    /// halfway between two rotations.
+    pub fn from_accel(accel: [f32; 3]) -> Option<Self> {
+        let [x, y, z] = accel;
+        let (abs_x, abs_y) = (x.abs(), y.abs());
+        // The display has to be tilted more than about 30° from horizontal
+        if abs_x.max(abs_y) < z.abs() * 0.58 {
+            return None;
+        }
+        // and gravity has to point clearly along one of the edges
+        if abs_x > 2.0 * abs_y {
+            Some(if x > 0.0 {
+                Rotation::Deg90
+            } else {
+                Rotation::Deg270
+            })
+        } else if abs_y > 2.0 * abs_x {
+            Some(if y > 0.0 {
+                Rotation::Deg0
+            } else {
+                Rotation::Deg180
+            })
+        } else {
+            None
+        }
+    }
+
+    /// The display orientation keeping the content upright
+    pub fn orientation(self) -> gc9a01a_driver::Orientation {
+        use gc9a01a_driver::Orientation;
+        // [crate::lcd::init] sets up landscape for the default position
+        match self {
+            Rotation::Deg0 => Orientation::Landscape,
+            Rotation::Deg90 => Orientation::PortraitSwapped,
+            Rotation::Deg180 => Orientation::LandscapeSwapped,
+            Rotation::Deg270 => Orientation::Portrait,
+        }
+    }
+}
+
+/// Keeps the display content upright as the board is turned
+///
+/// The rotation only changes once the board is held clearly in a new
+/// position, so it doesn't flip back and forth around the diagonals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AutoRotate {
+    rotation: Rotation,
+}
+
+impl Default for AutoRotate {
+    fn default() -> Self {
+        AutoRotate::new(Rotation::Deg0)
+    }
+}
+
+impl AutoRotate {
+    /// Start with the display set up for `rotation`
+    pub fn new(rotation: Rotation) -> Self {
+        AutoRotate { rotation }
+    }
+
+    /// The current rotation
+    pub fn rotation(&self) -> Rotation {
+        self.rotation
+    }
+
+    /// Feed an accelerometer reading, returns the new rotation if it changed
+    pub fn update(&mut self, accel: [f32; 3]) -> Option<Rotation> {
+        match Rotation::from_accel(accel) {
+            Some(rotation) if rotation != self.rotation => {
+                self.rotation = rotation;
+                Some(rotation)
+            }
+            _ => None,
+        }
+    }
+
+    /// Feed an accelerometer reading and rotate `display` if needed
+    ///
+    /// Returns `true` if the display has been rotated, its content has to be
+    /// redrawn then.
+    pub fn apply(&mut self, accel: [f32; 3], display: &mut Display) -> bool {
+        match self.update(accel) {
+            Some(rotation) => {
+                // Writing to the SPI bus of the RP2040 can't fail
+                display.set_orientation(&rotation.orientation()).ok();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::convert::Infallible;
+    use std::collections::VecDeque;
+    use std::vec::Vec;
+
+    use embedded_hal::i2c::{ErrorType, Operation};
+
+    use super::*;
+
+    /// A QMI8658 on a simulated bus
+    struct FakeImu {
+        regs: [u8; 0x80],
+        fifo: VecDeque<u8>,
+        /// Never signal a completed command
+        stuck: bool,
+    }
+
+    impl FakeImu {
+        fn new() -> Self {
+            let mut regs = [0; 0x80];
+            regs[usize::from(reg::WHO_AM_I)] = DEVICE_ID;
+            FakeImu {
+                regs,
+                fifo: VecDeque::new(),
+                stuck: false,
+            }
+        }
+
+        fn reg(&self, reg: u8) -> u8 {
+            self.regs[usize::from(reg)]
+        }
+
+        fn set_regs(&mut self, reg: u8, values: &[u8]) {
+            let at = usize::from(reg);
+            self.regs[at..at + values.len()].copy_from_slice(values);
+        }
+
+        fn write(&mut self, reg: u8, value: u8) {
+            self.regs[usize::from(reg)] = value;
+            if reg != reg::CTRL9 || self.stuck {
+                return;
+            }
+            if value == cmd::ACK {
+                self.regs[usize::from(reg::STATUSINT)] &= !STATUSINT_CMD_DONE;
+                return;
+            }
+            match value {
+                cmd::RST_FIFO => {
+                    self.fifo.clear();
+                    self.set_regs(reg::FIFO_SMPL_CNT, &[0, 0]);
+                }
+                cmd::REQ_FIFO => self.regs[usize::from(reg::FIFO_CTRL)] |= FIFO_CTRL_RD_MODE,
+                _ => panic!("unknown command {:#04x}", value),
+            }
+            self.regs[usize::from(reg::STATUSINT)] |= STATUSINT_CMD_DONE;
+        }
+    }
+
+    impl ErrorType for FakeImu {
+        type Error = Infallible;
+    }
+
+    impl I2c for FakeImu {
+        fn transaction(
+            &mut self,
+            address: u8,
+            operations: &mut [Operation<'_>],
+        ) -> Result<(), Infallible> {
+            assert_eq!(address, ADDRESS);
+            let mut at = 0;
+            for operation in operations {
+                match operation {
+                    Operation::Write(bytes) => {
+                        at = bytes[0];
+                        for &value in &bytes[1..] {
+                            self.write(at, value);
+                            at += 1;
+                        }
+                    }
+                    Operation::Read(buf) if at == reg::FIFO_DATA => {
+                        assert!(self.reg(reg::FIFO_CTRL) & FIFO_CTRL_RD_MODE != 0);
+                        for byte in buf.iter_mut() {
+                            *byte = self.fifo.pop_front().expect("FIFO is empty");
+                        }
+                    }
+                    Operation::Read(buf) => {
+                        for byte in buf.iter_mut() {
+                            *byte = self.reg(at);
+                            at += 1;
+                        }
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+
+    fn assert_close(actual: [f32; 3], expected: [f32; 3]) {
+        for (a, e) in actual.iter().zip(expected.iter()) {
+            assert!((a - e).abs() < 1e-4, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    fn vector_bytes(vector: [i16; 3]) -> Vec<u8> {
+        vector.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn init_checks_the_device_and_disables_the_sensors() {
+        let mut fake = FakeImu::new();
+        fake.regs[usize::from(reg::CTRL7)] = CTRL7_A_EN | CTRL7_G_EN;
+        let fake = Qmi8658::new(fake, ADDRESS).unwrap().free();
+        assert_eq!(fake.reg(reg::CTRL1), CTRL1_ADDR_AI);
+        assert_eq!(fake.reg(reg::CTRL7), 0);
+
+        let mut fake = FakeImu::new();
+        fake.regs[usize::from(reg::WHO_AM_I)] = 0x6c;
+        assert!(matches!(
+            Qmi8658::new(fake, ADDRESS),
+            Err(Error::WrongDevice(0x6c))
+        ));
+    }
+
+    #[test]
+    fn readings_are_scaled() {
+        let mut imu = Qmi8658::new(FakeImu::new(), ADDRESS).unwrap();
+        imu.i2c
+            .set_regs(reg::AX_L, &vector_bytes([16384, -8192, 0]));
+        imu.i2c
+            .set_regs(reg::GX_L, &vector_bytes([0, 16384, -32768]));
+        imu.i2c.set_regs(reg::TEMP_L, &[0x80, 0x19]);
+
+        // Disabled sensors read zero
+        assert_close(imu.accel().unwrap(), [0.0; 3]);
+        assert_close(imu.gyro().unwrap(), [0.0; 3]);
+
+        imu.set_accel(AccelScale::G4, DataRate::Hz125).unwrap();
+        imu.set_gyro(GyroScale::Dps512, DataRate::Hz1000).unwrap();
+        assert_eq!(imu.i2c.reg(reg::CTRL2), 0x16);
+        assert_eq!(imu.i2c.reg(reg::CTRL3), 0x53);
+        assert_eq!(imu.i2c.reg(reg::CTRL7), CTRL7_A_EN | CTRL7_G_EN);
+
+        assert_eq!(imu.accel_raw().unwrap(), [16384, -8192, 0]);
+        assert_close(imu.accel().unwrap(), [2.0 * GRAVITY, -GRAVITY, 0.0]);
+        let dps = 256f32.to_radians();
+        assert_close(imu.gyro().unwrap(), [0.0, dps, -2.0 * dps]);
+        assert_eq!(imu.temperature().unwrap(), 25.5);
+
+        imu.disable_accel().unwrap();
+        assert_eq!(imu.i2c.reg(reg::CTRL7), CTRL7_G_EN);
+        assert_close(imu.accel().unwrap(), [0.0; 3]);
+    }
+
+    #[test]
+    fn fifo_frames_are_drained_at_the_watermark() {
+        let mut imu = Qmi8658::new(FakeImu::new(), ADDRESS).unwrap();
+        imu.set_accel(AccelScale::G2, DataRate::Hz125).unwrap();
+        imu.set_gyro(GyroScale::Dps16, DataRate::Hz125).unwrap();
+        imu.enable_fifo(FifoSize::Samples32, 2, Interrupt::Int1)
+            .unwrap();
+        assert_eq!(
+            imu.i2c.reg(reg::CTRL1),
+            CTRL1_ADDR_AI | CTRL1_INT1_EN | CTRL1_FIFO_INT_SEL
+        );
+        assert_eq!(imu.i2c.reg(reg::FIFO_WTM_TH), 2);
+        assert_eq!(imu.i2c.reg(reg::FIFO_CTRL), 0b0110);
+        assert_eq!(imu.i2c.reg(reg::STATUSINT) & STATUSINT_CMD_DONE, 0);
+
+        // Two frames of both sensors, the count is in units of two bytes
+        for n in 1..=2 {
+            imu.i2c.fifo.extend(vector_bytes([n * 4096, 0, 0]));
+            imu.i2c.fifo.extend(vector_bytes([0, 0, -n * 2048]));
+        }
+        imu.i2c.set_regs(reg::FIFO_SMPL_CNT, &[12, 0x40]);
+        assert_eq!(
+            imu.fifo_status().unwrap(),
+            FifoStatus {
+                bytes: 24,
+                watermark: true,
+                overflow: false,
+                full: false,
+            }
+        );
+
+        let mut frames = Vec::new();
+        assert_eq!(imu.drain_fifo(|frame| frames.push(frame)).unwrap(), 2);
+        assert_eq!(frames.len(), 2);
+        for (n, frame) in frames.iter().enumerate() {
+            let n = n as f32 + 1.0;
+            assert_close(frame.accel.unwrap(), [n * GRAVITY / 4.0, 0.0, 0.0]);
+            let dps = n * 1f32.to_radians();
+            assert_close(frame.gyro.unwrap(), [0.0, 0.0, -dps]);
+        }
+        assert!(imu.i2c.fifo.is_empty());
+        // Back out of the read mode
+        assert_eq!(imu.i2c.reg(reg::FIFO_CTRL), 0b0110);
+
+        // Without any sensors there is nothing to read
+        imu.disable_accel().unwrap();
+        imu.disable_gyro().unwrap();
+        assert_eq!(imu.drain_fifo(|_| panic!("no frames expected")).unwrap(), 0);
+    }
+
+    #[test]
+    fn stuck_commands_time_out() {
+        let mut imu = Qmi8658::new(FakeImu::new(), ADDRESS).unwrap();
+        imu.i2c.stuck = true;
+        assert!(matches!(
+            imu.enable_fifo(FifoSize::Samples16, 8, Interrupt::Int2),
+            Err(Error::Timeout)
+        ));
+    }
+}
@@ -0,0 +1,512 @@
+//! The round 1.28" 240x240 LCD of the board
+//!
+//! The display is driven by a GC9A01A controller, connected to SPI1. Its
+//! backlight is dimmed by PWM slice 4.
+//!
+//! | LCD       | RP2040 | Alias                  |
+//! |-----------|--------|------------------------|
+//! | DC        | GPIO8  | [crate::LcdDc]         |
+//! | CS        | GPIO9  | [crate::LcdCs]         |
+//! | CLK       | GPIO10 | [crate::LcdClk]        |
+//! | DIN       | GPIO11 | [crate::LcdMosi]       |
+//! | RST       | GPIO12 | [crate::LcdRst]        |
+//! | BL        | GPIO25 | [crate::LcdBacklight]  |
+//!
+//! ```ignore
+//! let pwm_slices = hal::pwm::Slices::new(pac.PWM, &mut pac.RESETS);
+//! let (display, mut backlight) = lcd::init(
+//!     pac.SPI1,
+//!     LcdPins {
+//!         dc: pins.gp8.reconfigure(),
+//!         cs: pins.gp9.reconfigure(),
+//!         clk: pins.gp10.reconfigure(),
+//!         mosi: pins.gp11.reconfigure(),
+//!         rst: pins.gp12.reconfigure(),
+//!         backlight: pins.gp25.reconfigure(),
+//!     },
+//!     pwm_slices.pwm4,
+//!     &mut pac.RESETS,
+//!     &clocks.peripheral_clock,
+//!     &mut timer,
+//! );
+//! backlight.set_brightness(80);
+//! let mut display = RoundClip::new(display);
+//! ```
+
+use embedded_graphics::pixelcolor::Rgb565;
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::Rectangle;
+use embedded_hal::delay::DelayNs;
+use gc9a01a_driver::{Orientation, GC9A01A};
+
+use crate::hal;
+use crate::hal::clocks::{Clock, PeripheralClock};
+use crate::hal::fugit::RateExtU32;
+use crate::hal::pwm::{FreeRunning, Pwm4, Slice};
+use crate::hal::spi::Enabled;
+use crate::pac;
+use crate::{LcdBacklight, LcdClk, LcdCs, LcdDc, LcdMosi, LcdRst};
+
+/// Width of the display in pixels
+pub const LCD_WIDTH: u32 = 240;
+
+/// Height of the display in pixels
+pub const LCD_HEIGHT: u32 = 240;
+
+/// SPI clock of the display
+const SPI_FREQ_MHZ: u32 = 40;
+
+/// The SPI bus the display is connected to
+pub type LcdSpi = hal::Spi<Enabled, pac::SPI1, (LcdMosi, LcdClk), 8>;
+
+/// The display driver, as set up by [init]
+pub type Display = GC9A01A<LcdSpi, LcdDc, LcdCs, LcdRst>;
+
+/// The pins connected to the display
+pub struct LcdPins {
+    /// Data/command select
+    pub dc: LcdDc,
+    /// Chip select
+    pub cs: LcdCs,
+    /// SPI clock
+    pub clk: LcdClk,
+    /// SPI data
+    pub mosi: LcdMosi,
+    /// Reset
+    pub rst: LcdRst,
+    /// Backlight enable
+    pub backlight: LcdBacklight,
+}
+
+/// Set up SPI1 and the display, and the PWM slice for the backlight
+///
+/// The display is cleared to black while the backlight is still off, so no
+/// garbage is visible. Afterwards the backlight is turned on at full
+/// brightness.
+pub fn init(
+    spi1: pac::SPI1,
+    pins: LcdPins,
+    pwm4: Slice<Pwm4, FreeRunning>,
+    resets: &mut pac::RESETS,
+    peripheral_clock: &PeripheralClock,
+    delay: &mut impl DelayNs,
+) -> (Display, Backlight) {
+    let mut backlight = Backlight::new(pwm4, pins.backlight);
+    backlight.off();
+
+    let spi = hal::Spi::<_, _, _, 8>::new(spi1, (pins.mosi, pins.clk)).init(
+        resets,
+        peripheral_clock.freq(),
+        SPI_FREQ_MHZ.MHz(),
+        embedded_hal::spi::MODE_0,
+    );
+
+    let mut display = GC9A01A::new(
+        spi, pins.dc, pins.cs, pins.rst, false, LCD_WIDTH, LCD_HEIGHT,
+    );
+    display.init(delay).unwrap();
+    display.set_orientation(&Orientation::Landscape).unwrap();
+    display.clear(Rgb565::BLACK).unwrap();
+
+    backlight.on();
+    #[cfg(feature = "defmt")]
+    defmt::debug!("lcd: GC9A01A ready, backlight on");
+    (display, backlight)
+}
+
+/// The display backlight, dimmed by PWM slice 4
+pub type Backlight = board_common::Backlight<Pwm4, LcdBacklight>;
+
+/// A circle inscribed into a rectangle, in doubled coordinates so the
+/// center of even sized circles falls on a pixel corner
+#[derive(Debug, Clone, Copy)]
+struct Circle {
+    center_x2: i32,
+    center_y2: i32,
+    diameter: i32,
+}
+
+impl Circle {
+    fn inscribed(area: &Rectangle) -> Self {
+        let diameter = area.size.width.min(area.size.height) as i32;
+        Circle {
+            center_x2: 2 * area.top_left.x + area.size.width as i32,
+            center_y2: 2 * area.top_left.y + area.size.height as i32,
+            diameter,
+        }
+    }
+
+    fn contains(&self, point: Point) -> bool {
+        matches!(self.span(point.y), Some((start, end)) if (start..=end).contains(&point.x))
+    }
+
+    /// The first and last column of row `y` inside the circle
+    fn span(&self, y: i32) -> Option<(i32, i32)> {
+        let dy = 2 * y + 1 - self.center_y2;
+        let remaining = self.diameter * self.diameter - dy * dy;
+        if remaining < 0 {
+            return None;
+        }
+        // A pixel is inside if |2x + 1 - center_x2| <= half_width
+        let half_width = isqrt(remaining as u32) as i32;
+        Some((
+            (self.center_x2 - half_width).div_euclid(2),
+            (self.center_x2 + half_width - 1).div_euclid(2),
+        ))
+    }
+
+    /// The part of row `y` of `area` inside the circle, as first column and
+    /// number of pixels
+    fn clip_row(&self, area: &Rectangle, y: i32) -> Option<(i32, u32)> {
+        let (start, end) = self.span(y)?;
+        let start = start.max(area.top_left.x);
+        let end = end.min(area.top_left.x + area.size.width as i32 - 1);
+        if start > end {
+            return None;
+        }
+        Some((start, (end - start + 1) as u32))
+    }
+}
+
+fn isqrt(n: u32) -> u32 {
+    if n < 2 {
+        return n;
+    }
+    let mut x = n / 2;
+    let mut y = (x + n / x) / 2;
+    while y < x {
+        x = y;
+        y = (x + n / x) / 2;
+    }
+    x
+}
+
+/// Clips drawing to the circle inscribed into the bounding box of a display
+///
+/// Pixels outside the circle aren't visible on a round panel. They are
+/// skipped instead of being sent, and filled areas are split into one run per
+/// row covering only the visible part.
+pub struct RoundClip<D> {
+    inner: D,
+}
+
+impl<D: DrawTarget> RoundClip<D> {
+    /// Wrap a display
+    pub fn new(inner: D) -> Self {
+        RoundClip { inner }
+    }
+
+    /// Get a reference to the wrapped display
+    pub fn inner(&self) -> &D {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the wrapped display, to draw without
+    /// clipping
+    pub fn inner_mut(&mut self) -> &mut D {
+        &mut self.inner
+    }
+
+    /// Release the wrapped display
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    /// Whether `point` is visible
+    pub fn contains(&self, point: Point) -> bool {
+        Circle::inscribed(&self.inner.bounding_box()).contains(point)
+    }
+}
+
+impl<D: DrawTarget> Dimensions for RoundClip<D> {
+    fn bounding_box(&self) -> Rectangle {
+        self.inner.bounding_box()
+    }
+}
+
+impl<D: DrawTarget> DrawTarget for RoundClip<D> {
+    type Color = D::Color;
+    type Error = D::Error;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let circle = Circle::inscribed(&self.inner.bounding_box());
+        self.inner.draw_iter(
+            pixels
+                .into_iter()
+                .filter(|Pixel(point, _)| circle.contains(*point)),
+        )
+    }
+
+    fn fill_contiguous<I>(&mut self, area: &Rectangle, colors: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Self::Color>,
+    {
+        let circle = Circle::inscribed(&self.inner.bounding_box());
+        let mut colors = colors.into_iter();
+        for y in area.rows() {
+            let mut skip = area.size.width;
+            if let Some((start, len)) = circle.clip_row(area, y) {
+                let before = (start - area.top_left.x) as u32;
+                colors.by_ref().take(before as usize).for_each(drop);
+                let row = Rectangle::new(Point::new(start, y), Size::new(len, 1));
+                let mut visible = colors.by_ref().take(len as usize);
+                self.inner.fill_contiguous(&row, &mut visible)?;
+                // The colors the display didn't take
+                visible.for_each(drop);
+                skip -= before + len;
+            }
+            colors.by_ref().take(skip as usize).for_each(drop);
+        }
+        Ok(())
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        let circle = Circle::inscribed(&self.inner.bounding_box());
+        let area = area.intersection(&self.inner.bounding_box());
+        for y in area.rows() {
+            if let Some((start, len)) = circle.clip_row(&area, y) {
+                let row = Rectangle::new(Point::new(start, y), Size::new(len, 1));
+                self.inner.fill_solid(&row, color)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        self.fill_solid(&self.inner.bounding_box(), color)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::cell::Cell;
+    use core::convert::Infallible;
+    use std::collections::BTreeMap;
+    use std::vec::Vec;
+
+    use super::*;
+
+    /// A display which records the pixels drawn to it
+    struct Recorder {
+        size: Size,
+        pixels: BTreeMap<(i32, i32), Rgb565>,
+        /// Ignore the colors of filled areas
+        drop_fills: bool,
+    }
+
+    impl Recorder {
+        fn new(width: u32, height: u32) -> Self {
+            Recorder {
+                size: Size::new(width, height),
+                pixels: BTreeMap::new(),
+                drop_fills: false,
+            }
+        }
+
+        fn points(&self) -> Vec<(i32, i32)> {
+            self.pixels.keys().copied().collect()
+        }
+    }
+
+    impl OriginDimensions for Recorder {
+        fn size(&self) -> Size {
+            self.size
+        }
+    }
+
+    impl DrawTarget for Recorder {
+        type Color = Rgb565;
+        type Error = Infallible;
+
+        fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Infallible>
+        where
+            I: IntoIterator<Item = Pixel<Rgb565>>,
+        {
+            for Pixel(point, color) in pixels {
+                assert!(
+                    self.bounding_box().contains(point),
+                    "{:?} is outside",
+                    point
+                );
+                let drawn = self.pixels.insert((point.x, point.y), color);
+                assert!(drawn.is_none(), "{:?} is drawn twice", point);
+            }
+            Ok(())
+        }
+
+        fn fill_contiguous<I>(&mut self, area: &Rectangle, colors: I) -> Result<(), Infallible>
+        where
+            I: IntoIterator<Item = Rgb565>,
+        {
+            if self.drop_fills {
+                return Ok(());
+            }
+            let pixels = area.points().zip(colors);
+            self.draw_iter(pixels.map(|(point, color)| Pixel(point, color)))
+        }
+    }
+
+    /// Whether the center of the pixel at `x`, `y` is inside the circle
+    /// inscribed into a display of `width` and `height`
+    fn inside(width: u32, height: u32, x: i32, y: i32) -> bool {
+        let radius = f64::from(width.min(height)) / 2.0;
+        let dx = f64::from(x) + 0.5 - f64::from(width) / 2.0;
+        let dy = f64::from(y) + 0.5 - f64::from(height) / 2.0;
+        dx * dx + dy * dy <= radius * radius
+    }
+
+    /// The pixels of `area` which are visible on a display of `width` and
+    /// `height`
+    fn visible(width: u32, height: u32, area: &Rectangle) -> Vec<(i32, i32)> {
+        let mut points: Vec<_> = area
+            .points()
+            .filter(|p| p.x >= 0 && p.y >= 0 && inside(width, height, p.x, p.y))
+            .map(|p| (p.x, p.y))
+            .collect();
+        points.sort();
+        points
+    }
+
+    /// A color for each index, up to 2048
+    fn color(i: usize) -> Rgb565 {
+        Rgb565::new((i % 32) as u8, (i / 32) as u8, 0)
+    }
+
+    fn index(color: Rgb565) -> usize {
+        usize::from(color.g()) * 32 + usize::from(color.r())
+    }
+
+    /// Fills `area` with a color for each pixel, checking that exactly as
+    /// many colors are consumed and each pixel gets its own
+    fn fill(display: &mut RoundClip<Recorder>, area: Rectangle) {
+        let consumed = Cell::new(0);
+        let colors = (0..)
+            .map(color)
+            .inspect(|_| consumed.set(consumed.get() + 1));
+        display.fill_contiguous(&area, colors).unwrap();
+        assert_eq!(consumed.get(), area.size.width * area.size.height);
+
+        for (&(x, y), &color) in &display.inner().pixels {
+            let dx = (x - area.top_left.x) as usize;
+            let dy = (y - area.top_left.y) as usize;
+            assert_eq!(index(color), dy * area.size.width as usize + dx);
+        }
+    }
+
+    #[test]
+    fn isqrt_rounds_down() {
+        for n in 0..100_000 {
+            let root = isqrt(n);
+            assert!(root * root <= n && (root + 1) * (root + 1) > n, "{}", n);
+        }
+        assert_eq!(isqrt(240 * 240), 240);
+        assert_eq!(isqrt(u32::MAX), 65535);
+    }
+
+    #[test]
+    fn circle_matches_the_pixel_centers() {
+        for (width, height) in [(240, 240), (10, 10), (9, 9), (1, 1), (12, 7), (6, 11)] {
+            let display = RoundClip::new(Recorder::new(width, height));
+            for y in -2..height as i32 + 2 {
+                for x in -2..width as i32 + 2 {
+                    assert_eq!(
+                        display.contains(Point::new(x, y)),
+                        inside(width, height, x, y),
+                        "({}, {}) of {}x{}",
+                        x,
+                        y,
+                        width,
+                        height
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn circles_are_symmetric() {
+        for diameter in [10, 9] {
+            let circle = Circle::inscribed(&Rectangle::new(
+                Point::new(3, -5),
+                Size::new(diameter, diameter),
+            ));
+            let span = |y| circle.span(y).map(|(start, end)| (start - 3, end - 3));
+            let rows: Vec<_> = (-5..diameter as i32 - 5).map(span).collect();
+            for (row, mirrored) in rows.iter().zip(rows.iter().rev()) {
+                assert_eq!(row, mirrored);
+            }
+            for &(start, end) in rows.iter().flatten() {
+                assert_eq!(start + end, diameter as i32 - 1);
+            }
+            // The middle rows span the whole diameter
+            let middle = diameter as i32 / 2 - 5;
+            assert_eq!(span(middle), Some((0, diameter as i32 - 1)));
+            assert_eq!((span(-6), span(diameter as i32 - 5)), (None, None));
+        }
+    }
+
+    #[test]
+    fn fills_the_visible_pixels_of_even_and_odd_displays() {
+        for size in [10, 9] {
+            let mut display = RoundClip::new(Recorder::new(size, size));
+            let area = display.bounding_box();
+            fill(&mut display, area);
+            assert_eq!(display.inner().points(), visible(size, size, &area));
+        }
+    }
+
+    #[test]
+    fn fills_areas_partly_outside() {
+        for area in [
+            Rectangle::new(Point::new(-4, 6), Size::new(8, 7)),
+            Rectangle::new(Point::new(5, -3), Size::new(9, 4)),
+            Rectangle::new(Point::new(-2, -2), Size::new(14, 14)),
+            Rectangle::new(Point::new(20, 3), Size::new(3, 3)),
+            Rectangle::new(Point::new(0, 0), Size::new(0, 3)),
+        ] {
+            let mut display = RoundClip::new(Recorder::new(10, 10));
+            fill(&mut display, area);
+            assert_eq!(display.inner().points(), visible(10, 10, &area));
+        }
+
+        // Also if the display doesn't take the colors of a row
+        let mut display = RoundClip::new(Recorder::new(10, 10));
+        display.inner_mut().drop_fills = true;
+        fill(
+            &mut display,
+            Rectangle::new(Point::new(-2, -2), Size::new(14, 14)),
+        );
+        assert!(display.inner().points().is_empty());
+    }
+
+    #[test]
+    fn solid_fills_and_pixels_are_clipped() {
+        let mut display = RoundClip::new(Recorder::new(9, 9));
+        let area = Rectangle::new(Point::new(-3, 4), Size::new(20, 20));
+        display.fill_solid(&area, Rgb565::RED).unwrap();
+        assert_eq!(display.inner().points(), visible(9, 9, &area));
+
+        let mut display = RoundClip::new(Recorder::new(9, 9));
+        display.clear(Rgb565::RED).unwrap();
+        assert_eq!(
+            display.inner().points(),
+            visible(9, 9, &display.bounding_box())
+        );
+
+        let mut display = RoundClip::new(Recorder::new(9, 9));
+        let corners = [(0, 0), (8, 0), (0, 8), (8, 8), (4, 4), (1, 2), (-1, 4)];
+        display
+            .draw_iter(
+                corners
+                    .iter()
+                    .map(|&(x, y)| Pixel(Point::new(x, y), Rgb565::RED)),
+            )
+            .unwrap();
+        assert_eq!(display.inner().points(), [(1, 2), (4, 4)]);
+    }
+}
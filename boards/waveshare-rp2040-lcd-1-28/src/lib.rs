@@ -17,7 +17,14 @@ pub static BOOT2_FIRMWARE: [u8; 256] = rp2040_boot2::BOOT_LOADER_W25Q080;
 
 pub use hal::pac;
 
-hal::bsp_pins!(
+pub mod imu;
+pub mod lcd;
+#[cfg(feature = "panic-screen")]
+mod panic_screen;
+
+board_common::bsp_pins!(
+    embassy: crate::embassy::Pins;
+
     /// GPIO 0 supports following functions:
     ///
     /// | Function     | Alias with applied function |
@@ -196,9 +203,13 @@ hal::bsp_pins!(
     /// | `PWM3 A`     | [crate::Gp6Pwm3A]           |
     /// | `PIO0`       | [crate::Gp6Pio0]            |
     /// | `PIO1`       | [crate::Gp6Pio1]            |
+    ///
+    /// IMU I2C data, see [crate::ImuSda]
     Gpio6 {
         name: gp6,
         aliases: {
+            /// IMU I2C data, alias for pin [crate::Pins::gpio6].
+            FunctionI2C, PullUp: ImuSda,
             /// UART Function alias for pin [crate::Pins::gpio6].
             FunctionUart, PullNone: Gp6Uart1Cts,
             /// SPI Function alias for pin [crate::Pins::gpio6].
@@ -224,9 +235,13 @@ hal::bsp_pins!(
     /// | `PWM3 B`     | [crate::Gp7Pwm3B]           |
     /// | `PIO0`       | [crate::Gp7Pio0]            |
     /// | `PIO1`       | [crate::Gp7Pio1]            |
+    ///
+    /// IMU I2C clock, see [crate::ImuScl]
     Gpio7 {
         name: gp7,
         aliases: {
+            /// IMU I2C clock, alias for pin [crate::Pins::gpio7].
+            FunctionI2C, PullUp: ImuScl,
             /// UART Function alias for pin [crate::Pins::gpio7].
             FunctionUart, PullNone: Gp7Uart1Rts,
             /// SPI Function alias for pin [crate::Pins::gpio7].
@@ -252,9 +267,13 @@ hal::bsp_pins!(
     /// | `PWM4 A`     | [crate::Gp8Pwm4A]           |
     /// | `PIO0`       | [crate::Gp8Pio0]            |
     /// | `PIO1`       | [crate::Gp8Pio1]            |
+    ///
+    /// LCD data/command select, see [crate::LcdDc]
     Gpio8 {
         name: gp8,
         aliases: {
+            /// LCD data/command select, alias for pin [crate::Pins::gpio8].
+            FunctionSioOutput, PullNone: LcdDc,
             /// UART Function alias for pin [crate::Pins::gpio8].
             FunctionUart, PullNone: Gp8Uart1Tx,
             /// SPI Function alias for pin [crate::Pins::gpio8].
@@ -280,9 +299,13 @@ hal::bsp_pins!(
     /// | `PWM4 B`     | [crate::Gp9Pwm4B]           |
     /// | `PIO0`       | [crate::Gp9Pio0]            |
     /// | `PIO1`       | [crate::Gp9Pio1]            |
+    ///
+    /// LCD chip select, see [crate::LcdCs]
     Gpio9 {
         name: gp9,
         aliases: {
+            /// LCD chip select, alias for pin [crate::Pins::gpio9].
+            FunctionSioOutput, PullNone: LcdCs,
             /// UART Function alias for pin [crate::Pins::gpio9].
             FunctionUart, PullNone: Gp9Uart1Rx,
             /// SPI Function alias for pin [crate::Pins::gpio9].
@@ -308,9 +331,13 @@ hal::bsp_pins!(
     /// | `PWM5 A`     | [crate::Gp10Pwm5A]          |
     /// | `PIO0`       | [crate::Gp10Pio0]           |
     /// | `PIO1`       | [crate::Gp10Pio1]           |
+    ///
+    /// LCD SPI clock, see [crate::LcdClk]
     Gpio10 {
         name: gp10,
         aliases: {
+            /// LCD SPI clock, alias for pin [crate::Pins::gpio10].
+            FunctionSpi, PullNone: LcdClk,
             /// UART Function alias for pin [crate::Pins::gpio10].
             FunctionUart, PullNone: Gp10Uart1Cts,
             /// SPI Function alias for pin [crate::Pins::gpio10].
@@ -336,9 +363,13 @@ hal::bsp_pins!(
     /// | `PWM5 B`     | [crate::Gp11Pwm5B]          |
     /// | `PIO0`       | [crate::Gp11Pio0]           |
     /// | `PIO1`       | [crate::Gp11Pio1]           |
+    ///
+    /// LCD SPI data, see [crate::LcdMosi]
     Gpio11 {
         name: gp11,
         aliases: {
+            /// LCD SPI data, alias for pin [crate::Pins::gpio11].
+            FunctionSpi, PullNone: LcdMosi,
             /// UART Function alias for pin [crate::Pins::gpio11].
             FunctionUart, PullNone: Gp11Uart1Rts,
             /// SPI Function alias for pin [crate::Pins::gpio11].
@@ -364,9 +395,13 @@ hal::bsp_pins!(
     /// | `PWM6 A`     | [crate::Gp12Pwm6A]          |
     /// | `PIO0`       | [crate::Gp12Pio0]           |
     /// | `PIO1`       | [crate::Gp12Pio1]           |
+    ///
+    /// LCD reset, low active, see [crate::LcdRst]
     Gpio12 {
         name: gp12,
         aliases: {
+            /// LCD reset, low active, alias for pin [crate::Pins::gpio12].
+            FunctionSioOutput, PullNone: LcdRst,
             /// UART Function alias for pin [crate::Pins::gpio12].
             FunctionUart, PullNone: Gp12Uart0Tx,
             /// SPI Function alias for pin [crate::Pins::gpio12].
@@ -638,9 +673,12 @@ hal::bsp_pins!(
     },
 
     /// GPIO 23
+    ///
+    /// IMU interrupt 1, see [crate::ImuInt1]
     Gpio23 {
         name: gp23,
         aliases: {
+            FunctionSioInput, PullNone: ImuInt1,
             FunctionUart, PullNone: Gp23Uart1Rts,
             FunctionSpi, PullNone: Gp23Spi0Tx,
             FunctionI2C, PullUp: Gp23I2C1Scl,
@@ -651,9 +689,12 @@ hal::bsp_pins!(
     },
 
     /// GPIO 24
+    ///
+    /// IMU interrupt 2, see [crate::ImuInt2]
     Gpio24 {
         name: gp24,
         aliases: {
+            FunctionSioInput, PullNone: ImuInt2,
             FunctionUart, PullNone: Gp24Uart1Tx,
             FunctionSpi, PullNone: Gp24Spi1Rx,
             FunctionI2C, PullUp: Gp24I2C0Sda,
@@ -664,9 +705,12 @@ hal::bsp_pins!(
     },
 
     /// GPIO 25
+    ///
+    /// LCD backlight, driven by PWM, see [crate::LcdBacklight]
     Gpio25 {
         name: gp25,
         aliases: {
+            FunctionPwm, PullNone: LcdBacklight,
             FunctionUart, PullNone: Gp25Uart1Rx,
             FunctionSpi, PullNone: Gp25Spi1Csn,
             FunctionI2C, PullUp: Gp25I2C0Scl,
@@ -783,3 +827,13 @@ hal::bsp_pins!(
 );
 
 pub const XOSC_CRYSTAL_FREQ: u32 = 12_000_000;
+
+// FLASH_SIZE_BYTES and the reserved end of the flash, see build.rs
+include!(concat!(env!("OUT_DIR"), "/flash.rs"));
+
+#[cfg(feature = "embassy")]
+pub mod embassy;
+
+pub use board_common;
+
+board_common::board_pins!(Pins, XOSC_CRYSTAL_FREQ);
@@ -0,0 +1,64 @@
+//! The panic handler of the `panic-screen` feature
+//!
+//! Shows the panic on the LCD, in white on red, within the square which fits
+//! into the round display. The board has no button the application can
+//! read, so it stays there until it is reset.
+
+use core::panic::PanicInfo;
+
+use board_common::panic_screen::{self, PanicScreen};
+use embedded_graphics::pixelcolor::{Rgb565, RgbColor};
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::Rectangle;
+
+use crate::lcd::{self, LcdPins, RoundClip, LCD_HEIGHT, LCD_WIDTH};
+use crate::{hal, Board};
+
+/// Side of the square inscribed into the display
+const SQUARE: u32 = LCD_WIDTH * 7 / 10;
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    if !panic_screen::first_panic() {
+        panic_screen::halt();
+    }
+
+    // SAFETY: the application never runs again
+    let mut board = unsafe { Board::steal() };
+    // Keep the message on the screen
+    board.watchdog.disable();
+
+    let pins = board.pins;
+    let resets = &mut board.peripherals.RESETS;
+    let pwm_slices = hal::pwm::Slices::new(board.peripherals.PWM, resets);
+    let (display, _backlight) = lcd::init(
+        board.peripherals.SPI1,
+        LcdPins {
+            dc: pins.gp8.reconfigure(),
+            cs: pins.gp9.reconfigure(),
+            clk: pins.gp10.reconfigure(),
+            mosi: pins.gp11.reconfigure(),
+            rst: pins.gp12.reconfigure(),
+            backlight: pins.gp25.reconfigure(),
+        },
+        pwm_slices.pwm4,
+        resets,
+        &board.clocks.peripheral_clock,
+        &mut board.timer,
+    );
+    let mut display = RoundClip::new(display);
+
+    let area = Rectangle::new(
+        Point::new(
+            ((LCD_WIDTH - SQUARE) / 2) as i32,
+            ((LCD_HEIGHT - SQUARE) / 2) as i32,
+        ),
+        Size::new(SQUARE, SQUARE),
+    );
+    let _ = display.clear(Rgb565::RED);
+    let _ = PanicScreen::new(Rgb565::WHITE, Rgb565::RED)
+        .with_area(area)
+        .draw(&mut display, info);
+
+    panic_screen::halt()
+}